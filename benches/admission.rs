@@ -0,0 +1,103 @@
+//! Per-request admission checks against state populated with thousands of
+//! tracked clients, plus CAPTCHA clock rendering
+
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use rustwall::captcha::challenge::{ClockRenderer, ClockTime};
+use rustwall::clock::{Clock, MockClock};
+use rustwall::tor::TorSecurityConfig;
+use rustwall::tor::ddos_mitigation::DDoSMitigation;
+use rustwall::tor::exit_node_filter::{BlocklistSource, ExitNodeFilter};
+use rustwall::tor::onion_service::{OnionAddress, OnionServiceProtection};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+use std::time::Duration;
+
+const TRACKED: u32 = 5_000;
+const CLIENTS: u32 = 50_000;
+
+/// A distinct /24 per index, so subnet grouping doesn't merge clients
+fn ip(index: u32) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::from(0x0A00_0000 + (index << 8)))
+}
+
+fn ddos_mitigation(c: &mut Criterion) {
+    let clock = MockClock::new();
+    let mut ddos = DDoSMitigation::new(&TorSecurityConfig::default())
+        .unwrap()
+        .with_clock(Arc::new(clock));
+    for index in 0..TRACKED {
+        ddos.record_request(Some(ip(index)), 512, Some(format!("circuit_{}", index))).unwrap();
+    }
+
+    let circuit = Some(format!("circuit_{}", TRACKED / 2));
+    c.bench_function("ddos_should_allow_request", |b| {
+        b.iter(|| ddos.should_allow_request(black_box(Some(ip(TRACKED / 2))), circuit.clone()).unwrap())
+    });
+}
+
+fn exit_node_filter(c: &mut Criterion) {
+    let clock = MockClock::new();
+    let mut filter = ExitNodeFilter::new(&TorSecurityConfig::default())
+        .unwrap()
+        .with_clock(Arc::new(clock.clone()));
+    for index in 0..TRACKED {
+        filter.record_connection(ip(index), clock.now());
+    }
+    for index in (0..TRACKED).step_by(10) {
+        filter
+            .add_to_blocklist(ip(index), BlocklistSource::Manual, "bench".to_string(), None, 5)
+            .unwrap();
+    }
+
+    let mut group = c.benchmark_group("exit_node_should_allow");
+    group.bench_function("allowed", |b| {
+        b.iter(|| filter.should_allow_exit_node(black_box(ip(TRACKED / 2 + 1))).unwrap())
+    });
+    group.bench_function("blocked", |b| {
+        b.iter(|| filter.should_allow_exit_node(black_box(ip(TRACKED / 2))).unwrap())
+    });
+    group.finish();
+}
+
+fn onion_service(c: &mut Criterion) {
+    let clock = MockClock::new();
+    let mut protection = OnionServiceProtection::new(&TorSecurityConfig::default())
+        .unwrap()
+        .with_clock(Arc::new(clock.clone()));
+    let onion = OnionAddress::from_public_key(&[7; 32]);
+    for index in 0..TRACKED {
+        if protection.should_allow_connection(ip(index), &onion).unwrap() {
+            protection.connection_closed(ip(index), &onion);
+        }
+    }
+
+    // Cycle through enough clients that each sees one connection per window
+    let mut next = 0;
+    c.bench_function("onion_should_allow_connection", |b| {
+        b.iter(|| {
+            if next % CLIENTS == 0 {
+                clock.advance(Duration::from_secs(61));
+            }
+            let client = ip(next % CLIENTS);
+            next += 1;
+            let allowed = protection.should_allow_connection(black_box(client), &onion).unwrap();
+            if allowed {
+                protection.connection_closed(client, &onion);
+            }
+            allowed
+        })
+    });
+}
+
+fn render_clock(c: &mut Criterion) {
+    let renderer = ClockRenderer::new(200.0);
+    let time = ClockTime::new(4, 20);
+
+    let mut group = c.benchmark_group("render_clock");
+    group.bench_function("svg", |b| b.iter(|| renderer.render_clock(black_box(&time))));
+    group.bench_function("png", |b| b.iter(|| renderer.render_clock_png(black_box(&time))));
+    group.finish();
+}
+
+criterion_group!(benches, ddos_mitigation, exit_node_filter, onion_service, render_clock);
+criterion_main!(benches);
@@ -0,0 +1,95 @@
+//! Cleanup routines that walk whole tracking maps, timed on thousands of
+//! entries that have all gone stale
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use rustwall::clock::{Clock, MockClock};
+use rustwall::tor::TorSecurityConfig;
+use rustwall::tor::ddos_mitigation::DDoSMitigation;
+use rustwall::tor::exit_node_filter::{BlocklistSource, ExitNodeFilter};
+use rustwall::tor::onion_service::{OnionAddress, OnionServiceProtection};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+use std::time::Duration;
+
+const TRACKED: u32 = 5_000;
+
+/// A distinct /24 per index, so subnet grouping doesn't merge clients
+fn ip(index: u32) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::from(0x0A00_0000 + (index << 8)))
+}
+
+fn ddos_mitigation(c: &mut Criterion) {
+    // Analysis, and with it cleanup, runs on the first request recorded over 10s after the last
+    c.bench_function("ddos_cleanup_old_data", |b| {
+        b.iter_batched(
+            || {
+                let clock = MockClock::new();
+                let mut ddos = DDoSMitigation::new(&TorSecurityConfig::default())
+                    .unwrap()
+                    .with_clock(Arc::new(clock.clone()));
+                for index in 0..TRACKED {
+                    ddos.record_request(Some(ip(index)), 512, Some(format!("circuit_{}", index))).unwrap();
+                }
+                clock.advance(Duration::from_secs(600));
+                ddos
+            },
+            |mut ddos| ddos.record_request(None, 512, None).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn exit_node_filter(c: &mut Criterion) {
+    c.bench_function("exit_node_cleanup_expired_data", |b| {
+        b.iter_batched(
+            || {
+                let clock = MockClock::new();
+                let mut filter = ExitNodeFilter::new(&TorSecurityConfig::default())
+                    .unwrap()
+                    .with_clock(Arc::new(clock.clone()));
+                for index in 0..TRACKED {
+                    filter.record_connection(ip(index), clock.now());
+                    filter
+                        .add_to_blocklist(
+                            ip(index),
+                            BlocklistSource::Manual,
+                            "bench".to_string(),
+                            Some(clock.now() + Duration::from_secs(60)),
+                            5,
+                        )
+                        .unwrap();
+                }
+                clock.advance(Duration::from_secs(86400 * 31));
+                filter
+            },
+            |mut filter| filter.cleanup_expired_data(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn onion_service(c: &mut Criterion) {
+    let onion = OnionAddress::from_public_key(&[7; 32]);
+    c.bench_function("onion_cleanup_expired_connections", |b| {
+        b.iter_batched(
+            || {
+                let clock = MockClock::new();
+                let mut protection = OnionServiceProtection::new(&TorSecurityConfig::default())
+                    .unwrap()
+                    .with_clock(Arc::new(clock.clone()));
+                for index in 0..TRACKED {
+                    if protection.should_allow_connection(ip(index), &onion).unwrap() {
+                        protection.connection_closed(ip(index), &onion);
+                    }
+                }
+                clock.advance(Duration::from_secs(600));
+                protection
+            },
+            |mut protection| protection.cleanup_expired_connections(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, ddos_mitigation, exit_node_filter, onion_service);
+criterion_main!(benches);
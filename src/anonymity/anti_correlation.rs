@@ -1,5 +1,132 @@
 //! Anti-Correlation Measures Module
-//! 
-//! Prevent linking of different requests from same user
+//!
+//! Prevent linking of different requests from same user. Each registered flow
+//! draws its delays and cover-packet decisions from its own independently seeded
+//! generator, so flows that belong together show no shared timing pattern.
 
-// TODO: Implement anti-correlation functionality
+use crate::anonymity::{AnonymityConfig, AnonymityError, AnonymityResult};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Identifies a flow, such as one connection of a user's session
+pub type FlowId = u64;
+
+/// What a flow should do before its next packet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlowSchedule {
+    pub flow: FlowId,
+    pub delay: Duration,
+    /// Send a cover packet alongside the real one
+    pub cover_packet: bool,
+}
+
+/// Injects independent delays into linked flows
+pub struct AntiCorrelation {
+    min_delay_ms: u64,
+    max_delay_ms: u64,
+    cover_packet_probability: f64,
+    flows: HashMap<FlowId, StdRng>,
+}
+
+impl AntiCorrelation {
+    pub fn new(config: &AnonymityConfig) -> AnonymityResult<Self> {
+        config.validate()?;
+        Ok(Self {
+            min_delay_ms: config.correlation_jitter_min_ms,
+            max_delay_ms: config.correlation_jitter_max_ms,
+            cover_packet_probability: config.cover_packet_probability,
+            flows: HashMap::new(),
+        })
+    }
+
+    /// Start tracking a flow with a freshly seeded generator; re-registering reseeds it
+    pub fn register_flow(&mut self, id: FlowId) {
+        self.flows.insert(id, StdRng::from_entropy());
+    }
+
+    pub fn unregister_flow(&mut self, id: FlowId) -> bool {
+        self.flows.remove(&id).is_some()
+    }
+
+    pub fn flow_count(&self) -> usize {
+        self.flows.len()
+    }
+
+    fn rng(&mut self, id: FlowId) -> AnonymityResult<&mut StdRng> {
+        self.flows.get_mut(&id).ok_or(AnonymityError::UnknownFlow(id))
+    }
+
+    /// Delay to apply before the flow's next packet
+    pub fn next_delay(&mut self, id: FlowId) -> AnonymityResult<Duration> {
+        let (min, max) = (self.min_delay_ms, self.max_delay_ms);
+        let rng = self.rng(id)?;
+        Ok(Duration::from_millis(rng.gen_range(min..=max)))
+    }
+
+    /// Draw an independent delay and cover-packet decision for each linked flow
+    pub fn decorrelate(&mut self, flows: &[FlowId]) -> AnonymityResult<Vec<FlowSchedule>> {
+        flows
+            .iter()
+            .map(|&flow| {
+                let delay = self.next_delay(flow)?;
+                let probability = self.cover_packet_probability;
+                let cover_packet = self.rng(flow)?.gen_bool(probability);
+                Ok(FlowSchedule { flow, delay, cover_packet })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn correlation(a: &[f64], b: &[f64]) -> f64 {
+        let n = a.len() as f64;
+        let (mean_a, mean_b) = (a.iter().sum::<f64>() / n, b.iter().sum::<f64>() / n);
+        let covariance: f64 = a.iter().zip(b).map(|(x, y)| (x - mean_a) * (y - mean_b)).sum();
+        let spread_a: f64 = a.iter().map(|x| (x - mean_a).powi(2)).sum::<f64>().sqrt();
+        let spread_b: f64 = b.iter().map(|y| (y - mean_b).powi(2)).sum::<f64>().sqrt();
+        covariance / (spread_a * spread_b)
+    }
+
+    #[test]
+    fn test_linked_flows_get_independent_delays() {
+        let mut anti_correlation = AntiCorrelation::new(&AnonymityConfig::default()).unwrap();
+        anti_correlation.register_flow(1);
+        anti_correlation.register_flow(2);
+
+        let mut first = Vec::new();
+        let mut second = Vec::new();
+        for _ in 0..2000 {
+            let schedule = anti_correlation.decorrelate(&[1, 2]).unwrap();
+            first.push(schedule[0].delay.as_secs_f64());
+            second.push(schedule[1].delay.as_secs_f64());
+            assert!(schedule.iter().all(|entry| entry.delay <= Duration::from_millis(250)));
+        }
+
+        assert_ne!(first, second);
+        let r = correlation(&first, &second);
+        // Independent samples of this size land well inside +/-0.1
+        assert!(r.abs() < 0.1, "delays correlate with r = {}", r);
+        // Nor does a flow's sequence predict its own next value
+        let r = correlation(&first[..1999], &first[1..]);
+        assert!(r.abs() < 0.1, "lag-one correlation r = {}", r);
+    }
+
+    #[test]
+    fn test_cover_packets_and_unknown_flows() {
+        let config = AnonymityConfig { cover_packet_probability: 1.0, ..AnonymityConfig::default() };
+        let mut anti_correlation = AntiCorrelation::new(&config).unwrap();
+        anti_correlation.register_flow(7);
+
+        assert!(anti_correlation.decorrelate(&[7]).unwrap()[0].cover_packet);
+        assert!(matches!(anti_correlation.next_delay(8), Err(AnonymityError::UnknownFlow(8))));
+        assert!(anti_correlation.decorrelate(&[7, 8]).is_err());
+
+        assert!(anti_correlation.unregister_flow(7));
+        assert_eq!(anti_correlation.flow_count(), 0);
+    }
+}
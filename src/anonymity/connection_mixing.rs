@@ -1,5 +1,245 @@
 //! Connection Mixing Module
-//! 
-//! Pool and randomize connection handling
+//!
+//! Pool and randomize connection handling. Messages from many connections are
+//! held in a shared pool and released together, shuffled, once the pool reaches
+//! the batch size or its deadline passes. The deadline is drawn from an
+//! exponential distribution each time the pool starts filling (capped at the
+//! max hold time), so batch boundaries are as unpredictable as their order.
 
-// TODO: Implement connection mixing functionality
+use crate::anonymity::{AnonymityConfig, AnonymityResult};
+use rand::Rng;
+use rand::seq::SliceRandom;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// Identifies the connection a message arrived on
+pub type ConnectionId = u64;
+
+/// A message as it leaves the mixer
+#[derive(Debug, Clone, PartialEq)]
+pub struct MixedMessage {
+    pub conn_id: ConnectionId,
+    pub payload: Vec<u8>,
+    pub submitted_at: Instant,
+}
+
+/// Called with each shuffled batch. No lock is held while it runs, so batches
+/// released from different threads may be delivered concurrently.
+pub type ReleaseCallback = Box<dyn Fn(Vec<MixedMessage>) + Send + Sync>;
+
+#[derive(Default)]
+struct MixPool {
+    messages: Vec<MixedMessage>,
+    deadline: Option<Instant>,
+}
+
+/// Batches messages from several connections and releases them reordered
+pub struct ConnectionMixer {
+    batch_size: usize,
+    max_hold: Duration,
+    pool: Mutex<MixPool>,
+    release: ReleaseCallback,
+    submitted: Notify,
+}
+
+impl ConnectionMixer {
+    pub fn new(
+        config: &AnonymityConfig,
+        release: impl Fn(Vec<MixedMessage>) + Send + Sync + 'static,
+    ) -> AnonymityResult<Self> {
+        config.validate()?;
+        Ok(Self {
+            batch_size: config.mix_batch_size,
+            max_hold: Duration::from_millis(config.mix_max_hold_ms),
+            pool: Mutex::new(MixPool::default()),
+            release: Box::new(release),
+            submitted: Notify::new(),
+        })
+    }
+
+    /// Add a message to the pool, releasing the batch if it is now full
+    pub fn submit(&self, conn_id: ConnectionId, msg: Vec<u8>) {
+        self.submit_at(conn_id, msg, Instant::now());
+    }
+
+    fn submit_at(&self, conn_id: ConnectionId, msg: Vec<u8>, now: Instant) {
+        let batch = {
+            let mut pool = self.lock_pool();
+            if pool.deadline.is_none() {
+                pool.deadline = Some(now + self.hold_time(&mut rand::thread_rng()));
+            }
+            pool.messages.push(MixedMessage { conn_id, payload: msg, submitted_at: now });
+            if pool.messages.len() >= self.batch_size {
+                Some(Self::take_batch(&mut pool))
+            } else {
+                None
+            }
+        };
+        match batch {
+            Some(batch) => self.emit(batch),
+            None => self.submitted.notify_one(),
+        }
+    }
+
+    /// Release the pool if its deadline has passed
+    pub fn tick(&self) {
+        self.tick_at(Instant::now());
+    }
+
+    fn tick_at(&self, now: Instant) {
+        let batch = {
+            let mut pool = self.lock_pool();
+            match pool.deadline {
+                Some(deadline) if now >= deadline => Some(Self::take_batch(&mut pool)),
+                _ => None,
+            }
+        };
+        if let Some(batch) = batch {
+            self.emit(batch);
+        }
+    }
+
+    /// Messages waiting in the pool
+    pub fn pending(&self) -> usize {
+        self.lock_pool().messages.len()
+    }
+
+    /// Release pooled messages whenever their deadline passes; never returns
+    pub async fn run(&self) {
+        loop {
+            let deadline = self.lock_pool().deadline;
+            match deadline {
+                Some(deadline) => {
+                    tokio::select! {
+                        _ = tokio::time::sleep_until(deadline.into()) => {}
+                        _ = self.submitted.notified() => {}
+                    }
+                }
+                None => self.submitted.notified().await,
+            }
+            self.tick();
+        }
+    }
+
+    /// Exponential hold with a mean of half the max, capped at the max
+    fn hold_time(&self, rng: &mut impl Rng) -> Duration {
+        // 1 - U lies in (0, 1], keeping ln finite
+        let uniform: f64 = 1.0 - rng.r#gen::<f64>();
+        let hold = Duration::from_secs_f64(-uniform.ln() * self.max_hold.as_secs_f64() / 2.0);
+        hold.min(self.max_hold)
+    }
+
+    fn take_batch(pool: &mut MixPool) -> Vec<MixedMessage> {
+        pool.deadline = None;
+        let mut batch = std::mem::take(&mut pool.messages);
+        batch.shuffle(&mut rand::thread_rng());
+        batch
+    }
+
+    fn emit(&self, batch: Vec<MixedMessage>) {
+        (self.release)(batch);
+    }
+
+    fn lock_pool(&self) -> std::sync::MutexGuard<'_, MixPool> {
+        self.pool.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn collecting_mixer(config: AnonymityConfig) -> (ConnectionMixer, Arc<Mutex<Vec<Vec<MixedMessage>>>>) {
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&batches);
+        let mixer = ConnectionMixer::new(&config, move |batch| sink.lock().unwrap().push(batch)).unwrap();
+        (mixer, batches)
+    }
+
+    #[test]
+    fn test_full_batches_are_reordered() {
+        let (mixer, batches) = collecting_mixer(AnonymityConfig { mix_batch_size: 8, ..AnonymityConfig::default() });
+
+        for i in 0..80u8 {
+            mixer.submit(i as u64 % 4, vec![i]);
+        }
+        assert_eq!(mixer.pending(), 0);
+
+        let batches = batches.lock().unwrap();
+        assert_eq!(batches.len(), 10);
+        let released: Vec<u8> = batches.iter().flatten().map(|message| message.payload[0]).collect();
+        let mut sorted = released.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..80).collect::<Vec<u8>>());
+        assert_ne!(released, sorted);
+    }
+
+    #[test]
+    fn test_nothing_held_past_max_hold() {
+        let (mixer, batches) = collecting_mixer(AnonymityConfig {
+            mix_batch_size: 100,
+            mix_max_hold_ms: 200,
+            ..AnonymityConfig::default()
+        });
+        let start = Instant::now();
+        let max_hold = Duration::from_millis(200);
+
+        mixer.submit_at(1, b"a".to_vec(), start);
+        mixer.submit_at(2, b"b".to_vec(), start + Duration::from_millis(50));
+        assert!(batches.lock().unwrap().is_empty());
+
+        mixer.tick_at(start + max_hold);
+        assert_eq!(mixer.pending(), 0);
+        let batches = batches.lock().unwrap();
+        assert_eq!(batches.len(), 1);
+        assert!(batches[0].iter().all(|message| start + max_hold - message.submitted_at <= max_hold));
+    }
+
+    #[test]
+    fn test_releases_do_not_wait_on_each_other() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Each callback waits for the other to start; a lock around delivery would stall the second
+        let inside = Arc::new(AtomicUsize::new(0));
+        let overlapped = Arc::new(Mutex::new(Vec::new()));
+        let (counter, seen) = (Arc::clone(&inside), Arc::clone(&overlapped));
+        let mixer = ConnectionMixer::new(&AnonymityConfig::default(), move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let deadline = Instant::now() + Duration::from_secs(2);
+            while counter.load(Ordering::SeqCst) < 2 && Instant::now() < deadline {
+                std::thread::yield_now();
+            }
+            seen.lock().unwrap().push(counter.load(Ordering::SeqCst) == 2);
+        })
+        .unwrap();
+
+        std::thread::scope(|scope| {
+            for _ in 0..2 {
+                scope.spawn(|| mixer.emit(Vec::new()));
+            }
+        });
+        assert_eq!(*overlapped.lock().unwrap(), [true, true]);
+    }
+
+    #[tokio::test]
+    async fn test_run_releases_on_deadline() {
+        let (mixer, batches) = collecting_mixer(AnonymityConfig {
+            mix_batch_size: 100,
+            mix_max_hold_ms: 50,
+            ..AnonymityConfig::default()
+        });
+        let mixer = Arc::new(mixer);
+        let runner = Arc::clone(&mixer);
+        let task = tokio::spawn(async move { runner.run().await });
+
+        mixer.submit(1, b"late".to_vec());
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        task.abort();
+
+        let batches = batches.lock().unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0][0].payload, b"late");
+    }
+}
@@ -1,5 +1,134 @@
 //! Metadata Scrubbing Module
-//! 
-//! Remove identifying headers and server signatures
+//!
+//! Remove identifying headers and server signatures. Outgoing requests lose the
+//! headers that reveal where the client came from or what it has cached, and
+//! carry a generic `User-Agent` instead of the client's own.
 
-// TODO: Implement metadata scrubbing functionality
+use crate::anonymity::{AnonymityConfig, AnonymityError, AnonymityResult};
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+
+/// Strips and normalizes identifying headers on outgoing requests
+pub struct MetadataScrubber {
+    scrub_cookies: bool,
+    user_agent: HeaderValue,
+    preserved: Vec<HeaderName>,
+}
+
+impl MetadataScrubber {
+    pub fn new(config: &AnonymityConfig) -> AnonymityResult<Self> {
+        let user_agent = HeaderValue::from_str(&config.generic_user_agent).map_err(|_| {
+            AnonymityError::ConfigurationError(format!(
+                "Generic user agent {:?} is not a valid header value",
+                config.generic_user_agent
+            ))
+        })?;
+        let preserved = config
+            .preserved_headers
+            .iter()
+            .map(|name| {
+                HeaderName::from_bytes(name.as_bytes()).map_err(|_| {
+                    AnonymityError::ConfigurationError(format!("Preserved header {:?} is not a valid header name", name))
+                })
+            })
+            .collect::<AnonymityResult<_>>()?;
+
+        Ok(Self {
+            scrub_cookies: config.scrub_cookies,
+            user_agent,
+            preserved,
+        })
+    }
+
+    fn should_remove(&self, name: &HeaderName) -> bool {
+        // Header names are stored lowercase
+        let name_str = name.as_str();
+        *name == header::REFERER
+            || (self.scrub_cookies && *name == header::COOKIE)
+            || name_str.starts_with("x-")
+            || name_str.starts_with("if-")
+    }
+
+    /// Scrub `headers` in place; preserved headers are never touched
+    pub fn scrub_request(&self, headers: &mut HeaderMap) {
+        let doomed: Vec<HeaderName> = headers
+            .keys()
+            .filter(|name| !self.preserved.contains(name) && self.should_remove(name))
+            .cloned()
+            .collect();
+        for name in doomed {
+            headers.remove(&name);
+        }
+
+        if !self.preserved.contains(&header::USER_AGENT) {
+            headers.insert(header::USER_AGENT, self.user_agent.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in [
+            ("referer", "http://example.onion/private"),
+            ("cookie", "session=abc"),
+            ("user-agent", "CustomBrowser/1.2 (Linux; en-GB)"),
+            ("x-forwarded-for", "192.0.2.1"),
+            ("x-request-id", "42"),
+            ("if-none-match", "\"etag\""),
+            ("if-modified-since", "Tue, 01 Oct 2024 00:00:00 GMT"),
+            ("accept", "text/html"),
+        ] {
+            headers.insert(name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_fingerprinting_headers_are_removed() {
+        let scrubber = MetadataScrubber::new(&AnonymityConfig::default()).unwrap();
+        let mut headers = request_headers();
+        scrubber.scrub_request(&mut headers);
+
+        for name in ["referer", "x-forwarded-for", "x-request-id", "if-none-match", "if-modified-since"] {
+            assert!(!headers.contains_key(name), "{} survived", name);
+        }
+        assert_eq!(headers["user-agent"], AnonymityConfig::default().generic_user_agent.as_str());
+        assert_eq!(headers["accept"], "text/html");
+        // Cookies are only stripped when asked
+        assert!(headers.contains_key("cookie"));
+
+        let scrubber = MetadataScrubber::new(&AnonymityConfig { scrub_cookies: true, ..AnonymityConfig::default() }).unwrap();
+        let mut headers = request_headers();
+        scrubber.scrub_request(&mut headers);
+        assert!(!headers.contains_key("cookie"));
+    }
+
+    #[test]
+    fn test_preserved_headers_remain() {
+        let config = AnonymityConfig {
+            scrub_cookies: true,
+            preserved_headers: vec!["X-Request-Id".to_string(), "User-Agent".to_string(), "cookie".to_string()],
+            ..AnonymityConfig::default()
+        };
+        let scrubber = MetadataScrubber::new(&config).unwrap();
+        let mut headers = request_headers();
+        scrubber.scrub_request(&mut headers);
+
+        assert_eq!(headers["x-request-id"], "42");
+        assert_eq!(headers["user-agent"], "CustomBrowser/1.2 (Linux; en-GB)");
+        assert_eq!(headers["cookie"], "session=abc");
+        assert!(!headers.contains_key("x-forwarded-for"));
+        assert!(!headers.contains_key("referer"));
+    }
+
+    #[test]
+    fn test_invalid_config_is_rejected() {
+        let config = AnonymityConfig { generic_user_agent: "bad\nagent".to_string(), ..AnonymityConfig::default() };
+        assert!(MetadataScrubber::new(&config).is_err());
+        let config = AnonymityConfig { preserved_headers: vec!["not a header".to_string()], ..AnonymityConfig::default() };
+        assert!(MetadataScrubber::new(&config).is_err());
+    }
+}
@@ -9,4 +9,306 @@ pub mod connection_mixing;
 pub mod metadata_scrubbing;
 pub mod anti_correlation;
 
-// TODO: Implement anonymity functionality
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// Common error types for anonymity features
+#[derive(Debug)]
+pub enum AnonymityError {
+    ConfigurationError(String),
+    ObfuscationError(String),
+    UnknownFlow(u64),
+}
+
+impl fmt::Display for AnonymityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnonymityError::ConfigurationError(msg) => write!(f, "Configuration error: {}", msg),
+            AnonymityError::ObfuscationError(msg) => write!(f, "Obfuscation error: {}", msg),
+            AnonymityError::UnknownFlow(id) => write!(f, "Unknown flow: {}", id),
+        }
+    }
+}
+
+impl Error for AnonymityError {}
+
+/// Result type for anonymity operations
+pub type AnonymityResult<T> = Result<T, AnonymityError>;
+
+/// How payload sizes are drawn, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SizeDistribution {
+    Fixed(usize),
+    Uniform { min: usize, max: usize },
+    /// Favors small sizes the way real request traffic does
+    LogUniform { min: usize, max: usize },
+}
+
+impl SizeDistribution {
+    pub fn sample(&self, rng: &mut impl Rng) -> usize {
+        match *self {
+            SizeDistribution::Fixed(size) => size,
+            SizeDistribution::Uniform { min, max } => rng.gen_range(min..=max),
+            SizeDistribution::LogUniform { min, max } => {
+                // ln(0) is undefined, so shift the range by one
+                let (low, high) = (((min + 1) as f64).ln(), ((max + 1) as f64).ln());
+                let size = rng.gen_range(low..=high).exp().round() as usize - 1;
+                size.clamp(min, max)
+            }
+        }
+    }
+
+    fn bounds(&self) -> (usize, usize) {
+        match *self {
+            SizeDistribution::Fixed(size) => (size, size),
+            SizeDistribution::Uniform { min, max } | SizeDistribution::LogUniform { min, max } => (min, max),
+        }
+    }
+}
+
+/// How delays between emissions are drawn, in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntervalDistribution {
+    Fixed(u64),
+    Uniform { min_ms: u64, max_ms: u64 },
+    /// Poisson process with the given mean gap
+    Exponential { mean_ms: u64 },
+}
+
+impl IntervalDistribution {
+    pub fn sample(&self, rng: &mut impl Rng) -> Duration {
+        match *self {
+            IntervalDistribution::Fixed(ms) => Duration::from_millis(ms),
+            IntervalDistribution::Uniform { min_ms, max_ms } => Duration::from_millis(rng.gen_range(min_ms..=max_ms)),
+            IntervalDistribution::Exponential { mean_ms } => {
+                // 1 - U lies in (0, 1], keeping ln finite
+                let uniform: f64 = 1.0 - rng.r#gen::<f64>();
+                Duration::from_secs_f64(-uniform.ln() * mean_ms as f64 / 1000.0)
+            }
+        }
+    }
+
+    fn validate(&self, name: &str) -> AnonymityResult<()> {
+        match *self {
+            IntervalDistribution::Uniform { min_ms, max_ms } if min_ms > max_ms => Err(
+                AnonymityError::ConfigurationError(format!("{} minimum exceeds its maximum", name)),
+            ),
+            IntervalDistribution::Exponential { mean_ms: 0 } => Err(AnonymityError::ConfigurationError(
+                format!("{} mean must be greater than zero", name),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Configuration for anonymity features
+///
+/// Deserializing fills any missing field from `Default`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnonymityConfig {
+    pub enable_traffic_obfuscation: bool,
+    pub enable_dummy_traffic: bool,
+    /// Largest message, in bytes, accepted for obfuscation
+    pub max_message_size: usize,
+    /// Ascending on-wire frame sizes; each frame is padded up to the smallest one that fits
+    pub padding_buckets: Vec<usize>,
+    /// Payload size of generated dummy messages, before padding
+    pub dummy_size: SizeDistribution,
+    /// Delay between dummy messages
+    pub dummy_interval: IntervalDistribution,
+    /// Gap between release slots of the timing protection channel
+    pub timing_interval_ms: u64,
+    /// Each slot is shifted by up to this much either way
+    pub timing_jitter_ms: u64,
+    /// Messages pooled by the connection mixer before it releases a batch
+    pub mix_batch_size: usize,
+    /// Longest a message waits in the mixer pool
+    pub mix_max_hold_ms: u64,
+    /// Strip `Cookie` from outgoing requests, breaking any server-side session
+    pub scrub_cookies: bool,
+    /// `User-Agent` sent in place of the client's own
+    pub generic_user_agent: String,
+    /// Headers the metadata scrubber leaves alone, matched case-insensitively
+    pub preserved_headers: Vec<String>,
+    /// Shortest delay injected into a linked flow
+    pub correlation_jitter_min_ms: u64,
+    /// Longest delay injected into a linked flow
+    pub correlation_jitter_max_ms: u64,
+    /// Chance, between 0 and 1, that a flow sends a cover packet alongside each delay
+    pub cover_packet_probability: f64,
+}
+
+impl Default for AnonymityConfig {
+    fn default() -> Self {
+        Self {
+            enable_traffic_obfuscation: true,
+            enable_dummy_traffic: false,
+            max_message_size: 64 * 1024,
+            padding_buckets: vec![256, 512, 1024],
+            dummy_size: SizeDistribution::LogUniform { min: 32, max: 1000 },
+            dummy_interval: IntervalDistribution::Exponential { mean_ms: 2000 },
+            timing_interval_ms: 100,
+            timing_jitter_ms: 10,
+            mix_batch_size: 8,
+            mix_max_hold_ms: 500,
+            scrub_cookies: false,
+            generic_user_agent: "Mozilla/5.0 (Windows NT 10.0; rv:128.0) Gecko/20100101 Firefox/128.0".to_string(),
+            preserved_headers: Vec::new(),
+            correlation_jitter_min_ms: 0,
+            correlation_jitter_max_ms: 250,
+            cover_packet_probability: 0.1,
+        }
+    }
+}
+
+impl AnonymityConfig {
+    /// Reject out-of-range values, such as a zero maximum message size
+    pub fn validate(&self) -> AnonymityResult<()> {
+        if self.max_message_size == 0 {
+            return Err(AnonymityError::ConfigurationError(
+                "Max message size must be greater than zero".to_string(),
+            ));
+        }
+        if self.padding_buckets.is_empty() {
+            return Err(AnonymityError::ConfigurationError(
+                "At least one padding bucket is required".to_string(),
+            ));
+        }
+        if self.padding_buckets[0] <= traffic_obfuscation::HEADER_LEN {
+            return Err(AnonymityError::ConfigurationError(format!(
+                "Padding buckets must be larger than the {}-byte frame header",
+                traffic_obfuscation::HEADER_LEN
+            )));
+        }
+        if self.padding_buckets.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(AnonymityError::ConfigurationError(
+                "Padding buckets must be strictly ascending".to_string(),
+            ));
+        }
+        let (min, max) = self.dummy_size.bounds();
+        if min > max {
+            return Err(AnonymityError::ConfigurationError(
+                "Dummy size minimum exceeds its maximum".to_string(),
+            ));
+        }
+        if max > self.max_message_size {
+            return Err(AnonymityError::ConfigurationError(format!(
+                "Dummy size of up to {} bytes exceeds the {} byte message limit",
+                max, self.max_message_size
+            )));
+        }
+        self.dummy_interval.validate("Dummy interval")?;
+        if self.timing_interval_ms == 0 {
+            return Err(AnonymityError::ConfigurationError(
+                "Timing interval must be greater than zero".to_string(),
+            ));
+        }
+        // Jitter this large could release slots out of order
+        if self.timing_jitter_ms * 2 >= self.timing_interval_ms {
+            return Err(AnonymityError::ConfigurationError(format!(
+                "Timing jitter ({}ms) must be less than half the {}ms interval",
+                self.timing_jitter_ms, self.timing_interval_ms
+            )));
+        }
+        if self.mix_batch_size < 2 {
+            return Err(AnonymityError::ConfigurationError(
+                "Mix batch size must be at least two for reordering to matter".to_string(),
+            ));
+        }
+        if self.mix_max_hold_ms == 0 {
+            return Err(AnonymityError::ConfigurationError(
+                "Mix max hold time must be greater than zero".to_string(),
+            ));
+        }
+        if self.correlation_jitter_min_ms > self.correlation_jitter_max_ms {
+            return Err(AnonymityError::ConfigurationError(
+                "Correlation jitter minimum exceeds its maximum".to_string(),
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.cover_packet_probability) {
+            return Err(AnonymityError::ConfigurationError(format!(
+                "Cover packet probability {} must be between 0 and 1",
+                self.cover_packet_probability
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anonymity::traffic_obfuscation::TrafficObfuscation;
+
+    #[test]
+    fn test_config_validation_and_defaults() {
+        assert!(AnonymityConfig::default().validate().is_ok());
+
+        let config = AnonymityConfig { max_message_size: 0, ..AnonymityConfig::default() };
+        assert!(matches!(config.validate(), Err(AnonymityError::ConfigurationError(_))));
+        let config = AnonymityConfig { padding_buckets: vec![512, 256], ..AnonymityConfig::default() };
+        assert!(matches!(config.validate(), Err(AnonymityError::ConfigurationError(_))));
+        let config = AnonymityConfig { padding_buckets: vec![4], ..AnonymityConfig::default() };
+        assert!(matches!(config.validate(), Err(AnonymityError::ConfigurationError(_))));
+
+        let config = AnonymityConfig {
+            dummy_size: SizeDistribution::Uniform { min: 10, max: 5 },
+            ..AnonymityConfig::default()
+        };
+        assert!(matches!(config.validate(), Err(AnonymityError::ConfigurationError(_))));
+        let config = AnonymityConfig {
+            dummy_interval: IntervalDistribution::Exponential { mean_ms: 0 },
+            ..AnonymityConfig::default()
+        };
+        assert!(matches!(config.validate(), Err(AnonymityError::ConfigurationError(_))));
+
+        let config = AnonymityConfig { timing_interval_ms: 20, timing_jitter_ms: 10, ..AnonymityConfig::default() };
+        assert!(matches!(config.validate(), Err(AnonymityError::ConfigurationError(_))));
+
+        let config = AnonymityConfig { mix_batch_size: 1, ..AnonymityConfig::default() };
+        assert!(matches!(config.validate(), Err(AnonymityError::ConfigurationError(_))));
+
+        let config = AnonymityConfig { cover_packet_probability: 1.5, ..AnonymityConfig::default() };
+        assert!(matches!(config.validate(), Err(AnonymityError::ConfigurationError(_))));
+
+        let parsed: AnonymityConfig = serde_json::from_str(r#"{"enable_dummy_traffic": true}"#).unwrap();
+        assert!(parsed.enable_dummy_traffic);
+        assert_eq!(parsed.max_message_size, AnonymityConfig::default().max_message_size);
+
+        let parsed: AnonymityConfig =
+            serde_json::from_str(r#"{"dummy_interval": {"uniform": {"min_ms": 5, "max_ms": 10}}}"#).unwrap();
+        assert_eq!(parsed.dummy_interval, IntervalDistribution::Uniform { min_ms: 5, max_ms: 10 });
+    }
+
+    #[test]
+    fn test_distributions_stay_in_bounds() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..1000 {
+            assert!((32..=1000).contains(&SizeDistribution::LogUniform { min: 32, max: 1000 }.sample(&mut rng)));
+            assert!((0..=8).contains(&SizeDistribution::LogUniform { min: 0, max: 8 }.sample(&mut rng)));
+            let delay = IntervalDistribution::Uniform { min_ms: 5, max_ms: 10 }.sample(&mut rng);
+            assert!((Duration::from_millis(5)..=Duration::from_millis(10)).contains(&delay));
+        }
+
+        let mean = IntervalDistribution::Exponential { mean_ms: 100 };
+        let total: Duration = (0..5000).map(|_| mean.sample(&mut rng)).sum();
+        let average = total.as_secs_f64() / 5000.0;
+        assert!((0.08..0.12).contains(&average), "average gap {}", average);
+    }
+
+    #[test]
+    fn test_traffic_obfuscation_requires_initialization() {
+        let mut obfuscation = TrafficObfuscation::new(&AnonymityConfig::default()).unwrap();
+        assert!(matches!(obfuscation.obfuscate_outgoing(b"data"), Err(AnonymityError::ObfuscationError(_))));
+
+        obfuscation.initialize().unwrap();
+        assert!(matches!(obfuscation.initialize(), Err(AnonymityError::ConfigurationError(_))));
+        assert!(obfuscation.is_initialized());
+    }
+}
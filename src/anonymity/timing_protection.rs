@@ -1,5 +1,150 @@
 //! Timing Attack Protection Module
-//! 
-//! Add random delays to prevent timing correlation
+//!
+//! Add random delays to prevent timing correlation. Outgoing messages are queued
+//! and released on a constant-rate schedule with bounded jitter, so the gaps an
+//! observer sees say nothing about when the application produced them. Empty
+//! slots are filled with dummy frames when dummy traffic is enabled.
 
-// TODO: Implement timing attack protection functionality
+use crate::anonymity::traffic_obfuscation::TrafficObfuscation;
+use crate::anonymity::{AnonymityConfig, AnonymityResult};
+use rand::Rng;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{self, Instant};
+
+/// Queues outgoing messages and releases them on a normalized schedule
+pub struct TimingProtection {
+    config: AnonymityConfig,
+    obfuscation: TrafficObfuscation,
+    queue: Mutex<VecDeque<Vec<u8>>>,
+}
+
+impl TimingProtection {
+    pub fn new(config: &AnonymityConfig) -> AnonymityResult<Self> {
+        let mut obfuscation = TrafficObfuscation::new(config)?;
+        obfuscation.initialize()?;
+        Ok(Self {
+            config: config.clone(),
+            obfuscation,
+            queue: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    /// Frame `msg` and queue it for the next free slot
+    pub fn enqueue(&self, msg: &[u8]) -> AnonymityResult<()> {
+        let frame = self.obfuscation.obfuscate_outgoing(msg)?;
+        self.lock_queue().push_back(frame);
+        Ok(())
+    }
+
+    /// Messages waiting for a slot
+    pub fn queued(&self) -> usize {
+        self.lock_queue().len()
+    }
+
+    fn lock_queue(&self) -> std::sync::MutexGuard<'_, VecDeque<Vec<u8>>> {
+        self.queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Where the slot after `slot` is released, jittered around a fixed cadence
+    ///
+    /// Slots are anchored to the start time rather than to each other, so
+    /// scheduling delays never accumulate into drift.
+    fn release_time(&self, start: Instant, slot: u32, rng: &mut impl Rng) -> Instant {
+        let base = start + Duration::from_millis(self.config.timing_interval_ms) * slot;
+        let jitter = self.config.timing_jitter_ms as i64;
+        let offset = rng.gen_range(-jitter..=jitter);
+        if offset < 0 {
+            base.checked_sub(Duration::from_millis(offset.unsigned_abs())).unwrap_or(base)
+        } else {
+            base + Duration::from_millis(offset as u64)
+        }
+    }
+
+    /// Release one frame per slot into `output` until its receiver is dropped
+    pub async fn run(&self, output: mpsc::Sender<Vec<u8>>) {
+        let start = Instant::now();
+        let mut slot: u32 = 1;
+
+        loop {
+            let at = self.release_time(start, slot, &mut rand::thread_rng());
+            time::sleep_until(at).await;
+            slot = slot.wrapping_add(1);
+
+            let queued = self.lock_queue().pop_front();
+            let frame = match queued {
+                Some(frame) => frame,
+                // Dummy traffic is off, so this slot stays silent
+                None => match self.obfuscation.generate_dummy_traffic() {
+                    Ok(frame) => frame,
+                    Err(_) => continue,
+                },
+            };
+            if output.send(frame).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    // Paused time jumps straight to each release, so slots land exactly where scheduled
+    #[tokio::test(start_paused = true)]
+    async fn test_output_follows_configured_rate() {
+        let config = AnonymityConfig {
+            enable_dummy_traffic: true,
+            timing_interval_ms: 30,
+            timing_jitter_ms: 5,
+            ..AnonymityConfig::default()
+        };
+        let protection = Arc::new(TimingProtection::new(&config).unwrap());
+        protection.enqueue(b"first").unwrap();
+        protection.enqueue(b"second").unwrap();
+
+        let (sender, mut receiver) = mpsc::channel(4);
+        let runner = Arc::clone(&protection);
+        let task = tokio::spawn(async move { runner.run(sender).await });
+
+        let start = Instant::now();
+        let mut stamps = Vec::new();
+        let mut messages = Vec::new();
+        for _ in 0..10 {
+            let frame = receiver.recv().await.unwrap();
+            stamps.push(start.elapsed());
+            messages.push(protection.obfuscation.deobfuscate_incoming(&frame).unwrap());
+        }
+        drop(receiver);
+        task.await.unwrap();
+
+        assert_eq!(messages[0], b"first");
+        assert_eq!(messages[1], b"second");
+        assert!(messages[2..].iter().all(|message| message.is_empty()));
+        assert_eq!(protection.queued(), 0);
+
+        // Slot n is released at n * 30ms, give or take the 5ms jitter
+        for (n, stamp) in stamps.iter().enumerate() {
+            let expected = Duration::from_millis(30 * (n as u64 + 1));
+            let error = stamp.abs_diff(expected);
+            assert!(error <= Duration::from_millis(5), "slot {} released at {:?}", n + 1, stamp);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_silent_slots_without_dummy_traffic() {
+        let config = AnonymityConfig { timing_interval_ms: 10, timing_jitter_ms: 0, ..AnonymityConfig::default() };
+        let protection = TimingProtection::new(&config).unwrap();
+        let (sender, mut receiver) = mpsc::channel(4);
+
+        tokio::select! {
+            _ = protection.run(sender) => panic!("run stopped while the receiver was alive"),
+            _ = time::sleep(Duration::from_millis(60)) => {}
+        }
+        assert!(receiver.try_recv().is_err());
+    }
+}
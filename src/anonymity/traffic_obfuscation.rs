@@ -5,6 +5,11 @@
 //! dummy traffic to make it harder for adversaries to identify the actual communication patterns.
 
 use crate::anonymity::{AnonymityConfig, AnonymityResult, AnonymityError};
+use rand::RngCore;
+use std::time::Duration;
+
+/// Bytes taken by the little-endian original-length header at the start of each frame
+pub const HEADER_LEN: usize = 4;
 
 /// Traffic obfuscation component
 pub struct TrafficObfuscation {
@@ -15,6 +20,7 @@ pub struct TrafficObfuscation {
 impl TrafficObfuscation {
     /// Create a new traffic obfuscation instance
     pub fn new(config: &AnonymityConfig) -> AnonymityResult<Self> {
+        config.validate()?;
         Ok(Self {
             config: config.clone(),
             is_initialized: false,
@@ -58,65 +64,173 @@ impl TrafficObfuscation {
         self.is_initialized
     }
 
+    /// On-wire size of a frame carrying `len` bytes: the smallest bucket that fits,
+    /// or a multiple of the largest bucket for anything bigger
+    fn frame_size(&self, len: usize) -> usize {
+        let needed = len + HEADER_LEN;
+        match self.config.padding_buckets.iter().find(|bucket| **bucket >= needed) {
+            Some(bucket) => *bucket,
+            None => {
+                let largest = self.config.padding_buckets.last().copied().unwrap_or(needed);
+                needed.div_ceil(largest) * largest
+            }
+        }
+    }
+
     /// Obfuscate outgoing traffic
+    ///
+    /// The frame is the original length as a 4-byte little-endian header, the data,
+    /// then random padding up to the next bucket size. With obfuscation disabled the
+    /// data passes through unchanged.
     pub fn obfuscate_outgoing(&self, data: &[u8]) -> AnonymityResult<Vec<u8>> {
         if !self.is_initialized {
             return Err(AnonymityError::ObfuscationError(
                 "Traffic obfuscation not initialized".to_string()
             ));
         }
+        if !self.config.enable_traffic_obfuscation {
+            return Ok(data.to_vec());
+        }
+        if data.len() > self.config.max_message_size {
+            return Err(AnonymityError::ObfuscationError(format!(
+                "Message of {} bytes exceeds the {} byte limit",
+                data.len(),
+                self.config.max_message_size
+            )));
+        }
 
-        // TODO: Implement traffic obfuscation logic
-        // - Add padding to packets
-        // - Randomize packet sizes
-        // - Insert dummy data
-        
-        Ok(data.to_vec())
+        let mut frame = vec![0; self.frame_size(data.len())];
+        frame[..HEADER_LEN].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        frame[HEADER_LEN..HEADER_LEN + data.len()].copy_from_slice(data);
+        rand::thread_rng().fill_bytes(&mut frame[HEADER_LEN + data.len()..]);
+        Ok(frame)
     }
 
-    /// Deobfuscate incoming traffic
+    /// Deobfuscate incoming traffic, stripping the header and padding
     pub fn deobfuscate_incoming(&self, data: &[u8]) -> AnonymityResult<Vec<u8>> {
         if !self.is_initialized {
             return Err(AnonymityError::ObfuscationError(
                 "Traffic obfuscation not initialized".to_string()
             ));
         }
+        if !self.config.enable_traffic_obfuscation {
+            return Ok(data.to_vec());
+        }
 
-        // TODO: Implement traffic deobfuscation logic
-        // - Remove padding from packets
-        // - Extract actual data from obfuscated packets
-        // - Filter out dummy data
-        
-        Ok(data.to_vec())
+        let Some((header, body)) = data.split_first_chunk::<HEADER_LEN>() else {
+            return Err(AnonymityError::ObfuscationError(
+                "Frame is shorter than its header".to_string()
+            ));
+        };
+        let len = u32::from_le_bytes(*header) as usize;
+        if len > body.len() {
+            return Err(AnonymityError::ObfuscationError(format!(
+                "Frame header claims {} bytes but only {} follow",
+                len,
+                body.len()
+            )));
+        }
+        Ok(body[..len].to_vec())
     }
 
-    /// Generate dummy traffic
+    /// Generate a dummy frame
+    ///
+    /// The frame has the same bucket size as a real message of a size drawn from
+    /// `dummy_size`, but its header claims zero bytes and the rest is random, so it
+    /// deobfuscates to an empty message that receivers discard.
     pub fn generate_dummy_traffic(&self) -> AnonymityResult<Vec<u8>> {
         if !self.is_initialized {
             return Err(AnonymityError::ObfuscationError(
                 "Traffic obfuscation not initialized".to_string()
             ));
         }
+        if !self.config.enable_dummy_traffic {
+            return Err(AnonymityError::ObfuscationError(
+                "Dummy traffic is disabled".to_string()
+            ));
+        }
 
-        // TODO: Generate realistic dummy traffic
-        // - Create packets that look like real traffic
-        // - Vary packet sizes and timing
-        // - Maintain consistent traffic patterns
-        
-        Ok(vec![0; 1024]) // Placeholder
+        let mut rng = rand::thread_rng();
+        let size = self.config.dummy_size.sample(&mut rng);
+        let mut frame = vec![0; self.frame_size(size)];
+        rng.fill_bytes(&mut frame[HEADER_LEN..]);
+        Ok(frame)
+    }
+
+    /// Randomized delay before the next dummy frame should be sent
+    pub fn next_dummy_interval(&self) -> Duration {
+        self.config.dummy_interval.sample(&mut rand::thread_rng())
     }
 
     /// Update obfuscation configuration
     pub fn update_config(&mut self, config: &AnonymityConfig) -> AnonymityResult<()> {
+        config.validate()?;
+        // Framing reads the config on every call, so the new buckets apply from the next message
         self.config = config.clone();
-        
-        if self.is_initialized {
-            // TODO: Apply new configuration to running system
-            // - Update obfuscation strength
-            // - Reconfigure padding algorithms
-            // - Adjust dummy traffic generation
-        }
-        
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anonymity::{IntervalDistribution, SizeDistribution};
+    use std::collections::HashSet;
+
+    fn obfuscation(config: AnonymityConfig) -> TrafficObfuscation {
+        let mut obfuscation = TrafficObfuscation::new(&config).unwrap();
+        obfuscation.initialize().unwrap();
+        obfuscation
+    }
+
+    #[test]
+    fn test_round_trip_snaps_to_buckets() {
+        let obfuscation = obfuscation(AnonymityConfig::default());
+
+        for (len, wire) in [(0, 256), (1, 256), (252, 256), (253, 512), (700, 1024), (1020, 1024), (1021, 2048), (3000, 3072)] {
+            let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let frame = obfuscation.obfuscate_outgoing(&data).unwrap();
+            assert_eq!(frame.len(), wire, "payload of {} bytes", len);
+            assert_eq!(obfuscation.deobfuscate_incoming(&frame).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_rejects_bad_frames() {
+        let obfuscation = obfuscation(AnonymityConfig {
+            max_message_size: 100,
+            dummy_size: SizeDistribution::Fixed(100),
+            ..AnonymityConfig::default()
+        });
+
+        assert!(obfuscation.obfuscate_outgoing(&[0; 101]).is_err());
+        assert!(obfuscation.deobfuscate_incoming(&[1, 0]).is_err());
+        assert!(obfuscation.deobfuscate_incoming(&[9, 0, 0, 0, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn test_dummy_traffic_looks_like_padded_frames() {
+        let disabled = obfuscation(AnonymityConfig::default());
+        assert!(disabled.generate_dummy_traffic().is_err());
+
+        let obfuscation = obfuscation(AnonymityConfig {
+            enable_dummy_traffic: true,
+            dummy_size: SizeDistribution::Uniform { min: 0, max: 2000 },
+            dummy_interval: IntervalDistribution::Uniform { min_ms: 50, max_ms: 150 },
+            ..AnonymityConfig::default()
+        });
+
+        let mut sizes = HashSet::new();
+        for _ in 0..200 {
+            let frame = obfuscation.generate_dummy_traffic().unwrap();
+            assert!([256, 512].contains(&frame.len()) || frame.len().is_multiple_of(1024), "{} is not a bucket size", frame.len());
+            assert!(frame[HEADER_LEN..].iter().any(|byte| *byte != 0));
+            assert!(obfuscation.deobfuscate_incoming(&frame).unwrap().is_empty());
+            sizes.insert(frame.len());
+
+            let delay = obfuscation.next_dummy_interval();
+            assert!((Duration::from_millis(50)..=Duration::from_millis(150)).contains(&delay));
+        }
+        assert!(sizes.len() > 1);
+    }
+}
@@ -0,0 +1,275 @@
+//! RustWall daemon
+//!
+//! Runs the `RustWall` facade from a TOML file and serves its health and admin
+//! endpoints until SIGINT or SIGTERM. The daemon doesn't sit in front of the
+//! application, so it never evaluates requests itself; it keeps the Tor security
+//! state that the admin API manages.
+//!
+//! ```text
+//! rustwall [--config <path>] [--check]
+//! ```
+
+use log::{error, info};
+use rustwall::operational::admin_api;
+use rustwall::operational::emergency_shutdown::EmergencyShutdown;
+use rustwall::operational::health_monitoring::{self, HealthMonitor, HealthMonitorConfig, Readiness};
+use rustwall::tor::TorSecurityConfig;
+use rustwall::{RustWall, RustWallConfig};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "anonymity")]
+use rustwall::anonymity::AnonymityConfig;
+#[cfg(feature = "content-security")]
+use rustwall::content_security::ContentSecurityConfig;
+
+const DEFAULT_CONFIG_PATH: &str = "rustwall.toml";
+const USAGE: &str = "Usage: rustwall [--config <path>] [--check]";
+
+/// Challenge settings for clients DDoS mitigation wants to verify
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct CaptchaSection {
+    /// The CAPTCHA server challenged clients are sent to. It only sets
+    /// `RustWallConfig::serve_challenges`, as the daemon evaluates no requests.
+    challenge_url: Option<String>,
+}
+
+/// Where the health and admin endpoints listen
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+struct AdminSection {
+    listen: SocketAddr,
+    /// Bearer token for the `/admin` management API, which is only served
+    /// when one is set. It is also the `reset_token` for
+    /// `POST /admin/emergency/reset`, which clears the kill switch.
+    token: Option<String>,
+}
+
+impl Default for AdminSection {
+    fn default() -> Self {
+        Self { listen: SocketAddr::from(([127, 0, 0, 1], 9090)), token: None }
+    }
+}
+
+/// Layout of the config file; a missing section or field keeps its default
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+struct DaemonConfig {
+    tor: TorSecurityConfig,
+    captcha: CaptchaSection,
+    #[cfg(feature = "anonymity")]
+    anonymity: Option<AnonymityConfig>,
+    #[cfg(feature = "content-security")]
+    content_security: Option<ContentSecurityConfig>,
+    admin: AdminSection,
+    health: HealthMonitorConfig,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        let wall = RustWallConfig::default();
+        Self {
+            tor: wall.tor,
+            captcha: CaptchaSection::default(),
+            #[cfg(feature = "anonymity")]
+            anonymity: wall.anonymity,
+            #[cfg(feature = "content-security")]
+            content_security: wall.content_security,
+            admin: AdminSection::default(),
+            health: HealthMonitorConfig::default(),
+        }
+    }
+}
+
+impl DaemonConfig {
+    fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        toml::from_str(&contents).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    fn wall_config(&self) -> RustWallConfig {
+        RustWallConfig {
+            tor: self.tor.clone(),
+            serve_challenges: self.captcha.challenge_url.is_some(),
+            // Challenged clients go to the CAPTCHA server at `challenge_url`
+            #[cfg(feature = "captcha")]
+            captcha: None,
+            #[cfg(feature = "anonymity")]
+            anonymity: self.anonymity.clone(),
+            #[cfg(feature = "content-security")]
+            content_security: self.content_security.clone(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    config: PathBuf,
+    check: bool,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args { config: PathBuf::from(DEFAULT_CONFIG_PATH), check: false };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                parsed.config = args.next().map(PathBuf::from).ok_or("--config needs a path")?;
+            }
+            "--check" => parsed.check = true,
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+    Ok(parsed)
+}
+
+fn health_monitor(wall: &Arc<RustWall>, config: HealthMonitorConfig) -> HealthMonitor {
+    let mut monitor = HealthMonitor::new(config);
+    let tor = Arc::clone(wall.tor());
+    monitor.register_connection_check(move || tor.onion_service().get_connection_stats());
+    let tor = Arc::clone(wall.tor());
+    monitor.register_mitigation_check(move || tor.ddos_mitigation().get_mitigation_stats().current_state);
+    monitor
+}
+
+// Resolve on the first SIGINT or SIGTERM
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => info!("Received SIGINT"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
+
+async fn run(config: DaemonConfig, wall: Arc<RustWall>) -> Result<(), Box<dyn std::error::Error>> {
+    let readiness = Arc::new(Readiness::new(["rustwall"]));
+    wall.start()?;
+    readiness.mark_ready("rustwall");
+
+    let monitor = Arc::new(Mutex::new(health_monitor(&wall, config.health)));
+    let sampling = HealthMonitor::spawn_sampling(Arc::clone(&monitor));
+    let exit_maintenance = wall.tor().spawn_exit_maintenance(None);
+
+    // Statistics are served as `/admin/stats`, behind the admin token
+    let mut app = health_monitoring::health_router(monitor, readiness);
+    match &config.admin.token {
+        Some(token) => {
+            let shutdown = Arc::new(EmergencyShutdown::new(Arc::clone(wall.tor()), token)?);
+            app = app.merge(admin_api::admin_router(Arc::clone(wall.tor()), shutdown, token)?);
+        }
+        None => info!("No admin token configured; the /admin API is disabled"),
+    }
+
+    let listener = tokio::net::TcpListener::bind(config.admin.listen).await?;
+    info!("RustWall running, admin endpoints on http://{}", config.admin.listen);
+
+    let served = axum::serve(listener, app).with_graceful_shutdown(shutdown_signal()).await;
+    sampling.abort();
+    exit_maintenance.abort();
+    info!("Shutting down");
+    wall.stop()?;
+    Ok(served?)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    env_logger::init();
+
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let config = match DaemonConfig::load(&args.config) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    // Building the facade validates every section
+    let wall = match RustWall::new(config.wall_config()) {
+        Ok(wall) => Arc::new(wall),
+        Err(e) => {
+            eprintln!("Invalid configuration in {}: {}", args.config.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if args.check {
+        println!("{} is valid", args.config.display());
+        return ExitCode::SUCCESS;
+    }
+
+    match run(config, wall).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("RustWall stopped: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Result<Args, String> {
+        parse_args(list.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(args(&[]).unwrap(), Args { config: PathBuf::from(DEFAULT_CONFIG_PATH), check: false });
+        assert_eq!(
+            args(&["--check", "--config", "/etc/rustwall.toml"]).unwrap(),
+            Args { config: PathBuf::from("/etc/rustwall.toml"), check: true }
+        );
+        assert!(args(&["--config"]).is_err());
+        assert!(args(&["--verbose"]).is_err());
+    }
+
+    #[test]
+    fn test_config_sections_default_independently() {
+        let config: DaemonConfig = toml::from_str(
+            "[tor]\nmax_requests_per_window = 250\n\n[captcha]\nchallenge_url = \"http://127.0.0.1:3000/captcha/form\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.tor.max_requests_per_window, 250);
+        assert!(config.tor.enable_onion_protection);
+        assert_eq!(config.admin.listen, AdminSection::default().listen);
+        assert!(config.wall_config().serve_challenges);
+
+        let config: DaemonConfig = toml::from_str("").unwrap();
+        assert!(!config.wall_config().serve_challenges);
+        assert!(RustWall::new(config.wall_config()).is_ok());
+    }
+}
@@ -0,0 +1,495 @@
+//! Audio CAPTCHA
+//!
+//! Speaks the session's time ("three forty-five") by stitching together word
+//! samples. Recorded samples are 16-bit PCM WAV files named after the words below
+//! (`one.wav`, `forty.wav`, `oclock.wav`, ...) and must all share the same sample
+//! rate and channel count. Without a full set, a small built-in formant
+//! synthesizer speaks the words instead.
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use log::{debug, info, warn};
+use rand::Rng;
+
+/// Every word the speaker may need, doubling as the sample file stem
+pub const SAMPLE_WORDS: [&str; 26] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+    "eleven", "twelve", "thirteen", "fourteen", "fifteen", "sixteen", "seventeen", "eighteen",
+    "nineteen", "twenty", "thirty", "forty", "fifty", "oh", "oclock",
+];
+
+/// Silence inserted between words, in milliseconds
+const WORD_GAP_MS: u32 = 150;
+
+#[derive(Debug)]
+pub enum AudioError {
+    MissingSample(String),
+    InvalidSample(String),
+    FormatMismatch(String),
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioError::MissingSample(word) => write!(f, "Missing audio sample: {}", word),
+            AudioError::InvalidSample(msg) => write!(f, "Invalid audio sample: {}", msg),
+            AudioError::FormatMismatch(msg) => write!(f, "Audio format mismatch: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for AudioError {}
+
+/// Decoded PCM data for a single word
+#[derive(Clone, Debug)]
+struct Sample {
+    sample_rate: u32,
+    channels: u16,
+    data: Vec<u8>,
+}
+
+/// Table of word samples used to assemble spoken times
+#[derive(Clone, Debug, Default)]
+pub struct AudioLibrary {
+    samples: HashMap<String, Sample>,
+}
+
+impl AudioLibrary {
+    /// Load every `<word>.wav` found in `dir`; missing words are reported when spoken
+    pub fn load_dir(dir: impl AsRef<Path>) -> Self {
+        let dir = dir.as_ref();
+        let mut library = Self::default();
+
+        for word in SAMPLE_WORDS {
+            let path = dir.join(format!("{}.wav", word));
+            match fs::read(&path) {
+                Ok(bytes) => {
+                    if let Err(e) = library.insert_wav(word, &bytes) {
+                        warn!("Skipping audio sample {}: {}", path.display(), e);
+                    }
+                }
+                Err(_) => debug!("Audio sample not found: {}", path.display()),
+            }
+        }
+
+        info!("Loaded {}/{} audio CAPTCHA samples from {}", library.samples.len(), SAMPLE_WORDS.len(), dir.display());
+        library
+    }
+
+    /// Every word spoken by the built-in synthesizer
+    pub fn synthesized() -> Self {
+        let samples = SAMPLE_WORDS
+            .iter()
+            .map(|word| {
+                let sample = Sample { sample_rate: SYNTH_RATE, channels: 1, data: synthesize_word(word) };
+                (word.to_string(), sample)
+            })
+            .collect();
+        Self { samples }
+    }
+
+    /// Register a word sample from the bytes of a PCM WAV file
+    pub fn insert_wav(&mut self, word: &str, wav: &[u8]) -> Result<(), AudioError> {
+        let sample = parse_wav(wav)?;
+        self.samples.insert(word.to_string(), sample);
+        Ok(())
+    }
+
+    /// True when every word in `SAMPLE_WORDS` has a sample
+    pub fn is_complete(&self) -> bool {
+        SAMPLE_WORDS.iter().all(|word| self.samples.contains_key(*word))
+    }
+
+    /// Build a WAV file speaking the given time
+    pub fn speak_time(&self, hour: u8, minute: u8) -> Result<Vec<u8>, AudioError> {
+        let words = time_words(hour, minute);
+        debug!("Speaking time {:02}:{:02} as {:?}", hour, minute, words);
+
+        let mut format: Option<(u32, u16)> = None;
+        let mut data = Vec::new();
+
+        for (i, word) in words.iter().enumerate() {
+            let sample = self
+                .samples
+                .get(*word)
+                .ok_or_else(|| AudioError::MissingSample(word.to_string()))?;
+
+            match format {
+                None => format = Some((sample.sample_rate, sample.channels)),
+                Some((rate, channels)) if rate != sample.sample_rate || channels != sample.channels => {
+                    return Err(AudioError::FormatMismatch(format!(
+                        "{} is {} Hz/{} ch, expected {} Hz/{} ch",
+                        word, sample.sample_rate, sample.channels, rate, channels
+                    )));
+                }
+                Some(_) => {}
+            }
+
+            if i > 0 {
+                let gap = (sample.sample_rate * WORD_GAP_MS / 1000) as usize * sample.channels as usize * 2;
+                data.extend(std::iter::repeat_n(0u8, gap));
+            }
+            data.extend_from_slice(&sample.data);
+        }
+
+        let (sample_rate, channels) = format.unwrap_or((16_000, 1));
+        Ok(build_wav(sample_rate, channels, &data))
+    }
+}
+
+/// Words spoken for a time, e.g. 3:45 -> ["three", "forty", "five"], 7:05 -> ["seven", "oh", "five"]
+pub fn time_words(hour: u8, minute: u8) -> Vec<&'static str> {
+    let mut words = number_words(hour);
+
+    match minute {
+        0 => words.push("oclock"),
+        1..=9 => {
+            words.push("oh");
+            words.extend(number_words(minute));
+        }
+        _ => words.extend(number_words(minute)),
+    }
+
+    words
+}
+
+fn number_words(n: u8) -> Vec<&'static str> {
+    match n {
+        0..=20 => vec![SAMPLE_WORDS[n as usize]],
+        _ => {
+            let tens = match n / 10 {
+                2 => "twenty",
+                3 => "thirty",
+                4 => "forty",
+                _ => "fifty",
+            };
+            match n % 10 {
+                0 => vec![tens],
+                unit => vec![tens, SAMPLE_WORDS[unit as usize]],
+            }
+        }
+    }
+}
+
+/// Sample rate of the built-in voice
+const SYNTH_RATE: u32 = 16_000;
+/// Formant bandwidths in Hz
+const BANDWIDTHS: [f32; 3] = [60.0, 90.0, 150.0];
+/// Formants of a relaxed vocal tract, used when a word has no vowel to borrow from
+const NEUTRAL: [f32; 3] = [500.0, 1500.0, 2500.0];
+/// Per-sample smoothing towards formant targets (~12 ms) and source levels (~4 ms),
+/// so neighbouring phones blend instead of clicking
+const FORMANT_SMOOTHING: f32 = 1.0 / 192.0;
+const LEVEL_SMOOTHING: f32 = 1.0 / 64.0;
+
+/// A stretch of a synthesized word. Voicing and aspiration pass through the
+/// formant filters; frication is noise through a single resonator at `band`.
+#[derive(Clone, Copy)]
+struct Segment {
+    ms: u32,
+    voice: f32,
+    aspiration: f32,
+    frication: f32,
+    band: f32,
+    /// Formant targets at the start and end; `None` borrows the next phone's
+    formants: Option<([f32; 3], [f32; 3])>,
+}
+
+impl Segment {
+    const SILENCE: Self = Self { ms: 0, voice: 0.0, aspiration: 0.0, frication: 0.0, band: 0.0, formants: None };
+
+    fn voiced(ms: u32, voice: f32, from: [f32; 3], to: [f32; 3]) -> Self {
+        Self { ms, voice, formants: Some((from, to)), ..Self::SILENCE }
+    }
+
+    fn vowel(ms: u32, formants: [f32; 3]) -> Self {
+        Self::voiced(ms, 1.0, formants, formants)
+    }
+
+    fn fricative(ms: u32, band: f32, frication: f32) -> Self {
+        Self { ms, band, frication, ..Self::SILENCE }
+    }
+
+    /// Closure, burst and aspiration of a voiceless stop
+    fn stop(band: f32) -> [Self; 3] {
+        [
+            Self { ms: 45, ..Self::SILENCE },
+            Self::fricative(12, band, 0.4),
+            Self { ms: 35, aspiration: 0.5, ..Self::SILENCE },
+        ]
+    }
+}
+
+/// Segments for one ARPAbet phone
+fn phone(symbol: &str) -> Vec<Segment> {
+    match symbol {
+        "IY" => vec![Segment::vowel(150, [270.0, 2290.0, 3010.0])],
+        "IH" => vec![Segment::vowel(100, [390.0, 1990.0, 2550.0])],
+        "EH" => vec![Segment::vowel(130, [530.0, 1840.0, 2480.0])],
+        "AH" => vec![Segment::vowel(130, [640.0, 1190.0, 2390.0])],
+        "AX" => vec![Segment::vowel(70, [500.0, 1400.0, 2300.0])],
+        "AA" => vec![Segment::vowel(160, [730.0, 1090.0, 2440.0])],
+        "AO" => vec![Segment::vowel(160, [570.0, 840.0, 2410.0])],
+        "UW" => vec![Segment::vowel(180, [300.0, 870.0, 2240.0])],
+        "ER" => vec![Segment::vowel(150, [490.0, 1350.0, 1690.0])],
+        "OW" => vec![Segment::voiced(220, 1.0, [550.0, 960.0, 2400.0], [350.0, 800.0, 2300.0])],
+        "AY" => vec![Segment::voiced(230, 1.0, [730.0, 1090.0, 2440.0], [400.0, 1900.0, 2550.0])],
+        "EY" => vec![Segment::voiced(200, 1.0, [530.0, 1840.0, 2480.0], [300.0, 2200.0, 2900.0])],
+        "W" => vec![Segment::vowel(60, [300.0, 610.0, 2200.0])],
+        "R" => vec![Segment::vowel(70, [420.0, 1300.0, 1600.0])],
+        "L" => vec![Segment::vowel(70, [360.0, 1000.0, 2400.0])],
+        "N" => vec![Segment::voiced(80, 0.35, [250.0, 1450.0, 2500.0], [250.0, 1450.0, 2500.0])],
+        "V" => vec![Segment { frication: 0.05, band: 3500.0, ..Segment::voiced(70, 0.45, [300.0, 1100.0, 2300.0], [300.0, 1100.0, 2300.0]) }],
+        "Z" => vec![Segment { frication: 0.12, band: 5000.0, ..Segment::voiced(110, 0.4, [300.0, 1600.0, 2500.0], [300.0, 1600.0, 2500.0]) }],
+        "S" => vec![Segment::fricative(130, 5500.0, 0.2)],
+        "F" => vec![Segment::fricative(120, 4500.0, 0.08)],
+        "TH" => vec![Segment::fricative(110, 5000.0, 0.07)],
+        "T" => Segment::stop(4500.0).to_vec(),
+        "K" => Segment::stop(2200.0).to_vec(),
+        _ => Vec::new(),
+    }
+}
+
+/// Pronunciations of `SAMPLE_WORDS`
+fn pronunciation(word: &str) -> &'static [&'static str] {
+    match word {
+        "zero" => &["Z", "IH", "R", "OW"],
+        "one" => &["W", "AH", "N"],
+        "two" => &["T", "UW"],
+        "three" => &["TH", "R", "IY"],
+        "four" => &["F", "AO", "R"],
+        "five" => &["F", "AY", "V"],
+        "six" => &["S", "IH", "K", "S"],
+        "seven" => &["S", "EH", "V", "AX", "N"],
+        "eight" => &["EY", "T"],
+        "nine" => &["N", "AY", "N"],
+        "ten" => &["T", "EH", "N"],
+        "eleven" => &["IH", "L", "EH", "V", "AX", "N"],
+        "twelve" => &["T", "W", "EH", "L", "V"],
+        "thirteen" => &["TH", "ER", "T", "IY", "N"],
+        "fourteen" => &["F", "AO", "R", "T", "IY", "N"],
+        "fifteen" => &["F", "IH", "F", "T", "IY", "N"],
+        "sixteen" => &["S", "IH", "K", "S", "T", "IY", "N"],
+        "seventeen" => &["S", "EH", "V", "AX", "N", "T", "IY", "N"],
+        "eighteen" => &["EY", "T", "IY", "N"],
+        "nineteen" => &["N", "AY", "N", "T", "IY", "N"],
+        "twenty" => &["T", "W", "EH", "N", "T", "IY"],
+        "thirty" => &["TH", "ER", "T", "IY"],
+        "forty" => &["F", "AO", "R", "T", "IY"],
+        "fifty" => &["F", "IH", "F", "T", "IY"],
+        "oh" => &["OW"],
+        "oclock" => &["AX", "K", "L", "AA", "K"],
+        _ => &[],
+    }
+}
+
+/// Klatt's two-pole resonator, with unit gain at DC so a cascade of them keeps
+/// the low end and boosts each formant
+#[derive(Clone, Copy, Default)]
+struct Resonator {
+    y1: f32,
+    y2: f32,
+}
+
+impl Resonator {
+    fn filter(&mut self, x: f32, frequency: f32, bandwidth: f32) -> f32 {
+        let fs = SYNTH_RATE as f32;
+        let r = (-PI * bandwidth / fs).exp();
+        let theta = 2.0 * PI * frequency / fs;
+        let (b, c) = (2.0 * r * theta.cos(), -r * r);
+        let y = (1.0 - b - c) * x + b * self.y1 + c * self.y2;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+/// Speak `word` as 16-bit mono PCM at `SYNTH_RATE`
+fn synthesize_word(word: &str) -> Vec<u8> {
+    let segments: Vec<Segment> = pronunciation(word).iter().flat_map(|symbol| phone(symbol)).collect();
+    let targets: Vec<([f32; 3], [f32; 3])> = (0..segments.len())
+        .map(|i| {
+            segments[i]
+                .formants
+                .or_else(|| segments[i + 1..].iter().find_map(|s| s.formants))
+                .or_else(|| segments[..i].iter().rev().find_map(|s| s.formants))
+                .unwrap_or((NEUTRAL, NEUTRAL))
+        })
+        .collect();
+    let lengths: Vec<usize> = segments.iter().map(|s| (SYNTH_RATE * s.ms / 1000) as usize).collect();
+    let total = lengths.iter().sum::<usize>().max(1);
+
+    let mut rng = rand::thread_rng();
+    let mut formants = targets.first().map_or(NEUTRAL, |(from, _)| *from);
+    let mut levels = [0.0f32; 3];
+    let mut resonators = [Resonator::default(); 3];
+    let mut frication_filter = Resonator::default();
+    let mut phase = 0.0f32;
+    let mut output = Vec::with_capacity(total);
+
+    for ((segment, (from, to)), len) in segments.iter().zip(&targets).zip(&lengths) {
+        for i in 0..*len {
+            let t = i as f32 / *len as f32;
+            for k in 0..3 {
+                let target = from[k] + (to[k] - from[k]) * t;
+                formants[k] += (target - formants[k]) * FORMANT_SMOOTHING;
+            }
+            for (level, target) in levels.iter_mut().zip([segment.voice, segment.aspiration, segment.frication]) {
+                *level += (target - *level) * LEVEL_SMOOTHING;
+            }
+
+            // Glottal pulses with a falling pitch, minus their mean so there's no DC
+            let pitch = 130.0 - 30.0 * output.len() as f32 / total as f32;
+            phase = (phase + pitch / SYNTH_RATE as f32).fract();
+            let glottal = if phase < 0.4 { (PI * phase / 0.4).sin().powi(2) - 0.2 } else { -0.2 };
+            let noise: f32 = rng.gen_range(-1.0..1.0);
+
+            let mut tract = levels[0] * glottal + levels[1] * noise * 0.5;
+            for ((resonator, frequency), bandwidth) in resonators.iter_mut().zip(formants).zip(BANDWIDTHS) {
+                tract = resonator.filter(tract, frequency, bandwidth);
+            }
+            let band = segment.band.max(1000.0);
+            let frication = levels[2] * frication_filter.filter(noise, band, band / 4.0);
+            output.push(tract + frication);
+        }
+    }
+
+    // Normalize, with short fades so words don't click when joined
+    let peak = output.iter().fold(0.0f32, |peak, x| peak.max(x.abs())).max(f32::EPSILON);
+    let fade = (SYNTH_RATE / 200) as usize;
+    let len = output.len();
+    output
+        .iter()
+        .enumerate()
+        .flat_map(|(i, x)| {
+            let envelope = (i.min(len - 1 - i) as f32 / fade as f32).min(1.0);
+            ((x / peak * envelope * 0.7 * i16::MAX as f32) as i16).to_le_bytes()
+        })
+        .collect()
+}
+
+fn parse_wav(wav: &[u8]) -> Result<Sample, AudioError> {
+    if wav.len() < 12 || &wav[0..4] != b"RIFF" || &wav[8..12] != b"WAVE" {
+        return Err(AudioError::InvalidSample("not a RIFF/WAVE file".to_string()));
+    }
+
+    let mut format: Option<(u32, u16)> = None;
+    let mut offset = 12;
+
+    while offset + 8 <= wav.len() {
+        let id = &wav[offset..offset + 4];
+        let len = u32::from_le_bytes(wav[offset + 4..offset + 8].try_into().unwrap()) as usize;
+        let body_start = offset + 8;
+        let body_end = body_start.checked_add(len).filter(|&end| end <= wav.len())
+            .ok_or_else(|| AudioError::InvalidSample("truncated chunk".to_string()))?;
+        let body = &wav[body_start..body_end];
+
+        match id {
+            b"fmt " => {
+                if body.len() < 16 {
+                    return Err(AudioError::InvalidSample("short fmt chunk".to_string()));
+                }
+                let audio_format = u16::from_le_bytes([body[0], body[1]]);
+                let channels = u16::from_le_bytes([body[2], body[3]]);
+                let sample_rate = u32::from_le_bytes(body[4..8].try_into().unwrap());
+                let bits = u16::from_le_bytes([body[14], body[15]]);
+                if audio_format != 1 || bits != 16 {
+                    return Err(AudioError::InvalidSample("only 16-bit PCM is supported".to_string()));
+                }
+                format = Some((sample_rate, channels));
+            }
+            b"data" => {
+                let (sample_rate, channels) = format
+                    .ok_or_else(|| AudioError::InvalidSample("data chunk before fmt chunk".to_string()))?;
+                return Ok(Sample { sample_rate, channels, data: body.to_vec() });
+            }
+            _ => {}
+        }
+
+        // Chunks are padded to an even length
+        offset = body_end + (len & 1);
+    }
+
+    Err(AudioError::InvalidSample("no data chunk".to_string()))
+}
+
+/// Wrap 16-bit PCM data in a canonical 44-byte WAV header
+pub fn build_wav(sample_rate: u32, channels: u16, data: &[u8]) -> Vec<u8> {
+    let block_align = channels * 2;
+    let byte_rate = sample_rate * block_align as u32;
+
+    let mut wav = Vec::with_capacity(44 + data.len());
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&channels.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&byte_rate.to_le_bytes());
+    wav.extend_from_slice(&block_align.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(data);
+    wav
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_words() {
+        assert_eq!(time_words(3, 45), vec!["three", "forty", "five"]);
+        assert_eq!(time_words(7, 5), vec!["seven", "oh", "five"]);
+        assert_eq!(time_words(12, 0), vec!["twelve", "oclock"]);
+        assert_eq!(time_words(23, 30), vec!["twenty", "three", "thirty"]);
+        assert_eq!(time_words(10, 13), vec!["ten", "thirteen"]);
+    }
+
+    #[test]
+    fn test_speak_time_concatenates_samples() {
+        let mut library = AudioLibrary::default();
+        for (i, word) in SAMPLE_WORDS.iter().enumerate() {
+            let data = vec![i as u8; 4];
+            library.insert_wav(word, &build_wav(8_000, 1, &data)).unwrap();
+        }
+        assert!(library.is_complete());
+
+        let wav = library.speak_time(3, 45).unwrap();
+        let spoken = parse_wav(&wav).unwrap();
+        let gap = (8_000 * WORD_GAP_MS / 1000) as usize * 2;
+
+        assert_eq!(spoken.sample_rate, 8_000);
+        assert_eq!(spoken.data.len(), 3 * 4 + 2 * gap);
+        assert_eq!(&spoken.data[..4], &[3; 4]);
+    }
+
+    #[test]
+    fn test_synthesized_voice_speaks_every_word() {
+        let library = AudioLibrary::synthesized();
+        assert!(library.is_complete());
+
+        for word in SAMPLE_WORDS {
+            let sample = &library.samples[word];
+            let seconds = sample.data.len() as f32 / 2.0 / SYNTH_RATE as f32;
+            assert!((0.1..1.0).contains(&seconds), "{} lasts {}s", word, seconds);
+            let loudest = sample.data.chunks(2).map(|b| i16::from_le_bytes([b[0], b[1]]).unsigned_abs()).max();
+            assert!(loudest > Some(i16::MAX as u16 / 2), "{} is too quiet", word);
+        }
+
+        let spoken = parse_wav(&library.speak_time(10, 45).unwrap()).unwrap();
+        assert_eq!((spoken.sample_rate, spoken.channels), (SYNTH_RATE, 1));
+    }
+
+    #[test]
+    fn test_missing_sample() {
+        let library = AudioLibrary::default();
+        assert!(matches!(library.speak_time(1, 0), Err(AudioError::MissingSample(_))));
+    }
+}
@@ -0,0 +1,825 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use resvg::{tiny_skia, usvg};
+use serde::Deserialize;
+use std::f64::consts::PI;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use svg::node::element::{Circle, Description, Group, Line, Text, Title};
+use svg::Document;
+use log::{error, info, warn, debug};
+
+/// Half of the day a 12-hour time falls in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Am,
+    Pm,
+}
+
+impl Period {
+    /// The period a 0-23 hour falls in
+    pub fn of_hour(hour: u8) -> Self {
+        if hour % 24 < 12 { Period::Am } else { Period::Pm }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Period::Am => "AM",
+            Period::Pm => "PM",
+        }
+    }
+
+    /// Convert an answer to a 0-23 hour. Hours past 12 imply PM and 0 is
+    /// midnight; 1-12 need a period to say which half of the day they mean.
+    /// Returns `None` when the answer is ambiguous or contradicts itself.
+    pub fn to_24h(hour: u8, period: Option<Period>) -> Option<u8> {
+        match (hour, period) {
+            (0, None | Some(Period::Am)) => Some(0),
+            (1..=12, Some(Period::Am)) => Some(hour % 12),
+            (1..=12, Some(Period::Pm)) => Some(hour % 12 + 12),
+            (13..=23, None | Some(Period::Pm)) => Some(hour),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ClockTime {
+    pub hour: u8,
+    pub minute: u8,
+    /// Hour is 0-23 and rendered on a 24-hour dial
+    pub twenty_four_hour: bool,
+    /// Shown on the face of a 12-hour dial when set
+    pub period: Option<Period>,
+}
+
+impl ClockTime {
+    pub fn new(hour: u8, minute: u8) -> Self {
+        if hour == 0 || hour > 12 {
+            warn!("Hour value out of range (1-12): received {}", hour);
+        }
+        if minute >= 60 {
+            warn!("Minute value out of range (0-59): received {}", minute);
+        }
+        Self {
+            hour: hour % 12, // Convert to 12-hour format
+            minute: minute % 60,
+            twenty_four_hour: false,
+            period: None,
+        }
+    }
+
+    pub fn new_24h(hour: u8, minute: u8) -> Self {
+        if hour > 23 {
+            warn!("Hour value out of range (0-23): received {}", hour);
+        }
+        if minute >= 60 {
+            warn!("Minute value out of range (0-59): received {}", minute);
+        }
+        Self {
+            hour: hour % 24,
+            minute: minute % 60,
+            twenty_four_hour: true,
+            period: None,
+        }
+    }
+
+    /// Label the dial with `period`; ignored on the 24-hour dial
+    pub fn with_period(mut self, period: Period) -> Self {
+        self.period = Some(period).filter(|_| !self.twenty_four_hour);
+        self
+    }
+
+    pub fn random() -> Self {
+        Self::random_with_rng(&mut rand::thread_rng())
+    }
+
+    /// Random 12-hour time drawn from `rng`; pass a seeded RNG for reproducible output
+    pub fn random_with_rng<R: Rng>(rng: &mut R) -> Self {
+        let hour = rng.gen_range(1..=12); // 1-12 hours
+        let minute = rng.gen_range(0..60);  // 0-59 minutes
+        debug!("Generated random time: {:02}:{:02}", hour, minute);
+        Self::new(hour, minute)
+    }
+
+    pub fn random_24h() -> Self {
+        Self::random_24h_with_rng(&mut rand::thread_rng())
+    }
+
+    /// Random 24-hour time drawn from `rng`
+    pub fn random_24h_with_rng<R: Rng>(rng: &mut R) -> Self {
+        let hour = rng.gen_range(0..24); // 0-23 hours
+        let minute = rng.gen_range(0..60);  // 0-59 minutes
+        debug!("Generated random 24-hour time: {:02}:{:02}", hour, minute);
+        Self::new_24h(hour, minute)
+    }
+
+    /// Number of hour positions around the dial this time is shown on
+    pub fn dial_hours(&self) -> u8 {
+        if self.twenty_four_hour { 24 } else { 12 }
+    }
+
+    pub fn hour_angle(&self) -> f64 {
+        // Hour hand moves 30 degrees per hour + 0.5 degrees per minute on a 12-hour dial,
+        // or 15 degrees per hour + 0.25 degrees per minute on a 24-hour dial
+        let degrees_per_hour = 360.0 / self.dial_hours() as f64;
+        let hour_degrees = (self.hour as f64 * degrees_per_hour) + (self.minute as f64 * degrees_per_hour / 60.0);
+        // Convert to radians and adjust for SVG coordinate system (0 degrees at top)
+        let angle = (hour_degrees - 90.0) * PI / 180.0;
+        debug!("Hour angle for {:02}:{:02} is {} radians", self.hour, self.minute, angle);
+        angle
+    }
+
+    pub fn minute_angle(&self) -> f64 {
+        // Minute hand moves 6 degrees per minute
+        let minute_degrees = self.minute as f64 * 6.0;
+        // Convert to radians and adjust for SVG coordinate system (0 degrees at top)
+        let angle = (minute_degrees - 90.0) * PI / 180.0;
+        debug!("Minute angle for {:02}:{:02} is {} radians", self.hour, self.minute, angle);
+        angle
+    }
+}
+
+const ROMAN_NUMERALS: [&str; 12] = [
+    "I", "II", "III", "IV", "V", "VI", "VII", "VIII", "IX", "X", "XI", "XII",
+];
+
+/// How the hour positions are labelled on the clock face
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NumeralStyle {
+    #[default]
+    Arabic,
+    Roman,
+    /// Tick markers only
+    None,
+}
+
+impl FromStr for NumeralStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "arabic" => Ok(NumeralStyle::Arabic),
+            "roman" => Ok(NumeralStyle::Roman),
+            "none" => Ok(NumeralStyle::None),
+            other => Err(format!("unknown numeral style: {}", other)),
+        }
+    }
+}
+
+/// Radius of the 200px clock that stroke widths and font sizes are designed for
+const REFERENCE_RADIUS: f64 = 80.0;
+
+/// Accessible name of every image; none of the text describes the time shown
+const IMAGE_TITLE: &str = "Clock CAPTCHA";
+const CLOCK_DESCRIPTION: &str = "An analog clock face. Enter the time its hands show, or use the audio challenge.";
+const LOCKED_DESCRIPTION: &str = "This challenge is locked after too many wrong answers. Request a new one.";
+
+/// Highest distortion level accepted by `ClockRenderer::with_difficulty`
+pub const MAX_DIFFICULTY: u8 = 3;
+
+/// Random perturbations for one rendering, scaled by the renderer's difficulty.
+/// At difficulty 0 every perturbation is zero and no randomness is drawn.
+struct Distortion {
+    rng: StdRng,
+    /// Difficulty as a fraction of `MAX_DIFFICULTY`
+    level: f64,
+}
+
+impl Distortion {
+    fn new(difficulty: u8, seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            level: difficulty.min(MAX_DIFFICULTY) as f64 / MAX_DIFFICULTY as f64,
+        }
+    }
+
+    /// A uniform offset in `-max..=max` at full difficulty, shrinking with the level
+    fn jitter(&mut self, max: f64) -> f64 {
+        if self.level == 0.0 {
+            return 0.0;
+        }
+        self.rng.gen_range(-max..=max) * self.level
+    }
+
+    /// `width` thickened or thinned by up to 15%. Small enough that the hour
+    /// hand always stays thicker than the minute hand.
+    fn stroke(&mut self, width: f64) -> f64 {
+        width * (1.0 + self.jitter(0.15))
+    }
+}
+
+/// Colors used to draw the clock
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClockTheme {
+    pub face: String,
+    pub stroke: String,
+    pub numerals: String,
+    pub hands: String,
+    /// Face of the blank clock shown once a session is locked
+    pub locked_face: String,
+}
+
+impl ClockTheme {
+    /// Black on white, the original look
+    pub fn light() -> Self {
+        Self {
+            face: "white".to_string(),
+            stroke: "black".to_string(),
+            numerals: "black".to_string(),
+            hands: "black".to_string(),
+            locked_face: "#eeeeee".to_string(),
+        }
+    }
+
+    /// Light strokes on a dark face, for embedding in dark-mode pages
+    pub fn dark() -> Self {
+        Self {
+            face: "#1e1e1e".to_string(),
+            stroke: "#e0e0e0".to_string(),
+            numerals: "#e0e0e0".to_string(),
+            hands: "#f5f5f5".to_string(),
+            locked_face: "#2b2b2b".to_string(),
+        }
+    }
+}
+
+impl Default for ClockTheme {
+    fn default() -> Self {
+        Self::light()
+    }
+}
+
+impl FromStr for ClockTheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "light" => Ok(ClockTheme::light()),
+            "dark" => Ok(ClockTheme::dark()),
+            other => Err(format!("unknown clock theme: {}", other)),
+        }
+    }
+}
+
+pub struct ClockRenderer {
+    center_x: f64,
+    center_y: f64,
+    radius: f64,
+    numeral_style: NumeralStyle,
+    theme: ClockTheme,
+    difficulty: u8,
+    seed: u64,
+}
+
+impl ClockRenderer {
+    pub fn new(size: f64) -> Self {
+        Self::with_style(size, NumeralStyle::Arabic)
+    }
+
+    pub fn with_style(size: f64, numeral_style: NumeralStyle) -> Self {
+        if size <= 0.0 {
+            error!("Invalid clock size: {}. Must be positive.", size);
+        }
+        let center = size / 2.0;
+        let radius = center * 0.8; // Leave some margin
+
+        debug!(
+            "Initialized ClockRenderer with size {}, center ({}, {}), radius {}, numerals {:?}",
+            size, center, center, radius, numeral_style
+        );
+
+        Self {
+            center_x: center,
+            center_y: center,
+            radius,
+            numeral_style,
+            theme: ClockTheme::default(),
+            difficulty: 0,
+            seed: 0,
+        }
+    }
+
+    /// Scale a stroke width or font size designed for the 200px clock to this renderer
+    fn scaled(&self, value: f64) -> f64 {
+        value * self.radius / REFERENCE_RADIUS
+    }
+
+    /// Draw with `theme` instead of the default light colors
+    pub fn with_theme(mut self, theme: ClockTheme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Distort the clock to resist automated solvers, from 0 (a clean face) to
+    /// `MAX_DIFFICULTY`: the face is tilted, numerals jittered, strokes varied
+    /// and noise lines drawn across the face. Noise lines keep clear of the
+    /// center, so the hands stay the only strokes anchored there.
+    pub fn with_difficulty(mut self, difficulty: u8) -> Self {
+        self.difficulty = difficulty.min(MAX_DIFFICULTY);
+        self
+    }
+
+    /// Seed the distortion; the same seed always yields the same image, so
+    /// seed with something stable per session to survive reloads
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// An empty image with the accessibility metadata screen readers announce
+    fn document(&self, description: &str) -> Document {
+        let size = (self.center_x * 2.0) as u32;
+        Document::new()
+            .set("viewBox", (0, 0, size, size))
+            .set("width", size)
+            .set("height", size)
+            .set("role", "img")
+            .set("aria-label", format!("{}. {}", IMAGE_TITLE, description))
+            .add(Title::new(IMAGE_TITLE))
+            .add(Description::new().add(svg::node::Text::new(description)))
+    }
+
+    pub fn render_clock(&self, time: &ClockTime) -> String {
+        let size = (self.center_x * 2.0) as u32;
+        debug!("Rendering clock SVG with size {} for time {:02}:{:02}", size, time.hour, time.minute);
+
+        let mut distortion = Distortion::new(self.difficulty, self.seed);
+        let mut face = Group::new();
+        let tilt = distortion.jitter(12.0);
+        if tilt != 0.0 {
+            face = face.set("transform", format!("rotate({:.2} {} {})", tilt, self.center_x, self.center_y));
+        }
+
+        // Clock face (outer circle)
+        let clock_face = Circle::new()
+            .set("cx", self.center_x)
+            .set("cy", self.center_y)
+            .set("r", self.radius)
+            .set("fill", self.theme.face.as_str())
+            .set("stroke", self.theme.stroke.as_str())
+            .set("stroke-width", self.scaled(distortion.stroke(3.0)));
+
+        face = face.add(clock_face);
+
+        // Noise lines, under the numerals and hands
+        face = self.add_noise_lines(face, &mut distortion);
+
+        // Hour markers
+        face = self.add_hour_markers(face, time.dial_hours(), &mut distortion);
+
+        // Hour numbers
+        face = self.add_hour_numbers(face, time.dial_hours(), &mut distortion);
+
+        // AM/PM label in the lower half of the face
+        if let Some(period) = time.period {
+            let label = Text::new(period.label())
+                .set("x", self.center_x + distortion.jitter(self.scaled(4.0)))
+                .set("y", self.center_y + self.radius * 0.45 + distortion.jitter(self.scaled(4.0)))
+                .set("text-anchor", "middle")
+                .set("font-family", "Arial, sans-serif")
+                .set("font-size", self.scaled(13.0))
+                .set("font-weight", "bold")
+                .set("fill", self.theme.numerals.as_str())
+                .set("aria-hidden", "true");
+            face = face.add(label);
+        }
+
+        // Hour hand
+        face = self.add_hour_hand(face, time, &mut distortion);
+
+        // Minute hand
+        face = self.add_minute_hand(face, time, &mut distortion);
+
+        // Center dot
+        let center_dot = Circle::new()
+            .set("cx", self.center_x)
+            .set("cy", self.center_y)
+            .set("r", self.scaled(6.0))
+            .set("fill", self.theme.hands.as_str());
+
+        face = face.add(center_dot);
+
+        let svg_string = self.document(CLOCK_DESCRIPTION).add(face).to_string();
+        if svg_string.is_empty() {
+            error!("Generated SVG string is empty!");
+        } else {
+            debug!("SVG string generated successfully ({} bytes)", svg_string.len());
+        }
+        svg_string
+    }
+
+    /// Render a blank clock face with a notice, used once a session is locked
+    pub fn render_locked(&self) -> String {
+        let size = (self.center_x * 2.0) as u32;
+        debug!("Rendering locked clock SVG with size {}", size);
+
+        let clock_face = Circle::new()
+            .set("cx", self.center_x)
+            .set("cy", self.center_y)
+            .set("r", self.radius)
+            .set("fill", self.theme.locked_face.as_str())
+            .set("stroke", self.theme.stroke.as_str())
+            .set("stroke-width", self.scaled(3.0));
+
+        let notice = Text::new("Locked")
+            .set("x", self.center_x)
+            .set("y", self.center_y + self.scaled(6.0)) // Adjust for text baseline
+            .set("text-anchor", "middle")
+            .set("font-family", "Arial, sans-serif")
+            .set("font-size", self.scaled(20.0))
+            .set("font-weight", "bold")
+            .set("fill", "#c0392b");
+
+        let document = self.document(LOCKED_DESCRIPTION).add(clock_face).add(notice);
+
+        document.to_string()
+    }
+
+    /// Render the clock as a PNG of the same size as the SVG output
+    pub fn render_clock_png(&self, time: &ClockTime) -> Vec<u8> {
+        self.rasterize(&self.render_clock(time))
+    }
+
+    /// PNG counterpart of `render_locked`
+    pub fn render_locked_png(&self) -> Vec<u8> {
+        self.rasterize(&self.render_locked())
+    }
+
+    fn rasterize(&self, svg: &str) -> Vec<u8> {
+        let size = (self.center_x * 2.0) as u32;
+        let options = usvg::Options {
+            fontdb: system_fonts(),
+            ..usvg::Options::default()
+        };
+
+        let tree = match usvg::Tree::from_str(svg, &options) {
+            Ok(tree) => tree,
+            Err(e) => {
+                error!("Failed to parse clock SVG for rasterization: {}", e);
+                return Vec::new();
+            }
+        };
+
+        let Some(mut pixmap) = tiny_skia::Pixmap::new(size, size) else {
+            error!("Invalid PNG dimensions: {}x{}", size, size);
+            return Vec::new();
+        };
+        resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+
+        match pixmap.encode_png() {
+            Ok(png) => {
+                debug!("PNG generated successfully ({} bytes)", png.len());
+                png
+            }
+            Err(e) => {
+                error!("Failed to encode clock PNG: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Endpoints of a noise line: a chord of the face at least 0.35 radii from
+    /// the center, so it can't be mistaken for a hand
+    fn noise_chord(&self, rng: &mut StdRng) -> (f64, f64, f64, f64) {
+        let angle = rng.gen_range(0.0..2.0 * PI);
+        let offset = rng.gen_range(0.35..0.95) * self.radius;
+        let half_length = (self.radius * self.radius - offset * offset).sqrt();
+        let (mid_x, mid_y) = (self.center_x + offset * angle.cos(), self.center_y + offset * angle.sin());
+        let (dx, dy) = (-angle.sin() * half_length, angle.cos() * half_length);
+        (mid_x - dx, mid_y - dy, mid_x + dx, mid_y + dy)
+    }
+
+    fn add_noise_lines(&self, mut face: Group, distortion: &mut Distortion) -> Group {
+        let count = self.difficulty as usize * 4;
+        for _ in 0..count {
+            let (x1, y1, x2, y2) = self.noise_chord(&mut distortion.rng);
+            let width = self.scaled(distortion.rng.gen_range(0.8..1.6));
+            let line = Line::new()
+                .set("x1", x1)
+                .set("y1", y1)
+                .set("x2", x2)
+                .set("y2", y2)
+                .set("stroke", self.theme.numerals.as_str())
+                .set("stroke-width", width)
+                .set("stroke-opacity", 0.5);
+            face = face.add(line);
+        }
+        face
+    }
+
+    fn add_hour_markers(&self, mut document: Group, dial_hours: u8, distortion: &mut Distortion) -> Group {
+        let degrees_per_hour = 360.0 / dial_hours as f64;
+        for hour in 1..=dial_hours {
+            let angle = (hour as f64 * degrees_per_hour - 90.0) * PI / 180.0;
+            let outer_x = self.center_x + (self.radius * 0.9) * angle.cos();
+            let outer_y = self.center_y + (self.radius * 0.9) * angle.sin();
+            let inner_x = self.center_x + (self.radius * 0.8) * angle.cos();
+            let inner_y = self.center_y + (self.radius * 0.8) * angle.sin();
+
+            debug!("Hour marker {}: inner ({:.2},{:.2}), outer ({:.2},{:.2})", hour, inner_x, inner_y, outer_x, outer_y);
+
+            let marker = Line::new()
+                .set("x1", inner_x)
+                .set("y1", inner_y)
+                .set("x2", outer_x)
+                .set("y2", outer_y)
+                .set("stroke", self.theme.stroke.as_str())
+                .set("stroke-width", self.scaled(distortion.stroke(2.0)));
+
+            document = document.add(marker);
+        }
+        document
+    }
+
+    fn add_hour_numbers(&self, mut document: Group, dial_hours: u8, distortion: &mut Distortion) -> Group {
+        if self.numeral_style == NumeralStyle::None {
+            return document;
+        }
+
+        let degrees_per_hour = 360.0 / dial_hours as f64;
+        // 24 labels need a smaller font to fit around the dial
+        let font_size = self.scaled(if dial_hours == 24 { 11.0 } else { 16.0 });
+
+        for hour in 1..=dial_hours {
+            let angle = (hour as f64 * degrees_per_hour - 90.0) * PI / 180.0;
+            let text_x = self.center_x + (self.radius * 0.7) * angle.cos() + distortion.jitter(self.scaled(4.0));
+            let text_y = self.center_y + (self.radius * 0.7) * angle.sin() + distortion.jitter(self.scaled(4.0));
+
+            debug!("Hour number {}: position ({:.2},{:.2})", hour, text_x, text_y);
+
+            // Roman numerals only cover the 12-hour dial; the 24-hour dial reads 0-23
+            let label = match self.numeral_style {
+                NumeralStyle::Roman if dial_hours == 12 => ROMAN_NUMERALS[hour as usize - 1].to_string(),
+                _ if dial_hours == 24 => (hour % 24).to_string(),
+                _ => hour.to_string(),
+            };
+
+            let number = Text::new(label)
+                .set("x", text_x)
+                .set("y", text_y + self.scaled(5.0)) // Adjust for text baseline
+                .set("text-anchor", "middle")
+                .set("font-family", "Arial, sans-serif")
+                .set("font-size", font_size)
+                .set("font-weight", "bold")
+                .set("fill", self.theme.numerals.as_str())
+                .set("aria-hidden", "true");
+
+            document = document.add(number);
+        }
+        document
+    }
+
+    fn add_hour_hand(&self, document: Group, time: &ClockTime, distortion: &mut Distortion) -> Group {
+        let angle = time.hour_angle();
+        let hand_length = self.radius * 0.5;
+        let end_x = self.center_x + hand_length * angle.cos();
+        let end_y = self.center_y + hand_length * angle.sin();
+
+        debug!("Hour hand: start ({:.2},{:.2}), end ({:.2},{:.2})", self.center_x, self.center_y, end_x, end_y);
+
+        let hour_hand = Line::new()
+            .set("x1", self.center_x)
+            .set("y1", self.center_y)
+            .set("x2", end_x)
+            .set("y2", end_y)
+            .set("stroke", self.theme.hands.as_str())
+            .set("stroke-width", self.scaled(distortion.stroke(6.0)))
+            .set("stroke-linecap", "round");
+
+        document.add(hour_hand)
+    }
+
+    fn add_minute_hand(&self, document: Group, time: &ClockTime, distortion: &mut Distortion) -> Group {
+        let angle = time.minute_angle();
+        let hand_length = self.radius * 0.7;
+        let end_x = self.center_x + hand_length * angle.cos();
+        let end_y = self.center_y + hand_length * angle.sin();
+
+        debug!("Minute hand: start ({:.2},{:.2}), end ({:.2},{:.2})", self.center_x, self.center_y, end_x, end_y);
+
+        let minute_hand = Line::new()
+            .set("x1", self.center_x)
+            .set("y1", self.center_y)
+            .set("x2", end_x)
+            .set("y2", end_y)
+            .set("stroke", self.theme.hands.as_str())
+            .set("stroke-width", self.scaled(distortion.stroke(4.0)))
+            .set("stroke-linecap", "round");
+
+        document.add(minute_hand)
+    }
+}
+
+pub fn generate_captcha_24h() -> (ClockTime, String) {
+    info!("Generating new 24-hour CAPTCHA clock");
+    let time = ClockTime::random_24h();
+    let renderer = ClockRenderer::new(200.0);
+    let svg = renderer.render_clock(&time);
+    info!("CAPTCHA clock generated for time {:02}:{:02}", time.hour, time.minute);
+    (time, svg)
+}
+
+pub fn generate_captcha() -> (ClockTime, String) {
+    info!("Generating new CAPTCHA clock");
+    let time = ClockTime::random();
+    let renderer = ClockRenderer::new(200.0);
+    let svg = renderer.render_clock(&time);
+    info!("CAPTCHA clock generated for time {:02}:{:02}", time.hour, time.minute);
+    (time, svg)
+}
+
+/// Like `generate_captcha`, but the same seed always yields the same time and SVG
+pub fn generate_captcha_seeded(seed: u64) -> (ClockTime, String) {
+    let time = ClockTime::random_with_rng(&mut StdRng::seed_from_u64(seed));
+    let renderer = ClockRenderer::new(200.0);
+    let svg = renderer.render_clock(&time);
+    debug!("Seeded CAPTCHA clock generated for time {:02}:{:02}", time.hour, time.minute);
+    (time, svg)
+}
+
+// Font database used to rasterize the hour numbers, loaded once on first use
+fn system_fonts() -> Arc<usvg::fontdb::Database> {
+    static FONTS: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+    FONTS
+        .get_or_init(|| {
+            let mut db = usvg::fontdb::Database::new();
+            db.load_system_fonts();
+            debug!("Loaded {} system font faces for PNG rendering", db.len());
+            Arc::new(db)
+        })
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_clock_png() {
+        let renderer = ClockRenderer::new(120.0);
+        let png = renderer.render_clock_png(&ClockTime::new(3, 15));
+
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        // IHDR width and height follow the 8-byte signature and 8-byte chunk header
+        assert_eq!(u32::from_be_bytes(png[16..20].try_into().unwrap()), 120);
+        assert_eq!(u32::from_be_bytes(png[20..24].try_into().unwrap()), 120);
+    }
+
+    #[test]
+    fn test_twenty_four_hour_dial() {
+        let six = ClockTime::new_24h(6, 0);
+        assert!(six.hour_angle().abs() < 1e-9);
+
+        let eighteen = ClockTime::new_24h(18, 0);
+        assert!((eighteen.hour_angle() - PI).abs() < 1e-9);
+
+        let svg = ClockRenderer::new(200.0).render_clock(&ClockTime::new_24h(18, 0));
+        assert!(svg.lines().any(|line| line.trim() == "23"));
+        assert!(svg.lines().any(|line| line.trim() == "0"));
+    }
+
+    #[test]
+    fn test_period_label_and_conversion() {
+        let plain = ClockRenderer::new(200.0).render_clock(&ClockTime::new(3, 15));
+        let labelled = ClockRenderer::new(200.0).render_clock(&ClockTime::new(15, 15).with_period(Period::Pm));
+        assert!(!plain.lines().any(|line| line.trim() == "PM"));
+        assert!(labelled.lines().any(|line| line.trim() == "PM"));
+        assert!(ClockTime::new_24h(15, 15).with_period(Period::Pm).period.is_none());
+
+        assert_eq!(Period::of_hour(0), Period::Am);
+        assert_eq!(Period::of_hour(12), Period::Pm);
+        assert_eq!(Period::to_24h(12, Some(Period::Am)), Some(0));
+        assert_eq!(Period::to_24h(12, Some(Period::Pm)), Some(12));
+        assert_eq!(Period::to_24h(3, Some(Period::Pm)), Some(15));
+        assert_eq!(Period::to_24h(15, None), Some(15));
+        assert_eq!(Period::to_24h(3, None), None);
+        assert_eq!(Period::to_24h(15, Some(Period::Am)), None);
+    }
+
+    #[test]
+    fn test_accessibility_metadata_hides_the_answer() {
+        let svg = ClockRenderer::new(200.0).render_clock(&ClockTime::new(7, 25).with_period(Period::Pm));
+        assert!(svg.contains("role=\"img\""));
+        assert!(svg.contains("aria-label=\"Clock CAPTCHA. An analog clock face."));
+        assert!(svg.contains("<title>") && svg.contains("<desc>"));
+        assert!(!svg.contains("7:25") && !svg.contains("07:25"));
+        assert!(svg.lines().filter(|line| line.contains("<text")).all(|line| line.contains("aria-hidden=\"true\"")));
+
+        let locked = ClockRenderer::new(200.0).render_locked();
+        assert!(locked.contains("role=\"img\"") && locked.contains("<title>"));
+    }
+
+    // Compare with `snapshots/<name>`; run with UPDATE_SNAPSHOTS=1 to rewrite them
+    fn assert_snapshot(name: &str, actual: &str) {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/captcha/snapshots").join(name);
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            std::fs::write(&path, actual).unwrap();
+            return;
+        }
+        let expected = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("Missing snapshot {}: {}", path.display(), e));
+        assert!(actual == expected, "{} changed; rerun with UPDATE_SNAPSHOTS=1 if that is intended", name);
+    }
+
+    #[test]
+    fn test_distortion_snapshots() {
+        let (time, _) = generate_captcha_seeded(42);
+        for difficulty in 0..=MAX_DIFFICULTY {
+            let svg = ClockRenderer::new(200.0).with_difficulty(difficulty).with_seed(42).render_clock(&time);
+            assert_snapshot(&format!("clock_difficulty_{}.svg", difficulty), &svg);
+        }
+    }
+
+    #[test]
+    fn test_distortion_is_stable_per_seed() {
+        let time = ClockTime::new(4, 50);
+        let render = |difficulty, seed| ClockRenderer::new(200.0).with_difficulty(difficulty).with_seed(seed).render_clock(&time);
+
+        assert_eq!(render(0, 1), ClockRenderer::new(200.0).render_clock(&time));
+        assert_eq!(render(0, 1), render(0, 2));
+        assert_eq!(render(2, 7), render(2, 7));
+        assert_ne!(render(2, 7), render(2, 8));
+        assert_ne!(render(1, 7), render(3, 7));
+        assert_eq!(render(MAX_DIFFICULTY + 5, 7), render(MAX_DIFFICULTY, 7));
+        assert_eq!(render(3, 7).matches("stroke-opacity").count(), 12);
+    }
+
+    #[test]
+    fn test_noise_lines_keep_clear_of_the_hands() {
+        let renderer = ClockRenderer::new(200.0);
+        let mut rng = StdRng::seed_from_u64(9);
+        for _ in 0..1000 {
+            let (x1, y1, x2, y2) = renderer.noise_chord(&mut rng);
+            // Distance from the center to the line through both ends
+            let length = (x2 - x1).hypot(y2 - y1);
+            let distance = ((x2 - x1) * (y1 - renderer.center_y) - (x1 - renderer.center_x) * (y2 - y1)).abs() / length;
+            assert!(distance >= renderer.radius * 0.35 - 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_numeral_styles() {
+        let time = ClockTime::new(3, 15);
+        let labels = |svg: String| -> Vec<String> {
+            svg.lines()
+                .filter(|line| !line.trim_start().starts_with('<'))
+                .map(|line| line.trim().to_string())
+                .collect()
+        };
+
+        let arabic = labels(ClockRenderer::new(200.0).render_clock(&time));
+        assert!(arabic.contains(&"12".to_string()));
+
+        let roman = labels(ClockRenderer::with_style(200.0, NumeralStyle::Roman).render_clock(&time));
+        assert!(roman.contains(&"XII".to_string()));
+        assert!(roman.contains(&"IV".to_string()));
+        assert!(!roman.contains(&"12".to_string()));
+
+        let none = ClockRenderer::with_style(200.0, NumeralStyle::None).render_clock(&time);
+        assert!(!none.contains("<text"));
+    }
+
+    #[test]
+    fn test_seeded_generation_is_reproducible() {
+        let (first, first_svg) = generate_captcha_seeded(42);
+        let (second, second_svg) = generate_captcha_seeded(42);
+
+        assert_eq!((first.hour, first.minute), (second.hour, second.minute));
+        assert_eq!(first_svg, second_svg);
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let again = ClockTime::random_with_rng(&mut rng);
+        assert_eq!(again.hour_angle(), first.hour_angle());
+        assert_eq!(again.minute_angle(), first.minute_angle());
+    }
+
+    #[test]
+    fn test_dark_theme() {
+        let time = ClockTime::new(3, 15);
+        let light = ClockRenderer::new(200.0).render_clock(&time);
+        let dark = ClockRenderer::new(200.0).with_theme(ClockTheme::dark()).render_clock(&time);
+
+        assert_eq!(light, ClockRenderer::new(200.0).with_theme(ClockTheme::light()).render_clock(&time));
+        assert!(light.contains("fill=\"white\""));
+        assert!(dark.contains("fill=\"#1e1e1e\""));
+        assert!(!dark.contains("black"));
+
+        let locked = ClockRenderer::new(200.0).with_theme(ClockTheme::dark()).render_locked();
+        assert!(locked.contains("fill=\"#2b2b2b\""));
+        assert!(!locked.contains("#eeeeee"));
+        assert!(ClockRenderer::new(200.0).render_locked().contains("fill=\"#eeeeee\""));
+        assert_eq!("Dark".parse::<ClockTheme>(), Ok(ClockTheme::dark()));
+    }
+
+    #[test]
+    fn test_sizes_scale_uniformly() {
+        let time = ClockTime::new(3, 15);
+        let small = ClockRenderer::new(100.0).render_clock(&time);
+
+        assert!(small.contains("viewBox=\"0 0 100 100\""));
+        assert!(small.contains("r=\"40\""));
+        assert!(small.contains("font-size=\"8\""));
+        assert!(small.contains("stroke-width=\"1.5\""));
+    }
+}
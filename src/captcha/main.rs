@@ -1,42 +1,120 @@
-mod captcha;
-mod session;
+mod audio;
+mod metrics;
+mod rate_limit;
 
 use axum::{
-    extract::{Path, Query, State},
-    http::{header, StatusCode},
+    extract::{ConnectInfo, FromRequest, Path, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
     response::{Html, IntoResponse, Response},
     routing::{get, post},
-    Form, Router,
+    Form, Json, Router,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tera::{Context, Tera};
 use tokio::time::{interval, Duration};
 use tower_http::{cors::CorsLayer, services::ServeDir};
 
-use captcha::{generate_captcha, ClockTime};
-use session::SessionStore;
+use audio::AudioLibrary;
+use metrics::Metrics;
+use rate_limit::{FailureTracker, FailureTrackerConfig, RateLimitConfig, RateLimiter};
+use rustwall::captcha::challenge::{self, generate_captcha, generate_captcha_24h, ClockTheme, NumeralStyle, Period};
+use rustwall::captcha::pow;
+use rustwall::captcha::session::{
+    MemoryBackend, SessionBackend, SessionConfig, SessionStore, ValidationOutcome, DEFAULT_MAX_SESSIONS,
+};
+use rustwall::captcha::token::TokenSigner;
 
 use log::{debug, error, info, warn};
 
+#[cfg(feature = "content-security")]
+use rustwall::content_security::{middleware::ContentSecurityLayer, ContentSecurityManager};
+#[cfg(feature = "content-security")]
+use tower::Layer;
+
 #[derive(Clone)]
 struct AppState {
     session_store: SessionStore,
     templates: Arc<Tera>,
+    numeral_style: NumeralStyle,
+    /// Distortion applied to clock images, 0 to `challenge::MAX_DIFFICULTY`
+    difficulty: u8,
+    audio: Arc<AudioLibrary>,
+    metrics: Arc<Metrics>,
+    failures: Arc<FailureTracker>,
+    trust_forwarded_for: bool,
 }
 
 #[derive(Deserialize)]
 struct CaptchaVerifyForm {
     hour: u8,
     minute: u8,
+    /// Only checked for sessions that ask for AM or PM
+    #[serde(default)]
+    period: Option<Period>,
+    session_id: String,
+}
+
+#[derive(Deserialize)]
+struct PowVerifyRequest {
     session_id: String,
+    nonce: String,
+}
+
+#[derive(Serialize)]
+struct CaptchaVerifyResult {
+    valid: bool,
+    /// Set when the old session can't be retried and the client needs a fresh challenge
+    new_session_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CaptchaImageQuery {
+    theme: Option<String>,
+    /// Width and height in pixels; parsed leniently so bad values fall back to the default
+    size: Option<String>,
 }
 
+const DEFAULT_IMAGE_SIZE: u32 = 200;
+const MIN_IMAGE_SIZE: u32 = 64;
+const MAX_IMAGE_SIZE: u32 = 512;
+
 #[derive(Deserialize, Debug)]
 struct CaptchaQuery {
     session_id: Option<String>,
 }
 
+// Generate a time for the store's configured dial and open a session for it,
+// answering 503 when the store can't take more sessions. Sessions that ask for
+// AM or PM need an hour from the whole day.
+fn create_captcha_session(state: &AppState) -> Result<String, StatusCode> {
+    let config = state.session_store.config();
+    let (time, _) = if config.twenty_four_hour || config.ask_period {
+        generate_captcha_24h()
+    } else {
+        generate_captcha()
+    };
+    let session_id = state
+        .session_store
+        .create_session(time.hour, time.minute)
+        .map_err(|e| {
+            error!("Failed to create CAPTCHA session: {}", e);
+            StatusCode::SERVICE_UNAVAILABLE
+        })?;
+    state.metrics.sessions_created.inc();
+    Ok(session_id)
+}
+
+// Whether the form for `session_id` should offer the AM/PM choice
+fn session_asks_period(state: &AppState, session_id: &str) -> bool {
+    state.session_store.get_session(session_id).is_some_and(|session| session.ask_period)
+}
+
 // Route: GET /captcha/form - Display CAPTCHA form
 async fn captcha_form_handler(
     Query(params): Query<CaptchaQuery>,
@@ -46,23 +124,25 @@ async fn captcha_form_handler(
 
     let session_id = if let Some(existing_id) = params.session_id {
         debug!("Checking existing session_id: {}", existing_id);
-        // Check if session exists and is valid
-        if state.session_store.get_session(&existing_id).is_some() {
+        // Check if session exists and is still usable
+        if state.session_store.get_session(&existing_id).is_some_and(|s| !s.is_locked()) {
             info!("Reusing valid session_id: {}", existing_id);
             existing_id
         } else {
             warn!("Session_id {} not found or expired, creating new session", existing_id);
-            let (time, _) = generate_captcha();
-            state.session_store.create_session(time.hour, time.minute)
+            create_captcha_session(&state)?
         }
     } else {
         info!("No session_id provided, creating new session");
-        let (time, _) = generate_captcha();
-        state.session_store.create_session(time.hour, time.minute)
+        create_captcha_session(&state)?
     };
 
     let mut context = Context::new();
     context.insert("session_id", &session_id);
+    context.insert("twenty_four_hour", &state.session_store.config().twenty_four_hour);
+    context.insert("ask_period", &session_asks_period(&state, &session_id));
+    context.insert("pow_difficulty", &state.session_store.config().pow_difficulty);
+    context.insert("audio_available", &state.audio.is_complete());
 
     match state.templates.render("captcha_form.html", &context) {
         Ok(html) => {
@@ -76,18 +156,64 @@ async fn captcha_form_handler(
     }
 }
 
+// Seed a session's image distortion from its id, so reloads draw the same image
+fn image_seed(session_id: &str) -> u64 {
+    let digest = Sha256::digest(session_id.as_bytes());
+    u64::from_le_bytes(digest[..8].try_into().unwrap_or_default())
+}
+
 // Route: GET /captcha/image/{session_id} - Serve clock SVG image
+// Route: GET /captcha/image/{session_id}.png - Serve clock PNG image
 async fn captcha_image_handler(
     Path(session_id): Path<String>,
+    Query(params): Query<CaptchaImageQuery>,
     State(state): State<AppState>,
 ) -> Response {
     debug!("captcha_image_handler called for session_id: {}", session_id);
 
+    let (session_id, as_png) = match session_id.strip_suffix(".png") {
+        Some(id) => (id.to_string(), true),
+        None => (session_id, false),
+    };
+
     if let Some(session) = state.session_store.get_session(&session_id) {
-        if !session.is_expired() {
+        let theme = match params.theme.as_deref().map(str::parse::<ClockTheme>) {
+            Some(Ok(theme)) => theme,
+            Some(Err(e)) => {
+                warn!("Ignoring theme for session {}: {}", session_id, e);
+                ClockTheme::default()
+            }
+            None => ClockTheme::default(),
+        };
+        let size = params
+            .size
+            .as_deref()
+            .and_then(|size| size.parse::<u32>().ok())
+            .map_or(DEFAULT_IMAGE_SIZE, |size| size.clamp(MIN_IMAGE_SIZE, MAX_IMAGE_SIZE));
+        let renderer = challenge::ClockRenderer::with_style(size as f64, state.numeral_style)
+            .with_theme(theme)
+            .with_difficulty(state.difficulty)
+            .with_seed(image_seed(&session_id));
+        if session.is_locked() {
+            warn!("Session {} is locked, rendering locked clock", session_id);
+            return if as_png {
+                png_response(renderer.render_locked_png())
+            } else {
+                (
+                    StatusCode::OK,
+                    [(header::CONTENT_TYPE, "image/svg+xml")],
+                    renderer.render_locked(),
+                )
+                    .into_response()
+            };
+        } else if !session.is_expired() {
             debug!("Session {} found and valid, rendering clock image", session_id);
-            let time = ClockTime::new(session.correct_hour, session.correct_minute);
-            let renderer = captcha::ClockRenderer::new(200.0);
+            let time = session.clock_time();
+
+            if as_png {
+                return png_response(renderer.render_clock_png(&time));
+            }
+
             let svg = renderer.render_clock(&time);
 
             return (
@@ -112,50 +238,276 @@ async fn captcha_image_handler(
         .into_response()
 }
 
+fn png_response(png: Vec<u8>) -> Response {
+    if png.is_empty() {
+        error!("PNG rendering produced no output");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    (StatusCode::OK, [(header::CONTENT_TYPE, "image/png")], png).into_response()
+}
+
+// Route: GET /captcha/audio/{session_id} - Serve the spoken time as WAV
+async fn captcha_audio_handler(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+) -> Response {
+    debug!("captcha_audio_handler called for session_id: {}", session_id);
+
+    let session = match state.session_store.get_session(&session_id) {
+        Some(session) if !session.is_locked() && !session.is_expired() => session,
+        _ => {
+            warn!("Session {} not available for audio", session_id);
+            return (
+                StatusCode::NOT_FOUND,
+                [(header::CONTENT_TYPE, "text/plain")],
+                "CAPTCHA session not found or expired",
+            )
+                .into_response();
+        }
+    };
+
+    // 12-hour sessions store twelve o'clock as hour 0. There are no samples for
+    // AM and PM, so sessions that ask for one are spoken on the 24-hour clock.
+    let hour = if !session.twenty_four_hour && !session.ask_period && session.correct_hour == 0 {
+        12
+    } else {
+        session.correct_hour
+    };
+
+    match state.audio.speak_time(hour, session.correct_minute) {
+        Ok(wav) => (StatusCode::OK, [(header::CONTENT_TYPE, "audio/wav")], wav).into_response(),
+        Err(e) => {
+            error!("Failed to build audio CAPTCHA for session {}: {}", session_id, e);
+            StatusCode::SERVICE_UNAVAILABLE.into_response()
+        }
+    }
+}
+
+// Refuse clients with too many recent failed verifications before touching the store
+fn check_not_blocked(state: &AppState, client: IpAddr) -> Result<(), StatusCode> {
+    if state.failures.is_blocked(client) {
+        warn!("Blocking verification from {} after repeated failures", client);
+        state.metrics.blocked_verifications.inc();
+        return Err(StatusCode::TOO_MANY_REQUESTS);
+    }
+    Ok(())
+}
+
+// Count a verification result in the metrics and the client's failure history
+fn record_verification(state: &AppState, client: IpAddr, outcome: &ValidationOutcome) {
+    match outcome {
+        ValidationOutcome::Valid => state.failures.reset(client),
+        // No answer was checked, so it is neither a success nor a failure
+        ValidationOutcome::PowRequired => return,
+        _ => state.failures.record_failure(client),
+    }
+    state.metrics.record_verification(*outcome == ValidationOutcome::Valid);
+}
+
 // Route: POST /captcha/verify - Verify CAPTCHA answer
 async fn captcha_verify_handler(
     State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Form(form): Form<CaptchaVerifyForm>,
-) -> Result<Html<String>, StatusCode> {
+) -> Result<Response, StatusCode> {
     debug!(
         "captcha_verify_handler called for session_id: {}, hour: {}, minute: {}",
         form.session_id, form.hour, form.minute
     );
 
+    let client = client_ip(state.trust_forwarded_for, peer, &headers);
+    check_not_blocked(&state, client)?;
+
     let mut context = Context::new();
     context.insert("session_id", &form.session_id);
+    context.insert("twenty_four_hour", &state.session_store.config().twenty_four_hour);
+    context.insert("ask_period", &session_asks_period(&state, &form.session_id));
+    context.insert("pow_difficulty", &state.session_store.config().pow_difficulty);
+    context.insert("audio_available", &state.audio.is_complete());
 
-    let is_valid = state.session_store.validate_and_remove(
+    let outcome = state.session_store.validate_and_remove(
         &form.session_id,
         form.hour,
         form.minute,
+        form.period,
     );
+    record_verification(&state, client, &outcome);
 
-    if is_valid {
-        info!("CAPTCHA verified successfully for session_id: {}", form.session_id);
-        context.insert("success", "✅ CAPTCHA verified successfully!");
-    } else {
-        warn!("CAPTCHA verification failed for session_id: {}", form.session_id);
-        context.insert("error", "❌ Incorrect time or expired session. Please try again.");
-        // Generate new session for retry
-        let (time, _) = generate_captcha();
-        let new_session_id = state.session_store.create_session(time.hour, time.minute);
-        context.insert("session_id", &new_session_id);
-        debug!("New session_id {} created after failed verification", new_session_id);
-    }
+    let (status, template) = match outcome {
+        ValidationOutcome::Valid => {
+            info!("CAPTCHA verified successfully for session_id: {}", form.session_id);
+            context.insert("success", "✅ CAPTCHA verified successfully!");
+            (StatusCode::OK, "captcha_form.html")
+        }
+        ValidationOutcome::Invalid { attempts_remaining } => {
+            warn!(
+                "CAPTCHA verification failed for session_id: {}, {} attempts remaining",
+                form.session_id, attempts_remaining
+            );
+            context.insert(
+                "error",
+                &format!("❌ Incorrect time. {} attempt(s) remaining.", attempts_remaining),
+            );
+            (StatusCode::OK, "captcha_form.html")
+        }
+        ValidationOutcome::PowRequired => {
+            warn!("CAPTCHA answer submitted without proof-of-work for session_id: {}", form.session_id);
+            context.insert("error", "❌ Your browser hasn't finished the security check yet. Please try again.");
+            (StatusCode::OK, "captcha_form.html")
+        }
+        ValidationOutcome::Locked => {
+            warn!("CAPTCHA session locked after too many attempts: {}", form.session_id);
+            (StatusCode::TOO_MANY_REQUESTS, "captcha_locked.html")
+        }
+        ValidationOutcome::Expired | ValidationOutcome::NotFound => {
+            warn!("CAPTCHA verification failed for session_id: {}", form.session_id);
+            context.insert("error", "❌ Incorrect time or expired session. Please try again.");
+            // Generate new session for retry
+            let new_session_id = create_captcha_session(&state)?;
+            context.insert("session_id", &new_session_id);
+            debug!("New session_id {} created after failed verification", new_session_id);
+            (StatusCode::OK, "captcha_form.html")
+        }
+    };
 
-    match state.templates.render("captcha_form.html", &context) {
+    match state.templates.render(template, &context) {
         Ok(html) => {
-            debug!("Successfully rendered captcha_form.html after verification");
-            Ok(Html(html))
+            debug!("Successfully rendered {} after verification", template);
+            Ok((status, Html(html)).into_response())
         }
         Err(e) => {
-            error!("Failed to render captcha_form.html after verification: {}", e);
+            error!("Failed to render {} after verification: {}", template, e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
 }
 
+// Route: POST /captcha/verify.json - Verify CAPTCHA answer sent as JSON or form data
+async fn captcha_verify_json_handler(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+) -> Result<Response, StatusCode> {
+    let client = client_ip(state.trust_forwarded_for, peer, request.headers());
+    check_not_blocked(&state, client)?;
+
+    let is_json = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+
+    let form = if is_json {
+        Json::<CaptchaVerifyForm>::from_request(request, &())
+            .await
+            .map(|Json(form)| form)
+            .map_err(|_| StatusCode::BAD_REQUEST)?
+    } else {
+        Form::<CaptchaVerifyForm>::from_request(request, &())
+            .await
+            .map(|Form(form)| form)
+            .map_err(|_| StatusCode::BAD_REQUEST)?
+    };
+
+    debug!(
+        "captcha_verify_json_handler called for session_id: {}, hour: {}, minute: {}",
+        form.session_id, form.hour, form.minute
+    );
+
+    let outcome = state.session_store.validate_and_remove(&form.session_id, form.hour, form.minute, form.period);
+    record_verification(&state, client, &outcome);
+
+    let (status, result) = match outcome {
+        ValidationOutcome::Valid => {
+            info!("CAPTCHA verified successfully for session_id: {}", form.session_id);
+            (StatusCode::OK, CaptchaVerifyResult { valid: true, new_session_id: None })
+        }
+        ValidationOutcome::Invalid { attempts_remaining } => {
+            warn!(
+                "CAPTCHA verification failed for session_id: {}, {} attempts remaining",
+                form.session_id, attempts_remaining
+            );
+            (StatusCode::UNAUTHORIZED, CaptchaVerifyResult { valid: false, new_session_id: None })
+        }
+        ValidationOutcome::PowRequired => {
+            warn!("CAPTCHA answer submitted without proof-of-work for session_id: {}", form.session_id);
+            (StatusCode::UNAUTHORIZED, CaptchaVerifyResult { valid: false, new_session_id: None })
+        }
+        ValidationOutcome::Locked => {
+            warn!("CAPTCHA session locked after too many attempts: {}", form.session_id);
+            (StatusCode::UNAUTHORIZED, CaptchaVerifyResult { valid: false, new_session_id: None })
+        }
+        ValidationOutcome::Expired | ValidationOutcome::NotFound => {
+            warn!("CAPTCHA verification failed for session_id: {}", form.session_id);
+            let new_session_id = create_captcha_session(&state)?;
+            (StatusCode::UNAUTHORIZED, CaptchaVerifyResult { valid: false, new_session_id: Some(new_session_id) })
+        }
+    };
+
+    Ok((status, Json(result)).into_response())
+}
+
+// Route: POST /captcha/refresh/{session_id} - Show a new time in the same session
+async fn captcha_refresh_handler(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+) -> Response {
+    debug!("captcha_refresh_handler called for session_id: {}", session_id);
+
+    if !state.session_store.rotate_time(&session_id) {
+        warn!("Session {} not found or locked, cannot refresh", session_id);
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    // The id is unchanged, so bust any cached copy of the old image
+    let revision = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    let response = serde_json::json!({
+        "session_id": session_id,
+        "image_url": format!("/captcha/image/{}?rev={}", session_id, revision),
+    });
+
+    (StatusCode::OK, [(header::CONTENT_TYPE, "application/json")], response.to_string()).into_response()
+}
+
+// Route: GET /captcha/pow/{session_id} - Get the session's proof-of-work challenge
+async fn captcha_pow_challenge_handler(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+) -> Response {
+    match state.session_store.get_session(&session_id) {
+        Some(session) if session.pow_difficulty > 0 && !session.is_expired() => {
+            let response = serde_json::json!({
+                "challenge": session.pow_challenge,
+                "difficulty": session.pow_difficulty,
+            });
+            (StatusCode::OK, [(header::CONTENT_TYPE, "application/json")], response.to_string()).into_response()
+        }
+        _ => {
+            warn!("No proof-of-work challenge for session {}", session_id);
+            StatusCode::NOT_FOUND.into_response()
+        }
+    }
+}
+
+// Route: POST /captcha/pow/verify - Submit a proof-of-work nonce for a session
+async fn captcha_pow_verify_handler(
+    State(state): State<AppState>,
+    Json(request): Json<PowVerifyRequest>,
+) -> StatusCode {
+    match state.session_store.solve_pow(&request.session_id, &request.nonce) {
+        Some(true) => {
+            debug!("Proof-of-work solved for session {}", request.session_id);
+            StatusCode::OK
+        }
+        Some(false) => {
+            warn!("Rejected proof-of-work nonce for session {}", request.session_id);
+            StatusCode::BAD_REQUEST
+        }
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
 // Route: GET /captcha/widget/{session_id} - Get embeddable widget HTML
 async fn captcha_widget_handler(
     Path(session_id): Path<String>,
@@ -164,13 +516,16 @@ async fn captcha_widget_handler(
     debug!("captcha_widget_handler called for session_id: {}", session_id);
 
     // Verify session exists
-    if state.session_store.get_session(&session_id).is_none() {
+    let Some(session) = state.session_store.get_session(&session_id) else {
         warn!("Session {} not found for widget", session_id);
         return Err(StatusCode::NOT_FOUND);
-    }
+    };
 
     let mut context = Context::new();
     context.insert("session_id", &session_id);
+    context.insert("twenty_four_hour", &state.session_store.config().twenty_four_hour);
+    context.insert("ask_period", &session.ask_period);
+    context.insert("pow_difficulty", &state.session_store.config().pow_difficulty);
 
     match state.templates.render("captcha_widget.html", &context) {
         Ok(html) => {
@@ -188,13 +543,13 @@ async fn captcha_widget_handler(
 async fn captcha_new_handler(State(state): State<AppState>) -> Result<impl IntoResponse, StatusCode> {
     debug!("captcha_new_handler called");
 
-    let (time, _) = generate_captcha();
-    let session_id = state.session_store.create_session(time.hour, time.minute);
+    let session_id = create_captcha_session(&state)?;
 
     let response = serde_json::json!({
         "session_id": session_id,
         "image_url": format!("/captcha/image/{}", session_id),
-        "widget_url": format!("/captcha/widget/{}", session_id)
+        "widget_url": format!("/captcha/widget/{}", session_id),
+        "pow_difficulty": state.session_store.config().pow_difficulty
     });
 
     info!("New CAPTCHA session created: {}", session_id);
@@ -202,14 +557,151 @@ async fn captcha_new_handler(State(state): State<AppState>) -> Result<impl IntoR
     Ok((StatusCode::OK, [(header::CONTENT_TYPE, "application/json")], response.to_string()))
 }
 
+// Route: GET /metrics - Prometheus scrape endpoint
+async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    state.metrics.live_sessions.set(state.session_store.session_count() as i64);
+    state.metrics.blocked_clients.set(state.failures.blocked_count() as i64);
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+}
+
 // Background task to cleanup expired sessions
-async fn cleanup_sessions(session_store: SessionStore) {
-    let mut interval = interval(Duration::from_secs(60)); // Cleanup every minute
+async fn cleanup_sessions(session_store: SessionStore, metrics: Arc<Metrics>) {
+    let mut interval = interval(session_store.config().cleanup_interval());
 
     loop {
         interval.tick().await;
         debug!("Running session cleanup task");
-        session_store.cleanup_expired();
+        let reaped = session_store.cleanup_expired();
+        metrics.sessions_expired.inc_by(reaped as u64);
+    }
+}
+
+// Background task to forget idle rate limit buckets and stale failure histories
+async fn prune_rate_limits(limiter: Arc<RateLimiter>, failures: Arc<FailureTracker>) {
+    let mut interval = interval(limiter.config().window);
+
+    loop {
+        interval.tick().await;
+        limiter.prune();
+        failures.prune();
+    }
+}
+
+// Client address for rate limiting: the peer, or the first X-Forwarded-For hop when trusted
+fn client_ip(trust_forwarded_for: bool, peer: SocketAddr, headers: &HeaderMap) -> IpAddr {
+    if trust_forwarded_for
+        && let Some(ip) = headers
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .and_then(|first| first.trim().parse().ok())
+    {
+        return ip;
+    }
+    peer.ip()
+}
+
+// Middleware: reject clients that exceed their token bucket with 429 + Retry-After
+async fn rate_limit_middleware(
+    State(limiter): State<Arc<RateLimiter>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let client = client_ip(limiter.config().trust_forwarded_for, peer, request.headers());
+
+    match limiter.check(client) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            warn!("Rate limited {} on {}", client, request.uri().path());
+            let retry_secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_secs.to_string())],
+                "Too many requests",
+            )
+                .into_response()
+        }
+    }
+}
+
+// Read an environment variable, falling back to `default` when unset or unparsable
+fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    match std::env::var(key) {
+        Ok(raw) => match raw.parse() {
+            Ok(value) => value,
+            Err(_) => {
+                warn!("Ignoring invalid value for {}: {}", key, raw);
+                default
+            }
+        },
+        Err(_) => default,
+    }
+}
+
+// Build the session validation settings from CAPTCHA_* environment variables
+fn session_config_from_env() -> SessionConfig {
+    let defaults = SessionConfig::default();
+    SessionConfig {
+        minute_tolerance: env_or("CAPTCHA_MINUTE_TOLERANCE", defaults.minute_tolerance),
+        lenient_hour: env_or("CAPTCHA_LENIENT_HOUR", defaults.lenient_hour),
+        max_attempts: env_or("CAPTCHA_MAX_ATTEMPTS", defaults.max_attempts),
+        twenty_four_hour: env_or("CAPTCHA_24_HOUR", defaults.twenty_four_hour),
+        ask_period: env_or("CAPTCHA_ASK_PERIOD", defaults.ask_period),
+        ttl: Duration::from_secs(env_or("CAPTCHA_SESSION_TTL_SECS", defaults.ttl.as_secs()).max(1)),
+        pow_difficulty: env_or("CAPTCHA_POW_DIFFICULTY", defaults.pow_difficulty).min(pow::MAX_POW_DIFFICULTY),
+    }
+}
+
+// Build the session store: encrypted tokens when CAPTCHA_TOKEN_SECRET is set,
+// otherwise server-side sessions in the configured backend
+fn session_store_from_env() -> Result<SessionStore, Box<dyn std::error::Error>> {
+    let config = session_config_from_env();
+    if let Ok(secret) = std::env::var("CAPTCHA_TOKEN_SECRET") {
+        info!("Using stateless encrypted CAPTCHA tokens");
+        return Ok(SessionStore::with_signer(config, TokenSigner::new(secret.as_bytes())?));
+    }
+    Ok(SessionStore::with_backend(config, session_backend_from_env()?))
+}
+
+// Pick the session backend: Redis when REDIS_URL is set (and compiled in), memory otherwise
+fn session_backend_from_env() -> Result<Arc<dyn SessionBackend>, Box<dyn std::error::Error>> {
+    #[cfg(feature = "redis-sessions")]
+    if let Ok(url) = std::env::var("REDIS_URL") {
+        let pool_size = env_or("REDIS_POOL_SIZE", 8u32);
+        return Ok(Arc::new(rustwall::captcha::redis_backend::RedisBackend::connect(&url, pool_size)?));
+    }
+
+    #[cfg(not(feature = "redis-sessions"))]
+    if std::env::var("REDIS_URL").is_ok() {
+        warn!("REDIS_URL is set but Redis support was not compiled in; using in-memory sessions");
+    }
+
+    let max_sessions = env_or("CAPTCHA_MAX_SESSIONS", DEFAULT_MAX_SESSIONS);
+    info!("Using in-memory session storage (max {} sessions)", max_sessions);
+    Ok(Arc::new(MemoryBackend::with_capacity(max_sessions)))
+}
+
+// Build the failed-verification blocking settings from CAPTCHA_FAIL_* environment variables
+fn failure_tracker_config_from_env() -> FailureTrackerConfig {
+    let defaults = FailureTrackerConfig::default();
+    FailureTrackerConfig {
+        threshold: env_or("CAPTCHA_FAIL_THRESHOLD", defaults.threshold),
+        window: Duration::from_secs(env_or("CAPTCHA_FAIL_WINDOW_SECS", defaults.window.as_secs())),
+    }
+}
+
+// Build the rate limit settings from CAPTCHA_RATE_* environment variables
+fn rate_limit_config_from_env() -> RateLimitConfig {
+    let defaults = RateLimitConfig::default();
+    RateLimitConfig {
+        requests: env_or("CAPTCHA_RATE_LIMIT", defaults.requests),
+        window: Duration::from_secs(env_or("CAPTCHA_RATE_WINDOW_SECS", defaults.window.as_secs())),
+        trust_forwarded_for: env_or("CAPTCHA_TRUST_FORWARDED_FOR", defaults.trust_forwarded_for),
     }
 }
 
@@ -225,26 +717,63 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tera.autoescape_on(vec!["html"]);
 
     // Initialize session store
-    let session_store = SessionStore::new();
+    let session_store = session_store_from_env()?;
+
+    let metrics = Arc::new(Metrics::new()?);
 
     // Start background cleanup task
     let cleanup_store = session_store.clone();
+    let cleanup_metrics = metrics.clone();
     tokio::spawn(async move {
-        cleanup_sessions(cleanup_store).await;
+        cleanup_sessions(cleanup_store, cleanup_metrics).await;
     });
 
+    // Load the spoken-word samples for the audio CAPTCHA, falling back to the built-in voice
+    let mut audio = AudioLibrary::load_dir("static/audio");
+    if !audio.is_complete() {
+        info!("Audio CAPTCHA samples incomplete; speaking times with the built-in synthesizer");
+        audio = AudioLibrary::synthesized();
+    }
+
+    let limiter = Arc::new(RateLimiter::new(rate_limit_config_from_env()));
+    let failures = Arc::new(FailureTracker::new(failure_tracker_config_from_env()));
+    tokio::spawn(prune_rate_limits(limiter.clone(), failures.clone()));
+
     let app_state = AppState {
         session_store,
         templates: Arc::new(tera),
+        numeral_style: env_or("CAPTCHA_NUMERAL_STYLE", NumeralStyle::default()),
+        difficulty: env_or("CAPTCHA_DIFFICULTY", 0u8).min(challenge::MAX_DIFFICULTY),
+        audio: Arc::new(audio),
+        metrics,
+        failures,
+        trust_forwarded_for: limiter.config().trust_forwarded_for,
     };
 
-    let app = Router::new()
+    // Endpoints that create sessions or check answers are rate limited per client
+    let limited = Router::new()
         .route("/captcha/form", get(captcha_form_handler))
-        .route("/captcha/image/:session_id", get(captcha_image_handler))
         .route("/captcha/verify", post(captcha_verify_handler))
-        .route("/captcha/widget/:session_id", get(captcha_widget_handler))
+        .route("/captcha/verify.json", post(captcha_verify_json_handler))
         .route("/captcha/new", get(captcha_new_handler))
-        .nest_service("/static", ServeDir::new("static"))
+        .route("/captcha/refresh/:session_id", post(captcha_refresh_handler))
+        .route("/captcha/pow/verify", post(captcha_pow_verify_handler))
+        .route_layer(middleware::from_fn_with_state(limiter, rate_limit_middleware));
+
+    // Static assets go through content security sanitization when it is compiled in.
+    // The CAPTCHA pages are left out because sanitizing them would strip the PoW script.
+    let static_files = ServeDir::new("static");
+    #[cfg(feature = "content-security")]
+    let static_files = ContentSecurityLayer::new(ContentSecurityManager::new()?).layer(static_files);
+
+    let app = Router::new()
+        .merge(limited)
+        .route("/captcha/image/:session_id", get(captcha_image_handler))
+        .route("/captcha/audio/:session_id", get(captcha_audio_handler))
+        .route("/captcha/widget/:session_id", get(captcha_widget_handler))
+        .route("/captcha/pow/:session_id", get(captcha_pow_challenge_handler))
+        .route("/metrics", get(metrics_handler))
+        .nest_service("/static", static_files)
         .layer(CorsLayer::permissive())
         .with_state(app_state);
 
@@ -253,7 +782,61 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("🕐 Clock CAPTCHA server running on http://127.0.0.1:3000");
     info!("📋 Test the CAPTCHA at: http://127.0.0.1:3000/captcha/form");
 
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    fn state() -> AppState {
+        AppState {
+            session_store: SessionStore::new(),
+            templates: Arc::new(Tera::default()),
+            numeral_style: NumeralStyle::default(),
+            difficulty: 0,
+            audio: Arc::new(AudioLibrary::synthesized()),
+            metrics: Arc::new(Metrics::new().unwrap()),
+            failures: Arc::new(FailureTracker::new(FailureTrackerConfig::default())),
+            trust_forwarded_for: false,
+        }
+    }
+
+    #[test]
+    fn test_pow_required_is_not_counted() {
+        let state = state();
+        let client = IpAddr::from([192, 0, 2, 1]);
+        let counted = |result: &str| state.metrics.verifications.with_label_values(&[result]).get();
+
+        record_verification(&state, client, &ValidationOutcome::PowRequired);
+        assert_eq!((counted("success"), counted("failure")), (0, 0));
+
+        record_verification(&state, client, &ValidationOutcome::Invalid { attempts_remaining: 2 });
+        record_verification(&state, client, &ValidationOutcome::Valid);
+        assert_eq!((counted("success"), counted("failure")), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_audio_route_speaks_the_time() {
+        let state = state();
+        let session_id = state.session_store.create_session(3, 45).unwrap();
+        let router = Router::new()
+            .route("/captcha/audio/:session_id", get(captcha_audio_handler))
+            .with_state(state);
+
+        let request = Request::builder().uri(format!("/captcha/audio/{}", session_id)).body(Body::empty()).unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "audio/wav");
+        let wav = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&wav[..4], b"RIFF");
+        assert!(wav.len() > 44);
+
+        let request = Request::builder().uri("/captcha/audio/unknown").body(Body::empty()).unwrap();
+        assert_eq!(router.oneshot(request).await.unwrap().status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,90 @@
+//! Prometheus metrics for the CAPTCHA server
+
+use prometheus::{Encoder, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+use log::error;
+
+pub struct Metrics {
+    registry: Registry,
+    pub sessions_created: IntCounter,
+    pub verifications: IntCounterVec,
+    pub sessions_expired: IntCounter,
+    pub live_sessions: IntGauge,
+    pub blocked_verifications: IntCounter,
+    pub blocked_clients: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new();
+
+        let sessions_created = IntCounter::new("captcha_sessions_created_total", "CAPTCHA sessions created")?;
+        let verifications = IntCounterVec::new(
+            Opts::new("captcha_verifications_total", "CAPTCHA answers checked, by result"),
+            &["result"],
+        )?;
+        let sessions_expired = IntCounter::new(
+            "captcha_sessions_expired_total",
+            "Expired CAPTCHA sessions reaped by the cleanup task",
+        )?;
+        let live_sessions = IntGauge::new("captcha_live_sessions", "CAPTCHA sessions currently stored")?;
+        let blocked_verifications = IntCounter::new(
+            "captcha_blocked_verifications_total",
+            "Verifications refused because the client failed too often",
+        )?;
+        let blocked_clients = IntGauge::new(
+            "captcha_blocked_clients",
+            "Clients currently blocked for repeated failed verifications",
+        )?;
+
+        registry.register(Box::new(sessions_created.clone()))?;
+        registry.register(Box::new(verifications.clone()))?;
+        registry.register(Box::new(sessions_expired.clone()))?;
+        registry.register(Box::new(live_sessions.clone()))?;
+        registry.register(Box::new(blocked_verifications.clone()))?;
+        registry.register(Box::new(blocked_clients.clone()))?;
+
+        Ok(Self {
+            registry,
+            sessions_created,
+            verifications,
+            sessions_expired,
+            live_sessions,
+            blocked_verifications,
+            blocked_clients,
+        })
+    }
+
+    pub fn record_verification(&self, success: bool) {
+        let result = if success { "success" } else { "failure" };
+        self.verifications.with_label_values(&[result]).inc();
+    }
+
+    /// Render every registered metric in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            error!("Failed to encode metrics: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_includes_counters() {
+        let metrics = Metrics::new().unwrap();
+        metrics.sessions_created.inc();
+        metrics.record_verification(true);
+        metrics.record_verification(false);
+        metrics.record_verification(false);
+        metrics.live_sessions.set(4);
+
+        let text = metrics.render();
+        assert!(text.contains("captcha_sessions_created_total 1"));
+        assert!(text.contains("captcha_verifications_total{result=\"failure\"} 2"));
+        assert!(text.contains("captcha_live_sessions 4"));
+    }
+}
@@ -0,0 +1,14 @@
+//! Clock CAPTCHA challenges
+//!
+//! A challenge shows an analogue clock and asks for the time on it. This module
+//! holds everything needed to issue and check one: clock rendering, sessions and
+//! their backends, signed stateless tokens and the proof-of-work gate. The
+//! `rustwall-captcha` binary serves them over HTTP; `RustWall` issues and
+//! verifies them in-process.
+
+pub mod challenge;
+pub mod pow;
+pub mod session;
+pub mod token;
+#[cfg(feature = "redis-sessions")]
+pub mod redis_backend;
@@ -0,0 +1,66 @@
+//! Proof-of-work gate
+//!
+//! Each session carries a random challenge. A client solves it by finding a
+//! nonce such that `SHA256(challenge || nonce)` starts with `difficulty` zero
+//! bits, and must do so before its clock answer is accepted. Because the
+//! challenge lives in the session, a solution can't be reused for another one.
+
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+/// Highest difficulty accepted from configuration; beyond this browsers take too long
+pub const MAX_POW_DIFFICULTY: u8 = 32;
+
+/// Random hex challenge for a new session
+pub fn new_challenge() -> String {
+    let bytes: [u8; 16] = rand::thread_rng().r#gen();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        if *byte == 0 {
+            bits += 8;
+        } else {
+            bits += byte.leading_zeros();
+            break;
+        }
+    }
+    bits
+}
+
+/// True when `nonce` solves `challenge` at `difficulty`
+pub fn verify_solution(challenge: &str, nonce: &str, difficulty: u8) -> bool {
+    let hash = Sha256::new()
+        .chain_update(challenge.as_bytes())
+        .chain_update(nonce.as_bytes())
+        .finalize();
+    leading_zero_bits(&hash) >= difficulty as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leading_zero_bits() {
+        assert_eq!(leading_zero_bits(&[0x00, 0x0f, 0xff]), 12);
+        assert_eq!(leading_zero_bits(&[0x80]), 0);
+        assert_eq!(leading_zero_bits(&[0x00, 0x00]), 16);
+    }
+
+    #[test]
+    fn test_verify_solution() {
+        let challenge = new_challenge();
+        assert_eq!(challenge.len(), 32);
+
+        let nonce = (0u64..)
+            .map(|n| n.to_string())
+            .find(|nonce| verify_solution(&challenge, nonce, 8))
+            .unwrap();
+        assert!(verify_solution(&challenge, &nonce, 8));
+        assert!(verify_solution(&challenge, "anything", 0));
+        assert!(!verify_solution(&challenge, &nonce, 255));
+    }
+}
@@ -0,0 +1,241 @@
+//! Per-client token bucket rate limiting and failed-verification tracking
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use log::debug;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// Requests allowed per window; also the burst size
+    pub requests: u32,
+    #[serde(with = "rustwall::serde_duration::secs")]
+    pub window: Duration,
+    /// Key clients on the first `X-Forwarded-For` address instead of the peer address
+    pub trust_forwarded_for: bool,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            requests: 30,
+            window: Duration::from_secs(60),
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: DashMap<IpAddr, Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config: RateLimitConfig {
+                requests: config.requests.max(1),
+                window: config.window.max(Duration::from_secs(1)),
+                ..config
+            },
+            buckets: DashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.config
+    }
+
+    fn refill_per_sec(&self) -> f64 {
+        self.config.requests as f64 / self.config.window.as_secs_f64()
+    }
+
+    /// Take a token for `client`, or return how long until one is available
+    pub fn check(&self, client: IpAddr) -> Result<(), Duration> {
+        self.check_at(client, Instant::now())
+    }
+
+    fn check_at(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let capacity = self.config.requests as f64;
+        let rate = self.refill_per_sec();
+
+        let mut bucket = self.buckets.entry(client).or_insert(Bucket { tokens: capacity, updated: now });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            debug!("Rate limit exceeded for {}", client);
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+
+    /// Forget clients whose buckets have refilled completely
+    pub fn prune(&self) {
+        let now = Instant::now();
+        let window = self.config.window;
+        self.buckets.retain(|_, bucket| now.saturating_duration_since(bucket.updated) < window);
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FailureTrackerConfig {
+    /// Failed verifications within `window` after which a client is blocked
+    pub threshold: u32,
+    #[serde(with = "rustwall::serde_duration::secs")]
+    pub window: Duration,
+}
+
+impl Default for FailureTrackerConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 10,
+            window: Duration::from_secs(600),
+        }
+    }
+}
+
+/// Counts failed verifications per client over a sliding window
+pub struct FailureTracker {
+    config: FailureTrackerConfig,
+    failures: DashMap<IpAddr, VecDeque<Instant>>,
+}
+
+impl FailureTracker {
+    pub fn new(config: FailureTrackerConfig) -> Self {
+        Self {
+            config: FailureTrackerConfig {
+                threshold: config.threshold.max(1),
+                ..config
+            },
+            failures: DashMap::new(),
+        }
+    }
+
+    fn recent(&self, failures: &mut VecDeque<Instant>, now: Instant) -> usize {
+        while failures
+            .front()
+            .is_some_and(|at| now.saturating_duration_since(*at) >= self.config.window)
+        {
+            failures.pop_front();
+        }
+        failures.len()
+    }
+
+    pub fn is_blocked(&self, client: IpAddr) -> bool {
+        self.is_blocked_at(client, Instant::now())
+    }
+
+    fn is_blocked_at(&self, client: IpAddr, now: Instant) -> bool {
+        match self.failures.get_mut(&client) {
+            Some(mut failures) => self.recent(&mut failures, now) >= self.config.threshold as usize,
+            None => false,
+        }
+    }
+
+    pub fn record_failure(&self, client: IpAddr) {
+        self.record_failure_at(client, Instant::now());
+    }
+
+    fn record_failure_at(&self, client: IpAddr, now: Instant) {
+        let mut failures = self.failures.entry(client).or_default();
+        self.recent(&mut failures, now);
+        failures.push_back(now);
+        // Failures beyond the threshold don't change the outcome, so keep memory bounded
+        while failures.len() > self.config.threshold as usize {
+            failures.pop_front();
+        }
+    }
+
+    /// Forget a client's failures after a successful verification
+    pub fn reset(&self, client: IpAddr) {
+        self.failures.remove(&client);
+    }
+
+    /// Clients currently over the threshold
+    pub fn blocked_count(&self) -> usize {
+        let now = Instant::now();
+        self.failures
+            .iter()
+            .filter(|entry| {
+                let recent = entry
+                    .value()
+                    .iter()
+                    .filter(|at| now.saturating_duration_since(**at) < self.config.window)
+                    .count();
+                recent >= self.config.threshold as usize
+            })
+            .count()
+    }
+
+    /// Drop clients with no failures left in the window
+    pub fn prune(&self) {
+        let now = Instant::now();
+        self.failures.retain(|_, failures| self.recent(failures, now) > 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(requests: u32) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig { requests, window: Duration::from_secs(10), ..RateLimitConfig::default() })
+    }
+
+    #[test]
+    fn test_bucket_exhausts_and_refills() {
+        let limiter = limiter(2);
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        let start = Instant::now();
+
+        assert!(limiter.check_at(client, start).is_ok());
+        assert!(limiter.check_at(client, start).is_ok());
+        let retry_after = limiter.check_at(client, start).unwrap_err();
+        assert_eq!(retry_after.as_secs(), 5);
+
+        assert!(limiter.check_at(client, start + Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn test_clients_are_independent() {
+        let limiter = limiter(1);
+        let start = Instant::now();
+
+        assert!(limiter.check_at("192.0.2.1".parse().unwrap(), start).is_ok());
+        assert!(limiter.check_at("192.0.2.1".parse().unwrap(), start).is_err());
+        assert!(limiter.check_at("192.0.2.2".parse().unwrap(), start).is_ok());
+    }
+
+    #[test]
+    fn test_failure_tracker_blocks_and_resets() {
+        let tracker = FailureTracker::new(FailureTrackerConfig { threshold: 2, window: Duration::from_secs(60) });
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        let start = Instant::now();
+
+        tracker.record_failure_at(client, start);
+        assert!(!tracker.is_blocked_at(client, start));
+        tracker.record_failure_at(client, start + Duration::from_secs(1));
+        assert!(tracker.is_blocked_at(client, start + Duration::from_secs(1)));
+
+        // The first failure slides out of the window
+        assert!(!tracker.is_blocked_at(client, start + Duration::from_secs(60)));
+
+        tracker.record_failure_at(client, start + Duration::from_secs(61));
+        tracker.reset(client);
+        assert!(!tracker.is_blocked(client));
+    }
+}
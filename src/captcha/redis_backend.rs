@@ -0,0 +1,280 @@
+//! Redis session backend
+//!
+//! Stores each session as JSON under `rustwall:captcha:<session_id>` with a TTL
+//! matching its expiry, so a cluster of CAPTCHA servers can share sessions and
+//! Redis takes care of evicting stale ones. A sorted set of session ids scored
+//! by expiry keeps counting cheap without scanning the keyspace.
+
+use log::{debug, error, info};
+use redis::Commands;
+use std::time::Instant;
+
+use crate::captcha::session::{instant_to_unix, CaptchaSession, SessionAction, SessionBackend, SessionError, StoredSession};
+
+const KEY_PREFIX: &str = "rustwall:captcha:";
+/// Sorted set of live session ids, scored by their unix expiry
+const EXPIRY_INDEX: &str = "rustwall:captcha-expiries";
+
+/// Seconds until the session expires, never less than one so Redis accepts the TTL
+fn ttl_secs(session: &CaptchaSession) -> u64 {
+    session
+        .expires_at
+        .saturating_duration_since(Instant::now())
+        .as_secs()
+        .max(1)
+}
+
+/// Queue a write of `session`, resetting the key's TTL and its place in the
+/// expiry index only when the session's expiry moved away from
+/// `previous_expiry`, as it does on time rotation
+fn queue_write(pipe: &mut redis::Pipeline, session_id: &str, session: &CaptchaSession, previous_expiry: Instant) {
+    let write = pipe.cmd("SET").arg(RedisBackend::key(session_id)).arg(encode(session));
+    if session.expires_at == previous_expiry {
+        write.arg("KEEPTTL").ignore();
+    } else {
+        write.arg("EX").arg(ttl_secs(session)).ignore();
+        pipe.zadd(EXPIRY_INDEX, session_id, instant_to_unix(session.expires_at)).ignore();
+    }
+}
+
+fn encode(session: &CaptchaSession) -> String {
+    // Serializing plain integers and booleans cannot fail
+    serde_json::to_string(&StoredSession::from(session)).unwrap_or_default()
+}
+
+fn decode(session_id: &str, raw: &str) -> Option<CaptchaSession> {
+    match serde_json::from_str::<StoredSession>(raw) {
+        Ok(stored) => Some(stored.into()),
+        Err(e) => {
+            error!("Discarding unreadable session {}: {}", session_id, e);
+            None
+        }
+    }
+}
+
+pub struct RedisBackend {
+    pool: r2d2::Pool<redis::Client>,
+}
+
+impl RedisBackend {
+    /// Connect to `url` with a pool of up to `pool_size` connections
+    pub fn connect(url: &str, pool_size: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        let client = redis::Client::open(url)?;
+        let pool = r2d2::Pool::builder().max_size(pool_size.max(1)).build(client)?;
+        info!("Connected Redis session backend (pool size {})", pool_size.max(1));
+        Ok(Self { pool })
+    }
+
+    fn key(session_id: &str) -> String {
+        format!("{}{}", KEY_PREFIX, session_id)
+    }
+
+    fn connection(&self) -> Option<r2d2::PooledConnection<redis::Client>> {
+        match self.pool.get() {
+            Ok(conn) => Some(conn),
+            Err(e) => {
+                error!("Failed to get Redis connection: {}", e);
+                None
+            }
+        }
+    }
+}
+
+impl SessionBackend for RedisBackend {
+    fn create(&self, session_id: String, session: CaptchaSession) -> Result<(), SessionError> {
+        let mut conn = self
+            .connection()
+            .ok_or_else(|| SessionError::Backend("no Redis connection available".to_string()))?;
+        let result: redis::RedisResult<()> = redis::pipe()
+            .atomic()
+            .set_ex(Self::key(&session_id), encode(&session), ttl_secs(&session))
+            .ignore()
+            .zadd(EXPIRY_INDEX, &session_id, instant_to_unix(session.expires_at))
+            .ignore()
+            .query(&mut *conn);
+        result.map_err(|e| {
+            error!("Failed to store session {}: {}", session_id, e);
+            SessionError::Backend(e.to_string())
+        })
+    }
+
+    fn get(&self, session_id: &str) -> Option<CaptchaSession> {
+        let mut conn = self.connection()?;
+        let raw: Option<String> = match conn.get(Self::key(session_id)) {
+            Ok(raw) => raw,
+            Err(e) => {
+                error!("Failed to load session {}: {}", session_id, e);
+                return None;
+            }
+        };
+        raw.and_then(|raw| decode(session_id, &raw))
+    }
+
+    fn remove(&self, session_id: &str) -> Option<CaptchaSession> {
+        let mut conn = self.connection()?;
+        // GETDEL hands the session to exactly one caller
+        let removed: redis::RedisResult<(Option<String>,)> = redis::pipe()
+            .atomic()
+            .cmd("GETDEL")
+            .arg(Self::key(session_id))
+            .zrem(EXPIRY_INDEX, session_id)
+            .ignore()
+            .query(&mut *conn);
+        let raw = match removed {
+            Ok((raw,)) => raw,
+            Err(e) => {
+                error!("Failed to remove session {}: {}", session_id, e);
+                return None;
+            }
+        };
+        raw.and_then(|raw| decode(session_id, &raw))
+    }
+
+    fn update(&self, session_id: &str, f: &mut dyn FnMut(&mut CaptchaSession) -> SessionAction) -> bool {
+        let Some(mut conn) = self.connection() else { return false };
+        let key = Self::key(session_id);
+
+        // WATCH/MULTI/EXEC: the transaction is retried if another server touched
+        // the key in between, so each attempt is applied exactly once
+        let result = redis::transaction(&mut *conn, &[&key], |conn, pipe| {
+            let raw: Option<String> = conn.get(&key)?;
+            let Some(mut session) = raw.and_then(|raw| decode(session_id, &raw)) else {
+                return Ok(Some(false));
+            };
+
+            let previous_expiry = session.expires_at;
+            match f(&mut session) {
+                SessionAction::Remove => {
+                    pipe.del(&key).ignore().zrem(EXPIRY_INDEX, session_id).ignore();
+                }
+                SessionAction::Keep => queue_write(pipe, session_id, &session, previous_expiry),
+            }
+            let committed: Option<()> = pipe.query(conn)?;
+            Ok(committed.map(|_| true))
+        });
+
+        match result {
+            Ok(found) => found,
+            Err(e) => {
+                error!("Failed to update session {}: {}", session_id, e);
+                false
+            }
+        }
+    }
+
+    fn cleanup_expired(&self, now: Instant) -> usize {
+        // Keys carry their own TTL and Redis evicts them; only the index needs pruning
+        let Some(mut conn) = self.connection() else { return 0 };
+        match conn.zrembyscore(EXPIRY_INDEX, "-inf", instant_to_unix(now)) {
+            Ok(pruned) => {
+                debug!("Pruned {} expired sessions from the Redis expiry index", pruned);
+                pruned
+            }
+            Err(e) => {
+                error!("Failed to prune the session expiry index: {}", e);
+                0
+            }
+        }
+    }
+
+    fn snapshot(&self) -> Vec<(String, CaptchaSession)> {
+        let Some(mut conn) = self.connection() else { return Vec::new() };
+        let ids: Vec<String> = match conn.zrangebyscore(EXPIRY_INDEX, instant_to_unix(Instant::now()), "+inf") {
+            Ok(ids) => ids,
+            Err(e) => {
+                error!("Failed to list sessions: {}", e);
+                return Vec::new();
+            }
+        };
+        if ids.is_empty() {
+            return Vec::new();
+        }
+        let keys: Vec<String> = ids.iter().map(|id| Self::key(id)).collect();
+        let raw: Vec<Option<String>> = match conn.mget(&keys) {
+            Ok(raw) => raw,
+            Err(e) => {
+                error!("Failed to load sessions: {}", e);
+                return Vec::new();
+            }
+        };
+        ids.into_iter()
+            .zip(raw)
+            .filter_map(|(id, raw)| {
+                let session = decode(&id, &raw?)?;
+                Some((id, session))
+            })
+            .collect()
+    }
+
+    fn count(&self) -> usize {
+        let Some(mut conn) = self.connection() else { return 0 };
+        let counted: redis::RedisResult<(usize,)> = redis::pipe()
+            .atomic()
+            .zrembyscore(EXPIRY_INDEX, "-inf", instant_to_unix(Instant::now()))
+            .ignore()
+            .zcard(EXPIRY_INDEX)
+            .query(&mut *conn);
+        match counted {
+            Ok((count,)) => count,
+            Err(e) => {
+                error!("Failed to count sessions: {}", e);
+                0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::captcha::session::SessionConfig;
+
+    #[test]
+    fn test_session_round_trip() {
+        let mut session = CaptchaSession::new(9, 41, &SessionConfig::default());
+        session.attempts = 2;
+        session.locked = true;
+
+        let raw = encode(&session);
+        let restored = decode("test", &raw).unwrap();
+
+        assert_eq!(restored.correct_hour, 9);
+        assert_eq!(restored.correct_minute, 41);
+        assert_eq!(restored.attempts, 2);
+        assert!(restored.is_locked());
+        assert!(!restored.is_expired());
+        assert!((299..=300).contains(&ttl_secs(&restored)));
+    }
+
+    #[test]
+    fn test_write_resets_ttl_only_when_expiry_moves() {
+        let mut session = CaptchaSession::new(9, 41, &SessionConfig::default());
+        let previous_expiry = session.expires_at;
+        let packed = |session: &CaptchaSession| {
+            let mut pipe = redis::pipe();
+            queue_write(&mut pipe, "test", session, previous_expiry);
+            String::from_utf8_lossy(&pipe.get_packed_pipeline()).into_owned()
+        };
+        let has_arg = |packed: &str, arg: &str| packed.contains(&format!("\r\n{}\r\n", arg));
+
+        // Recording an attempt leaves the key's TTL alone
+        session.attempts = 1;
+        let write = packed(&session);
+        assert!(has_arg(&write, "KEEPTTL"));
+        assert!(!has_arg(&write, "EX"));
+        assert!(!has_arg(&write, "ZADD"));
+
+        // A rotated session gets a TTL matching its new expiry
+        session.expires_at = Instant::now() + std::time::Duration::from_secs(600);
+        let write = packed(&session);
+        assert!(!has_arg(&write, "KEEPTTL"));
+        assert!(has_arg(&write, "EX"));
+        assert!(["599", "600"].iter().any(|ttl| has_arg(&write, ttl)));
+        assert!(has_arg(&write, "ZADD") && has_arg(&write, EXPIRY_INDEX));
+    }
+
+    #[test]
+    fn test_unreadable_session_is_discarded() {
+        assert!(decode("test", "not json").is_none());
+    }
+}
@@ -1,38 +1,147 @@
 use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 use log::{debug, error, info, warn};
+use crate::clock::{Clock, SystemClock};
+
+use crate::captcha::challenge::{ClockTime, Period};
+use crate::captcha::pow;
+use crate::captcha::token::{TokenError, TokenSigner};
+
+/// Largest minute tolerance a session may be configured with
+pub const MAX_MINUTE_TOLERANCE: u8 = 5;
+
+/// Minutes either side of the hour in which the adjacent hour is also accepted
+/// when `lenient_hour` is enabled
+const HOUR_BOUNDARY_MINUTES: u8 = 5;
+
+/// Default session lifetime
+pub const DEFAULT_SESSION_TTL: Duration = Duration::from_secs(300);
+
+/// Live sessions an in-memory store holds by default before rejecting new ones
+pub const DEFAULT_MAX_SESSIONS: usize = 100_000;
+
+/// Longest gap between cleanup passes, whatever the TTL
+const MAX_CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Per-session validation settings
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// Allowed difference in minutes between the answer and the shown time (clamped to 0..=5)
+    pub minute_tolerance: u8,
+    /// Accept the neighbouring hour when the minute hand is close to the top of the dial
+    pub lenient_hour: bool,
+    /// Failed answers allowed before the session is locked
+    pub max_attempts: u8,
+    /// Show and validate times on a 0-23 hour dial instead of the 12-hour face
+    pub twenty_four_hour: bool,
+    /// Label the 12-hour dial with AM or PM and require the answer to match it.
+    /// Ignored on the 24-hour dial.
+    pub ask_period: bool,
+    /// How long a session stays answerable after it is created
+    #[serde(with = "crate::serde_duration::secs")]
+    pub ttl: Duration,
+    /// Leading zero bits required of the proof-of-work solution; 0 disables it
+    pub pow_difficulty: u8,
+}
+
+impl SessionConfig {
+    /// How often expired sessions should be swept: at most a minute, and never
+    /// longer than a session lives so stale entries don't pile up
+    pub fn cleanup_interval(&self) -> Duration {
+        self.ttl.clamp(Duration::from_secs(1), MAX_CLEANUP_INTERVAL)
+    }
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            minute_tolerance: 2,
+            lenient_hour: false,
+            max_attempts: 3,
+            twenty_four_hour: false,
+            ask_period: false,
+            ttl: DEFAULT_SESSION_TTL,
+            pow_difficulty: 0,
+        }
+    }
+}
+
+/// Outcome of submitting an answer against a stored session
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationOutcome {
+    Valid,
+    Invalid { attempts_remaining: u8 },
+    /// The proof-of-work challenge has not been solved yet; no attempt was counted
+    PowRequired,
+    Locked,
+    Expired,
+    NotFound,
+}
 
 #[derive(Clone, Debug)]
 pub struct CaptchaSession {
     pub correct_hour: u8,
     pub correct_minute: u8,
+    pub minute_tolerance: u8,
+    pub lenient_hour: bool,
+    pub attempts: u8,
+    pub max_attempts: u8,
+    pub locked: bool,
+    pub twenty_four_hour: bool,
+    /// `correct_hour` is 0-23 and answers must say AM or PM; see `SessionConfig::ask_period`
+    pub ask_period: bool,
+    pub pow_challenge: String,
+    pub pow_difficulty: u8,
+    pub pow_solved: bool,
     #[allow(dead_code)]
     pub created_at: Instant,
     pub expires_at: Instant,
 }
 
 impl CaptchaSession {
-    pub fn new(hour: u8, minute: u8) -> Self {
-        let now = Instant::now();
-        let expires_at = now + Duration::from_secs(300); // 5 minutes expiration
+    pub fn new(hour: u8, minute: u8, config: &SessionConfig) -> Self {
+        Self::new_at(hour, minute, config, Instant::now())
+    }
+
+    /// A session created at `now`
+    pub fn new_at(hour: u8, minute: u8, config: &SessionConfig, now: Instant) -> Self {
+        let expires_at = now + config.ttl;
+        let minute_tolerance = config.minute_tolerance.min(MAX_MINUTE_TOLERANCE);
 
         debug!(
-            "Creating new CaptchaSession: hour={}, minute={}, expires_at={:?}",
-            hour, minute, expires_at
+            "Creating new CaptchaSession: hour={}, minute={}, minute_tolerance={}, lenient_hour={}, expires_at={:?}",
+            hour, minute, minute_tolerance, config.lenient_hour, expires_at
         );
 
         Self {
             correct_hour: hour,
             correct_minute: minute,
+            minute_tolerance,
+            lenient_hour: config.lenient_hour,
+            attempts: 0,
+            max_attempts: config.max_attempts.max(1),
+            locked: false,
+            twenty_four_hour: config.twenty_four_hour,
+            ask_period: config.ask_period && !config.twenty_four_hour,
+            pow_challenge: pow::new_challenge(),
+            pow_difficulty: config.pow_difficulty.min(pow::MAX_POW_DIFFICULTY),
+            pow_solved: false,
             created_at: now,
             expires_at,
         }
     }
 
     pub fn is_expired(&self) -> bool {
-        let expired = Instant::now() > self.expires_at;
+        self.is_expired_at(Instant::now())
+    }
+
+    pub fn is_expired_at(&self, now: Instant) -> bool {
+        let expired = now > self.expires_at;
         if expired {
             warn!(
                 "CaptchaSession expired: correct_hour={}, correct_minute={}, expires_at={:?}",
@@ -42,7 +151,76 @@ impl CaptchaSession {
         expired
     }
 
-    pub fn validate_answer(&self, user_hour: u8, user_minute: u8) -> bool {
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// The period answers must give, for sessions that ask for one
+    pub fn period(&self) -> Option<Period> {
+        self.ask_period.then(|| Period::of_hour(self.correct_hour))
+    }
+
+    /// The time to draw for this session
+    pub fn clock_time(&self) -> ClockTime {
+        if self.twenty_four_hour {
+            return ClockTime::new_24h(self.correct_hour, self.correct_minute);
+        }
+        let time = ClockTime::new(self.correct_hour, self.correct_minute);
+        match self.period() {
+            Some(period) => time.with_period(period),
+            None => time,
+        }
+    }
+
+    /// Mark the proof-of-work as solved if `nonce` meets the session's difficulty
+    pub fn solve_pow(&mut self, nonce: &str) -> bool {
+        if pow::verify_solution(&self.pow_challenge, nonce, self.pow_difficulty) {
+            self.pow_solved = true;
+        }
+        self.pow_solved
+    }
+
+    /// Validate an answer and count it against the session's attempt limit,
+    /// locking the session once `max_attempts` failures have been recorded.
+    /// `period` is only checked when the session asks for one.
+    pub fn record_attempt(
+        &mut self,
+        user_hour: u8,
+        user_minute: u8,
+        period: Option<Period>,
+        now: Instant,
+    ) -> ValidationOutcome {
+        if self.locked {
+            warn!("Attempt on locked CaptchaSession: attempts={}", self.attempts);
+            return ValidationOutcome::Locked;
+        }
+        if self.is_expired_at(now) {
+            return ValidationOutcome::Expired;
+        }
+        if self.pow_difficulty > 0 && !self.pow_solved {
+            warn!("Answer submitted before the proof-of-work was solved");
+            return ValidationOutcome::PowRequired;
+        }
+        if self.answer_matches(user_hour, user_minute, period) {
+            return ValidationOutcome::Valid;
+        }
+
+        self.attempts = self.attempts.saturating_add(1);
+        if self.attempts >= self.max_attempts {
+            warn!(
+                "CaptchaSession locked after {} failed attempts",
+                self.attempts
+            );
+            self.locked = true;
+            ValidationOutcome::Locked
+        } else {
+            ValidationOutcome::Invalid {
+                attempts_remaining: self.max_attempts - self.attempts,
+            }
+        }
+    }
+
+    pub fn validate_answer(&self, user_hour: u8, user_minute: u8, period: Option<Period>) -> bool {
         if self.is_expired() {
             error!(
                 "Attempted to validate expired session: correct_hour={}, correct_minute={}, user_hour={}, user_minute={}",
@@ -50,15 +228,14 @@ impl CaptchaSession {
             );
             return false;
         }
+        self.answer_matches(user_hour, user_minute, period)
+    }
 
-        // Allow some tolerance for minute precision (±2 minutes)
-        let minute_diff = if self.correct_minute >= user_minute {
-            self.correct_minute - user_minute
-        } else {
-            user_minute - self.correct_minute
-        };
+    fn answer_matches(&self, user_hour: u8, user_minute: u8, period: Option<Period>) -> bool {
+        // Allow some tolerance for minute precision
+        let minute_diff = self.correct_minute.abs_diff(user_minute);
 
-        let valid = self.correct_hour == user_hour && minute_diff <= 2;
+        let valid = self.hour_matches(user_hour, period) && minute_diff <= self.minute_tolerance;
 
         if valid {
             info!(
@@ -74,37 +251,353 @@ impl CaptchaSession {
 
         valid
     }
+
+    /// Compare hours on the session's dial, optionally accepting the adjacent hour
+    /// when the minute hand sits near the top of the clock face. The 12-hour dial
+    /// compares modulo 12; the 24-hour dial compares exactly, as do sessions
+    /// that ask for a period, once the answer is converted with it.
+    fn hour_matches(&self, user_hour: u8, period: Option<Period>) -> bool {
+        if user_hour >= 24 {
+            return false;
+        }
+        let (dial_hours, user) = if self.twenty_four_hour {
+            (24, user_hour)
+        } else if self.ask_period {
+            match Period::to_24h(user_hour, period) {
+                Some(user) => (24, user),
+                None => return false,
+            }
+        } else {
+            (12, user_hour % 12)
+        };
+        let correct = self.correct_hour % dial_hours;
+
+        if correct == user {
+            return true;
+        }
+        if !self.lenient_hour {
+            return false;
+        }
+
+        if self.correct_minute >= 60 - HOUR_BOUNDARY_MINUTES {
+            user == (correct + 1) % dial_hours
+        } else if self.correct_minute < HOUR_BOUNDARY_MINUTES {
+            user == (correct + dial_hours - 1) % dial_hours
+        } else {
+            false
+        }
+    }
+}
+
+fn unix_now() -> Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+/// Convert a monotonic deadline to whole seconds since the unix epoch, for
+/// storage outside this process
+pub fn instant_to_unix(instant: Instant) -> u64 {
+    let now = Instant::now();
+    let unix = if instant >= now {
+        unix_now() + (instant - now)
+    } else {
+        unix_now().saturating_sub(now - instant)
+    };
+    unix.as_secs()
+}
+
+/// Inverse of `instant_to_unix`
+pub fn unix_to_instant(secs: u64) -> Instant {
+    let now = Instant::now();
+    let target = Duration::from_secs(secs);
+    let unix = unix_now();
+    if target >= unix {
+        now + (target - unix)
+    } else {
+        now.checked_sub(unix - target).unwrap_or(now)
+    }
+}
+
+/// Wire format of a session for storage outside this process; `Instant`s are
+/// stored as unix timestamps
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct StoredSession {
+    correct_hour: u8,
+    correct_minute: u8,
+    minute_tolerance: u8,
+    lenient_hour: bool,
+    attempts: u8,
+    max_attempts: u8,
+    locked: bool,
+    twenty_four_hour: bool,
+    #[serde(default)]
+    ask_period: bool,
+    #[serde(default)]
+    pow_challenge: String,
+    #[serde(default)]
+    pow_difficulty: u8,
+    #[serde(default)]
+    pow_solved: bool,
+    created_at: u64,
+    expires_at: u64,
+}
+
+impl From<&CaptchaSession> for StoredSession {
+    fn from(session: &CaptchaSession) -> Self {
+        Self {
+            correct_hour: session.correct_hour,
+            correct_minute: session.correct_minute,
+            minute_tolerance: session.minute_tolerance,
+            lenient_hour: session.lenient_hour,
+            attempts: session.attempts,
+            max_attempts: session.max_attempts,
+            locked: session.locked,
+            twenty_four_hour: session.twenty_four_hour,
+            ask_period: session.ask_period,
+            pow_challenge: session.pow_challenge.clone(),
+            pow_difficulty: session.pow_difficulty,
+            pow_solved: session.pow_solved,
+            created_at: instant_to_unix(session.created_at),
+            expires_at: instant_to_unix(session.expires_at),
+        }
+    }
+}
+
+impl From<StoredSession> for CaptchaSession {
+    fn from(stored: StoredSession) -> Self {
+        Self {
+            correct_hour: stored.correct_hour,
+            correct_minute: stored.correct_minute,
+            minute_tolerance: stored.minute_tolerance,
+            lenient_hour: stored.lenient_hour,
+            attempts: stored.attempts,
+            max_attempts: stored.max_attempts,
+            locked: stored.locked,
+            twenty_four_hour: stored.twenty_four_hour,
+            ask_period: stored.ask_period,
+            pow_challenge: stored.pow_challenge,
+            pow_difficulty: stored.pow_difficulty,
+            pow_solved: stored.pow_solved,
+            created_at: unix_to_instant(stored.created_at),
+            expires_at: unix_to_instant(stored.expires_at),
+        }
+    }
+}
+
+/// Why a session could not be created
+#[derive(Debug, PartialEq, Eq)]
+pub enum SessionError {
+    /// The store already holds its maximum number of live sessions
+    CapacityExceeded,
+    /// The store failed or lost the session
+    Backend(String),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::CapacityExceeded => write!(f, "Session store is full"),
+            SessionError::Backend(msg) => write!(f, "Session backend error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+/// What a backend should do with a session after `SessionBackend::update`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionAction {
+    Keep,
+    Remove,
+}
+
+/// Storage for CAPTCHA sessions. Implementations must be safe to share across
+/// handlers and must apply `update` atomically with respect to other callers.
+pub trait SessionBackend: Send + Sync {
+    fn create(&self, session_id: String, session: CaptchaSession) -> Result<(), SessionError>;
+
+    fn get(&self, session_id: &str) -> Option<CaptchaSession>;
+
+    fn remove(&self, session_id: &str) -> Option<CaptchaSession>;
+
+    /// Mutate a session in place, removing it afterwards when `f` returns
+    /// `SessionAction::Remove`. Returns `false` when the session does not exist.
+    fn update(&self, session_id: &str, f: &mut dyn FnMut(&mut CaptchaSession) -> SessionAction) -> bool;
+
+    /// Drop sessions that expired before `now`, returning how many were removed
+    fn cleanup_expired(&self, now: Instant) -> usize;
+
+    /// Every stored session with its id, for backups
+    fn snapshot(&self) -> Vec<(String, CaptchaSession)>;
+
+    /// Number of sessions currently stored
+    fn count(&self) -> usize;
+}
+
+/// In-process session storage backed by a `DashMap`
+#[derive(Default)]
+pub struct MemoryBackend {
+    sessions: DashMap<String, CaptchaSession>,
+    /// Upper bound on stored sessions; `None` is unbounded
+    max_sessions: Option<usize>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject new sessions once `max_sessions` are live, so a flood of
+    /// requests can't grow the map without bound between cleanup ticks
+    pub fn with_capacity(max_sessions: usize) -> Self {
+        Self {
+            sessions: DashMap::new(),
+            max_sessions: Some(max_sessions.max(1)),
+        }
+    }
+}
+
+impl SessionBackend for MemoryBackend {
+    fn create(&self, session_id: String, session: CaptchaSession) -> Result<(), SessionError> {
+        if let Some(max) = self.max_sessions
+            && self.sessions.len() >= max
+        {
+            // Only sweep when full; concurrent creators may overshoot by a few
+            self.cleanup_expired(session.created_at);
+            if self.sessions.len() >= max {
+                warn!("Session store full ({} sessions), rejecting new session", max);
+                return Err(SessionError::CapacityExceeded);
+            }
+        }
+        self.sessions.insert(session_id, session);
+        Ok(())
+    }
+
+    fn get(&self, session_id: &str) -> Option<CaptchaSession> {
+        self.sessions.get(session_id).map(|entry| entry.clone())
+    }
+
+    fn remove(&self, session_id: &str) -> Option<CaptchaSession> {
+        self.sessions.remove(session_id).map(|(_, session)| session)
+    }
+
+    fn update(&self, session_id: &str, f: &mut dyn FnMut(&mut CaptchaSession) -> SessionAction) -> bool {
+        let mut found = false;
+        // remove_if_mut holds the shard lock across the mutation and the removal
+        self.sessions.remove_if_mut(session_id, |_, session| {
+            found = true;
+            f(session) == SessionAction::Remove
+        });
+        found
+    }
+
+    fn cleanup_expired(&self, now: Instant) -> usize {
+        let before = self.sessions.len();
+        self.sessions.retain(|_, session| now <= session.expires_at);
+        before.saturating_sub(self.sessions.len())
+    }
+
+    fn snapshot(&self) -> Vec<(String, CaptchaSession)> {
+        self.sessions.iter().map(|entry| (entry.key().clone(), entry.value().clone())).collect()
+    }
+
+    fn count(&self) -> usize {
+        self.sessions.len()
+    }
+}
+
+/// Where a store keeps its sessions
+#[derive(Clone)]
+enum Storage {
+    Backend(Arc<dyn SessionBackend>),
+    /// Sessions are carried in encrypted tokens; only tokens that have had their
+    /// answer are remembered, with their expiry
+    Tokens { signer: Arc<TokenSigner>, spent: Arc<DashMap<String, Instant>> },
 }
 
 #[derive(Clone)]
 pub struct SessionStore {
-    sessions: Arc<DashMap<String, CaptchaSession>>,
+    storage: Storage,
+    config: SessionConfig,
+    clock: Arc<dyn Clock>,
 }
 
 impl SessionStore {
     pub fn new() -> Self {
-        info!("Initializing new SessionStore");
+        Self::with_config(SessionConfig::default())
+    }
+
+    pub fn with_config(config: SessionConfig) -> Self {
+        Self::with_backend(config, Arc::new(MemoryBackend::new()))
+    }
+
+    pub fn with_backend(config: SessionConfig, backend: Arc<dyn SessionBackend>) -> Self {
+        info!("Initializing new SessionStore with config: {:?}", config);
+        Self { storage: Storage::Backend(backend), config, clock: Arc::new(SystemClock) }
+    }
+
+    /// Stateless store: session ids are encrypted tokens produced by `signer`,
+    /// each accepted for one answer. Proof-of-work needs server-side state to
+    /// record a solution, so it is disabled in this mode.
+    pub fn with_signer(mut config: SessionConfig, signer: TokenSigner) -> Self {
+        if config.pow_difficulty > 0 {
+            warn!("Proof-of-work is not supported with encrypted tokens; disabling it");
+            config.pow_difficulty = 0;
+        }
+        info!("Initializing stateless SessionStore with config: {:?}", config);
         Self {
-            sessions: Arc::new(DashMap::new()),
+            storage: Storage::Tokens { signer: Arc::new(signer), spent: Arc::default() },
+            config,
+            clock: Arc::new(SystemClock),
         }
     }
 
-    pub fn create_session(&self, hour: u8, minute: u8) -> String {
-        let session_id = Uuid::new_v4().to_string();
-        let session = CaptchaSession::new(hour, minute);
-        self.sessions.insert(session_id.clone(), session);
+    /// Judge creation and expiry by `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn config(&self) -> &SessionConfig {
+        &self.config
+    }
+
+    pub fn create_session(&self, hour: u8, minute: u8) -> Result<String, SessionError> {
+        self.create_session_with_config(hour, minute, &self.config)
+    }
+
+    pub fn create_session_with_config(&self, hour: u8, minute: u8, config: &SessionConfig) -> Result<String, SessionError> {
+        let session = CaptchaSession::new_at(hour, minute, config, self.clock.now());
+        let session_id = match &self.storage {
+            Storage::Tokens { signer, .. } => signer.sign(&session),
+            Storage::Backend(backend) => {
+                let session_id = Uuid::new_v4().to_string();
+                backend.create(session_id.clone(), session)?;
+                session_id
+            }
+        };
         info!(
             "Created new session: session_id={}, hour={}, minute={}",
             session_id, hour, minute
         );
-        session_id
+        Ok(session_id)
     }
 
     pub fn get_session(&self, session_id: &str) -> Option<CaptchaSession> {
-        match self.sessions.get(session_id) {
-            Some(entry) => {
+        let backend = match &self.storage {
+            Storage::Backend(backend) => backend,
+            Storage::Tokens { spent, .. } if spent.contains_key(session_id) => return None,
+            Storage::Tokens { signer, .. } => {
+                return signer
+                    .verify(session_id, &self.config, self.clock.now())
+                    .inspect_err(|e| warn!("Rejected session token: {}", e))
+                    .ok();
+            }
+        };
+
+        match backend.get(session_id) {
+            Some(session) => {
                 debug!("Session found: session_id={}", session_id);
-                Some(entry.clone())
+                Some(session)
             }
             None => {
                 warn!("Session not found: session_id={}", session_id);
@@ -113,9 +606,13 @@ impl SessionStore {
         }
     }
 
+    /// Drop a session without answering it; encrypted tokens can't be removed
     pub fn remove_session(&self, session_id: &str) -> Option<CaptchaSession> {
-        match self.sessions.remove(session_id) {
-            Some((_, session)) => {
+        let Storage::Backend(backend) = &self.storage else {
+            return None;
+        };
+        match backend.remove(session_id) {
+            Some(session) => {
                 debug!("Session removed: session_id={}", session_id);
                 Some(session)
             }
@@ -126,36 +623,169 @@ impl SessionStore {
         }
     }
 
-    pub fn cleanup_expired(&self) {
-        let now = Instant::now();
-        let before = self.sessions.len();
-        self.sessions.retain(|_, session| now <= session.expires_at);
-        let after = self.sessions.len();
-        let cleaned = before.saturating_sub(after);
+    /// Show a fresh time in an existing session, keeping its id, attempt count
+    /// and proof-of-work state and restarting its expiry. Returns `false` when
+    /// the session is missing, locked or expired; encrypted tokens can't be
+    /// changed in place, so this always fails in stateless mode.
+    pub fn rotate_time(&self, session_id: &str) -> bool {
+        let Storage::Backend(backend) = &self.storage else {
+            return false;
+        };
+
+        let ttl = self.config.ttl;
+        let now = self.clock.now();
+        let mut rotated = false;
+        backend.update(session_id, &mut |session| {
+            if session.is_locked() || session.is_expired_at(now) {
+                return SessionAction::Keep;
+            }
+            let time = if session.twenty_four_hour {
+                ClockTime::random_24h()
+            } else {
+                ClockTime::random()
+            };
+            session.correct_hour = time.hour;
+            session.correct_minute = time.minute;
+            session.expires_at = now + ttl;
+            rotated = true;
+            SessionAction::Keep
+        });
+
+        if rotated {
+            info!("Rotated time for session: session_id={}", session_id);
+        }
+        rotated
+    }
+
+    /// Submit a proof-of-work nonce for a session. Returns `None` when the
+    /// session is missing, locked or expired, otherwise whether it is now solved.
+    pub fn solve_pow(&self, session_id: &str, nonce: &str) -> Option<bool> {
+        let Storage::Backend(backend) = &self.storage else {
+            return None;
+        };
+
+        let now = self.clock.now();
+        let mut solved = None;
+        backend.update(session_id, &mut |session| {
+            if !session.is_locked() && !session.is_expired_at(now) {
+                solved = Some(session.solve_pow(nonce));
+            }
+            SessionAction::Keep
+        });
+        solved
+    }
+
+    /// Drop expired sessions, returning how many were reaped
+    pub fn cleanup_expired(&self) -> usize {
+        let now = self.clock.now();
+        let cleaned = match &self.storage {
+            Storage::Backend(backend) => backend.cleanup_expired(now),
+            // Spent tokens can be forgotten once they would be rejected as expired
+            Storage::Tokens { spent, .. } => {
+                let before = spent.len();
+                spent.retain(|_, expires_at| *expires_at > now);
+                before.saturating_sub(spent.len())
+            }
+        };
         if cleaned > 0 {
             info!("Cleaned up {} expired sessions", cleaned);
         } else {
             debug!("No expired sessions to clean up");
         }
+        cleaned
     }
 
-    pub fn validate_and_remove(&self, session_id: &str, user_hour: u8, user_minute: u8) -> bool {
-        match self.remove_session(session_id) {
-            Some(session) => {
-                debug!(
-                    "Validating and removing session: session_id={}, user_hour={}, user_minute={}",
-                    session_id, user_hour, user_minute
-                );
-                session.validate_answer(user_hour, user_minute)
+    /// Unexpired sessions held server-side with their ids; always empty for
+    /// encrypted tokens, which the client holds
+    pub fn snapshot(&self) -> Vec<(String, CaptchaSession)> {
+        let Storage::Backend(backend) = &self.storage else {
+            return Vec::new();
+        };
+        let now = self.clock.now();
+        backend.snapshot().into_iter().filter(|(_, session)| !session.is_expired_at(now)).collect()
+    }
+
+    /// Put back a session from `snapshot` under its old id. Expired sessions are
+    /// skipped, and so is everything in stateless mode.
+    pub fn restore_session(&self, session_id: String, session: CaptchaSession) -> Result<(), SessionError> {
+        let Storage::Backend(backend) = &self.storage else {
+            return Ok(());
+        };
+        if session.is_expired_at(self.clock.now()) {
+            debug!("Skipping expired session on restore: session_id={}", session_id);
+            return Ok(());
+        }
+        backend.create(session_id, session)
+    }
+
+    /// Live sessions held server-side; always zero for encrypted tokens
+    pub fn session_count(&self) -> usize {
+        match &self.storage {
+            Storage::Backend(backend) => backend.count(),
+            Storage::Tokens { .. } => 0,
+        }
+    }
+
+    /// Check an answer against a session. The session is removed only when the
+    /// answer is correct; failures are counted and lock the session at the limit.
+    ///
+    /// Tokens take a single answer in stateless mode: a wrong one ends the
+    /// challenge and is reported as `Expired`, and any later one is `NotFound`.
+    pub fn validate_and_remove(
+        &self,
+        session_id: &str,
+        user_hour: u8,
+        user_minute: u8,
+        period: Option<Period>,
+    ) -> ValidationOutcome {
+        debug!(
+            "Validating session: session_id={}, user_hour={}, user_minute={}",
+            session_id, user_hour, user_minute
+        );
+
+        let now = self.clock.now();
+        let backend = match &self.storage {
+            Storage::Backend(backend) => backend,
+            Storage::Tokens { signer, spent } => {
+                let mut session = match signer.verify(session_id, &self.config, now) {
+                    Ok(session) => session,
+                    Err(TokenError::Expired) => return ValidationOutcome::Expired,
+                    Err(e) => {
+                        warn!("Rejected session token: {}", e);
+                        return ValidationOutcome::NotFound;
+                    }
+                };
+                // Marking the token spent before judging the answer means concurrent
+                // submissions of one token get a single verdict between them
+                if spent.insert(session_id.to_string(), session.expires_at).is_some() {
+                    warn!("Rejected session token that was already answered");
+                    return ValidationOutcome::NotFound;
+                }
+                return match session.record_attempt(user_hour, user_minute, period, now) {
+                    ValidationOutcome::Valid => ValidationOutcome::Valid,
+                    _ => ValidationOutcome::Expired,
+                };
             }
-            None => {
-                error!(
-                    "Failed to validate: session not found or already removed: session_id={}",
-                    session_id
-                );
-                false
+        };
+
+        let mut outcome = ValidationOutcome::NotFound;
+        // The backend applies the attempt and the removal atomically, so a
+        // session validates at most once even under concurrent submissions
+        let found = backend.update(session_id, &mut |session| {
+            outcome = session.record_attempt(user_hour, user_minute, period, now);
+            match outcome {
+                ValidationOutcome::Valid | ValidationOutcome::Expired => SessionAction::Remove,
+                _ => SessionAction::Keep,
             }
+        });
+
+        if !found {
+            error!(
+                "Failed to validate: session not found or already removed: session_id={}",
+                session_id
+            );
         }
+        outcome
     }
 }
 
@@ -164,3 +794,245 @@ impl Default for SessionStore {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    #[test]
+    fn test_minute_tolerance() {
+        let config = SessionConfig { minute_tolerance: 1, ..SessionConfig::default() };
+        let session = CaptchaSession::new(3, 30, &config);
+
+        assert!(session.validate_answer(3, 31, None));
+        assert!(!session.validate_answer(3, 32, None));
+
+        let clamped = CaptchaSession::new(3, 30, &SessionConfig { minute_tolerance: 20, ..SessionConfig::default() });
+        assert_eq!(clamped.minute_tolerance, MAX_MINUTE_TOLERANCE);
+    }
+
+    #[test]
+    fn test_lenient_hour() {
+        let strict = CaptchaSession::new(11, 58, &SessionConfig::default());
+        assert!(!strict.validate_answer(12, 58, None));
+
+        let config = SessionConfig { lenient_hour: true, ..SessionConfig::default() };
+        let near_top = CaptchaSession::new(11, 58, &config);
+        assert!(near_top.validate_answer(11, 58, None));
+        assert!(near_top.validate_answer(12, 58, None));
+
+        let past_top = CaptchaSession::new(0, 2, &config);
+        assert!(past_top.validate_answer(12, 2, None));
+        assert!(past_top.validate_answer(11, 2, None));
+
+        let mid_hour = CaptchaSession::new(5, 30, &config);
+        assert!(!mid_hour.validate_answer(6, 30, None));
+    }
+
+    #[test]
+    fn test_twenty_four_hour_mode() {
+        let config = SessionConfig { twenty_four_hour: true, ..SessionConfig::default() };
+        let session = CaptchaSession::new(15, 40, &config);
+
+        assert!(session.validate_answer(15, 40, None));
+        assert!(!session.validate_answer(3, 40, None));
+
+        let midnight = CaptchaSession::new(0, 10, &config);
+        assert!(midnight.validate_answer(0, 10, None));
+        assert!(!midnight.validate_answer(12, 10, None));
+
+        // 12-hour sessions still treat 12 and 0 as the same hour
+        let twelve = CaptchaSession::new(0, 10, &SessionConfig::default());
+        assert!(twelve.validate_answer(12, 10, None));
+    }
+
+    #[test]
+    fn test_period_checked_only_when_asked() {
+        let config = SessionConfig { ask_period: true, lenient_hour: true, ..SessionConfig::default() };
+        let session = CaptchaSession::new(15, 30, &config);
+        assert_eq!(session.period(), Some(Period::Pm));
+        assert_eq!(session.clock_time().period, Some(Period::Pm));
+        assert!(session.validate_answer(3, 30, Some(Period::Pm)));
+        assert!(session.validate_answer(15, 30, None));
+        assert!(!session.validate_answer(3, 30, Some(Period::Am)));
+        assert!(!session.validate_answer(3, 30, None));
+
+        // The adjacent hour across noon flips the period
+        let before_noon = CaptchaSession::new(11, 58, &config);
+        assert!(before_noon.validate_answer(12, 58, Some(Period::Pm)));
+        assert!(!before_noon.validate_answer(12, 58, Some(Period::Am)));
+
+        // Sessions from a store that doesn't ask ignore the period, so one
+        // deployment can serve both kinds
+        let store = SessionStore::with_config(SessionConfig::default());
+        let plain = store.create_session(3, 30).unwrap();
+        let asking = store.create_session_with_config(15, 30, &config).unwrap();
+        assert!(!store.get_session(&plain).unwrap().ask_period);
+        assert_eq!(store.validate_and_remove(&plain, 3, 30, Some(Period::Am)), ValidationOutcome::Valid);
+        assert_eq!(
+            store.validate_and_remove(&asking, 3, 30, Some(Period::Am)),
+            ValidationOutcome::Invalid { attempts_remaining: 2 }
+        );
+        assert_eq!(store.validate_and_remove(&asking, 3, 30, Some(Period::Pm)), ValidationOutcome::Valid);
+
+        // The 24-hour dial never asks
+        let dial = CaptchaSession::new(15, 30, &SessionConfig { twenty_four_hour: true, ..config });
+        assert!(!dial.ask_period);
+    }
+
+    #[test]
+    fn test_lockout_after_max_attempts() {
+        let store = SessionStore::with_config(SessionConfig { max_attempts: 2, ..SessionConfig::default() });
+        let session_id = store.create_session(4, 20).unwrap();
+
+        assert_eq!(
+            store.validate_and_remove(&session_id, 9, 0, None),
+            ValidationOutcome::Invalid { attempts_remaining: 1 }
+        );
+        assert_eq!(store.validate_and_remove(&session_id, 9, 0, None), ValidationOutcome::Locked);
+
+        // The correct answer no longer helps once locked
+        assert_eq!(store.validate_and_remove(&session_id, 4, 20, None), ValidationOutcome::Locked);
+        assert!(store.get_session(&session_id).unwrap().is_locked());
+    }
+
+    #[test]
+    fn test_valid_answer_removes_session() {
+        let store = SessionStore::new();
+        let session_id = store.create_session(4, 20).unwrap();
+
+        assert_eq!(store.validate_and_remove(&session_id, 4, 21, None), ValidationOutcome::Valid);
+        assert_eq!(store.validate_and_remove(&session_id, 4, 21, None), ValidationOutcome::NotFound);
+    }
+
+    #[test]
+    fn test_memory_backend_cleanup_expired() {
+        let backend = Arc::new(MemoryBackend::new());
+        let store = SessionStore::with_backend(SessionConfig::default(), backend.clone());
+        let live_id = store.create_session(1, 0).unwrap();
+        let stale_id = store.create_session(2, 0).unwrap();
+
+        backend.update(&stale_id, &mut |session| {
+            session.expires_at = Instant::now() - Duration::from_secs(1);
+            SessionAction::Keep
+        });
+
+        assert_eq!(store.cleanup_expired(), 1);
+        assert_eq!(store.session_count(), 1);
+        assert!(store.get_session(&live_id).is_some());
+        assert!(store.get_session(&stale_id).is_none());
+        assert!(!backend.update("missing", &mut |_| SessionAction::Remove));
+    }
+
+    #[test]
+    fn test_session_expires_as_clock_advances() {
+        let clock = MockClock::new();
+        let config = SessionConfig { ttl: Duration::from_secs(30), ..SessionConfig::default() };
+        let store = SessionStore::with_config(config).with_clock(Arc::new(clock.clone()));
+        let expiring_id = store.create_session(5, 0).unwrap();
+        let reaped_id = store.create_session(6, 0).unwrap();
+
+        clock.advance(Duration::from_secs(29));
+        assert!(store.rotate_time(&expiring_id));
+        assert_eq!(store.cleanup_expired(), 0);
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(store.cleanup_expired(), 1);
+        assert!(store.get_session(&reaped_id).is_none());
+        assert!(store.get_session(&expiring_id).is_some());
+
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(store.validate_and_remove(&expiring_id, 5, 0, None), ValidationOutcome::Expired);
+    }
+
+    #[test]
+    fn test_token_expires_as_clock_advances() {
+        let clock = MockClock::new();
+        let signer = TokenSigner::new(&[1; crate::captcha::token::MIN_SECRET_LEN]).unwrap();
+        let config = SessionConfig { ttl: Duration::from_secs(30), ..SessionConfig::default() };
+        let store = SessionStore::with_signer(config, signer).with_clock(Arc::new(clock.clone()));
+        let token = store.create_session(5, 0).unwrap();
+
+        clock.advance(Duration::from_secs(20));
+        assert!(store.get_session(&token).is_some());
+        clock.advance(Duration::from_secs(20));
+        assert!(store.get_session(&token).is_none());
+        assert_eq!(store.validate_and_remove(&token, 5, 0, None), ValidationOutcome::Expired);
+    }
+
+    #[test]
+    fn test_signed_token_store() {
+        let signer = TokenSigner::new(&[1; crate::captcha::token::MIN_SECRET_LEN]).unwrap();
+        let store = SessionStore::with_signer(SessionConfig::default(), signer);
+        let token = store.create_session(6, 15).unwrap();
+
+        assert_eq!(store.get_session(&token).map(|s| s.correct_minute), Some(15));
+        assert_eq!(store.validate_and_remove(&token, 6, 40, None), ValidationOutcome::Expired);
+        // The failed attempt spent the token, so the right answer comes too late
+        assert_eq!(store.validate_and_remove(&token, 6, 15, None), ValidationOutcome::NotFound);
+        assert!(store.get_session(&token).is_none());
+
+        let token = store.create_session(6, 15).unwrap();
+        assert_eq!(store.validate_and_remove(&token, 6, 15, None), ValidationOutcome::Valid);
+        assert_eq!(store.validate_and_remove(&token, 6, 15, None), ValidationOutcome::NotFound);
+        assert_eq!(store.validate_and_remove("garbage", 6, 15, None), ValidationOutcome::NotFound);
+    }
+
+    #[test]
+    fn test_configurable_ttl() {
+        let config = SessionConfig { ttl: Duration::from_secs(30), ..SessionConfig::default() };
+        let session = CaptchaSession::new(2, 10, &config);
+
+        assert!(session.expires_at <= Instant::now() + Duration::from_secs(30));
+        assert!(session.expires_at > Instant::now() + Duration::from_secs(25));
+        assert_eq!(config.cleanup_interval(), Duration::from_secs(30));
+        assert_eq!(SessionConfig::default().cleanup_interval(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_pow_required_before_answer() {
+        let store = SessionStore::with_config(SessionConfig { pow_difficulty: 4, ..SessionConfig::default() });
+        let session_id = store.create_session(8, 5).unwrap();
+
+        assert_eq!(store.validate_and_remove(&session_id, 8, 5, None), ValidationOutcome::PowRequired);
+
+        let challenge = store.get_session(&session_id).unwrap().pow_challenge;
+        let nonce = (0u64..)
+            .map(|n| n.to_string())
+            .find(|nonce| pow::verify_solution(&challenge, nonce, 4))
+            .unwrap();
+        assert_eq!(store.solve_pow(&session_id, &nonce), Some(true));
+        assert_eq!(store.solve_pow("missing", &nonce), None);
+        assert_eq!(store.validate_and_remove(&session_id, 8, 5, None), ValidationOutcome::Valid);
+    }
+
+    #[test]
+    fn test_memory_backend_capacity() {
+        let store = SessionStore::with_backend(SessionConfig::default(), Arc::new(MemoryBackend::with_capacity(2)));
+        let first = store.create_session(1, 0).unwrap();
+        store.create_session(2, 0).unwrap();
+
+        assert_eq!(store.create_session(3, 0), Err(SessionError::CapacityExceeded));
+
+        assert_eq!(store.validate_and_remove(&first, 1, 0, None), ValidationOutcome::Valid);
+        assert!(store.create_session(3, 0).is_ok());
+    }
+
+    #[test]
+    fn test_rotate_time_keeps_attempts() {
+        let store = SessionStore::new();
+        let session_id = store.create_session(4, 20).unwrap();
+        store.validate_and_remove(&session_id, 9, 59, None);
+
+        assert!(store.rotate_time(&session_id));
+        let rotated = store.get_session(&session_id).unwrap();
+        assert_eq!(rotated.attempts, 1);
+        assert!(rotated.correct_hour < 12 && rotated.correct_minute < 60);
+        assert!(!store.rotate_time("missing"));
+
+        store.validate_and_remove(&session_id, 9, 59, None);
+        store.validate_and_remove(&session_id, 9, 59, None);
+        assert!(!store.rotate_time(&session_id));
+    }
+}
@@ -0,0 +1,211 @@
+//! Stateless encrypted CAPTCHA tokens
+//!
+//! A token is `base64url(nonce || AES-256-GCM(claims))`, where the claims are the
+//! JSON of the answer, the settings it is judged by and the expiry, and the key
+//! is derived from the configured secret. The client can neither read the answer
+//! nor alter the token without it failing to decrypt. The server keeps no
+//! session, so any instance holding the secret can render or verify a challenge.
+//!
+//! A token can't be revoked by itself. `SessionStore` remembers the tokens it
+//! has checked until they expire, so each gets a single answer, but only on the
+//! instance that checked it. Keep the session TTL short when running several.
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fmt;
+use std::time::Instant;
+
+use crate::captcha::session::{instant_to_unix, unix_to_instant, CaptchaSession, SessionConfig};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Shortest secret accepted, matching the 256-bit cipher key
+pub const MIN_SECRET_LEN: usize = 32;
+
+const NONCE_LEN: usize = 12;
+
+/// Binds the derived key to this use of the secret
+const KEY_CONTEXT: &[u8] = b"rustwall captcha token v1";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TokenError {
+    WeakSecret,
+    Malformed,
+    /// The token was altered or sealed under another secret
+    Tampered,
+    Expired,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::WeakSecret => write!(f, "Token secret must be at least {} bytes", MIN_SECRET_LEN),
+            TokenError::Malformed => write!(f, "Malformed CAPTCHA token"),
+            TokenError::Tampered => write!(f, "CAPTCHA token failed authentication"),
+            TokenError::Expired => write!(f, "CAPTCHA token expired"),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+/// Everything `CaptchaSession::validate_answer` reads, so a token is judged
+/// exactly as a stored session would be
+#[derive(Serialize, Deserialize, Debug)]
+struct TokenClaims {
+    hour: u8,
+    minute: u8,
+    minute_tolerance: u8,
+    lenient_hour: bool,
+    twenty_four_hour: bool,
+    ask_period: bool,
+    exp: u64,
+}
+
+pub struct TokenSigner {
+    cipher: Aes256Gcm,
+}
+
+impl TokenSigner {
+    pub fn new(secret: &[u8]) -> Result<Self, TokenError> {
+        if secret.len() < MIN_SECRET_LEN {
+            return Err(TokenError::WeakSecret);
+        }
+        // HMAC accepts keys of any length and yields exactly one cipher key
+        let mut mac = <HmacSha256 as Mac>::new_from_slice(secret).expect("HMAC key of any length");
+        mac.update(KEY_CONTEXT);
+        let key = mac.finalize().into_bytes();
+        Ok(Self { cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)) })
+    }
+
+    /// Seal a session's time and expiry into an encrypted token
+    pub fn sign(&self, session: &CaptchaSession) -> String {
+        let claims = TokenClaims {
+            hour: session.correct_hour,
+            minute: session.correct_minute,
+            minute_tolerance: session.minute_tolerance,
+            lenient_hour: session.lenient_hour,
+            twenty_four_hour: session.twenty_four_hour,
+            ask_period: session.ask_period,
+            exp: instant_to_unix(session.expires_at),
+        };
+        // Serializing plain integers cannot fail, and encrypting only fails on
+        // inputs far larger than these claims
+        let plaintext = serde_json::to_vec(&claims).unwrap_or_default();
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let ciphertext = self.cipher.encrypt(Nonce::from_slice(&nonce), plaintext.as_slice()).unwrap_or_default();
+
+        let mut token = nonce.to_vec();
+        token.extend_from_slice(&ciphertext);
+        URL_SAFE_NO_PAD.encode(token)
+    }
+
+    /// Decrypt a token and check it hasn't expired by `now`, rebuilding the
+    /// session it describes. Settings the token doesn't carry come from `config`.
+    pub fn verify(&self, token: &str, config: &SessionConfig, now: Instant) -> Result<CaptchaSession, TokenError> {
+        let token = URL_SAFE_NO_PAD.decode(token).map_err(|_| TokenError::Malformed)?;
+        if token.len() <= NONCE_LEN {
+            return Err(TokenError::Malformed);
+        }
+        let (nonce, ciphertext) = token.split_at(NONCE_LEN);
+
+        let plaintext = self.cipher.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|_| {
+            warn!("Rejected CAPTCHA token that failed authentication");
+            TokenError::Tampered
+        })?;
+        let claims: TokenClaims = serde_json::from_slice(&plaintext).map_err(|_| TokenError::Malformed)?;
+
+        let expires_at = unix_to_instant(claims.exp);
+        if now >= expires_at {
+            return Err(TokenError::Expired);
+        }
+
+        let mut session = CaptchaSession::new_at(claims.hour, claims.minute, config, now);
+        session.minute_tolerance = claims.minute_tolerance;
+        session.lenient_hour = claims.lenient_hour;
+        session.twenty_four_hour = claims.twenty_four_hour;
+        session.ask_period = claims.ask_period;
+        session.expires_at = expires_at;
+        Ok(session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::captcha::challenge::Period;
+    use std::time::Duration;
+
+    fn signer() -> TokenSigner {
+        TokenSigner::new(&[7; MIN_SECRET_LEN]).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let config = SessionConfig::default();
+        let token = signer().sign(&CaptchaSession::new(3, 45, &config));
+
+        let session = signer().verify(&token, &config, Instant::now()).unwrap();
+        assert_eq!((session.correct_hour, session.correct_minute), (3, 45));
+        assert!(session.validate_answer(3, 45, None));
+    }
+
+    #[test]
+    fn test_round_trip_keeps_session_settings() {
+        let config = SessionConfig { ask_period: true, minute_tolerance: 0, ..SessionConfig::default() };
+        let token = signer().sign(&CaptchaSession::new(15, 45, &config));
+
+        // Judged by the settings it was issued with, not the verifier's
+        let session = signer().verify(&token, &SessionConfig::default(), Instant::now()).unwrap();
+        assert!(session.ask_period);
+        assert_eq!(session.minute_tolerance, 0);
+        assert!(session.validate_answer(3, 45, Some(Period::Pm)));
+        assert!(!session.validate_answer(3, 45, Some(Period::Am)));
+        assert!(!session.validate_answer(3, 46, Some(Period::Pm)));
+
+        let config = SessionConfig { twenty_four_hour: true, ..SessionConfig::default() };
+        let token = signer().sign(&CaptchaSession::new(15, 45, &config));
+        let session = signer().verify(&token, &SessionConfig::default(), Instant::now()).unwrap();
+        assert!(session.twenty_four_hour);
+        assert!(session.validate_answer(15, 45, None));
+        assert!(!session.validate_answer(3, 45, None));
+    }
+
+    #[test]
+    fn test_token_hides_the_answer() {
+        let config = SessionConfig::default();
+        let token = signer().sign(&CaptchaSession::new(3, 45, &config));
+
+        let decoded = URL_SAFE_NO_PAD.decode(&token).unwrap();
+        assert!(!decoded.windows(4).any(|window| window == b"hour"));
+        assert_ne!(token, signer().sign(&CaptchaSession::new(3, 45, &config)));
+    }
+
+    #[test]
+    fn test_rejects_tampered_and_expired_tokens() {
+        let config = SessionConfig::default();
+        let signer = signer();
+        let token = signer.sign(&CaptchaSession::new(3, 45, &config));
+
+        let mut altered = URL_SAFE_NO_PAD.decode(&token).unwrap();
+        *altered.last_mut().unwrap() ^= 1;
+        let altered = URL_SAFE_NO_PAD.encode(altered);
+        assert_eq!(signer.verify(&altered, &config, Instant::now()).unwrap_err(), TokenError::Tampered);
+
+        let other = TokenSigner::new(&[8; MIN_SECRET_LEN]).unwrap();
+        assert_eq!(other.verify(&token, &config, Instant::now()).unwrap_err(), TokenError::Tampered);
+
+        let mut stale = CaptchaSession::new(3, 45, &config);
+        stale.expires_at = Instant::now() - Duration::from_secs(5);
+        assert_eq!(signer.verify(&signer.sign(&stale), &config, Instant::now()).unwrap_err(), TokenError::Expired);
+
+        assert_eq!(signer.verify("not-a-token", &config, Instant::now()).unwrap_err(), TokenError::Malformed);
+        assert_eq!(TokenSigner::new(b"short").err(), Some(TokenError::WeakSecret));
+    }
+}
@@ -0,0 +1,76 @@
+//! Injectable time source
+//!
+//! Components that expire, decay or window their state read the time through a
+//! `Clock` instead of calling `Instant::now()` directly. Production code uses
+//! `SystemClock`; tests hand in a `MockClock` and advance it to cross window and
+//! expiry boundaries without sleeping.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A source of monotonic time
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// The real monotonic clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time, so a test
+/// can keep one handle and advance the clock a component holds.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockClock {
+    /// Start at the current real time
+    pub fn new() -> Self {
+        Self { now: Arc::new(Mutex::new(Instant::now())) }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// The clock components use unless given another
+#[cfg_attr(not(feature = "tor"), allow(dead_code))]
+pub(crate) fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_clock_advances_shared_time() {
+        let clock = MockClock::new();
+        let handle = clock.clone();
+        let start = clock.now();
+        assert_eq!(clock.now(), start);
+
+        handle.advance(Duration::from_secs(90));
+        assert_eq!(clock.now() - start, Duration::from_secs(90));
+    }
+}
@@ -1,5 +1,285 @@
 //! Font Fingerprinting Protection Module
-//! 
-//! Limit font access to prevent browser fingerprinting
+//!
+//! Limit font access to prevent browser fingerprinting. Fonts loaded from other
+//! origins both leak the visit to the font host and widen the set of fonts a
+//! script can probe, so remote `@font-face` rules, font-host `@import`s and
+//! `<link>`s are removed. References to the fonts they provided are pointed at a
+//! local font stack instead.
 
-// TODO: Implement font fingerprinting protection functionality
+use crate::content_security::markup::{self, find_ignore_case};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+/// Where fonts may come from and what replaces the ones that can't
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FontProtectionConfig {
+    /// Hosts serving web fonts; links and imports pointing at them are removed
+    pub blocked_font_hosts: Vec<String>,
+    /// The site's own hosts, whose `@font-face` rules are kept
+    pub internal_hosts: Vec<String>,
+    /// Local stack substituted for a blocked family with no explicit mapping
+    pub fallback_stack: String,
+    /// Lowercase family name to the local stack that replaces it
+    pub substitutions: HashMap<String, String>,
+}
+
+impl Default for FontProtectionConfig {
+    fn default() -> Self {
+        Self {
+            blocked_font_hosts: vec![
+                "fonts.googleapis.com".to_string(),
+                "fonts.gstatic.com".to_string(),
+                "use.typekit.net".to_string(),
+            ],
+            internal_hosts: Vec::new(),
+            fallback_stack: "sans-serif".to_string(),
+            substitutions: HashMap::new(),
+        }
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    value.trim().trim_matches(|c| c == '"' || c == '\'').trim()
+}
+
+/// Every `url(...)` target in a CSS snippet
+fn css_urls(css: &str) -> Vec<&str> {
+    let mut urls = Vec::new();
+    let mut pos = 0;
+    while let Some(start) = find_ignore_case(css, pos, "url(") {
+        let Some(end) = css[start..].find(')') else { break };
+        urls.push(unquote(&css[start + 4..start + end]));
+        pos = start + end;
+    }
+    urls
+}
+
+/// Value of a `name: value` declaration, as a byte range of `css`
+fn declaration_value(css: &str, from: usize, name: &str) -> Option<Range<usize>> {
+    let mut pos = from;
+    loop {
+        let start = find_ignore_case(css, pos, name)?;
+        pos = start + name.len();
+        // Skip `other-font-family` and the like
+        if css[..start].ends_with(|c: char| c.is_ascii_alphanumeric() || c == '-') {
+            continue;
+        }
+        let after = &css[pos..];
+        let colon = after.len() - after.trim_start().len();
+        if !after[colon..].starts_with(':') {
+            continue;
+        }
+        let value_start = pos + colon + 1;
+        let value_end = css[value_start..]
+            .find([';', '}'])
+            .map_or(css.len(), |offset| value_start + offset);
+        return Some(value_start..value_end);
+    }
+}
+
+/// Blocks external font loading in HTML and CSS
+pub struct FontProtection {
+    config: FontProtectionConfig,
+}
+
+impl Default for FontProtection {
+    fn default() -> Self {
+        Self::new(FontProtectionConfig::default())
+    }
+}
+
+impl FontProtection {
+    pub fn new(config: FontProtectionConfig) -> Self {
+        let config = FontProtectionConfig {
+            blocked_font_hosts: config.blocked_font_hosts.iter().map(|host| host.to_ascii_lowercase()).collect(),
+            internal_hosts: config.internal_hosts.iter().map(|host| host.to_ascii_lowercase()).collect(),
+            substitutions: config
+                .substitutions
+                .into_iter()
+                .map(|(family, stack)| (family.to_ascii_lowercase(), stack))
+                .collect(),
+            ..config
+        };
+        Self { config }
+    }
+
+    fn is_remote(&self, url: &str) -> bool {
+        markup::url_host(url).is_some_and(|host| !self.config.internal_hosts.contains(&host))
+    }
+
+    fn is_font_host(&self, url: &str) -> bool {
+        markup::url_host(url).is_some_and(|host| self.config.blocked_font_hosts.contains(&host))
+    }
+
+    /// Remove remote `@font-face` rules and font-host `@import`s, then point
+    /// `font-family` declarations naming the removed fonts at local stacks
+    pub fn sanitize_css(&self, css: &str) -> String {
+        let mut removals = Vec::new();
+        let mut blocked = HashSet::new();
+
+        let mut pos = 0;
+        while let Some(start) = find_ignore_case(css, pos, "@font-face") {
+            let Some(open) = css[start..].find('{').map(|offset| start + offset) else { break };
+            let Some(close) = css[open..].find('}').map(|offset| open + offset) else { break };
+            let body = &css[open + 1..close];
+            if css_urls(body).iter().any(|url| self.is_remote(url)) {
+                if let Some(family) = declaration_value(body, 0, "font-family") {
+                    blocked.insert(unquote(&body[family]).to_ascii_lowercase());
+                }
+                removals.push(start..close + 1);
+            }
+            pos = close + 1;
+        }
+
+        let mut pos = 0;
+        while let Some(start) = find_ignore_case(css, pos, "@import") {
+            let end = css[start..].find(';').map_or(css.len(), |offset| start + offset + 1);
+            let rule = &css[start + "@import".len()..end];
+            let target = css_urls(rule).first().copied().unwrap_or_else(|| unquote(rule.trim_end_matches(';')));
+            if self.is_font_host(target) {
+                removals.push(start..end);
+            }
+            pos = end;
+        }
+
+        // An `@import` can sit inside a removed `@font-face` or run on into one,
+        // so merge overlapping removals before splicing
+        removals.sort_by_key(|range| range.start);
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(removals.len());
+        for range in removals {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        let css = markup::splice(css, merged.into_iter().map(|range| (range, String::new())).collect());
+        self.substitute_families(&css, &blocked)
+    }
+
+    fn substitute_families(&self, css: &str, blocked: &HashSet<String>) -> String {
+        let mut edits = Vec::new();
+        let mut pos = 0;
+        while let Some(value) = declaration_value(css, pos, "font-family") {
+            pos = value.end;
+            let families: Vec<&str> = css[value.clone()].split(',').map(str::trim).collect();
+            let mut changed = false;
+            let replaced: Vec<&str> = families
+                .iter()
+                .map(|family| {
+                    let name = unquote(family).to_ascii_lowercase();
+                    if let Some(stack) = self.config.substitutions.get(&name) {
+                        changed = true;
+                        stack.as_str()
+                    } else if blocked.contains(&name) {
+                        changed = true;
+                        self.config.fallback_stack.as_str()
+                    } else {
+                        family
+                    }
+                })
+                .collect();
+            if changed {
+                // Keep the whitespace around the value as it was
+                let raw = &css[value.clone()];
+                let leading = raw.len() - raw.trim_start().len();
+                let trailing = raw.len() - raw.trim_end().len();
+                edits.push((value.start + leading..value.end - trailing, replaced.join(", ")));
+            }
+        }
+        markup::splice(css, edits)
+    }
+
+    fn blocks_link(&self, tag: &markup::StartTag) -> bool {
+        let Some(href) = tag.attribute("href") else { return false };
+        let preloads_font = tag.attribute("as").is_some_and(|kind| kind.value.eq_ignore_ascii_case("font"));
+        self.is_font_host(&href.value) || (preloads_font && self.is_remote(&href.value))
+    }
+
+    /// Drop font-host `<link>`s and sanitize `<style>` blocks and `style` attributes
+    pub fn sanitize_html(&self, html: &str) -> String {
+        let mut edits = Vec::new();
+        for tag in markup::start_tags(html) {
+            if tag.name == "link" && self.blocks_link(&tag) {
+                edits.push((tag.span.clone(), String::new()));
+                continue;
+            }
+            if let Some(style) = tag.attribute("style") {
+                let sanitized = self.sanitize_css(&style.value);
+                if sanitized != style.value {
+                    edits.push((style.span.clone(), format!("style=\"{}\"", sanitized.replace('"', "&quot;"))));
+                }
+            }
+            if let Some(content) = tag.content.clone().filter(|_| tag.name == "style") {
+                let sanitized = self.sanitize_css(&html[content.clone()]);
+                if sanitized != html[content.clone()] {
+                    edits.push((content, sanitized));
+                }
+            }
+        }
+        markup::splice(html, edits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remote_font_face_is_removed() {
+        let protection = FontProtection::new(FontProtectionConfig {
+            internal_hosts: vec!["example.onion".to_string()],
+            ..FontProtectionConfig::default()
+        });
+        let css = "@font-face { font-family: 'Tracker Sans'; src: url(\"https://fonts.example.net/t.woff2\") format('woff2'); }\n\
+                   @FONT-FACE { font-family: Local; src: url(/fonts/local.woff2), url(http://example.onion/l.woff); }\n\
+                   body { font-family: \"Tracker Sans\", Local, serif; }";
+
+        assert_eq!(
+            protection.sanitize_css(css),
+            "\n@FONT-FACE { font-family: Local; src: url(/fonts/local.woff2), url(http://example.onion/l.woff); }\n\
+             body { font-family: sans-serif, Local, serif; }",
+        );
+    }
+
+    #[test]
+    fn test_google_fonts_are_stripped() {
+        let mut substitutions = HashMap::new();
+        substitutions.insert("Roboto".to_string(), "system-ui, sans-serif".to_string());
+        let protection = FontProtection::new(FontProtectionConfig { substitutions, ..FontProtectionConfig::default() });
+
+        let html = "<head><link rel=\"preconnect\" href=\"https://fonts.gstatic.com\">\
+                    <link href=\"https://fonts.googleapis.com/css2?family=Roboto&display=swap\" rel=\"stylesheet\">\
+                    <link rel=\"stylesheet\" href=\"/site.css\">\
+                    <style>@import url('https://fonts.googleapis.com/css?family=Lato');\nh1 { font-family:Roboto }</style></head>\
+                    <p style='font-family: \"Roboto\"; color: red'>hi</p>";
+
+        assert_eq!(
+            protection.sanitize_html(html),
+            "<head><link rel=\"stylesheet\" href=\"/site.css\">\
+             <style>\nh1 { font-family:system-ui, sans-serif }</style></head>\
+             <p style=\"font-family: system-ui, sans-serif; color: red\">hi</p>",
+        );
+    }
+
+    #[test]
+    fn test_local_fonts_are_untouched() {
+        let protection = FontProtection::default();
+        let html = "<style>@font-face { font-family: Mine; src: url(mine.woff2); } p { font-family: Mine; }</style>";
+        assert_eq!(protection.sanitize_html(html), html);
+    }
+
+    #[test]
+    fn test_overlapping_removals_do_not_panic() {
+        let protection = FontProtection::default();
+
+        // An import commented out inside a removed font face
+        let css = "@font-face { /* @import url(https://fonts.googleapis.com/y); */ src: url(https://fonts.gstatic.com/x.woff2); } p {}";
+        assert_eq!(protection.sanitize_css(css), " p {}");
+
+        // An unterminated import that runs on into the next rule
+        let css = "@import url(https://fonts.googleapis.com/y) @font-face{ src: url(https://fonts.gstatic.com/x.woff2) } ; p {}";
+        assert_eq!(protection.sanitize_css(css), " p {}");
+    }
+}
@@ -1,5 +1,495 @@
 //! Image Metadata Removal Module
-//! 
-//! Auto-strip EXIF and other identifying data from images
+//!
+//! Auto-strip EXIF and other identifying data from images. Metadata is removed
+//! at the container level, without decoding, so the compressed image data is
+//! copied through byte for byte and the pixels cannot change:
+//!
+//! - JPEG: APP1 (EXIF, XMP), APP13 (IPTC) and comment segments are dropped
+//! - PNG: `tEXt`, `iTXt`, `zTXt`, `tIME` and `eXIf` chunks are dropped
+//! - WebP: `EXIF` and `XMP ` chunks are dropped and the `VP8X` flags cleared
+//!
+//! For anything else, or when container surgery can't be trusted,
+//! [`reencode_strip`] decodes the image and encodes it afresh instead.
 
-// TODO: Implement image metadata removal functionality
+use image::ImageOutputFormat;
+use std::error::Error;
+use std::fmt;
+use std::io::Cursor;
+
+const JPEG_SOI: [u8; 2] = [0xFF, 0xD8];
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+const APP1: u8 = 0xE1;
+const APP13: u8 = 0xED;
+const COM: u8 = 0xFE;
+/// Start of scan; entropy-coded data follows until the end of the file
+const SOS: u8 = 0xDA;
+const EOI: u8 = 0xD9;
+
+const STRIPPED_PNG_CHUNKS: &[&[u8; 4]] = &[b"tEXt", b"iTXt", b"zTXt", b"tIME", b"eXIf"];
+
+const STRIPPED_WEBP_CHUNKS: &[&[u8; 4]] = &[b"EXIF", b"XMP "];
+/// `VP8X` flag bits announcing EXIF and XMP chunks
+const VP8X_EXIF_FLAG: u8 = 0x08;
+const VP8X_XMP_FLAG: u8 = 0x04;
+
+/// EXIF pointer to the GPS sub-directory
+const GPS_IFD_TAG: u16 = 0x8825;
+
+/// Errors raised while stripping metadata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageMetadataError {
+    /// Not a JPEG, PNG or WebP
+    UnsupportedFormat,
+    /// A segment or chunk runs past the end of the file
+    Malformed(String),
+    /// Decoding or encoding failed while re-encoding
+    Codec(String),
+}
+
+impl fmt::Display for ImageMetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageMetadataError::UnsupportedFormat => write!(f, "Image is not a JPEG, PNG or WebP"),
+            ImageMetadataError::Malformed(msg) => write!(f, "Malformed image: {}", msg),
+            ImageMetadataError::Codec(msg) => write!(f, "Failed to re-encode image: {}", msg),
+        }
+    }
+}
+
+impl Error for ImageMetadataError {}
+
+/// One marker segment of a JPEG header
+struct JpegSegment<'a> {
+    marker: u8,
+    /// The whole segment, marker included
+    raw: &'a [u8],
+    /// The segment body after its length field
+    body: &'a [u8],
+}
+
+/// Split a JPEG into its header segments and everything from the first scan on
+fn jpeg_segments(image: &[u8]) -> Result<(Vec<JpegSegment<'_>>, &[u8]), ImageMetadataError> {
+    let mut segments = Vec::new();
+    let mut pos = JPEG_SOI.len();
+
+    loop {
+        let start = pos;
+        // Markers may be preceded by any number of 0xFF fill bytes
+        while image.get(pos) == Some(&0xFF) {
+            pos += 1;
+        }
+        if pos == start {
+            return Err(ImageMetadataError::Malformed(format!("expected a marker at offset {}", pos)));
+        }
+        let marker = *image
+            .get(pos)
+            .ok_or_else(|| ImageMetadataError::Malformed("file ends before the image data".to_string()))?;
+        pos += 1;
+
+        match marker {
+            SOS => return Ok((segments, &image[start..])),
+            EOI => return Ok((segments, &image[start..])),
+            // Standalone markers carry no length
+            0x01 | 0xD0..=0xD7 => {
+                segments.push(JpegSegment { marker, raw: &image[start..pos], body: &[] });
+            }
+            _ => {
+                let length = image
+                    .get(pos..pos + 2)
+                    .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+                    .filter(|length| *length >= 2)
+                    .ok_or_else(|| ImageMetadataError::Malformed(format!("bad length for marker {:#04x}", marker)))?;
+                let end = pos + length;
+                if end > image.len() {
+                    return Err(ImageMetadataError::Malformed(format!(
+                        "marker {:#04x} runs past the end of the file",
+                        marker
+                    )));
+                }
+                segments.push(JpegSegment { marker, raw: &image[start..end], body: &image[pos + 2..end] });
+                pos = end;
+            }
+        }
+    }
+}
+
+/// One PNG chunk
+struct PngChunk<'a> {
+    kind: [u8; 4],
+    /// The whole chunk, length and CRC included
+    raw: &'a [u8],
+    data: &'a [u8],
+}
+
+fn png_chunks(image: &[u8]) -> Result<Vec<PngChunk<'_>>, ImageMetadataError> {
+    let mut chunks = Vec::new();
+    let mut pos = PNG_SIGNATURE.len();
+
+    while pos < image.len() {
+        let header = image
+            .get(pos..pos + 8)
+            .ok_or_else(|| ImageMetadataError::Malformed(format!("truncated chunk header at offset {}", pos)))?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let kind = [header[4], header[5], header[6], header[7]];
+        // Length, type, data and CRC
+        let end = pos
+            .checked_add(12)
+            .and_then(|end| end.checked_add(length))
+            .filter(|end| *end <= image.len())
+            .ok_or_else(|| {
+                ImageMetadataError::Malformed(format!(
+                    "chunk {} runs past the end of the file",
+                    String::from_utf8_lossy(&kind)
+                ))
+            })?;
+        chunks.push(PngChunk { kind, raw: &image[pos..end], data: &image[pos + 8..end - 4] });
+        pos = end;
+        if &kind == b"IEND" {
+            break;
+        }
+    }
+    Ok(chunks)
+}
+
+fn is_webp(image: &[u8]) -> bool {
+    image.len() >= 12 && &image[..4] == b"RIFF" && &image[8..12] == b"WEBP"
+}
+
+/// One chunk of a WebP RIFF container
+struct RiffChunk<'a> {
+    kind: [u8; 4],
+    /// The whole chunk, header and padding byte included
+    raw: &'a [u8],
+    data: &'a [u8],
+}
+
+fn webp_chunks(image: &[u8]) -> Result<Vec<RiffChunk<'_>>, ImageMetadataError> {
+    let riff_size = u32::from_le_bytes([image[4], image[5], image[6], image[7]]) as usize;
+    let end = riff_size
+        .checked_add(8)
+        .filter(|end| *end <= image.len())
+        .ok_or_else(|| ImageMetadataError::Malformed("RIFF container runs past the end of the file".to_string()))?;
+
+    let mut chunks = Vec::new();
+    let mut pos = 12;
+    while pos < end {
+        let header = image
+            .get(pos..pos + 8)
+            .filter(|_| pos + 8 <= end)
+            .ok_or_else(|| ImageMetadataError::Malformed(format!("truncated chunk header at offset {}", pos)))?;
+        let kind = [header[0], header[1], header[2], header[3]];
+        let length = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let data_end = pos
+            .checked_add(8)
+            .and_then(|start| start.checked_add(length))
+            .filter(|data_end| *data_end <= end)
+            .ok_or_else(|| {
+                ImageMetadataError::Malformed(format!(
+                    "chunk {} runs past the end of the file",
+                    String::from_utf8_lossy(&kind)
+                ))
+            })?;
+        // Chunks are padded to an even length
+        let chunk_end = (data_end + length % 2).min(end);
+        chunks.push(RiffChunk { kind, raw: &image[pos..chunk_end], data: &image[pos + 8..data_end] });
+        pos = chunk_end;
+    }
+    Ok(chunks)
+}
+
+/// Remove identifying metadata, keeping the encoded image data untouched
+pub fn strip_metadata(image: &[u8]) -> Result<Vec<u8>, ImageMetadataError> {
+    if image.starts_with(&JPEG_SOI) {
+        let (segments, scan) = jpeg_segments(image)?;
+        let mut out = Vec::with_capacity(image.len());
+        out.extend_from_slice(&JPEG_SOI);
+        for segment in segments.iter().filter(|segment| !matches!(segment.marker, APP1 | APP13 | COM)) {
+            out.extend_from_slice(segment.raw);
+        }
+        out.extend_from_slice(scan);
+        Ok(out)
+    } else if image.starts_with(&PNG_SIGNATURE) {
+        let mut out = Vec::with_capacity(image.len());
+        out.extend_from_slice(&PNG_SIGNATURE);
+        for chunk in png_chunks(image)? {
+            if !STRIPPED_PNG_CHUNKS.contains(&&chunk.kind) {
+                out.extend_from_slice(chunk.raw);
+            }
+        }
+        Ok(out)
+    } else if is_webp(image) {
+        let mut out = Vec::with_capacity(image.len());
+        out.extend_from_slice(b"RIFF\0\0\0\0WEBP");
+        for chunk in webp_chunks(image)? {
+            if STRIPPED_WEBP_CHUNKS.contains(&&chunk.kind) {
+                continue;
+            }
+            let start = out.len();
+            out.extend_from_slice(chunk.raw);
+            // The extended header must not announce the chunks just dropped
+            if &chunk.kind == b"VP8X" && chunk.data.len() >= 4 {
+                out[start + 8] &= !(VP8X_EXIF_FLAG | VP8X_XMP_FLAG);
+            }
+        }
+        let riff_size = (out.len() - 8) as u32;
+        out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        Ok(out)
+    } else {
+        Err(ImageMetadataError::UnsupportedFormat)
+    }
+}
+
+/// Remove all metadata by decoding the image and encoding it again as `format`
+///
+/// Unlike [`strip_metadata`] this accepts any format the `image` crate can
+/// read, and nothing from the original container survives. Dimensions are
+/// kept, but lossy formats lose a little quality, so pick the JPEG quality
+/// accordingly; WebP output is lossless.
+pub fn reencode_strip(image: &[u8], format: ImageOutputFormat) -> Result<Vec<u8>, ImageMetadataError> {
+    let decoded = image::load_from_memory(image).map_err(|e| ImageMetadataError::Codec(e.to_string()))?;
+    let mut out = Vec::new();
+    decoded
+        .write_to(&mut Cursor::new(&mut out), format)
+        .map_err(|e| ImageMetadataError::Codec(e.to_string()))?;
+    Ok(out)
+}
+
+/// True when the TIFF structure in an EXIF block points at a non-empty GPS directory
+fn tiff_has_gps(tiff: &[u8]) -> bool {
+    let little_endian = match tiff.get(..2) {
+        Some(b"II") => true,
+        Some(b"MM") => false,
+        _ => return false,
+    };
+    let u16_at = |offset: usize| {
+        tiff.get(offset..offset + 2).map(|bytes| {
+            let bytes = [bytes[0], bytes[1]];
+            if little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) }
+        })
+    };
+    let u32_at = |offset: usize| {
+        tiff.get(offset..offset + 4).map(|bytes| {
+            let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            if little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) }
+        })
+    };
+
+    if u16_at(2) != Some(42) {
+        return false;
+    }
+    let Some(ifd0) = u32_at(4).map(|offset| offset as usize) else { return false };
+    let Some(entries) = u16_at(ifd0) else { return false };
+
+    (0..entries as usize).any(|index| {
+        let entry = ifd0 + 2 + index * 12;
+        u16_at(entry) == Some(GPS_IFD_TAG)
+            && u32_at(entry + 8).and_then(|offset| u16_at(offset as usize)).is_some_and(|count| count > 0)
+    })
+}
+
+/// True when the image carries EXIF GPS tags
+pub fn has_gps(image: &[u8]) -> bool {
+    if image.starts_with(&JPEG_SOI) {
+        let Ok((segments, _)) = jpeg_segments(image) else { return false };
+        segments
+            .iter()
+            .filter(|segment| segment.marker == APP1)
+            .filter_map(|segment| segment.body.strip_prefix(b"Exif\0\0"))
+            .any(tiff_has_gps)
+    } else if image.starts_with(&PNG_SIGNATURE) {
+        let Ok(chunks) = png_chunks(image) else { return false };
+        chunks.iter().filter(|chunk| &chunk.kind == b"eXIf").any(|chunk| tiff_has_gps(chunk.data))
+    } else if is_webp(image) {
+        let Ok(chunks) = webp_chunks(image) else { return false };
+        // Some writers keep the JPEG-style `Exif` prefix
+        chunks
+            .iter()
+            .filter(|chunk| &chunk.kind == b"EXIF")
+            .any(|chunk| tiff_has_gps(chunk.data.strip_prefix(b"Exif\0\0").unwrap_or(chunk.data)))
+    } else {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageOutputFormat, Rgb, RgbImage};
+    use std::io::Cursor;
+
+    /// Little-endian TIFF with one IFD0 entry pointing at a GPS directory holding GPSLatitudeRef
+    fn exif_with_gps() -> Vec<u8> {
+        let mut tiff = b"II".to_vec();
+        tiff.extend_from_slice(&42u16.to_le_bytes());
+        tiff.extend_from_slice(&8u32.to_le_bytes());
+        // IFD0: one entry, then the next-IFD offset
+        tiff.extend_from_slice(&1u16.to_le_bytes());
+        tiff.extend_from_slice(&GPS_IFD_TAG.to_le_bytes());
+        tiff.extend_from_slice(&4u16.to_le_bytes());
+        tiff.extend_from_slice(&1u32.to_le_bytes());
+        tiff.extend_from_slice(&26u32.to_le_bytes());
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        // GPS IFD at offset 26: GPSLatitudeRef = "N"
+        tiff.extend_from_slice(&1u16.to_le_bytes());
+        tiff.extend_from_slice(&1u16.to_le_bytes());
+        tiff.extend_from_slice(&2u16.to_le_bytes());
+        tiff.extend_from_slice(&2u32.to_le_bytes());
+        tiff.extend_from_slice(b"N\0\0\0");
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        tiff
+    }
+
+    fn fixture_image() -> RgbImage {
+        RgbImage::from_fn(16, 16, |x, y| Rgb([(x * 16) as u8, (y * 16) as u8, 128]))
+    }
+
+    fn encode(format: ImageOutputFormat) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        fixture_image().write_to(&mut Cursor::new(&mut bytes), format).unwrap();
+        bytes
+    }
+
+    fn jpeg_with_metadata() -> Vec<u8> {
+        let jpeg = encode(ImageOutputFormat::Jpeg(90));
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend_from_slice(&exif_with_gps());
+
+        let mut out = JPEG_SOI.to_vec();
+        for (marker, body) in [(APP1, app1), (APP13, b"Photoshop 3.0\0".to_vec()), (COM, b"taken by alice".to_vec())] {
+            out.extend_from_slice(&[0xFF, marker]);
+            out.extend_from_slice(&((body.len() + 2) as u16).to_be_bytes());
+            out.extend_from_slice(&body);
+        }
+        out.extend_from_slice(&jpeg[2..]);
+        out
+    }
+
+    fn crc32(bytes: &[u8]) -> u32 {
+        let mut crc = 0xFFFF_FFFFu32;
+        for byte in bytes {
+            crc ^= *byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            }
+        }
+        !crc
+    }
+
+    fn png_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(data);
+        chunk.extend_from_slice(&crc32(&chunk[4..]).to_be_bytes());
+        chunk
+    }
+
+    fn png_with_metadata() -> Vec<u8> {
+        let png = encode(ImageOutputFormat::Png);
+        // Signature plus the 25-byte IHDR chunk
+        let (head, rest) = png.split_at(8 + 25);
+        let mut out = head.to_vec();
+        out.extend_from_slice(&png_chunk(b"eXIf", &exif_with_gps()));
+        out.extend_from_slice(&png_chunk(b"tEXt", b"Author\0alice"));
+        out.extend_from_slice(&png_chunk(b"tIME", &[0x07, 0xE8, 1, 2, 3, 4, 5]));
+        out.extend_from_slice(rest);
+        out
+    }
+
+    fn riff_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = kind.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    /// Lossless WebP promoted to the extended format, with odd-length EXIF and XMP chunks
+    fn webp_with_metadata() -> Vec<u8> {
+        let webp = encode(ImageOutputFormat::WebP);
+        let mut vp8x = vec![VP8X_EXIF_FLAG | VP8X_XMP_FLAG, 0, 0, 0];
+        vp8x.extend_from_slice(&15u32.to_le_bytes()[..3]);
+        vp8x.extend_from_slice(&15u32.to_le_bytes()[..3]);
+
+        let mut out = b"RIFF\0\0\0\0WEBP".to_vec();
+        out.extend_from_slice(&riff_chunk(b"VP8X", &vp8x));
+        out.extend_from_slice(&webp[12..]);
+        out.extend_from_slice(&riff_chunk(b"EXIF", &exif_with_gps()));
+        out.extend_from_slice(&riff_chunk(b"XMP ", b"<x:xmpmeta>alice</x:xmpmeta>!"));
+        let riff_size = (out.len() - 8) as u32;
+        out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        out
+    }
+
+    fn pixels(bytes: &[u8]) -> Vec<u8> {
+        image::load_from_memory(bytes).unwrap().to_rgb8().into_raw()
+    }
+
+    #[test]
+    fn test_jpeg_gps_is_detected_and_removed() {
+        let original = jpeg_with_metadata();
+        assert!(has_gps(&original));
+
+        let stripped = strip_metadata(&original).unwrap();
+        assert!(!has_gps(&stripped));
+        assert!(!stripped.windows(4).any(|window| window == b"Exif"));
+        assert!(!stripped.windows(5).any(|window| window == b"alice"));
+        assert_eq!(pixels(&stripped), pixels(&original));
+        assert_eq!(strip_metadata(&stripped).unwrap(), stripped);
+    }
+
+    #[test]
+    fn test_png_text_and_exif_chunks_are_removed() {
+        let original = png_with_metadata();
+        assert!(has_gps(&original));
+
+        let stripped = strip_metadata(&original).unwrap();
+        assert!(!has_gps(&stripped));
+        assert_eq!(stripped, encode(ImageOutputFormat::Png));
+        assert_eq!(pixels(&stripped), fixture_image().into_raw());
+    }
+
+    #[test]
+    fn test_webp_exif_and_xmp_chunks_are_removed() {
+        let original = webp_with_metadata();
+        assert!(has_gps(&original));
+        assert_eq!(pixels(&original), fixture_image().into_raw());
+
+        let stripped = strip_metadata(&original).unwrap();
+        assert!(!has_gps(&stripped));
+        assert!(!stripped.windows(5).any(|window| window == b"alice"));
+        let chunks = webp_chunks(&stripped).unwrap();
+        let kinds: Vec<&[u8; 4]> = chunks.iter().map(|chunk| &chunk.kind).collect();
+        assert_eq!(kinds, [b"VP8X", b"VP8L"]);
+        assert_eq!(chunks[0].data[0] & (VP8X_EXIF_FLAG | VP8X_XMP_FLAG), 0);
+        assert_eq!(pixels(&stripped), fixture_image().into_raw());
+        assert_eq!(strip_metadata(&stripped).unwrap(), stripped);
+    }
+
+    #[test]
+    fn test_reencode_drops_metadata_and_keeps_dimensions() {
+        let webp = reencode_strip(&webp_with_metadata(), ImageOutputFormat::WebP).unwrap();
+        assert!(!has_gps(&webp));
+        assert_eq!(pixels(&webp), fixture_image().into_raw());
+
+        let jpeg = reencode_strip(&jpeg_with_metadata(), ImageOutputFormat::Jpeg(90)).unwrap();
+        assert!(!has_gps(&jpeg));
+        assert!(!jpeg.windows(4).any(|window| window == b"Exif"));
+        let decoded = image::load_from_memory(&jpeg).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (16, 16));
+
+        assert!(matches!(reencode_strip(b"not an image", ImageOutputFormat::Png), Err(ImageMetadataError::Codec(_))));
+    }
+
+    #[test]
+    fn test_rejects_other_and_truncated_input() {
+        assert_eq!(strip_metadata(b"GIF89a"), Err(ImageMetadataError::UnsupportedFormat));
+        assert!(matches!(strip_metadata(&jpeg_with_metadata()[..10]), Err(ImageMetadataError::Malformed(_))));
+        assert!(matches!(strip_metadata(&png_with_metadata()[..40]), Err(ImageMetadataError::Malformed(_))));
+        assert!(matches!(strip_metadata(&webp_with_metadata()[..40]), Err(ImageMetadataError::Malformed(_))));
+        assert!(!has_gps(b"not an image"));
+        assert!(!has_gps(&encode(ImageOutputFormat::Jpeg(90))));
+    }
+}
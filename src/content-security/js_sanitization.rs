@@ -1,5 +1,236 @@
 //! JavaScript Sanitization Module
-//! 
-//! Strip or modify JS that could compromise anonymity
+//!
+//! Strip or modify JS that could compromise anonymity. HTML is parsed with a real
+//! HTML5 parser and re-serialized from an allowlist: tags outside it are unwrapped
+//! (their text survives), script-bearing elements are dropped along with their
+//! content, `on*` handlers never survive, and `javascript:` URLs are removed.
 
-// TODO: Implement JavaScript sanitization functionality
+use scraper::{ElementRef, Html, Node};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Elements whose content is dropped along with them when not allowlisted
+const DROP_WITH_CONTENT: &[&str] = &[
+    "script", "style", "iframe", "frame", "frameset", "object", "embed", "applet", "noscript", "noembed",
+    "noframes", "template", "xmp", "title", "textarea",
+];
+
+/// Elements whose text content is written as-is rather than escaped
+const RAW_TEXT: &[&str] = &["style", "script", "xmp", "noembed", "noframes", "noscript"];
+
+/// Elements with no closing tag
+const VOID: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr",
+];
+
+/// Attributes whose value is loaded or navigated to as a URL
+const URL_ATTRIBUTES: &[&str] = &["href", "src", "action", "formaction", "poster", "background", "cite"];
+
+/// Tags and attributes allowed through sanitization
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JsSanitizerConfig {
+    pub allowed_tags: HashSet<String>,
+    /// Allowed on any allowed tag; `on*` handlers are stripped even if listed
+    pub allowed_attributes: HashSet<String>,
+}
+
+impl Default for JsSanitizerConfig {
+    fn default() -> Self {
+        let tags = [
+            "html", "head", "body", "title", "meta", "link", "style", "a", "abbr", "b", "blockquote", "br",
+            "code", "dd", "div", "dl", "dt", "em", "h1", "h2", "h3", "h4", "h5", "h6", "hr", "i", "img", "li",
+            "ol", "p", "pre", "s", "small", "span", "strong", "sub", "sup", "table", "tbody", "td", "th",
+            "thead", "tr", "u", "ul",
+        ];
+        let attributes = [
+            "href", "src", "alt", "title", "class", "id", "lang", "dir", "width", "height", "colspan",
+            "rowspan", "rel", "target", "type", "charset", "name", "content",
+        ];
+        Self {
+            allowed_tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            allowed_attributes: attributes.iter().map(|attribute| attribute.to_string()).collect(),
+        }
+    }
+}
+
+/// True for URLs a browser would run as script
+fn is_script_url(value: &str) -> bool {
+    // Browsers ignore tabs and newlines anywhere in a URL, and leading controls and spaces
+    let cleaned: String = value
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .skip_while(|c| c.is_ascii_control() || *c == ' ')
+        .collect::<String>()
+        .to_ascii_lowercase();
+    cleaned.starts_with("javascript:") || cleaned.starts_with("vbscript:")
+}
+
+pub(crate) fn escape_text(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+pub(crate) fn escape_attribute(value: &str, out: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+/// Full documents keep their doctype and `<html>` element; anything else is a fragment
+pub(crate) fn is_document(input: &str) -> bool {
+    let start: String = input.trim_start().chars().take(9).collect::<String>().to_ascii_lowercase();
+    start.starts_with("<!doctype") || start.starts_with("<html")
+}
+
+/// Allowlist-driven HTML sanitizer
+pub struct JsSanitizer {
+    config: JsSanitizerConfig,
+}
+
+impl Default for JsSanitizer {
+    fn default() -> Self {
+        Self::new(JsSanitizerConfig::default())
+    }
+}
+
+impl JsSanitizer {
+    pub fn new(config: JsSanitizerConfig) -> Self {
+        Self { config }
+    }
+
+    /// Parse `input` and re-serialize only what the allowlist permits
+    pub fn sanitize_html(&self, input: &str) -> String {
+        let mut out = String::with_capacity(input.len());
+        if is_document(input) {
+            let html = Html::parse_document(input);
+            out.push_str("<!DOCTYPE html>");
+            self.write_element(html.root_element(), &mut out);
+        } else {
+            // Fragments are parsed inside a synthetic <html> element
+            let html = Html::parse_fragment(input);
+            self.write_children(html.root_element(), &mut out);
+        }
+        out
+    }
+
+    fn write_children(&self, element: ElementRef<'_>, out: &mut String) {
+        let raw = RAW_TEXT.contains(&element.value().name());
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) if raw => out.push_str(text),
+                Node::Text(text) => escape_text(text, out),
+                Node::Element(_) => {
+                    if let Some(child) = ElementRef::wrap(child) {
+                        self.write_element(child, out);
+                    }
+                }
+                // Comments can hide conditional markup for old browsers
+                _ => {}
+            }
+        }
+    }
+
+    fn write_element(&self, element: ElementRef<'_>, out: &mut String) {
+        let name = element.value().name();
+        if !self.config.allowed_tags.contains(name) {
+            if !DROP_WITH_CONTENT.contains(&name) {
+                self.write_children(element, out);
+            }
+            return;
+        }
+
+        out.push('<');
+        out.push_str(name);
+        for (attribute, value) in element.value().attrs() {
+            let attribute = attribute.to_ascii_lowercase();
+            if attribute.starts_with("on") || !self.config.allowed_attributes.contains(&attribute) {
+                continue;
+            }
+            if URL_ATTRIBUTES.contains(&attribute.as_str()) && is_script_url(value) {
+                continue;
+            }
+            out.push(' ');
+            out.push_str(&attribute);
+            out.push_str("=\"");
+            escape_attribute(value, out);
+            out.push('"');
+        }
+        out.push('>');
+
+        if VOID.contains(&name) {
+            return;
+        }
+        self.write_children(element, out);
+        out.push_str("</");
+        out.push_str(name);
+        out.push('>');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_handlers_are_stripped() {
+        let sanitizer = JsSanitizer::default();
+        let output = sanitizer.sanitize_html(
+            r#"<p class="intro" onclick="steal()">Hello <b onmouseover='x()'>world</b></p><img src="a.png" ONERROR="y()" alt="a">"#,
+        );
+        assert_eq!(output, r#"<p class="intro">Hello <b>world</b></p><img alt="a" src="a.png">"#);
+    }
+
+    #[test]
+    fn test_nested_scripts_are_removed() {
+        let sanitizer = JsSanitizer::default();
+        let output = sanitizer.sanitize_html(
+            "<div>before<script>alert(1)</script><section>inside<script>alert(2)</script></section>after</div>\
+             <scr<script>ipt>alert(3)</script>",
+        );
+        // The unknown <section> is unwrapped, keeping its text, and the broken tag
+        // only survives as escaped text
+        assert_eq!(output, "<div>beforeinsideafter</div>ipt&gt;alert(3)");
+    }
+
+    #[test]
+    fn test_javascript_links_are_neutralized() {
+        let sanitizer = JsSanitizer::default();
+        let output = sanitizer.sanitize_html(
+            "<a href=\"javascript:alert(1)\">one</a>\
+             <a href=\" JaVa&#x09;Script:alert(2)\">two</a>\
+             <a href=\"https://example.com/?q=a&amp;b\" title='say \"hi\"'>three</a>\
+             <img src=\"vbscript:run\">",
+        );
+        assert_eq!(
+            output,
+            "<a>one</a><a>two</a><a href=\"https://example.com/?q=a&amp;b\" title=\"say &quot;hi&quot;\">three</a><img>",
+        );
+    }
+
+    #[test]
+    fn test_documents_keep_their_structure() {
+        let sanitizer = JsSanitizer::new(JsSanitizerConfig {
+            allowed_tags: ["html", "head", "body", "title", "p"].iter().map(|tag| tag.to_string()).collect(),
+            allowed_attributes: HashSet::new(),
+        });
+        let output = sanitizer.sanitize_html(
+            "<!doctype html><html><head><title>a &lt; b</title><script src=x.js></script></head>\
+             <body><p id=x>text</p><iframe src=evil>fallback</iframe></body></html>",
+        );
+        assert_eq!(
+            output,
+            "<!DOCTYPE html><html><head><title>a &lt; b</title></head><body><p>text</p></body></html>",
+        );
+    }
+}
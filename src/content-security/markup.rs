@@ -0,0 +1,195 @@
+//! Lightweight start-tag scanner
+//!
+//! Link and font rewriting only touch a few tags, so instead of re-serializing a
+//! parsed tree (which normalizes the whole document) they locate tags here by byte
+//! offset and splice replacements into the original text.
+
+use std::ops::Range;
+
+/// Elements whose content is text up to the matching end tag
+const RAW_TEXT: &[&str] = &["script", "style", "textarea", "title", "xmp"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Attribute {
+    /// Lowercased
+    pub name: String,
+    /// As written, without quotes or entity decoding
+    pub value: String,
+    /// The attribute from its name to the end of its value
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct StartTag {
+    /// Lowercased
+    pub name: String,
+    /// From `<` to `>` inclusive
+    pub span: Range<usize>,
+    pub attributes: Vec<Attribute>,
+    /// Text between the tag and its end tag, for raw text elements like `<style>`
+    pub content: Option<Range<usize>>,
+}
+
+impl StartTag {
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attributes.iter().find(|attribute| attribute.name == name)
+    }
+
+    /// Where new attributes can be inserted: before `>` or `/>`
+    pub fn insertion_point(&self, html: &str) -> usize {
+        let end = self.span.end - 1;
+        if html[..end].ends_with('/') { end - 1 } else { end }
+    }
+}
+
+pub(crate) fn find_ignore_case(haystack: &str, from: usize, needle: &str) -> Option<usize> {
+    let needle = needle.as_bytes();
+    haystack.as_bytes()[from..]
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle))
+        .map(|offset| from + offset)
+}
+
+/// Every start tag in `html`, in document order, skipping comments and raw text
+pub(crate) fn start_tags(html: &str) -> Vec<StartTag> {
+    let bytes = html.as_bytes();
+    let mut tags = Vec::new();
+    let mut pos = 0;
+
+    while let Some(offset) = html[pos..].find('<') {
+        let start = pos + offset;
+        let rest = &html[start..];
+
+        if rest.starts_with("<!--") {
+            match html[start + 4..].find("-->") {
+                Some(end) => pos = start + 4 + end + 3,
+                None => break,
+            }
+            continue;
+        }
+        if !bytes.get(start + 1).is_some_and(|b| b.is_ascii_alphabetic()) {
+            // End tags, doctypes and stray '<' characters
+            pos = start + 1;
+            continue;
+        }
+
+        let mut cursor = start + 1;
+        while bytes.get(cursor).is_some_and(|b| b.is_ascii_alphanumeric() || *b == b'-' || *b == b':') {
+            cursor += 1;
+        }
+        let name = html[start + 1..cursor].to_ascii_lowercase();
+
+        let mut attributes = Vec::new();
+        let end = loop {
+            while bytes.get(cursor).is_some_and(|b| b.is_ascii_whitespace() || *b == b'/') {
+                cursor += 1;
+            }
+            match bytes.get(cursor) {
+                None => break None,
+                Some(b'>') => break Some(cursor + 1),
+                Some(_) => {}
+            }
+
+            let name_start = cursor;
+            while bytes
+                .get(cursor)
+                .is_some_and(|b| !b.is_ascii_whitespace() && !matches!(b, b'=' | b'>' | b'/'))
+            {
+                cursor += 1;
+            }
+            // A name can't be empty; a lone '=' just gets skipped
+            if cursor == name_start {
+                cursor += 1;
+                continue;
+            }
+            let attribute_name = html[name_start..cursor].to_ascii_lowercase();
+
+            let mut lookahead = cursor;
+            while bytes.get(lookahead).is_some_and(|b| b.is_ascii_whitespace()) {
+                lookahead += 1;
+            }
+            let mut value = String::new();
+            if bytes.get(lookahead) == Some(&b'=') {
+                cursor = lookahead + 1;
+                while bytes.get(cursor).is_some_and(|b| b.is_ascii_whitespace()) {
+                    cursor += 1;
+                }
+                match bytes.get(cursor) {
+                    Some(quote @ (b'"' | b'\'')) => {
+                        let Some(close) = html[cursor + 1..].find(*quote as char) else { break None };
+                        value = html[cursor + 1..cursor + 1 + close].to_string();
+                        cursor += close + 2;
+                    }
+                    _ => {
+                        let value_start = cursor;
+                        while bytes.get(cursor).is_some_and(|b| !b.is_ascii_whitespace() && *b != b'>') {
+                            cursor += 1;
+                        }
+                        value = html[value_start..cursor].to_string();
+                    }
+                }
+            }
+            attributes.push(Attribute { name: attribute_name, value, span: name_start..cursor });
+        };
+
+        let Some(end) = end else { break };
+        let mut tag = StartTag { name, span: start..end, attributes, content: None };
+        pos = end;
+        if RAW_TEXT.contains(&tag.name.as_str()) {
+            let close = find_ignore_case(html, end, &format!("</{}", tag.name)).unwrap_or(html.len());
+            tag.content = Some(end..close);
+            pos = close;
+        }
+        tags.push(tag);
+    }
+    tags
+}
+
+/// Host of an absolute or protocol-relative http(s) URL, lowercased
+pub(crate) fn url_host(url: &str) -> Option<String> {
+    let url = url.trim().to_ascii_lowercase();
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .or_else(|| url.strip_prefix("//"))?;
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    let host = host.split(':').next().unwrap_or_default();
+    Some(host.to_string())
+}
+
+/// Apply non-overlapping `(range, replacement)` edits, given in document order
+pub(crate) fn splice(html: &str, edits: Vec<(Range<usize>, String)>) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut pos = 0;
+    for (range, replacement) in edits {
+        out.push_str(&html[pos..range.start]);
+        out.push_str(&replacement);
+        pos = range.end;
+    }
+    out.push_str(&html[pos..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scans_tags_and_attributes() {
+        let html = "<!-- <a href=x> --><P class=\"a b\" data-x='1' hidden><br/><style>a{}<b></style ><img src=y.png />";
+        let tags = start_tags(html);
+        let names: Vec<&str> = tags.iter().map(|tag| tag.name.as_str()).collect();
+        assert_eq!(names, ["p", "br", "style", "img"]);
+
+        let p = &tags[0];
+        assert_eq!(p.attribute("class").unwrap().value, "a b");
+        assert_eq!(p.attribute("data-x").unwrap().value, "1");
+        assert_eq!(p.attribute("hidden").unwrap().value, "");
+        assert_eq!(&html[p.attribute("class").unwrap().span.clone()], "class=\"a b\"");
+
+        assert_eq!(&html[tags[2].content.clone().unwrap()], "a{}<b>");
+        assert_eq!(tags[3].attribute("src").unwrap().value, "y.png");
+        assert_eq!(&html[tags[3].insertion_point(html)..], "/>");
+    }
+}
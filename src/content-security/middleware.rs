@@ -0,0 +1,209 @@
+//! Tower middleware for response sanitization
+//!
+//! `ContentSecurityLayer` runs `ContentSecurityManager::sanitize_response` on every
+//! response an inner service produces. Only bodies the manager rewrites (HTML,
+//! CSS, JPEG, PNG and WebP) are buffered; everything else streams through untouched
+//! apart from the added headers. A response that can't be sanitized, because it is
+//! too large, compressed, or malformed, is replaced with a 500 rather than sent as
+//! is. Place the layer inside any compression layer so it sees plain bodies.
+
+use crate::content_security::ContentSecurityManager;
+use axum::body::{Body, Bytes, HttpBody};
+use axum::http::header::{self, HeaderValue};
+use axum::http::{Request, Response, StatusCode};
+use axum::BoxError;
+use log::warn;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+/// Largest body buffered for sanitization by default
+pub const DEFAULT_BODY_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Content types whose bodies the manager rewrites
+fn is_sanitized(media_type: &str) -> bool {
+    matches!(media_type, "text/html" | "application/xhtml+xml" | "text/css" | "image/jpeg" | "image/png" | "image/webp")
+}
+
+fn media_type(content_type: &str) -> String {
+    content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase()
+}
+
+fn refuse(reason: &str) -> Response<Body> {
+    warn!("Refusing to send unsanitized response: {}", reason);
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+    response
+}
+
+/// Wraps services so their responses pass through a `ContentSecurityManager`
+#[derive(Clone)]
+pub struct ContentSecurityLayer {
+    manager: Arc<ContentSecurityManager>,
+    body_limit: usize,
+}
+
+impl ContentSecurityLayer {
+    pub fn new(manager: ContentSecurityManager) -> Self {
+        Self { manager: Arc::new(manager), body_limit: DEFAULT_BODY_LIMIT }
+    }
+
+    /// Refuse sanitizable bodies larger than `limit` bytes
+    pub fn with_body_limit(mut self, limit: usize) -> Self {
+        self.body_limit = limit;
+        self
+    }
+}
+
+impl<S> Layer<S> for ContentSecurityLayer {
+    type Service = ContentSecurityService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ContentSecurityService { inner, manager: Arc::clone(&self.manager), body_limit: self.body_limit }
+    }
+}
+
+/// Service produced by `ContentSecurityLayer`
+#[derive(Clone)]
+pub struct ContentSecurityService<S> {
+    inner: S,
+    manager: Arc<ContentSecurityManager>,
+    body_limit: usize,
+}
+
+async fn sanitize<B>(response: Response<B>, manager: &ContentSecurityManager, body_limit: usize) -> Response<Body>
+where
+    B: HttpBody<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let (mut parts, body) = response.into_parts();
+
+    if !is_sanitized(&media_type(&content_type)) {
+        // Headers still apply; the body streams through
+        let mut empty = Vec::new();
+        if let Err(e) = manager.sanitize_response(&mut parts.headers, &mut empty, &content_type) {
+            return refuse(&e.to_string());
+        }
+        return Response::from_parts(parts, Body::new(body));
+    }
+
+    let encoded = parts
+        .headers
+        .get(header::CONTENT_ENCODING)
+        .is_some_and(|encoding| encoding != HeaderValue::from_static("identity"));
+    if encoded {
+        return refuse("body is compressed");
+    }
+
+    let mut bytes = match axum::body::to_bytes(Body::new(body), body_limit).await {
+        Ok(bytes) => bytes.to_vec(),
+        Err(e) => return refuse(&format!("failed to read body: {}", e)),
+    };
+    // Bodiless responses such as 304s have nothing to clean
+    if !bytes.is_empty()
+        && let Err(e) = manager.sanitize_response(&mut parts.headers, &mut bytes, &content_type)
+    {
+        return refuse(&e.to_string());
+    }
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ContentSecurityService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+    ResBody: HttpBody<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let future = self.inner.call(request);
+        let manager = Arc::clone(&self.manager);
+        let body_limit = self.body_limit;
+        Box::pin(async move {
+            let response = future.await?;
+            Ok(sanitize(response, &manager, body_limit).await)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use tower::{service_fn, ServiceExt};
+
+    fn respond(content_type: &'static str, body: &'static str) -> impl Service<
+        Request<Body>,
+        Response = Response<Body>,
+        Error = Infallible,
+        Future = impl Future<Output = Result<Response<Body>, Infallible>> + Send,
+    > + Clone {
+        service_fn(move |_request: Request<Body>| async move {
+            let mut response = Response::new(Body::from(body));
+            response.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+            Ok::<_, Infallible>(response)
+        })
+    }
+
+    async fn body_text(response: Response<Body>) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_html_is_sanitized() {
+        let layer = ContentSecurityLayer::new(ContentSecurityManager::new().unwrap());
+        let service = layer.layer(respond("text/html", "<h1 onclick=\"x()\">Title</h1><script>steal()</script>"));
+
+        let response = service.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key(header::CONTENT_SECURITY_POLICY));
+        assert_eq!(body_text(response).await, "<h1>Title</h1>");
+    }
+
+    #[tokio::test]
+    async fn test_other_types_stream_through() {
+        let layer = ContentSecurityLayer::new(ContentSecurityManager::new().unwrap());
+        let service = layer.layer(respond("application/javascript", "alert(1)"));
+
+        let response = service.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(response.headers()[header::REFERRER_POLICY], "no-referrer");
+        assert_eq!(body_text(response).await, "alert(1)");
+    }
+
+    #[tokio::test]
+    async fn test_unsanitizable_bodies_are_refused() {
+        let layer = ContentSecurityLayer::new(ContentSecurityManager::new().unwrap()).with_body_limit(8);
+        let response = layer
+            .layer(respond("text/html", "<p>far more than eight bytes</p>"))
+            .oneshot(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let layer = ContentSecurityLayer::new(ContentSecurityManager::new().unwrap());
+        let response = layer
+            .layer(respond("image/png", "not a png"))
+            .oneshot(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
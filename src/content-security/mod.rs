@@ -1,11 +1,264 @@
 //! Content Security Module
-//! 
+//!
 //! This module provides comprehensive content security features to protect against various
 //! web-based attacks and privacy violations.
 
 pub mod js_sanitization;
+pub mod csp;
 pub mod image_metadata;
 pub mod referrer_policy;
 pub mod font_protection;
+pub mod middleware;
+mod markup;
 
-// TODO: Implement content security functionality
+use axum::http::header::{self, HeaderMap, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+
+use csp::CspBuilder;
+use font_protection::{FontProtection, FontProtectionConfig};
+use image_metadata::ImageMetadataError;
+use js_sanitization::JsSanitizer;
+use referrer_policy::{ReferrerPolicy, ReferrerPolicyManager};
+
+/// Common error types for content security features
+#[derive(Debug)]
+pub enum ContentSecurityError {
+    ConfigurationError(String),
+    /// The body doesn't match its content type, such as HTML that isn't UTF-8
+    InvalidContent(String),
+    MetadataError(ImageMetadataError),
+}
+
+impl fmt::Display for ContentSecurityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContentSecurityError::ConfigurationError(msg) => write!(f, "Configuration error: {}", msg),
+            ContentSecurityError::InvalidContent(msg) => write!(f, "Invalid content: {}", msg),
+            ContentSecurityError::MetadataError(e) => write!(f, "Metadata error: {}", e),
+        }
+    }
+}
+
+impl Error for ContentSecurityError {}
+
+impl From<ImageMetadataError> for ContentSecurityError {
+    fn from(e: ImageMetadataError) -> Self {
+        ContentSecurityError::MetadataError(e)
+    }
+}
+
+/// Result type for content security operations
+pub type ContentSecurityResult<T> = Result<T, ContentSecurityError>;
+
+/// Configuration for content security features
+///
+/// Deserializing fills any missing field from `Default`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContentSecurityConfig {
+    pub enable_js_sanitization: bool,
+    pub enable_csp: bool,
+    pub enable_image_metadata_removal: bool,
+    pub enable_referrer_policy: bool,
+    pub enable_font_protection: bool,
+    /// Sent instead of the strict default policy when set
+    pub content_security_policy: Option<String>,
+    pub referrer_policy: ReferrerPolicy,
+    /// The site's own hosts, for telling internal links and fonts from external ones
+    pub internal_hosts: Vec<String>,
+}
+
+impl Default for ContentSecurityConfig {
+    fn default() -> Self {
+        Self {
+            enable_js_sanitization: true,
+            enable_csp: true,
+            enable_image_metadata_removal: true,
+            enable_referrer_policy: true,
+            enable_font_protection: true,
+            content_security_policy: None,
+            referrer_policy: ReferrerPolicy::NoReferrer,
+            internal_hosts: Vec::new(),
+        }
+    }
+}
+
+/// Applies every enabled content security feature to a response
+pub struct ContentSecurityManager {
+    config: ContentSecurityConfig,
+    js_sanitizer: JsSanitizer,
+    font_protection: FontProtection,
+    referrer_policy: ReferrerPolicyManager,
+    csp_header: HeaderValue,
+}
+
+impl ContentSecurityManager {
+    /// Create a content security manager with default configuration
+    pub fn new() -> ContentSecurityResult<Self> {
+        Self::with_config(ContentSecurityConfig::default())
+    }
+
+    /// Create a content security manager with custom configuration
+    pub fn with_config(config: ContentSecurityConfig) -> ContentSecurityResult<Self> {
+        let policy = config.content_security_policy.clone().unwrap_or_else(|| CspBuilder::strict().build());
+        let csp_header = HeaderValue::from_str(&policy).map_err(|_| {
+            ContentSecurityError::ConfigurationError(format!("Content security policy {:?} is not a valid header", policy))
+        })?;
+
+        let referrer_policy = config
+            .internal_hosts
+            .iter()
+            .fold(ReferrerPolicyManager::new(config.referrer_policy), |manager, host| manager.with_internal_host(host));
+        let font_protection = FontProtection::new(FontProtectionConfig {
+            internal_hosts: config.internal_hosts.clone(),
+            ..FontProtectionConfig::default()
+        });
+
+        Ok(Self {
+            js_sanitizer: JsSanitizer::default(),
+            font_protection,
+            referrer_policy,
+            csp_header,
+            config,
+        })
+    }
+
+    pub fn config(&self) -> &ContentSecurityConfig {
+        &self.config
+    }
+
+    fn sanitize_html(&self, html: &str) -> String {
+        let mut html = if self.config.enable_js_sanitization {
+            self.js_sanitizer.sanitize_html(html)
+        } else {
+            html.to_string()
+        };
+        if self.config.enable_font_protection {
+            html = self.font_protection.sanitize_html(&html);
+        }
+        if self.config.enable_referrer_policy {
+            html = self.referrer_policy.rewrite_links(&html);
+        }
+        html
+    }
+
+    /// Clean a response in place according to its content type
+    ///
+    /// HTML is sanitized and gets a CSP header, CSS loses remote fonts, and JPEG,
+    /// PNG and WebP images lose their metadata. Every response gets the referrer
+    /// policy; other bodies are left alone.
+    pub fn sanitize_response(
+        &self,
+        headers: &mut HeaderMap,
+        body: &mut Vec<u8>,
+        content_type: &str,
+    ) -> ContentSecurityResult<()> {
+        let media_type = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        let original_len = body.len();
+
+        match media_type.as_str() {
+            "text/html" | "application/xhtml+xml" => {
+                let html = std::str::from_utf8(body)
+                    .map_err(|e| ContentSecurityError::InvalidContent(format!("HTML body is not UTF-8: {}", e)))?;
+                *body = self.sanitize_html(html).into_bytes();
+                if self.config.enable_csp {
+                    headers.insert(header::CONTENT_SECURITY_POLICY, self.csp_header.clone());
+                }
+            }
+            "text/css" if self.config.enable_font_protection => {
+                let css = std::str::from_utf8(body)
+                    .map_err(|e| ContentSecurityError::InvalidContent(format!("CSS body is not UTF-8: {}", e)))?;
+                *body = self.font_protection.sanitize_css(css).into_bytes();
+            }
+            "image/jpeg" | "image/png" | "image/webp" if self.config.enable_image_metadata_removal => {
+                *body = image_metadata::strip_metadata(body)?;
+            }
+            _ => {}
+        }
+
+        if self.config.enable_referrer_policy {
+            headers.insert(header::REFERRER_POLICY, HeaderValue::from_static(self.referrer_policy.header_value()));
+        }
+        if body.len() != original_len && headers.contains_key(header::CONTENT_LENGTH) {
+            headers.insert(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageOutputFormat, Rgb, RgbImage};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_html_response_is_sanitized() {
+        let manager = ContentSecurityManager::new().unwrap();
+        let mut headers = HeaderMap::new();
+        let mut body = b"<p onclick=\"x()\">Hi<script>alert(1)</script></p>".to_vec();
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+
+        manager.sanitize_response(&mut headers, &mut body, "text/html; charset=utf-8").unwrap();
+
+        assert_eq!(body, b"<p>Hi</p>");
+        assert_eq!(headers[header::CONTENT_SECURITY_POLICY], CspBuilder::strict().build().as_str());
+        assert_eq!(headers[header::REFERRER_POLICY], "no-referrer");
+        assert_eq!(headers[header::CONTENT_LENGTH], "9");
+    }
+
+    #[test]
+    fn test_jpeg_response_loses_exif() {
+        let mut jpeg = Vec::new();
+        RgbImage::from_pixel(8, 8, Rgb([10, 20, 30]))
+            .write_to(&mut Cursor::new(&mut jpeg), ImageOutputFormat::Jpeg(90))
+            .unwrap();
+        let exif = b"Exif\0\0II*\0\x08\0\0\0\0\0";
+        let mut body = jpeg[..2].to_vec();
+        body.extend_from_slice(&[0xFF, 0xE1]);
+        body.extend_from_slice(&((exif.len() + 2) as u16).to_be_bytes());
+        body.extend_from_slice(exif);
+        body.extend_from_slice(&jpeg[2..]);
+
+        let manager = ContentSecurityManager::new().unwrap();
+        let mut headers = HeaderMap::new();
+        manager.sanitize_response(&mut headers, &mut body, "image/jpeg").unwrap();
+
+        assert_eq!(body, jpeg);
+        assert!(!headers.contains_key(header::CONTENT_SECURITY_POLICY));
+    }
+
+    #[test]
+    fn test_disabled_features_and_other_types_pass_through() {
+        let config = ContentSecurityConfig {
+            enable_js_sanitization: false,
+            enable_csp: false,
+            enable_referrer_policy: false,
+            ..ContentSecurityConfig::default()
+        };
+        let manager = ContentSecurityManager::with_config(config).unwrap();
+        let mut headers = HeaderMap::new();
+        let mut body = b"<script>ok()</script>".to_vec();
+        manager.sanitize_response(&mut headers, &mut body, "text/html").unwrap();
+        assert_eq!(body, b"<script>ok()</script>");
+        assert!(headers.is_empty());
+
+        let mut body = vec![0xFF, 0xFE];
+        assert!(matches!(
+            manager.sanitize_response(&mut headers, &mut body, "application/octet-stream"),
+            Ok(())
+        ));
+        assert!(matches!(
+            manager.sanitize_response(&mut headers, &mut body, "text/html"),
+            Err(ContentSecurityError::InvalidContent(_))
+        ));
+
+        let config = ContentSecurityConfig { content_security_policy: Some("bad\npolicy".to_string()), ..ContentSecurityConfig::default() };
+        assert!(matches!(
+            ContentSecurityManager::with_config(config),
+            Err(ContentSecurityError::ConfigurationError(_))
+        ));
+    }
+}
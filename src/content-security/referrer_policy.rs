@@ -1,5 +1,152 @@
 //! Referrer Policy Enforcement Module
-//! 
-//! Prevent referrer leaks between sites
+//!
+//! Prevent referrer leaks between sites. Responses carry a `Referrer-Policy`
+//! header, and links that open external sites in a new tab get
+//! `rel="noreferrer noopener"` so the target neither learns where the visitor
+//! came from nor gains a handle on the opening page.
 
-// TODO: Implement referrer policy enforcement functionality
+use crate::content_security::markup::{self, StartTag};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Standard `Referrer-Policy` values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReferrerPolicy {
+    #[default]
+    NoReferrer,
+    NoReferrerWhenDowngrade,
+    Origin,
+    OriginWhenCrossOrigin,
+    SameOrigin,
+    StrictOrigin,
+    StrictOriginWhenCrossOrigin,
+    UnsafeUrl,
+}
+
+impl ReferrerPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReferrerPolicy::NoReferrer => "no-referrer",
+            ReferrerPolicy::NoReferrerWhenDowngrade => "no-referrer-when-downgrade",
+            ReferrerPolicy::Origin => "origin",
+            ReferrerPolicy::OriginWhenCrossOrigin => "origin-when-cross-origin",
+            ReferrerPolicy::SameOrigin => "same-origin",
+            ReferrerPolicy::StrictOrigin => "strict-origin",
+            ReferrerPolicy::StrictOriginWhenCrossOrigin => "strict-origin-when-cross-origin",
+            ReferrerPolicy::UnsafeUrl => "unsafe-url",
+        }
+    }
+}
+
+impl fmt::Display for ReferrerPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Referrer policy for a deployment, plus the hosts its own links point at
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReferrerPolicyManager {
+    policy: ReferrerPolicy,
+    internal_hosts: Vec<String>,
+}
+
+impl ReferrerPolicyManager {
+    pub fn new(policy: ReferrerPolicy) -> Self {
+        Self { policy, internal_hosts: Vec::new() }
+    }
+
+    /// Treat links to `host` as internal; relative links always are
+    pub fn with_internal_host(mut self, host: &str) -> Self {
+        self.internal_hosts.push(host.to_ascii_lowercase());
+        self
+    }
+
+    pub fn policy(&self) -> ReferrerPolicy {
+        self.policy
+    }
+
+    /// Value for the `Referrer-Policy` header
+    pub fn header_value(&self) -> &'static str {
+        self.policy.as_str()
+    }
+
+    fn is_external(&self, href: &str) -> bool {
+        markup::url_host(href).is_some_and(|host| !self.internal_hosts.contains(&host))
+    }
+
+    fn needs_rel(&self, tag: &StartTag) -> bool {
+        tag.name == "a"
+            && tag.attribute("target").is_some_and(|target| target.value.trim().eq_ignore_ascii_case("_blank"))
+            && tag.attribute("href").is_some_and(|href| self.is_external(&href.value))
+    }
+
+    /// Add `rel="noreferrer noopener"` to external links opening in a new tab,
+    /// leaving the rest of the document byte for byte as it was
+    pub fn rewrite_links(&self, html: &str) -> String {
+        let edits = markup::start_tags(html)
+            .into_iter()
+            .filter(|tag| self.needs_rel(tag))
+            .map(|tag| match tag.attribute("rel") {
+                Some(rel) => {
+                    let mut tokens: Vec<String> = rel.value.split_ascii_whitespace().map(str::to_string).collect();
+                    for required in ["noreferrer", "noopener"] {
+                        if !tokens.iter().any(|token| token.eq_ignore_ascii_case(required)) {
+                            tokens.push(required.to_string());
+                        }
+                    }
+                    (rel.span.clone(), format!("rel=\"{}\"", tokens.join(" ")))
+                }
+                None => {
+                    let at = tag.insertion_point(html);
+                    (at..at, " rel=\"noreferrer noopener\"".to_string())
+                }
+            })
+            .collect();
+        markup::splice(html, edits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager() -> ReferrerPolicyManager {
+        ReferrerPolicyManager::new(ReferrerPolicy::StrictOrigin).with_internal_host("Example.onion")
+    }
+
+    #[test]
+    fn test_header_value() {
+        assert_eq!(manager().header_value(), "strict-origin");
+        assert_eq!(ReferrerPolicyManager::default().header_value(), "no-referrer");
+
+        let parsed: ReferrerPolicy = serde_json::from_str("\"strict-origin-when-cross-origin\"").unwrap();
+        assert_eq!(parsed, ReferrerPolicy::StrictOriginWhenCrossOrigin);
+    }
+
+    #[test]
+    fn test_internal_links_are_untouched() {
+        let html = "<p><a href=\"/about\" target=\"_blank\">About</a> \
+                    <a href=\"http://example.onion/faq\" target=_blank>FAQ</a> \
+                    <a href=\"https://elsewhere.org\">same tab</a></p>";
+        assert_eq!(manager().rewrite_links(html), html);
+    }
+
+    #[test]
+    fn test_external_links_get_rel() {
+        let manager = manager();
+        assert_eq!(
+            manager.rewrite_links("<a href=\"https://elsewhere.org/x\" target=\"_blank\">x</a>"),
+            "<a href=\"https://elsewhere.org/x\" target=\"_blank\" rel=\"noreferrer noopener\">x</a>",
+        );
+        assert_eq!(
+            manager.rewrite_links("<A HREF='//cdn.example.com' TARGET=_BLANK rel='nofollow'/>"),
+            "<A HREF='//cdn.example.com' TARGET=_BLANK rel=\"nofollow noreferrer noopener\"/>",
+        );
+        assert_eq!(
+            manager.rewrite_links("<a target=_blank href=https://user@evil.example:8080 rel=noopener>y</a>"),
+            "<a target=_blank href=https://user@evil.example:8080 rel=\"noopener noreferrer\">y</a>",
+        );
+    }
+}
@@ -0,0 +1,435 @@
+//! Single entry point tying the enabled subsystems together
+//!
+//! `RustWall` owns the Tor security manager and, when their features are
+//! compiled in, the CAPTCHA, anonymity and content security pieces. A request
+//! goes through `handle_request` before the application sees it; a challenged
+//! client gets a clock from `issue_challenge` and its answer goes to
+//! `verify_challenge`. The application's response goes through
+//! `sanitize_response`, and `request_finished` releases what the request held
+//! once it is done.
+
+use crate::tor::{RequestContext, RequestDecision, TorSecurityConfig, TorSecurityError, TorSecurityManager};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(feature = "anonymity")]
+use crate::anonymity::{AnonymityConfig, AnonymityError, metadata_scrubbing::MetadataScrubber};
+#[cfg(feature = "captcha")]
+use crate::captcha::challenge::{ClockRenderer, ClockTime, Period};
+#[cfg(feature = "captcha")]
+use crate::captcha::session::{
+    DEFAULT_MAX_SESSIONS, MemoryBackend, SessionConfig, SessionError, SessionStore, ValidationOutcome,
+};
+#[cfg(feature = "content-security")]
+use crate::content_security::{ContentSecurityConfig, ContentSecurityError, ContentSecurityManager};
+#[cfg(any(feature = "anonymity", feature = "content-security"))]
+use http::HeaderMap;
+
+/// Errors from any subsystem behind `RustWall`
+#[derive(Debug)]
+pub enum RustWallError {
+    Tor(TorSecurityError),
+    #[cfg(feature = "captcha")]
+    Captcha(SessionError),
+    #[cfg(feature = "anonymity")]
+    Anonymity(AnonymityError),
+    #[cfg(feature = "content-security")]
+    ContentSecurity(ContentSecurityError),
+}
+
+impl fmt::Display for RustWallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RustWallError::Tor(e) => write!(f, "Tor security: {}", e),
+            #[cfg(feature = "captcha")]
+            RustWallError::Captcha(e) => write!(f, "CAPTCHA: {}", e),
+            #[cfg(feature = "anonymity")]
+            RustWallError::Anonymity(e) => write!(f, "Anonymity: {}", e),
+            #[cfg(feature = "content-security")]
+            RustWallError::ContentSecurity(e) => write!(f, "Content security: {}", e),
+        }
+    }
+}
+
+impl Error for RustWallError {}
+
+impl From<TorSecurityError> for RustWallError {
+    fn from(e: TorSecurityError) -> Self {
+        RustWallError::Tor(e)
+    }
+}
+
+#[cfg(feature = "captcha")]
+impl From<SessionError> for RustWallError {
+    fn from(e: SessionError) -> Self {
+        RustWallError::Captcha(e)
+    }
+}
+
+#[cfg(feature = "anonymity")]
+impl From<AnonymityError> for RustWallError {
+    fn from(e: AnonymityError) -> Self {
+        RustWallError::Anonymity(e)
+    }
+}
+
+#[cfg(feature = "content-security")]
+impl From<ContentSecurityError> for RustWallError {
+    fn from(e: ContentSecurityError) -> Self {
+        RustWallError::ContentSecurity(e)
+    }
+}
+
+/// Result type for `RustWall` operations
+pub type RustWallResult<T> = Result<T, RustWallError>;
+
+/// Configuration for every subsystem `RustWall` runs
+///
+/// A subsystem set to `None` is left out. Deserializing fills any missing
+/// field from `Default`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RustWallConfig {
+    pub tor: TorSecurityConfig,
+    /// Pass DDoS challenges on to the caller, to answer with `issue_challenge`
+    /// or an external CAPTCHA server. Turn this off to deny them instead.
+    pub serve_challenges: bool,
+    /// Clock challenges issued and checked in-process
+    #[cfg(feature = "captcha")]
+    pub captcha: Option<SessionConfig>,
+    #[cfg(feature = "anonymity")]
+    pub anonymity: Option<AnonymityConfig>,
+    #[cfg(feature = "content-security")]
+    pub content_security: Option<ContentSecurityConfig>,
+}
+
+impl Default for RustWallConfig {
+    fn default() -> Self {
+        Self {
+            tor: TorSecurityConfig::default(),
+            serve_challenges: true,
+            #[cfg(feature = "captcha")]
+            captcha: Some(SessionConfig::default()),
+            #[cfg(feature = "anonymity")]
+            anonymity: Some(AnonymityConfig::default()),
+            #[cfg(feature = "content-security")]
+            content_security: Some(ContentSecurityConfig::default()),
+        }
+    }
+}
+
+impl RustWallConfig {
+    /// Validate every included subsystem's configuration
+    pub fn validate(&self) -> RustWallResult<()> {
+        self.tor.validate()?;
+        #[cfg(feature = "anonymity")]
+        if let Some(anonymity) = &self.anonymity {
+            anonymity.validate()?;
+        }
+        Ok(())
+    }
+}
+
+/// A clock challenge for a client to solve
+#[cfg(feature = "captcha")]
+#[derive(Debug, Clone)]
+pub struct IssuedChallenge {
+    /// Submit this with the answer to `verify_challenge`
+    pub session_id: String,
+    /// The clock face as an SVG document
+    pub svg: String,
+    /// The answer must name AM or PM as well as the time
+    pub ask_period: bool,
+}
+
+/// The enabled RustWall subsystems behind one request pipeline
+///
+/// Requests are denied until `start` and again after `stop`. Every method takes
+/// `&self`, so share it as an `Arc<RustWall>`.
+pub struct RustWall {
+    config: RustWallConfig,
+    tor: Arc<TorSecurityManager>,
+    #[cfg(feature = "captcha")]
+    challenges: Option<SessionStore>,
+    #[cfg(feature = "anonymity")]
+    metadata_scrubber: Option<MetadataScrubber>,
+    #[cfg(feature = "content-security")]
+    content_security: Option<ContentSecurityManager>,
+    running: AtomicBool,
+}
+
+impl RustWall {
+    /// Build every subsystem included in `config`
+    pub fn new(config: RustWallConfig) -> RustWallResult<Self> {
+        config.validate()?;
+        Ok(Self {
+            tor: Arc::new(TorSecurityManager::with_config(config.tor.clone())?),
+            #[cfg(feature = "captcha")]
+            challenges: config.captcha.clone().map(|captcha| {
+                SessionStore::with_backend(captcha, Arc::new(MemoryBackend::with_capacity(DEFAULT_MAX_SESSIONS)))
+            }),
+            #[cfg(feature = "anonymity")]
+            metadata_scrubber: config.anonymity.as_ref().map(MetadataScrubber::new).transpose()?,
+            #[cfg(feature = "content-security")]
+            content_security: config
+                .content_security
+                .clone()
+                .map(ContentSecurityManager::with_config)
+                .transpose()?,
+            config,
+            running: AtomicBool::new(false),
+        })
+    }
+
+    /// Initialize the subsystems and start admitting requests. Starting a
+    /// running instance does nothing.
+    pub fn start(&self) -> RustWallResult<()> {
+        if self.running.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        self.tor.initialize()?;
+        self.running.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Deny further requests and shut the subsystems down
+    pub fn stop(&self) -> RustWallResult<()> {
+        if !self.running.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        self.tor.shutdown()?;
+        Ok(())
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Decide whether a request may reach the application
+    ///
+    /// Runs exit node filtering, circuit analysis, DDoS mitigation and onion
+    /// service limits in that order. `Challenge` means the client should solve
+    /// a challenge first; pass the circuit to `challenge_passed` once it has.
+    /// An allowed request holds a connection slot until `request_finished`.
+    pub fn handle_request(&self, ctx: &RequestContext) -> RustWallResult<RequestDecision> {
+        if !self.is_running() {
+            return Ok(RequestDecision::Deny);
+        }
+        Ok(match self.tor.evaluate_request(ctx)? {
+            RequestDecision::Challenge if !self.config.serve_challenges => RequestDecision::Deny,
+            decision => decision,
+        })
+    }
+
+    /// Let `circuit_id` through DDoS challenges from now on
+    pub fn challenge_passed(&self, circuit_id: impl Into<String>) {
+        self.tor.mark_challenge_passed(circuit_id);
+    }
+
+    /// Open a clock challenge for a client `handle_request` answered with
+    /// `Challenge`. `None` when CAPTCHA is left out of the configuration.
+    #[cfg(feature = "captcha")]
+    pub fn issue_challenge(&self) -> RustWallResult<Option<IssuedChallenge>> {
+        let Some(challenges) = &self.challenges else {
+            return Ok(None);
+        };
+        // Sessions that ask for AM or PM need an hour from the whole day
+        let config = challenges.config();
+        let time = if config.twenty_four_hour || config.ask_period {
+            ClockTime::random_24h()
+        } else {
+            ClockTime::random()
+        };
+        let session_id = challenges.create_session(time.hour, time.minute)?;
+        // Only a zero TTL ends a session before it can be drawn
+        let session = challenges
+            .get_session(&session_id)
+            .ok_or_else(|| SessionError::Backend("Session expired as soon as it was created".to_string()))?;
+        Ok(Some(IssuedChallenge {
+            svg: ClockRenderer::new(200.0).render_clock(&session.clock_time()),
+            ask_period: session.ask_period,
+            session_id,
+        }))
+    }
+
+    /// Check an answer to `issue_challenge`. A correct one lets the request's
+    /// circuit through DDoS challenges from then on, as `challenge_passed` does.
+    #[cfg(feature = "captcha")]
+    pub fn verify_challenge(
+        &self,
+        ctx: &RequestContext,
+        session_id: &str,
+        hour: u8,
+        minute: u8,
+        period: Option<Period>,
+    ) -> ValidationOutcome {
+        let Some(challenges) = &self.challenges else {
+            return ValidationOutcome::NotFound;
+        };
+        let outcome = challenges.validate_and_remove(session_id, hour, minute, period);
+        if outcome == ValidationOutcome::Valid
+            && let Some(circuit_id) = &ctx.circuit_id
+        {
+            self.challenge_passed(circuit_id.clone());
+        }
+        outcome
+    }
+
+    /// Release what an allowed request held
+    pub fn request_finished(&self, ctx: &RequestContext) {
+        self.tor.request_finished(ctx);
+    }
+
+    /// Clean the application's response before it is sent; a no-op when content
+    /// security is left out of the configuration
+    #[cfg(feature = "content-security")]
+    pub fn sanitize_response(&self, headers: &mut HeaderMap, body: &mut Vec<u8>, content_type: &str) -> RustWallResult<()> {
+        if let Some(content_security) = &self.content_security {
+            content_security.sanitize_response(headers, body, content_type)?;
+        }
+        Ok(())
+    }
+
+    /// Strip identifying headers from a request leaving for another service
+    #[cfg(feature = "anonymity")]
+    pub fn scrub_outgoing_request(&self, headers: &mut HeaderMap) {
+        if let Some(scrubber) = &self.metadata_scrubber {
+            scrubber.scrub_request(headers);
+        }
+    }
+
+    pub fn config(&self) -> &RustWallConfig {
+        &self.config
+    }
+
+    /// The Tor security manager, shareable with the operational tools
+    pub fn tor(&self) -> &Arc<TorSecurityManager> {
+        &self.tor
+    }
+
+    /// The in-process challenge sessions, e.g. to sweep expired ones
+    #[cfg(feature = "captcha")]
+    pub fn challenges(&self) -> Option<&SessionStore> {
+        self.challenges.as_ref()
+    }
+
+    #[cfg(feature = "anonymity")]
+    pub fn metadata_scrubber(&self) -> Option<&MetadataScrubber> {
+        self.metadata_scrubber.as_ref()
+    }
+
+    #[cfg(feature = "content-security")]
+    pub fn content_security(&self) -> Option<&ContentSecurityManager> {
+        self.content_security.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tor::exit_node_filter::BlocklistSource;
+    use std::net::IpAddr;
+
+    fn context(exit_node: &str) -> RequestContext {
+        RequestContext {
+            circuit_id: Some("circuit_1".to_string()),
+            exit_node: Some(exit_node.parse().unwrap()),
+            request_size: 512,
+            ..RequestContext::default()
+        }
+    }
+
+    #[test]
+    fn test_requests_only_pass_while_running() {
+        let wall = RustWall::new(RustWallConfig::default()).unwrap();
+        assert_eq!(wall.handle_request(&context("198.51.100.2")).unwrap(), RequestDecision::Deny);
+
+        wall.start().unwrap();
+        assert!(wall.is_running());
+        assert_eq!(wall.handle_request(&context("198.51.100.2")).unwrap(), RequestDecision::Allow);
+
+        wall.stop().unwrap();
+        wall.stop().unwrap();
+        assert_eq!(wall.handle_request(&context("198.51.100.2")).unwrap(), RequestDecision::Deny);
+    }
+
+    #[test]
+    fn test_pipeline_applies_exit_node_blocklist() {
+        let wall = RustWall::new(RustWallConfig::default()).unwrap();
+        wall.start().unwrap();
+        wall.tor().exit_node_filter().add_to_blocklist(
+            "198.51.100.1".parse::<IpAddr>().unwrap(),
+            BlocklistSource::Manual,
+            "test".to_string(),
+            None,
+            5,
+        ).unwrap();
+
+        assert_eq!(wall.handle_request(&context("198.51.100.1")).unwrap(), RequestDecision::Deny);
+        assert_eq!(wall.handle_request(&context("198.51.100.2")).unwrap(), RequestDecision::Allow);
+    }
+
+    #[test]
+    fn test_invalid_config_is_rejected() {
+        let config = RustWallConfig {
+            tor: TorSecurityConfig { rate_limit_window_seconds: 0, ..TorSecurityConfig::default() },
+            ..RustWallConfig::default()
+        };
+        assert!(matches!(RustWall::new(config), Err(RustWallError::Tor(_))));
+    }
+
+    #[cfg(feature = "captcha")]
+    #[test]
+    fn test_solved_challenge_lets_the_circuit_through() {
+        let wall = RustWall::new(RustWallConfig::default()).unwrap();
+        wall.start().unwrap();
+        let ctx = context("198.51.100.2");
+        assert!(!wall.tor().ddos_mitigation().has_passed_challenge("circuit_1"));
+
+        let challenge = wall.issue_challenge().unwrap().unwrap();
+        assert!(challenge.svg.starts_with("<svg"));
+        let session = wall.challenges().unwrap().get_session(&challenge.session_id).unwrap();
+        let outcome = wall.verify_challenge(&ctx, &challenge.session_id, session.correct_hour, session.correct_minute, None);
+        assert_eq!(outcome, ValidationOutcome::Valid);
+        assert!(wall.tor().ddos_mitigation().has_passed_challenge("circuit_1"));
+
+        // Sessions are single-use
+        let outcome = wall.verify_challenge(&ctx, &challenge.session_id, session.correct_hour, session.correct_minute, None);
+        assert_eq!(outcome, ValidationOutcome::NotFound);
+    }
+
+    #[cfg(feature = "captcha")]
+    #[test]
+    fn test_wrong_answer_leaves_the_circuit_challenged() {
+        let wall = RustWall::new(RustWallConfig::default()).unwrap();
+        let challenge = wall.issue_challenge().unwrap().unwrap();
+        let session = wall.challenges().unwrap().get_session(&challenge.session_id).unwrap();
+        let wrong_hour = session.correct_hour % 12 + 6;
+        let outcome = wall.verify_challenge(&context("198.51.100.2"), &challenge.session_id, wrong_hour, 30, None);
+        assert!(matches!(outcome, ValidationOutcome::Invalid { .. }));
+        assert!(!wall.tor().ddos_mitigation().has_passed_challenge("circuit_1"));
+
+        let wall = RustWall::new(RustWallConfig { captcha: None, ..RustWallConfig::default() }).unwrap();
+        assert!(wall.issue_challenge().unwrap().is_none());
+        assert_eq!(wall.verify_challenge(&context("198.51.100.2"), "id", 1, 0, None), ValidationOutcome::NotFound);
+    }
+
+    #[cfg(feature = "content-security")]
+    #[test]
+    fn test_response_is_sanitized_when_included() {
+        let wall = RustWall::new(RustWallConfig::default()).unwrap();
+        let mut headers = HeaderMap::new();
+        let mut body = b"<p>Hi<script>alert(1)</script></p>".to_vec();
+        wall.sanitize_response(&mut headers, &mut body, "text/html").unwrap();
+        assert_eq!(body, b"<p>Hi</p>");
+
+        let wall = RustWall::new(RustWallConfig { content_security: None, ..RustWallConfig::default() }).unwrap();
+        let mut body = b"<p>Hi<script>alert(1)</script></p>".to_vec();
+        wall.sanitize_response(&mut HeaderMap::new(), &mut body, "text/html").unwrap();
+        assert!(wall.content_security().is_none());
+        assert_eq!(body, b"<p>Hi<script>alert(1)</script></p>");
+    }
+}
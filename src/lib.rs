@@ -1,9 +1,58 @@
 //! RustWall - Advanced Firewall and Security System
-//! 
+//!
 //! A comprehensive security solution built in Rust, providing firewall capabilities,
 //! DDoS protection, CAPTCHA verification, and specialized Tor network security features.
+//!
+//! # Getting started
+//!
+//! With the `tor` feature, [`RustWall`] runs every compiled-in subsystem behind
+//! one request pipeline, built from a single [`RustWallConfig`]. The individual
+//! managers it holds stay reachable through its accessors for finer control.
+//!
+//! # Cargo features
+//!
+//! Each subsystem sits behind a feature so embedders only compile what they use.
+//! The default is `captcha` and `tor`; `full` turns everything on.
+//!
+//! | Feature            | Enables                                            | Implies |
+//! |--------------------|----------------------------------------------------|---------|
+//! | `captcha`          | `captcha`: clock challenges, and the `rustwall-captcha` server binary | |
+//! | `redis-sessions`   | Redis-backed CAPTCHA sessions                      | `captcha` |
+//! | `tor`              | [`tor`]: onion service, DDoS, circuit, exit node and rendezvous protection | |
+//! | `anonymity`        | `anonymity`: traffic obfuscation, timing protection and metadata scrubbing | |
+//! | `content-security` | `content_security`: response sanitization and the tower layer | |
+//! | `network`          | `network`: multi-onion hosting, load balancing and decoy traffic | `tor` |
+//! | `operational`      | `operational`: backups, audit logs, kill switch, config reloads and the admin API | `tor` |
+//! | `geoip`            | Country filtering of exit nodes                    | `tor` |
+//! | `blocking-timing`  | Blocking rendezvous timing delays                  | `tor` |
+//! | `daemon`           | The `rustwall` daemon binary, run from a TOML file | `operational` |
+//!
+//! `tor` on its own pulls in no web framework, templating or HTTP client.
+//! `tor-security` and `network-advanced` are older names for `tor` and `network`.
 
 pub mod ddos;
+#[cfg(feature = "captcha")]
+pub mod captcha;
+#[cfg(feature = "tor")]
 pub mod tor;
+pub mod serde_duration;
+pub mod clock;
+#[cfg(feature = "anonymity")]
+pub mod anonymity;
+#[cfg(feature = "content-security")]
+#[path = "content-security/mod.rs"]
+pub mod content_security;
+#[cfg(feature = "operational")]
+pub mod operational;
+#[cfg(feature = "network")]
+pub mod network;
 
+#[cfg(feature = "tor")]
+mod facade;
+
+#[cfg(feature = "tor")]
 pub use tor::{TorSecurityManager, TorSecurityConfig, TorSecurityError, TorSecurityResult};
+#[cfg(feature = "tor")]
+pub use facade::{RustWall, RustWallConfig, RustWallError, RustWallResult};
+#[cfg(all(feature = "tor", feature = "captcha"))]
+pub use facade::IssuedChallenge;
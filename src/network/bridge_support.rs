@@ -1,5 +1,315 @@
 //! Tor Bridge Support Module
 //! 
 //! Integrate with Tor bridges for censorship resistance
+//!
+//! `Bridge::parse` reads bridge lines as handed out by BridgeDB, e.g.
+//! `obfs4 192.0.2.3:443 <fingerprint> cert=... iat-mode=0`, and `BridgeSet`
+//! turns a collection of them into torrc directives.
 
-// TODO: Implement Tor bridge integration functionality
+use super::tor_config::TorrcConfig;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// Errors raised for malformed bridge lines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BridgeError {
+    Empty,
+    InvalidAddress(String),
+    /// Fingerprints are 40 hex characters
+    InvalidFingerprint(String),
+    /// Pluggable transport arguments are `key=value`
+    InvalidArgument(String),
+    MissingArgument { transport: String, argument: String },
+}
+
+impl fmt::Display for BridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BridgeError::Empty => write!(f, "Empty bridge line"),
+            BridgeError::InvalidAddress(address) => write!(f, "Invalid bridge address '{}', expected ip:port", address),
+            BridgeError::InvalidFingerprint(fingerprint) => {
+                write!(f, "Invalid bridge fingerprint '{}', expected 40 hex characters", fingerprint)
+            }
+            BridgeError::InvalidArgument(argument) => {
+                write!(f, "Invalid transport argument '{}', expected key=value", argument)
+            }
+            BridgeError::MissingArgument { transport, argument } => {
+                write!(f, "{} bridge is missing its {} argument", transport, argument)
+            }
+        }
+    }
+}
+
+impl Error for BridgeError {}
+
+/// Pluggable transport a bridge is reached through
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Transport {
+    /// Plain Tor protocol, no pluggable transport
+    Vanilla,
+    Obfs4,
+    Snowflake,
+    Meek,
+    WebTunnel,
+    Other(String),
+}
+
+impl Transport {
+    fn parse(name: &str) -> Self {
+        match name.to_ascii_lowercase().as_str() {
+            "obfs4" => Transport::Obfs4,
+            "snowflake" => Transport::Snowflake,
+            "meek" | "meek_lite" => Transport::Meek,
+            "webtunnel" => Transport::WebTunnel,
+            _ => Transport::Other(name.to_string()),
+        }
+    }
+
+    /// Name used in bridge lines and `ClientTransportPlugin`; `None` for vanilla bridges
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Transport::Vanilla => None,
+            Transport::Obfs4 => Some("obfs4"),
+            Transport::Snowflake => Some("snowflake"),
+            Transport::Meek => Some("meek_lite"),
+            Transport::WebTunnel => Some("webtunnel"),
+            Transport::Other(name) => Some(name),
+        }
+    }
+
+    /// Arguments a bridge line for this transport can't work without
+    fn required_arguments(&self) -> &'static [&'static str] {
+        match self {
+            Transport::Obfs4 => &["cert", "iat-mode"],
+            Transport::WebTunnel => &["url"],
+            _ => &[],
+        }
+    }
+}
+
+/// A single bridge line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bridge {
+    pub transport: Transport,
+    pub address: SocketAddr,
+    /// Uppercase hex identity fingerprint
+    pub fingerprint: Option<String>,
+    /// Pluggable transport arguments, in line order
+    pub args: Vec<(String, String)>,
+}
+
+impl Bridge {
+    /// Parse a bridge line, with or without the leading `Bridge` keyword
+    pub fn parse(line: &str) -> Result<Bridge, BridgeError> {
+        let mut parts = line.split_whitespace().peekable();
+        if parts.peek().is_some_and(|first| first.eq_ignore_ascii_case("bridge")) {
+            parts.next();
+        }
+        let first = parts.next().ok_or(BridgeError::Empty)?;
+
+        // Vanilla bridge lines start straight with the address
+        let (transport, address) = match first.parse::<SocketAddr>() {
+            Ok(address) => (Transport::Vanilla, address),
+            Err(_) => {
+                let address = parts.next().ok_or_else(|| BridgeError::InvalidAddress(String::new()))?;
+                let address = address.parse().map_err(|_| BridgeError::InvalidAddress(address.to_string()))?;
+                (Transport::parse(first), address)
+            }
+        };
+
+        let mut fingerprint = None;
+        let mut args = Vec::new();
+        for part in parts {
+            match part.split_once('=') {
+                Some((key, value)) if !key.is_empty() => args.push((key.to_string(), value.to_string())),
+                Some(_) => return Err(BridgeError::InvalidArgument(part.to_string())),
+                None if fingerprint.is_none() && args.is_empty() => {
+                    if part.len() != 40 || !part.chars().all(|c| c.is_ascii_hexdigit()) {
+                        return Err(BridgeError::InvalidFingerprint(part.to_string()));
+                    }
+                    fingerprint = Some(part.to_ascii_uppercase());
+                }
+                None => return Err(BridgeError::InvalidArgument(part.to_string())),
+            }
+        }
+
+        let bridge = Bridge { transport, address, fingerprint, args };
+        for argument in bridge.transport.required_arguments() {
+            if bridge.arg(argument).is_none() {
+                return Err(BridgeError::MissingArgument {
+                    transport: bridge.transport.name().unwrap_or_default().to_string(),
+                    argument: argument.to_string(),
+                });
+            }
+        }
+        if bridge.transport == Transport::Obfs4 && bridge.iat_mode().is_none() {
+            return Err(BridgeError::InvalidArgument(format!(
+                "iat-mode={}",
+                bridge.arg("iat-mode").unwrap_or_default()
+            )));
+        }
+        Ok(bridge)
+    }
+
+    /// Value of transport argument `key`
+    pub fn arg(&self, key: &str) -> Option<&str> {
+        self.args.iter().find(|(k, _)| k == key).map(|(_, value)| value.as_str())
+    }
+
+    /// obfs4 inter-arrival time obfuscation: 0 off, 1 on, 2 paranoid
+    pub fn iat_mode(&self) -> Option<u8> {
+        self.arg("iat-mode").and_then(|mode| mode.parse().ok()).filter(|mode| *mode <= 2)
+    }
+}
+
+impl fmt::Display for Bridge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(name) = self.transport.name() {
+            write!(f, "{} ", name)?;
+        }
+        write!(f, "{}", self.address)?;
+        if let Some(fingerprint) = &self.fingerprint {
+            write!(f, " {}", fingerprint)?;
+        }
+        for (key, value) in &self.args {
+            write!(f, " {}={}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Bridges to configure Tor with, and the binaries that provide their transports
+#[derive(Debug, Clone, Default)]
+pub struct BridgeSet {
+    bridges: Vec<Bridge>,
+    transport_plugins: BTreeMap<String, PathBuf>,
+}
+
+impl BridgeSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `bridge`; returns false if a bridge with the same transport and address is already present
+    pub fn add(&mut self, bridge: Bridge) -> bool {
+        if self.bridges.iter().any(|b| b.transport == bridge.transport && b.address == bridge.address) {
+            return false;
+        }
+        self.bridges.push(bridge);
+        true
+    }
+
+    /// Remove every bridge at `address`, returning how many were removed
+    pub fn remove(&mut self, address: SocketAddr) -> usize {
+        let before = self.bridges.len();
+        self.bridges.retain(|bridge| bridge.address != address);
+        before - self.bridges.len()
+    }
+
+    pub fn bridges(&self) -> &[Bridge] {
+        &self.bridges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bridges.is_empty()
+    }
+
+    /// Run `path` as the client for `transport`, e.g. `obfs4` and `/usr/bin/lyrebird`
+    pub fn set_transport_plugin(&mut self, transport: &str, path: impl Into<PathBuf>) {
+        self.transport_plugins.insert(transport.to_string(), path.into());
+    }
+
+    /// `UseBridges`, `ClientTransportPlugin` for each transport in use that has
+    /// a plugin set, then one `Bridge` line per bridge. Empty when there are no bridges.
+    pub fn to_torrc_lines(&self) -> Vec<String> {
+        if self.bridges.is_empty() {
+            return Vec::new();
+        }
+
+        let mut lines = vec!["UseBridges 1".to_string()];
+        for (transport, path) in &self.transport_plugins {
+            if self.bridges.iter().any(|bridge| bridge.transport.name() == Some(transport.as_str())) {
+                lines.push(format!("ClientTransportPlugin {} exec {}", transport, path.display()));
+            }
+        }
+        lines.extend(self.bridges.iter().map(|bridge| format!("Bridge {}", bridge)));
+        lines
+    }
+
+    /// Replace any bridge directives in `torrc` with this set's
+    pub fn apply_to(&self, torrc: &mut TorrcConfig) {
+        torrc.other_lines.retain(|line| {
+            let keyword = line.split_whitespace().next().unwrap_or("").to_ascii_lowercase();
+            !matches!(keyword.as_str(), "usebridges" | "bridge" | "clienttransportplugin")
+        });
+        torrc.other_lines.extend(self.to_torrc_lines());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OBFS4: &str = "obfs4 192.0.2.3:443 0123456789abcdef0123456789ABCDEF01234567 \
+                         cert=ssH+9rP8dG2NLDN2XuFw63hIO/9MNNinLmxQDpVa+7kTOa9/m+tGWT1SmSYpQ9uTBGa6Hw iat-mode=0";
+
+    #[test]
+    fn test_parse_obfs4_line() {
+        let bridge = Bridge::parse(OBFS4).unwrap();
+        assert_eq!(bridge.transport, Transport::Obfs4);
+        assert_eq!(bridge.address, "192.0.2.3:443".parse().unwrap());
+        assert_eq!(bridge.fingerprint.as_deref(), Some("0123456789ABCDEF0123456789ABCDEF01234567"));
+        assert!(bridge.arg("cert").unwrap().starts_with("ssH+9rP8"));
+        assert_eq!(bridge.iat_mode(), Some(0));
+        assert_eq!(Bridge::parse(&format!("Bridge {}", bridge)).unwrap(), bridge);
+
+        let vanilla = Bridge::parse("[2001:db8::1]:9001").unwrap();
+        assert_eq!(vanilla.transport, Transport::Vanilla);
+        assert_eq!(vanilla.to_string(), "[2001:db8::1]:9001");
+    }
+
+    #[test]
+    fn test_rejects_malformed_lines() {
+        assert_eq!(Bridge::parse("  ").unwrap_err(), BridgeError::Empty);
+        assert_eq!(
+            Bridge::parse("obfs4 bridge.example:443").unwrap_err(),
+            BridgeError::InvalidAddress("bridge.example:443".to_string())
+        );
+        assert!(matches!(Bridge::parse("obfs4 192.0.2.3:443 ABCD").unwrap_err(), BridgeError::InvalidFingerprint(_)));
+        assert_eq!(
+            Bridge::parse("obfs4 192.0.2.3:443 iat-mode=0").unwrap_err(),
+            BridgeError::MissingArgument { transport: "obfs4".to_string(), argument: "cert".to_string() }
+        );
+        assert!(Bridge::parse("obfs4 192.0.2.3:443 cert=abc iat-mode=7").is_err());
+        assert!(Bridge::parse("obfs4 192.0.2.3:443 cert=abc iat-mode=0 stray").is_err());
+    }
+
+    #[test]
+    fn test_bridge_set_torrc_lines() {
+        let mut bridges = BridgeSet::new();
+        assert!(bridges.to_torrc_lines().is_empty());
+        assert!(bridges.add(Bridge::parse(OBFS4).unwrap()));
+        assert!(!bridges.add(Bridge::parse(OBFS4).unwrap()));
+        assert!(bridges.add(Bridge::parse("198.51.100.9:9001").unwrap()));
+        bridges.set_transport_plugin("obfs4", "/usr/bin/lyrebird");
+        bridges.set_transport_plugin("snowflake", "/usr/bin/snowflake-client");
+
+        let lines = bridges.to_torrc_lines();
+        assert_eq!(lines[0], "UseBridges 1");
+        assert_eq!(lines[1], "ClientTransportPlugin obfs4 exec /usr/bin/lyrebird");
+        assert!(lines[2].starts_with("Bridge obfs4 192.0.2.3:443 "));
+        assert_eq!(lines[3], "Bridge 198.51.100.9:9001");
+        assert_eq!(lines.len(), 4);
+
+        let mut torrc = TorrcConfig::parse("Bridge 203.0.113.1:443\nUseBridges 0\nLog notice stdout\n").unwrap();
+        bridges.apply_to(&mut torrc);
+        assert_eq!(torrc.other_lines[0], "Log notice stdout");
+        assert_eq!(&torrc.other_lines[1..], &lines[..]);
+
+        assert_eq!(bridges.remove("192.0.2.3:443".parse().unwrap()), 1);
+        assert_eq!(bridges.bridges().len(), 1);
+    }
+}
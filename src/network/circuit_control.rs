@@ -1,5 +1,709 @@
 //! Circuit Control Module
 //! 
 //! Manage Tor circuits and path selection
+//!
+//! `ControlPortClient` speaks Tor's control protocol: authenticate, then issue
+//! commands such as `SIGNAL NEWNYM`, `GETINFO circuit-status` and
+//! `CLOSECIRCUIT`. Replies are parsed into typed structs.
+//!
+//! `EventSubscription` keeps a connection subscribed to circuit and stream
+//! events, reconnecting when it drops, and `apply_circuit_event` feeds circuit
+//! events into `CircuitAnalysis`.
 
-// TODO: Implement Tor circuit management functionality
+use crate::tor::circuit_analysis::{CircuitAnalysis, CircuitPath, CircuitState};
+use crate::tor::{TorSecurityError, TorSecurityResult};
+use log::{info, warn};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Code of asynchronous event replies
+const EVENT_CODE: u16 = 650;
+/// Events buffered for a subscriber that isn't keeping up
+const EVENT_BUFFER: usize = 256;
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// How to authenticate to the control port
+#[derive(Debug, Clone)]
+pub enum ControlAuth {
+    /// No authentication configured
+    Null,
+    /// The `control_auth_cookie` file written by Tor (`CookieAuthentication 1`)
+    Cookie(PathBuf),
+    /// Plaintext of the password whose hash is configured as `HashedControlPassword`
+    Password(String),
+}
+
+/// One line of a control port reply, with the data block that follows a `+` line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplyLine {
+    pub text: String,
+    pub data: Vec<String>,
+}
+
+/// A complete control port reply
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlReply {
+    pub code: u16,
+    pub lines: Vec<ReplyLine>,
+}
+
+impl ControlReply {
+    pub fn is_ok(&self) -> bool {
+        (200..300).contains(&self.code)
+    }
+}
+
+/// Build state of a circuit, from `circuit-status`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CircuitStatus {
+    Launched,
+    Built,
+    GuardWait,
+    Extended,
+    Failed,
+    Closed,
+    Other(String),
+}
+
+impl CircuitStatus {
+    /// The matching analyzer state; Tor reports launch and extension while building
+    pub fn to_circuit_state(&self) -> Option<CircuitState> {
+        match self {
+            CircuitStatus::Launched | CircuitStatus::GuardWait | CircuitStatus::Extended => Some(CircuitState::Building),
+            CircuitStatus::Built => Some(CircuitState::Built),
+            CircuitStatus::Failed => Some(CircuitState::Failed),
+            CircuitStatus::Closed => Some(CircuitState::Closed),
+            CircuitStatus::Other(_) => None,
+        }
+    }
+
+    fn parse(status: &str) -> Self {
+        match status {
+            "LAUNCHED" => CircuitStatus::Launched,
+            "BUILT" => CircuitStatus::Built,
+            "GUARD_WAIT" => CircuitStatus::GuardWait,
+            "EXTENDED" => CircuitStatus::Extended,
+            "FAILED" => CircuitStatus::Failed,
+            "CLOSED" => CircuitStatus::Closed,
+            other => CircuitStatus::Other(other.to_string()),
+        }
+    }
+}
+
+/// A relay on a circuit's path, given as `$FINGERPRINT~nickname`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayHop {
+    pub fingerprint: Option<String>,
+    pub nickname: Option<String>,
+}
+
+impl RelayHop {
+    fn parse(hop: &str) -> Self {
+        match hop.strip_prefix('$') {
+            Some(rest) => {
+                let (fingerprint, nickname) = match rest.split_once(['~', '=']) {
+                    Some((fingerprint, nickname)) => (fingerprint, Some(nickname.to_string())),
+                    None => (rest, None),
+                };
+                RelayHop { fingerprint: Some(fingerprint.to_string()), nickname }
+            }
+            None => RelayHop { fingerprint: None, nickname: Some(hop.to_string()) },
+        }
+    }
+}
+
+/// A circuit as reported by `GETINFO circuit-status`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitSummary {
+    pub id: String,
+    pub status: CircuitStatus,
+    pub path: Vec<RelayHop>,
+    pub build_flags: Vec<String>,
+    pub purpose: Option<String>,
+    /// Remaining `KEY=VALUE` attributes, such as `HS_STATE` or `TIME_CREATED`
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl CircuitSummary {
+    /// The path in the analyzer's terms: first hop as guard, last as exit
+    pub fn to_circuit_path(&self) -> CircuitPath {
+        let name = |hop: &RelayHop| hop.fingerprint.clone().or_else(|| hop.nickname.clone());
+        let length = self.path.len();
+        CircuitPath {
+            guard_node: self.path.first().and_then(name),
+            middle_node: (length >= 3).then(|| name(&self.path[1])).flatten(),
+            exit_node: (length >= 2).then(|| self.path.last().and_then(name)).flatten(),
+            path_length: length.min(u8::MAX as usize) as u8,
+        }
+    }
+
+    /// Parse one `ID STATUS [PATH] [KEY=VALUE ...]` line
+    fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        let id = parts.next()?.to_string();
+        let status = CircuitStatus::parse(parts.next()?);
+
+        let mut summary = CircuitSummary {
+            id,
+            status,
+            path: Vec::new(),
+            build_flags: Vec::new(),
+            purpose: None,
+            attributes: BTreeMap::new(),
+        };
+        for part in parts {
+            match part.split_once('=') {
+                // Old-style `$FPR=nick` hops contain `=` too, but always start with `$`
+                Some(_) if part.starts_with('$') => summary.path = part.split(',').map(RelayHop::parse).collect(),
+                Some(("BUILD_FLAGS", flags)) => summary.build_flags = flags.split(',').map(str::to_string).collect(),
+                Some(("PURPOSE", purpose)) => summary.purpose = Some(purpose.to_string()),
+                Some((key, value)) => {
+                    summary.attributes.insert(key.to_string(), value.to_string());
+                }
+                None => summary.path = part.split(',').map(RelayHop::parse).collect(),
+            }
+        }
+        Some(summary)
+    }
+}
+
+/// A `STREAM` event: `StreamID StreamStatus CircuitID Target [KEY=VALUE ...]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamEvent {
+    pub id: String,
+    /// `NEW`, `SUCCEEDED`, `CLOSED` and so on, verbatim
+    pub status: String,
+    /// `0` while the stream is not attached to a circuit
+    pub circuit_id: String,
+    pub target: String,
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl StreamEvent {
+    fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        Some(StreamEvent {
+            id: parts.next()?.to_string(),
+            status: parts.next()?.to_string(),
+            circuit_id: parts.next()?.to_string(),
+            target: parts.next()?.to_string(),
+            attributes: parts
+                .filter_map(|part| part.split_once('='))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        })
+    }
+}
+
+/// Asynchronous events that can be subscribed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventType {
+    Circ,
+    Stream,
+}
+
+impl EventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventType::Circ => "CIRC",
+            EventType::Stream => "STREAM",
+        }
+    }
+}
+
+/// A parsed asynchronous event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlEvent {
+    /// Circuit lifecycle change; `REASON` and the like land in `attributes`
+    Circuit(CircuitSummary),
+    Stream(StreamEvent),
+    /// Any other event, as its first reply line
+    Other(String),
+}
+
+impl ControlEvent {
+    fn from_reply(reply: &ControlReply) -> Option<Self> {
+        let text = &reply.lines.first()?.text;
+        let (keyword, rest) = text.split_once(' ').unwrap_or((text.as_str(), ""));
+        Some(match keyword {
+            "CIRC" => ControlEvent::Circuit(CircuitSummary::parse(rest)?),
+            "STREAM" => ControlEvent::Stream(StreamEvent::parse(rest)?),
+            _ => ControlEvent::Other(text.clone()),
+        })
+    }
+}
+
+/// Mirror a circuit event in `analysis`: launches register the circuit, later
+/// events update its state. Circuits first seen after launch are registered then.
+pub fn apply_circuit_event(analysis: &mut CircuitAnalysis, circuit: &CircuitSummary) -> TorSecurityResult<()> {
+    let Some(state) = circuit.status.to_circuit_state() else { return Ok(()) };
+    let tracked = analysis.is_tracking(&circuit.id);
+    if !tracked && matches!(state, CircuitState::Closed | CircuitState::Failed) {
+        return Ok(());
+    }
+    if !tracked {
+        analysis.register_circuit(circuit.id.clone(), None, circuit.to_circuit_path())?;
+    }
+    if state != CircuitState::Building {
+        analysis.update_circuit_state(&circuit.id, state)?;
+    }
+    Ok(())
+}
+
+fn network_error(e: std::io::Error) -> TorSecurityError {
+    TorSecurityError::NetworkError(format!("Control port I/O failed: {}", e))
+}
+
+/// Quote a string for the control protocol. Control characters are refused
+/// rather than escaped: a CR or LF would end the command early.
+fn quote(value: &str) -> TorSecurityResult<String> {
+    if value.chars().any(char::is_control) {
+        return Err(TorSecurityError::ConfigurationError(
+            "Control port strings must not contain control characters".to_string(),
+        ));
+    }
+    let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+    Ok(format!("\"{}\"", escaped))
+}
+
+/// Tor circuit ids are short alphanumeric strings
+fn validate_circuit_id(id: &str) -> TorSecurityResult<()> {
+    if id.is_empty() || id.len() > 16 || !id.bytes().all(|b| b.is_ascii_alphanumeric()) {
+        return Err(TorSecurityError::CircuitError(format!("Invalid circuit id {:?}", id)));
+    }
+    Ok(())
+}
+
+/// Client for Tor's control port
+pub struct ControlPortClient<S> {
+    stream: BufReader<S>,
+    /// Events that arrived while waiting for a command's reply
+    pending_events: VecDeque<ControlReply>,
+}
+
+impl ControlPortClient<TcpStream> {
+    /// Connect to the control port at `addr` and authenticate
+    pub async fn connect(addr: impl ToSocketAddrs, auth: &ControlAuth) -> TorSecurityResult<Self> {
+        let stream = TcpStream::connect(addr).await.map_err(network_error)?;
+        let mut client = Self::from_stream(stream);
+        client.authenticate(auth).await?;
+        Ok(client)
+    }
+
+    /// Connect, subscribe to `events` and deliver them from a background task.
+    /// When the connection drops the task reconnects with backoff and
+    /// resubscribes; events Tor emitted in between are lost. Fails only if the
+    /// first connection can't be set up.
+    pub async fn subscribe_events(
+        addr: impl Into<String>,
+        auth: ControlAuth,
+        events: &[EventType],
+    ) -> TorSecurityResult<EventSubscription> {
+        let addr = addr.into();
+        let events = events.to_vec();
+        let mut client = Self::connect(addr.as_str(), &auth).await?;
+        client.set_events(&events).await?;
+
+        let (sender, receiver) = mpsc::channel(EVENT_BUFFER);
+        let task = tokio::spawn(async move {
+            loop {
+                loop {
+                    match client.next_event().await {
+                        Ok(event) => {
+                            if sender.send(event).await.is_err() {
+                                return;
+                            }
+                        }
+                        Err(e) => {
+                            warn!("Control port event connection lost: {}", e);
+                            break;
+                        }
+                    }
+                }
+
+                let mut delay = Duration::from_secs(1);
+                client = loop {
+                    if sender.is_closed() {
+                        return;
+                    }
+                    tokio::time::sleep(delay).await;
+                    match Self::connect(addr.as_str(), &auth).await {
+                        Ok(mut reconnected) => match reconnected.set_events(&events).await {
+                            Ok(()) => break reconnected,
+                            Err(e) => warn!("Failed to resubscribe to control port events: {}", e),
+                        },
+                        Err(e) => warn!("Control port reconnect failed: {}", e),
+                    }
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                };
+                info!("Reconnected to control port at {}", addr);
+            }
+        });
+
+        Ok(EventSubscription { receiver, task })
+    }
+}
+
+/// Events from a control port subscription; the connection closes when this is dropped
+pub struct EventSubscription {
+    receiver: mpsc::Receiver<ControlEvent>,
+    task: JoinHandle<()>,
+}
+
+impl EventSubscription {
+    /// The next event, across reconnects
+    pub async fn next(&mut self) -> Option<ControlEvent> {
+        self.receiver.recv().await
+    }
+}
+
+impl Drop for EventSubscription {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> ControlPortClient<S> {
+    /// Wrap an already connected stream; call `authenticate` before other commands
+    pub fn from_stream(stream: S) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+            pending_events: VecDeque::new(),
+        }
+    }
+
+    pub async fn authenticate(&mut self, auth: &ControlAuth) -> TorSecurityResult<()> {
+        let command = match auth {
+            ControlAuth::Null => "AUTHENTICATE".to_string(),
+            ControlAuth::Cookie(path) => {
+                let cookie = tokio::fs::read(path).await.map_err(|e| {
+                    TorSecurityError::ConfigurationError(format!(
+                        "Failed to read control cookie {}: {}",
+                        path.display(),
+                        e
+                    ))
+                })?;
+                let hex: String = cookie.iter().map(|b| format!("{:02x}", b)).collect();
+                format!("AUTHENTICATE {}", hex)
+            }
+            ControlAuth::Password(password) => format!("AUTHENTICATE {}", quote(password)?),
+        };
+
+        let reply = self.command(&command).await?;
+        if !reply.is_ok() {
+            return Err(TorSecurityError::SecurityViolation(format!(
+                "Control port authentication failed: {}",
+                reply.lines.first().map_or("", |line| line.text.as_str())
+            )));
+        }
+        Ok(())
+    }
+
+    /// Send a raw command and read its reply. Error replies are returned, not raised.
+    /// Commands spanning more than one line are refused unsent.
+    pub async fn command(&mut self, command: &str) -> TorSecurityResult<ControlReply> {
+        if command.contains(['\r', '\n']) {
+            return Err(TorSecurityError::ConfigurationError(
+                "Control port commands must be a single line".to_string(),
+            ));
+        }
+        let stream = self.stream.get_mut();
+        stream.write_all(command.as_bytes()).await.map_err(network_error)?;
+        stream.write_all(b"\r\n").await.map_err(network_error)?;
+        stream.flush().await.map_err(network_error)?;
+        loop {
+            let reply = self.read_reply().await?;
+            if reply.code != EVENT_CODE {
+                return Ok(reply);
+            }
+            self.pending_events.push_back(reply);
+        }
+    }
+
+    /// Send a command and fail with a `CircuitError` unless Tor accepts it
+    async fn expect_ok(&mut self, command: &str) -> TorSecurityResult<ControlReply> {
+        let reply = self.command(command).await?;
+        if !reply.is_ok() {
+            return Err(TorSecurityError::CircuitError(format!(
+                "{} rejected with {}: {}",
+                command.split_whitespace().next().unwrap_or(command),
+                reply.code,
+                reply.lines.first().map_or("", |line| line.text.as_str())
+            )));
+        }
+        Ok(reply)
+    }
+
+    async fn read_line(&mut self) -> TorSecurityResult<String> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line).await.map_err(network_error)? == 0 {
+            return Err(TorSecurityError::NetworkError("Control port closed the connection".to_string()));
+        }
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    async fn read_reply(&mut self) -> TorSecurityResult<ControlReply> {
+        let mut lines = Vec::new();
+        loop {
+            let line = self.read_line().await?;
+            let malformed = || TorSecurityError::NetworkError(format!("Malformed control reply: {}", line));
+            let code: u16 = line.get(..3).and_then(|code| code.parse().ok()).ok_or_else(malformed)?;
+            let separator = line.as_bytes().get(3).copied().unwrap_or(b' ');
+            let text = line.get(4..).unwrap_or("").to_string();
+
+            let mut data = Vec::new();
+            if separator == b'+' {
+                loop {
+                    let data_line = self.read_line().await?;
+                    if data_line == "." {
+                        break;
+                    }
+                    let unescaped = data_line.strip_prefix('.').filter(|rest| rest.starts_with('.'));
+                    data.push(unescaped.unwrap_or(&data_line).to_string());
+                }
+            }
+            lines.push(ReplyLine { text, data });
+
+            match separator {
+                b' ' => return Ok(ControlReply { code, lines }),
+                b'-' | b'+' => continue,
+                _ => return Err(malformed()),
+            }
+        }
+    }
+
+    /// Ask Tor for fresh circuits for new connections (`SIGNAL NEWNYM`)
+    pub async fn new_identity(&mut self) -> TorSecurityResult<()> {
+        self.expect_ok("SIGNAL NEWNYM").await?;
+        Ok(())
+    }
+
+    /// Every circuit Tor currently knows about
+    pub async fn list_circuits(&mut self) -> TorSecurityResult<Vec<CircuitSummary>> {
+        let reply = self.expect_ok("GETINFO circuit-status").await?;
+        let mut circuits = Vec::new();
+        for line in &reply.lines {
+            let Some(inline) = line.text.strip_prefix("circuit-status=") else { continue };
+            circuits.extend(
+                std::iter::once(inline)
+                    .chain(line.data.iter().map(String::as_str))
+                    .filter_map(CircuitSummary::parse),
+            );
+        }
+        Ok(circuits)
+    }
+
+    /// Tear down circuit `id`
+    pub async fn close_circuit(&mut self, id: &str) -> TorSecurityResult<()> {
+        validate_circuit_id(id)?;
+        self.expect_ok(&format!("CLOSECIRCUIT {}", id)).await?;
+        Ok(())
+    }
+
+    /// Replace the set of events Tor sends on this connection (`SETEVENTS`)
+    pub async fn set_events(&mut self, events: &[EventType]) -> TorSecurityResult<()> {
+        let names: Vec<_> = events.iter().map(EventType::as_str).collect();
+        let command = format!("SETEVENTS {}", names.join(" "));
+        self.expect_ok(command.trim_end()).await?;
+        Ok(())
+    }
+
+    /// Wait for the next event on this connection. Unparseable events are skipped.
+    pub async fn next_event(&mut self) -> TorSecurityResult<ControlEvent> {
+        loop {
+            let reply = match self.pending_events.pop_front() {
+                Some(reply) => reply,
+                None => self.read_reply().await?,
+            };
+            if reply.code != EVENT_CODE {
+                continue;
+            }
+            if let Some(event) = ControlEvent::from_reply(&reply) {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Close every open circuit `analysis` considers suspicious, returning their IDs
+    pub async fn close_suspicious_circuits(&mut self, analysis: &CircuitAnalysis) -> TorSecurityResult<Vec<String>> {
+        let mut closed = Vec::new();
+        for circuit in self.list_circuits().await? {
+            if matches!(circuit.status, CircuitStatus::Closed | CircuitStatus::Failed)
+                || !analysis.is_circuit_suspicious(&circuit.id)
+            {
+                continue;
+            }
+            self.close_circuit(&circuit.id).await?;
+            info!("Closed suspicious circuit {}", circuit.id);
+            closed.push(circuit.id);
+        }
+        Ok(closed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tor::TorSecurityConfig;
+    use tokio::io::{AsyncReadExt, DuplexStream};
+
+    /// Play Tor's side: expect each command in turn and answer with its reply
+    fn fake_tor(script: &'static [(&'static str, &'static str)]) -> ControlPortClient<DuplexStream> {
+        let (client, mut server) = tokio::io::duplex(4096);
+        tokio::spawn(async move {
+            for (expected, reply) in script {
+                let mut received = Vec::new();
+                while !received.ends_with(b"\r\n") {
+                    let mut byte = [0u8; 1];
+                    if server.read_exact(&mut byte).await.is_err() {
+                        return;
+                    }
+                    received.push(byte[0]);
+                }
+                assert_eq!(String::from_utf8_lossy(&received).trim_end(), *expected);
+                server.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+        ControlPortClient::from_stream(client)
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_and_new_identity() {
+        let mut client = fake_tor(&[
+            ("AUTHENTICATE \"pa\\\"ss\"", "250 OK\r\n"),
+            ("SIGNAL NEWNYM", "250 OK\r\n"),
+            ("CLOSECIRCUIT 99", "552 Unknown circuit \"99\"\r\n"),
+        ]);
+        client.authenticate(&ControlAuth::Password("pa\"ss".to_string())).await.unwrap();
+        client.new_identity().await.unwrap();
+        assert!(matches!(client.close_circuit("99").await, Err(TorSecurityError::CircuitError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_injected_commands_are_refused_unsent() {
+        let mut client = fake_tor(&[("SIGNAL NEWNYM", "250 OK\r\n")]);
+        for id in ["", "12345678901234567", "4\r\nSIGNAL HALT", "4 5", "4\n"] {
+            assert!(matches!(client.close_circuit(id).await, Err(TorSecurityError::CircuitError(_))), "{:?}", id);
+        }
+        for password in ["pass\r\nSIGNAL HALT", "pass\nword", "pass\rword", "pass\0"] {
+            assert!(client.authenticate(&ControlAuth::Password(password.to_string())).await.is_err());
+        }
+        assert!(client.command("GETINFO version\r\nSIGNAL HALT").await.is_err());
+        // Nothing reached Tor, so the next command is the first it sees
+        client.new_identity().await.unwrap();
+        assert!(quote("tab\there").is_err());
+        assert_eq!(quote("a\\b").unwrap(), "\"a\\\\b\"");
+    }
+
+    #[tokio::test]
+    async fn test_cookie_auth_and_rejection() {
+        let dir = tempfile::tempdir().unwrap();
+        let cookie = dir.path().join("control_auth_cookie");
+        std::fs::write(&cookie, [0xab, 0x01]).unwrap();
+
+        let mut client = fake_tor(&[("AUTHENTICATE ab01", "515 Authentication failed: Wrong length\r\n")]);
+        assert!(matches!(
+            client.authenticate(&ControlAuth::Cookie(cookie)).await,
+            Err(TorSecurityError::SecurityViolation(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_list_circuits() {
+        let mut client = fake_tor(&[(
+            "GETINFO circuit-status",
+            "250+circuit-status=\r\n\
+             4 BUILT $AAAA~alpha,$BBBB~beta,$CCCC~gamma BUILD_FLAGS=IS_INTERNAL,NEED_CAPACITY PURPOSE=HS_SERVICE_REND HS_STATE=HSSR_JOINED\r\n\
+             7 LAUNCHED PURPOSE=GENERAL\r\n\
+             .\r\n\
+             250 OK\r\n",
+        )]);
+
+        let circuits = client.list_circuits().await.unwrap();
+        assert_eq!(circuits.len(), 2);
+        assert_eq!(circuits[0].id, "4");
+        assert_eq!(circuits[0].status, CircuitStatus::Built);
+        assert_eq!(circuits[0].path.len(), 3);
+        assert_eq!(
+            circuits[0].path[1],
+            RelayHop { fingerprint: Some("BBBB".to_string()), nickname: Some("beta".to_string()) }
+        );
+        assert_eq!(circuits[0].build_flags, ["IS_INTERNAL", "NEED_CAPACITY"]);
+        assert_eq!(circuits[0].purpose.as_deref(), Some("HS_SERVICE_REND"));
+        assert_eq!(circuits[0].attributes["HS_STATE"], "HSSR_JOINED");
+        assert_eq!(circuits[1].status, CircuitStatus::Launched);
+        assert!(circuits[1].path.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_events_feed_circuit_analysis() {
+        let mut client = fake_tor(&[
+            ("SETEVENTS CIRC STREAM", "250 OK\r\n"),
+            (
+                "SIGNAL NEWNYM",
+                "650 CIRC 5 LAUNCHED PURPOSE=GENERAL\r\n\
+                 250 OK\r\n\
+                 650 STREAM 12 NEW 0 example.onion:80 SOURCE_ADDR=127.0.0.1:5000 PURPOSE=USER\r\n\
+                 650 CIRC 5 BUILT $AAAA~a,$BBBB~b,$CCCC~c PURPOSE=GENERAL\r\n\
+                 650 CIRC 5 CLOSED $AAAA~a,$BBBB~b,$CCCC~c REASON=FINISHED\r\n",
+            ),
+        ]);
+        client.set_events(&[EventType::Circ, EventType::Stream]).await.unwrap();
+        client.new_identity().await.unwrap();
+
+        let mut analysis = CircuitAnalysis::new(&TorSecurityConfig::default()).unwrap();
+        let ControlEvent::Circuit(launched) = client.next_event().await.unwrap() else { panic!() };
+        apply_circuit_event(&mut analysis, &launched).unwrap();
+        assert!(analysis.is_tracking("5"));
+
+        let ControlEvent::Stream(stream) = client.next_event().await.unwrap() else { panic!() };
+        assert_eq!((stream.id.as_str(), stream.circuit_id.as_str()), ("12", "0"));
+        assert_eq!(stream.attributes["SOURCE_ADDR"], "127.0.0.1:5000");
+
+        let ControlEvent::Circuit(built) = client.next_event().await.unwrap() else { panic!() };
+        let path = built.to_circuit_path();
+        assert_eq!(path.guard_node.as_deref(), Some("AAAA"));
+        assert_eq!(path.middle_node.as_deref(), Some("BBBB"));
+        assert_eq!(path.exit_node.as_deref(), Some("CCCC"));
+        apply_circuit_event(&mut analysis, &built).unwrap();
+
+        let ControlEvent::Circuit(closed) = client.next_event().await.unwrap() else { panic!() };
+        assert_eq!(closed.attributes["REASON"], "FINISHED");
+        apply_circuit_event(&mut analysis, &closed).unwrap();
+        assert!(!analysis.is_tracking("5"));
+        assert_eq!(analysis.get_analysis_stats().historical_circuits, 1);
+    }
+
+    #[tokio::test]
+    async fn test_subscription_reconnects() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for id in 0..2 {
+                let (socket, _) = listener.accept().await.unwrap();
+                let mut socket = BufReader::new(socket);
+                for _ in 0..2 {
+                    let mut line = String::new();
+                    socket.read_line(&mut line).await.unwrap();
+                    socket.get_mut().write_all(b"250 OK\r\n").await.unwrap();
+                }
+                let event = format!("650 CIRC {} LAUNCHED\r\n", id);
+                socket.get_mut().write_all(event.as_bytes()).await.unwrap();
+            }
+        });
+
+        let mut events = ControlPortClient::subscribe_events(addr.to_string(), ControlAuth::Null, &[EventType::Circ])
+            .await
+            .unwrap();
+        for id in ["0", "1"] {
+            let event = tokio::time::timeout(Duration::from_secs(5), events.next()).await.unwrap();
+            let Some(ControlEvent::Circuit(circuit)) = event else { panic!("unexpected {:?}", event) };
+            assert_eq!(circuit.id, id);
+        }
+    }
+}
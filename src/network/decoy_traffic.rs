@@ -1,5 +1,320 @@
 //! Decoy Traffic Generation Module
 //! 
 //! Generate fake traffic to mask real usage patterns
+//!
+//! Decoy requests form a Poisson process: gaps between them are drawn from an
+//! exponential distribution, so they carry no rhythm an observer could tell
+//! apart from user activity. Payload sizes are log-uniform, mostly small with
+//! the occasional large one, like ordinary web traffic.
 
-// TODO: Implement decoy traffic generation functionality
+use crate::tor::{TorSecurityError, TorSecurityResult};
+use log::error;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// Decoy traffic configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DecoyTrafficConfig {
+    /// Endpoints decoys are sent to, picked at random for each request
+    pub endpoints: Vec<String>,
+    /// Average decoy rate
+    pub requests_per_minute: f64,
+    pub min_payload_size: usize,
+    pub max_payload_size: usize,
+}
+
+impl Default for DecoyTrafficConfig {
+    fn default() -> Self {
+        Self {
+            endpoints: Vec::new(),
+            requests_per_minute: 6.0,
+            min_payload_size: 64,
+            max_payload_size: 16 * 1024,
+        }
+    }
+}
+
+impl DecoyTrafficConfig {
+    fn validate(&self) -> TorSecurityResult<()> {
+        if self.endpoints.is_empty() {
+            return Err(TorSecurityError::ConfigurationError(
+                "Decoy traffic needs at least one endpoint".to_string(),
+            ));
+        }
+        if !(self.requests_per_minute.is_finite() && self.requests_per_minute > 0.0) {
+            return Err(TorSecurityError::ConfigurationError(format!(
+                "Decoy rate must be positive, got {}",
+                self.requests_per_minute
+            )));
+        }
+        if self.min_payload_size == 0 || self.min_payload_size > self.max_payload_size {
+            return Err(TorSecurityError::ConfigurationError(format!(
+                "Decoy payload sizes must satisfy 0 < min ({}) <= max ({})",
+                self.min_payload_size, self.max_payload_size
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// One decoy request to emit
+#[derive(Debug, Clone)]
+pub struct DecoyRequest {
+    pub endpoint: String,
+    pub payload: Vec<u8>,
+}
+
+/// Where decoy requests go. `send` is called from the generator's task and
+/// must not block; hand slow work off to another task.
+pub trait DecoySink: Send + Sync + 'static {
+    fn send(&self, request: DecoyRequest);
+}
+
+/// Posts decoys over HTTP with a caller-built client, e.g. one proxied through Tor.
+/// Responses and errors are ignored.
+pub struct HttpDecoySink {
+    client: reqwest::Client,
+}
+
+impl HttpDecoySink {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+}
+
+impl DecoySink for HttpDecoySink {
+    fn send(&self, request: DecoyRequest) {
+        let post = self.client.post(request.endpoint).body(request.payload).send();
+        tokio::spawn(async move {
+            let _ = post.await;
+        });
+    }
+}
+
+/// Fills decoy payloads of a requested size
+pub trait PayloadSource: Send + 'static {
+    fn payload(&mut self, size: usize) -> Vec<u8>;
+}
+
+/// Uniformly random bytes, indistinguishable from encrypted content
+pub struct RandomPayload;
+
+impl PayloadSource for RandomPayload {
+    fn payload(&mut self, size: usize) -> Vec<u8> {
+        let mut payload = vec![0u8; size];
+        rand::thread_rng().fill(payload.as_mut_slice());
+        payload
+    }
+}
+
+/// Dummy frames from the anonymity layer, padded to the same buckets as real
+/// obfuscated traffic. The obfuscator picks the frame size from its own
+/// `dummy_size` distribution, so the requested size is only used for the random
+/// fallback when dummy traffic is unavailable.
+#[cfg(feature = "anonymity")]
+impl PayloadSource for crate::anonymity::traffic_obfuscation::TrafficObfuscation {
+    fn payload(&mut self, size: usize) -> Vec<u8> {
+        match self.generate_dummy_traffic() {
+            Ok(frame) => frame,
+            Err(e) => {
+                error!("Dummy traffic unavailable, sending random decoy: {}", e);
+                RandomPayload.payload(size)
+            }
+        }
+    }
+}
+
+/// Time until the next decoy, exponentially distributed around the configured rate
+fn next_interval(config: &DecoyTrafficConfig, rng: &mut impl Rng) -> Duration {
+    let rate_per_sec = config.requests_per_minute / 60.0;
+    // 1 - U lies in (0, 1], keeping ln finite
+    let uniform: f64 = 1.0 - rng.r#gen::<f64>();
+    Duration::from_secs_f64(-uniform.ln() / rate_per_sec)
+}
+
+/// Log-uniform payload size between the configured bounds
+fn payload_size(config: &DecoyTrafficConfig, rng: &mut impl Rng) -> usize {
+    let (min, max) = ((config.min_payload_size as f64).ln(), (config.max_payload_size as f64).ln());
+    let size = rng.gen_range(min..=max).exp().round() as usize;
+    size.clamp(config.min_payload_size, config.max_payload_size)
+}
+
+/// Emits decoy requests on a randomized schedule until stopped
+pub struct DecoyTrafficGenerator {
+    config: DecoyTrafficConfig,
+    sink: Arc<dyn DecoySink>,
+    payloads: Arc<Mutex<Box<dyn PayloadSource>>>,
+    sent: Arc<AtomicU64>,
+    stop: Option<oneshot::Sender<()>>,
+}
+
+impl DecoyTrafficGenerator {
+    pub fn new(config: DecoyTrafficConfig, sink: impl DecoySink) -> TorSecurityResult<Self> {
+        config.validate()?;
+        Ok(Self {
+            config,
+            sink: Arc::new(sink),
+            payloads: Arc::new(Mutex::new(Box::new(RandomPayload))),
+            sent: Arc::new(AtomicU64::new(0)),
+            stop: None,
+        })
+    }
+
+    /// Take payload bytes from `source` instead of plain random bytes
+    pub fn with_payload_source(self, source: impl PayloadSource) -> Self {
+        *self.payloads.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Box::new(source);
+        self
+    }
+
+    /// Decoys emitted since creation
+    pub fn sent_count(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.stop.as_ref().is_some_and(|stop| !stop.is_closed())
+    }
+
+    /// Start emitting decoys, stopping any earlier run first. The returned task
+    /// ends once `stop` is called or the generator is dropped.
+    pub fn start(&mut self) -> JoinHandle<()> {
+        self.stop();
+        let (stop, mut stopped) = oneshot::channel();
+        self.stop = Some(stop);
+
+        let config = self.config.clone();
+        let sink = Arc::clone(&self.sink);
+        let payloads = Arc::clone(&self.payloads);
+        let sent = Arc::clone(&self.sent);
+        tokio::spawn(async move {
+            loop {
+                let delay = next_interval(&config, &mut rand::thread_rng());
+                tokio::select! {
+                    _ = &mut stopped => return,
+                    _ = tokio::time::sleep(delay) => {}
+                }
+
+                let (endpoint, size) = {
+                    let mut rng = rand::thread_rng();
+                    let endpoint = config.endpoints[rng.gen_range(0..config.endpoints.len())].clone();
+                    (endpoint, payload_size(&config, &mut rng))
+                };
+                let payload = match payloads.lock() {
+                    Ok(mut source) => source.payload(size),
+                    Err(_) => {
+                        error!("Decoy payload source lock poisoned, stopping decoy traffic");
+                        return;
+                    }
+                };
+                sink.send(DecoyRequest { endpoint, payload });
+                sent.fetch_add(1, Ordering::Relaxed);
+            }
+        })
+    }
+
+    /// Stop the running task, if any
+    pub fn stop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Recorder(Arc<Mutex<Vec<DecoyRequest>>>);
+
+    impl DecoySink for Recorder {
+        fn send(&self, request: DecoyRequest) {
+            self.0.lock().unwrap().push(request);
+        }
+    }
+
+    fn config(requests_per_minute: f64) -> DecoyTrafficConfig {
+        DecoyTrafficConfig {
+            endpoints: vec!["http://a.onion/".to_string(), "http://b.onion/".to_string()],
+            requests_per_minute,
+            ..DecoyTrafficConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_schedule_matches_configured_rate() {
+        let config = config(120.0);
+        let mut rng = rand::thread_rng();
+        let samples = 20_000;
+        let total: f64 = (0..samples).map(|_| next_interval(&config, &mut rng).as_secs_f64()).sum();
+        let mean = total / samples as f64;
+        assert!((mean - 0.5).abs() < 0.05, "mean interval {}", mean);
+
+        let sizes: Vec<_> = (0..1000).map(|_| payload_size(&config, &mut rng)).collect();
+        assert!(sizes.iter().all(|size| (64..=16 * 1024).contains(size)));
+        // Log-uniform: the lower half of the log range holds half of the sizes
+        assert!(sizes.iter().filter(|size| **size < 1024).count() > 300);
+    }
+
+    #[tokio::test]
+    async fn test_generator_emits_until_stopped() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let mut generator = DecoyTrafficGenerator::new(config(6000.0), Recorder(Arc::clone(&requests))).unwrap();
+
+        let task = generator.start();
+        assert!(generator.is_running());
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        generator.stop();
+        task.await.unwrap();
+
+        // 100 per second on average over half a second
+        let sent = generator.sent_count();
+        assert!((15..=150).contains(&sent), "sent {}", sent);
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len() as u64, sent);
+        assert!(requests.iter().all(|r| r.endpoint.ends_with(".onion/") && !r.payload.is_empty()));
+        assert!(!generator.is_running());
+    }
+
+    #[test]
+    fn test_rejects_invalid_config() {
+        let sink = || Recorder(Arc::new(Mutex::new(Vec::new())));
+        assert!(DecoyTrafficGenerator::new(DecoyTrafficConfig::default(), sink()).is_err());
+        assert!(DecoyTrafficGenerator::new(config(0.0), sink()).is_err());
+        let sizes = DecoyTrafficConfig { min_payload_size: 10, max_payload_size: 5, ..config(1.0) };
+        assert!(DecoyTrafficGenerator::new(sizes, sink()).is_err());
+    }
+
+    #[cfg(feature = "anonymity")]
+    #[tokio::test]
+    async fn test_obfuscation_payloads_are_padded_frames() {
+        use crate::anonymity::traffic_obfuscation::{TrafficObfuscation, HEADER_LEN};
+        use crate::anonymity::AnonymityConfig;
+
+        let anonymity = AnonymityConfig { enable_dummy_traffic: true, ..AnonymityConfig::default() };
+        let mut obfuscation = TrafficObfuscation::new(&anonymity).unwrap();
+        obfuscation.initialize().unwrap();
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let mut generator = DecoyTrafficGenerator::new(config(6000.0), Recorder(Arc::clone(&requests)))
+            .unwrap()
+            .with_payload_source(obfuscation);
+        let task = generator.start();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        generator.stop();
+        task.await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert!(!requests.is_empty());
+        for request in requests.iter() {
+            assert!(anonymity.padding_buckets.contains(&request.payload.len()), "{}", request.payload.len());
+            // Dummy frames claim an empty body
+            assert_eq!(request.payload[..HEADER_LEN], [0; HEADER_LEN]);
+        }
+    }
+}
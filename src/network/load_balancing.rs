@@ -1,5 +1,330 @@
 //! Onion Load Balancing Module
 //! 
 //! Distribute load across multiple hidden service instances
+//!
+//! A backend that fails `failure_threshold` times in a row is taken out of
+//! rotation. It stays out until a probe against it succeeds; `due_for_probe`
+//! lists the ones worth probing.
 
-// TODO: Implement onion service load balancing functionality
+use super::multi_onion::HostedOnionService;
+use crate::tor::{TorSecurityError, TorSecurityResult};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Anything that can be load balanced; the ID is what successes and failures are reported against
+pub trait Backend {
+    fn id(&self) -> &str;
+}
+
+impl Backend for HostedOnionService {
+    fn id(&self) -> &str {
+        self.address.as_str()
+    }
+}
+
+impl Backend for String {
+    fn id(&self) -> &str {
+        self
+    }
+}
+
+/// How `pick` chooses among healthy backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    RoundRobin,
+    /// Smooth weighted round robin: proportional to weight, without bursts
+    Weighted,
+    LeastConnections,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoadBalancerConfig {
+    pub strategy: Strategy,
+    /// Consecutive failures after which a backend leaves rotation
+    pub failure_threshold: u32,
+    /// Time between probes of an unhealthy backend
+    #[serde(with = "crate::serde_duration::secs")]
+    pub probe_interval: Duration,
+}
+
+impl Default for LoadBalancerConfig {
+    fn default() -> Self {
+        Self {
+            strategy: Strategy::RoundRobin,
+            failure_threshold: 3,
+            probe_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+struct BackendSlot<B> {
+    backend: B,
+    weight: u32,
+    current_weight: i64,
+    healthy: bool,
+    consecutive_failures: u32,
+    active_connections: u32,
+    last_probe: Option<Instant>,
+}
+
+/// Spreads requests over a set of backends, skipping unhealthy ones
+pub struct LoadBalancer<B> {
+    config: LoadBalancerConfig,
+    backends: Vec<BackendSlot<B>>,
+    next: usize,
+}
+
+impl<B: Backend> LoadBalancer<B> {
+    pub fn new(config: LoadBalancerConfig) -> Self {
+        Self {
+            config: LoadBalancerConfig {
+                failure_threshold: config.failure_threshold.max(1),
+                ..config
+            },
+            backends: Vec::new(),
+            next: 0,
+        }
+    }
+
+    /// Add a healthy backend; `weight` only matters for `Strategy::Weighted`
+    pub fn add_backend(&mut self, backend: B, weight: u32) -> TorSecurityResult<()> {
+        if weight == 0 {
+            return Err(TorSecurityError::ConfigurationError(format!(
+                "Backend {} needs a weight above zero",
+                backend.id()
+            )));
+        }
+        if self.position(backend.id()).is_some() {
+            return Err(TorSecurityError::ConfigurationError(format!(
+                "Backend {} is already registered",
+                backend.id()
+            )));
+        }
+        self.backends.push(BackendSlot {
+            backend,
+            weight,
+            current_weight: 0,
+            healthy: true,
+            consecutive_failures: 0,
+            active_connections: 0,
+            last_probe: None,
+        });
+        Ok(())
+    }
+
+    pub fn remove_backend(&mut self, id: &str) -> Option<B> {
+        let index = self.position(id)?;
+        Some(self.backends.remove(index).backend)
+    }
+
+    fn position(&self, id: &str) -> Option<usize> {
+        self.backends.iter().position(|slot| slot.backend.id() == id)
+    }
+
+    /// Choose a healthy backend for the next request, or `None` if there is
+    /// none. Each pick counts as an open connection until it is answered with
+    /// `report_success` or `report_failure`.
+    pub fn pick(&mut self) -> Option<&B> {
+        let index = match self.config.strategy {
+            Strategy::RoundRobin => {
+                let count = self.backends.len();
+                let index = (0..count)
+                    .map(|offset| (self.next + offset) % count)
+                    .find(|&index| self.backends[index].healthy)?;
+                self.next = index + 1;
+                index
+            }
+            Strategy::Weighted => {
+                let total: i64 = self.backends.iter().filter(|slot| slot.healthy).map(|slot| slot.weight as i64).sum();
+                for slot in self.backends.iter_mut().filter(|slot| slot.healthy) {
+                    slot.current_weight += slot.weight as i64;
+                }
+                let index = (0..self.backends.len())
+                    .filter(|&index| self.backends[index].healthy)
+                    .max_by_key(|&index| (self.backends[index].current_weight, std::cmp::Reverse(index)))?;
+                self.backends[index].current_weight -= total;
+                index
+            }
+            Strategy::LeastConnections => (0..self.backends.len())
+                .filter(|&index| self.backends[index].healthy)
+                .min_by_key(|&index| (self.backends[index].active_connections, index))?,
+        };
+
+        let slot = &mut self.backends[index];
+        slot.active_connections += 1;
+        Some(&slot.backend)
+    }
+
+    /// A request to `id` succeeded; a successful probe brings an unhealthy backend back
+    pub fn report_success(&mut self, id: &str) {
+        let Some(index) = self.position(id) else { return };
+        let slot = &mut self.backends[index];
+        slot.active_connections = slot.active_connections.saturating_sub(1);
+        slot.consecutive_failures = 0;
+        if !slot.healthy {
+            slot.healthy = true;
+            slot.current_weight = 0;
+            slot.last_probe = None;
+            info!("Backend {} recovered", id);
+        }
+    }
+
+    /// A request to `id` failed
+    pub fn report_failure(&mut self, id: &str) {
+        let threshold = self.config.failure_threshold;
+        let Some(index) = self.position(id) else { return };
+        let slot = &mut self.backends[index];
+        slot.active_connections = slot.active_connections.saturating_sub(1);
+        slot.consecutive_failures += 1;
+        if slot.healthy && slot.consecutive_failures >= threshold {
+            slot.healthy = false;
+            slot.last_probe = Some(Instant::now());
+            warn!("Backend {} marked unhealthy after {} consecutive failures", id, slot.consecutive_failures);
+        }
+    }
+
+    /// Unhealthy backends not probed within `probe_interval`. Returning a backend
+    /// counts as probing it; report the outcome as usual.
+    pub fn due_for_probe(&mut self) -> Vec<&B> {
+        self.due_for_probe_at(Instant::now())
+    }
+
+    fn due_for_probe_at(&mut self, now: Instant) -> Vec<&B> {
+        let interval = self.config.probe_interval;
+        self.backends
+            .iter_mut()
+            .filter(|slot| !slot.healthy)
+            .filter(|slot| slot.last_probe.is_none_or(|at| now.saturating_duration_since(at) >= interval))
+            .map(|slot| {
+                slot.last_probe = Some(now);
+                slot.active_connections += 1;
+                &slot.backend
+            })
+            .collect()
+    }
+
+    pub fn is_healthy(&self, id: &str) -> bool {
+        self.position(id).is_some_and(|index| self.backends[index].healthy)
+    }
+
+    pub fn healthy_count(&self) -> usize {
+        self.backends.iter().filter(|slot| slot.healthy).count()
+    }
+
+    pub fn len(&self) -> usize {
+        self.backends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.backends.is_empty()
+    }
+
+    /// Grade the pool for a `HealthMonitor` check: degraded while any backend is
+    /// out of rotation, critical when none is left
+    #[cfg(feature = "operational")]
+    pub fn check_health(&self) -> crate::operational::health_monitoring::CheckResult {
+        use crate::operational::health_monitoring::CheckResult;
+
+        let healthy = self.healthy_count();
+        let message = format!("{} of {} backends healthy", healthy, self.backends.len());
+        if healthy == self.backends.len() {
+            CheckResult::healthy(message)
+        } else if healthy == 0 {
+            CheckResult::critical(message)
+        } else {
+            CheckResult::degraded(message)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn balancer(strategy: Strategy, backends: &[(&str, u32)]) -> LoadBalancer<String> {
+        let mut balancer = LoadBalancer::new(LoadBalancerConfig { strategy, ..LoadBalancerConfig::default() });
+        for (id, weight) in backends {
+            balancer.add_backend(id.to_string(), *weight).unwrap();
+        }
+        balancer
+    }
+
+    fn pick(balancer: &mut LoadBalancer<String>) -> String {
+        let id = balancer.pick().unwrap().clone();
+        balancer.report_success(&id);
+        id
+    }
+
+    #[test]
+    fn test_strategies() {
+        let mut round_robin = balancer(Strategy::RoundRobin, &[("a", 1), ("b", 1), ("c", 1)]);
+        let picks: Vec<_> = (0..4).map(|_| pick(&mut round_robin)).collect();
+        assert_eq!(picks, ["a", "b", "c", "a"]);
+
+        let mut weighted = balancer(Strategy::Weighted, &[("a", 3), ("b", 1)]);
+        let picks: Vec<_> = (0..8).map(|_| pick(&mut weighted)).collect();
+        assert_eq!(picks.iter().filter(|id| *id == "a").count(), 6);
+        assert_eq!(picks[..4], ["a", "a", "b", "a"]);
+
+        let mut least = balancer(Strategy::LeastConnections, &[("a", 1), ("b", 1)]);
+        assert_eq!(least.pick().unwrap(), "a");
+        assert_eq!(least.pick().unwrap(), "b");
+        least.report_success("b");
+        assert_eq!(least.pick().unwrap(), "b");
+
+        assert!(round_robin.add_backend("a".to_string(), 1).is_err());
+        assert!(round_robin.add_backend("d".to_string(), 0).is_err());
+    }
+
+    #[test]
+    fn test_failover_and_recovery() {
+        let mut balancer = balancer(Strategy::RoundRobin, &[("a", 1), ("b", 1)]);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            balancer.pick();
+            balancer.report_failure("a");
+            balancer.report_success("b");
+        }
+        assert!(!balancer.is_healthy("a"));
+        assert_eq!(balancer.healthy_count(), 1);
+        assert!((0..4).all(|_| pick(&mut balancer) == "b"));
+
+        // Just marked unhealthy, so not due yet
+        assert!(balancer.due_for_probe_at(now).is_empty());
+        let later = now + Duration::from_secs(11);
+        assert_eq!(balancer.due_for_probe_at(later), ["a"]);
+        balancer.report_failure("a");
+        assert!(balancer.due_for_probe_at(later + Duration::from_secs(1)).is_empty());
+
+        assert_eq!(balancer.due_for_probe_at(later + Duration::from_secs(10)), ["a"]);
+        balancer.report_success("a");
+        assert!(balancer.is_healthy("a"));
+        assert!((0..4).any(|_| pick(&mut balancer) == "a"));
+
+        balancer.remove_backend("b");
+        balancer.remove_backend("a");
+        assert!(balancer.pick().is_none());
+    }
+
+    #[cfg(feature = "operational")]
+    #[test]
+    fn test_check_health() {
+        use crate::operational::health_monitoring::HealthStatus;
+
+        let mut balancer = balancer(Strategy::RoundRobin, &[("a", 1), ("b", 1)]);
+        assert_eq!(balancer.check_health().status, HealthStatus::Healthy);
+        for _ in 0..3 {
+            balancer.report_failure("a");
+        }
+        assert_eq!(balancer.check_health().status, HealthStatus::Degraded);
+        for _ in 0..3 {
+            balancer.report_failure("b");
+        }
+        assert_eq!(balancer.check_health().message, "0 of 2 backends healthy");
+        assert_eq!(balancer.check_health().status, HealthStatus::Critical);
+    }
+}
@@ -11,5 +11,3 @@ pub mod load_balancing;
 pub mod decoy_traffic;
 pub mod multi_hop_proxy;
 pub mod steganography;
-
-// TODO: Implement network functionality
@@ -1,5 +1,349 @@
-//! Multi-Hop Proxying Module
+//! Multi-Hop Proxy Module
 //! 
 //! Additional proxy layers beyond Tor for extra security
+//!
+//! `ProxyChain` tunnels a TCP connection through SOCKS5 proxies in order: it
+//! connects to the first proxy, asks it to connect to the second, negotiates
+//! with the second inside that tunnel, and so on until the last proxy connects
+//! to the destination. Each proxy only learns the hop before and after it.
 
-// TODO: Implement multi-hop proxy functionality
+use std::io;
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const SOCKS_VERSION: u8 = 0x05;
+const METHOD_NO_AUTH: u8 = 0x00;
+const METHOD_USERNAME_PASSWORD: u8 = 0x02;
+const METHOD_UNACCEPTABLE: u8 = 0xff;
+const COMMAND_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// One SOCKS5 proxy in a chain
+#[derive(Debug, Clone)]
+pub struct ProxyHop {
+    /// `host:port` of the proxy
+    pub address: String,
+    /// Username and password for RFC 1929 authentication
+    pub credentials: Option<(String, String)>,
+    /// Limit for reaching this hop and negotiating with it
+    pub connect_timeout: Duration,
+}
+
+impl ProxyHop {
+    pub fn new(address: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+            credentials: None,
+            connect_timeout: Duration::from_secs(30),
+        }
+    }
+
+    pub fn with_credentials(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.credentials = Some((username.into(), password.into()));
+        self
+    }
+
+    pub fn with_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+}
+
+/// Ordered list of SOCKS5 proxies to tunnel connections through
+#[derive(Debug, Clone)]
+pub struct ProxyChain {
+    hops: Vec<ProxyHop>,
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn protocol_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Split `host:port`, accepting bracketed IPv6 hosts
+fn split_host_port(address: &str) -> io::Result<(&str, u16)> {
+    let (host, port) = address
+        .rsplit_once(':')
+        .ok_or_else(|| invalid_input(format!("'{}' is not host:port", address)))?;
+    let port = port.parse().map_err(|_| invalid_input(format!("Invalid port in '{}'", address)))?;
+    let host = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')).unwrap_or(host);
+    if host.is_empty() || host.len() > 255 {
+        return Err(invalid_input(format!("Invalid host in '{}'", address)));
+    }
+    Ok((host, port))
+}
+
+async fn with_timeout<T>(
+    hop: &ProxyHop,
+    future: impl std::future::Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    tokio::time::timeout(hop.connect_timeout, future).await.map_err(|_| {
+        io::Error::new(io::ErrorKind::TimedOut, format!("Proxy {} timed out", hop.address))
+    })?
+}
+
+/// Authenticate with the proxy at the other end of `stream`
+async fn negotiate<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, hop: &ProxyHop) -> io::Result<()> {
+    let method = if hop.credentials.is_some() { METHOD_USERNAME_PASSWORD } else { METHOD_NO_AUTH };
+    stream.write_all(&[SOCKS_VERSION, 1, method]).await?;
+
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS_VERSION {
+        return Err(protocol_error(format!("{} is not a SOCKS5 proxy", hop.address)));
+    }
+    if reply[1] == METHOD_UNACCEPTABLE || reply[1] != method {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("Proxy {} refused our authentication method", hop.address),
+        ));
+    }
+
+    if let Some((username, password)) = &hop.credentials {
+        if username.len() > 255 || password.len() > 255 {
+            return Err(invalid_input("SOCKS5 credentials are limited to 255 bytes".to_string()));
+        }
+        let mut request = vec![0x01, username.len() as u8];
+        request.extend_from_slice(username.as_bytes());
+        request.push(password.len() as u8);
+        request.extend_from_slice(password.as_bytes());
+        stream.write_all(&request).await?;
+
+        let mut status = [0u8; 2];
+        stream.read_exact(&mut status).await?;
+        if status[1] != 0x00 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Proxy {} rejected the credentials", hop.address),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Ask the proxy at the other end of `stream` to connect to `target`
+async fn request_connect<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, proxy: &str, target: &str) -> io::Result<()> {
+    let (host, port) = split_host_port(target)?;
+    let mut request = vec![SOCKS_VERSION, COMMAND_CONNECT, 0x00];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(ATYP_IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(ATYP_IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+        // Hostnames are resolved by the proxy, so nothing leaks to local DNS
+        Err(_) => {
+            request.push(ATYP_DOMAIN);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await?;
+    if header[0] != SOCKS_VERSION {
+        return Err(protocol_error(format!("Malformed reply from proxy {}", proxy)));
+    }
+    if header[1] != 0x00 {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("Proxy {} could not connect to {} (SOCKS5 error {})", proxy, target, header[1]),
+        ));
+    }
+
+    // Skip the bound address
+    let address_len = match header[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => stream.read_u8().await? as usize,
+        other => return Err(protocol_error(format!("Unknown address type {} from proxy {}", other, proxy))),
+    };
+    let mut bound = vec![0u8; address_len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+impl ProxyChain {
+    pub fn new(hops: Vec<ProxyHop>) -> Self {
+        Self { hops }
+    }
+
+    pub fn hops(&self) -> &[ProxyHop] {
+        &self.hops
+    }
+
+    /// Open a connection to `target` (`host:port`) through every hop. The
+    /// returned stream talks to `target` directly; all SOCKS framing is done.
+    pub async fn connect(&self, target: &str) -> io::Result<TcpStream> {
+        let first = self
+            .hops
+            .first()
+            .ok_or_else(|| invalid_input("Proxy chain has no hops".to_string()))?;
+        let mut stream = with_timeout(first, TcpStream::connect(first.address.as_str())).await?;
+        stream.set_nodelay(true)?;
+
+        for (index, hop) in self.hops.iter().enumerate() {
+            let next = self.hops.get(index + 1).map_or(target, |next| next.address.as_str());
+            // The next hop's timeout covers reaching it through this one and negotiating with it
+            let timeout_hop = self.hops.get(index + 1).unwrap_or(hop);
+            with_timeout(timeout_hop, async {
+                if index == 0 {
+                    negotiate(&mut stream, hop).await?;
+                }
+                request_connect(&mut stream, &hop.address, next).await?;
+                if let Some(next_hop) = self.hops.get(index + 1) {
+                    negotiate(&mut stream, next_hop).await?;
+                }
+                Ok(())
+            })
+            .await?;
+        }
+        Ok(stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+    use tokio::net::TcpListener;
+
+    /// Resolve a SOCKS5 address from raw parts
+    fn socket_addr(atyp: u8, address: &[u8], port: u16) -> Option<String> {
+        match atyp {
+            ATYP_IPV4 => Some(SocketAddr::new(Ipv4Addr::from(<[u8; 4]>::try_from(address).ok()?).into(), port).to_string()),
+            ATYP_IPV6 => Some(SocketAddr::new(Ipv6Addr::from(<[u8; 16]>::try_from(address).ok()?).into(), port).to_string()),
+            ATYP_DOMAIN => Some(format!("{}:{}", String::from_utf8_lossy(address), port)),
+            _ => None,
+        }
+    }
+
+    async fn read_field(client: &mut TcpStream) -> io::Result<Vec<u8>> {
+        let len = client.read_u8().await? as usize;
+        let mut value = vec![0u8; len];
+        client.read_exact(&mut value).await?;
+        Ok(value)
+    }
+
+    /// Minimal SOCKS5 server: optional username/password, CONNECT only
+    async fn mock_proxy(credentials: Option<(&'static str, &'static str)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((mut client, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut greeting = [0u8; 2];
+                    client.read_exact(&mut greeting).await?;
+                    let mut methods = vec![0u8; greeting[1] as usize];
+                    client.read_exact(&mut methods).await?;
+                    let wanted = if credentials.is_some() { METHOD_USERNAME_PASSWORD } else { METHOD_NO_AUTH };
+                    if !methods.contains(&wanted) {
+                        client.write_all(&[SOCKS_VERSION, METHOD_UNACCEPTABLE]).await?;
+                        return Ok::<_, io::Error>(());
+                    }
+                    client.write_all(&[SOCKS_VERSION, wanted]).await?;
+
+                    if let Some((username, password)) = credentials {
+                        client.read_u8().await?;
+                        let given_username = read_field(&mut client).await?;
+                        let given_password = read_field(&mut client).await?;
+                        let ok = given_username == username.as_bytes() && given_password == password.as_bytes();
+                        client.write_all(&[0x01, if ok { 0x00 } else { 0x01 }]).await?;
+                        if !ok {
+                            return Ok(());
+                        }
+                    }
+
+                    let mut header = [0u8; 4];
+                    client.read_exact(&mut header).await?;
+                    let len = match header[3] {
+                        ATYP_IPV4 => 4,
+                        ATYP_IPV6 => 16,
+                        _ => client.read_u8().await? as usize,
+                    };
+                    let mut address = vec![0u8; len];
+                    client.read_exact(&mut address).await?;
+                    let port = client.read_u16().await?;
+                    let target = socket_addr(header[3], &address, port).unwrap();
+
+                    match TcpStream::connect(target).await {
+                        Ok(mut upstream) => {
+                            client.write_all(&[SOCKS_VERSION, 0x00, 0x00, ATYP_IPV4, 0, 0, 0, 0, 0, 0]).await?;
+                            tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+                        }
+                        Err(_) => client.write_all(&[SOCKS_VERSION, 0x05, 0x00, ATYP_IPV4, 0, 0, 0, 0, 0, 0]).await?,
+                    }
+                    Ok(())
+                });
+            }
+        });
+        address
+    }
+
+    async fn echo_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (mut reader, mut writer) = socket.split();
+                    let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                });
+            }
+        });
+        address
+    }
+
+    #[tokio::test]
+    async fn test_two_hop_chain() {
+        let first = mock_proxy(Some(("alice", "secret"))).await;
+        let second = mock_proxy(None).await;
+        let target = echo_server().await;
+
+        let chain = ProxyChain::new(vec![
+            ProxyHop::new(first).with_credentials("alice", "secret"),
+            ProxyHop::new(second).with_timeout(Duration::from_secs(5)),
+        ]);
+        let mut stream = chain.connect(&target).await.unwrap();
+        stream.write_all(b"through two hops").await.unwrap();
+        let mut echoed = [0u8; 16];
+        stream.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"through two hops");
+    }
+
+    #[tokio::test]
+    async fn test_chain_failures() {
+        let guarded = mock_proxy(Some(("alice", "secret"))).await;
+        let open = mock_proxy(None).await;
+        let target = echo_server().await;
+
+        let wrong_password = ProxyChain::new(vec![
+            ProxyHop::new(open.clone()),
+            ProxyHop::new(guarded.clone()).with_credentials("alice", "wrong"),
+        ]);
+        let error = wrong_password.connect(&target).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+
+        let missing_auth = ProxyChain::new(vec![ProxyHop::new(guarded)]);
+        assert_eq!(missing_auth.connect(&target).await.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+
+        let unreachable = ProxyChain::new(vec![ProxyHop::new(open)]);
+        assert_eq!(unreachable.connect("127.0.0.1:1").await.unwrap_err().kind(), io::ErrorKind::ConnectionRefused);
+
+        assert_eq!(ProxyChain::new(Vec::new()).connect(&target).await.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(split_host_port("[::1]:9050").is_ok());
+        assert!(split_host_port("nohost").is_err());
+    }
+}
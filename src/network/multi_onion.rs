@@ -1,5 +1,195 @@
 //! Multi-Onion Management Module
 //! 
 //! Host multiple .onion addresses with different security levels
+//!
+//! Each hosted onion gets its own `OnionServiceProtection`, so connection
+//! counts and limits of one site never affect another.
 
-// TODO: Implement multi-onion address management functionality
+use crate::tor::onion_service::{ConnectionStats, OnionAddress, OnionServiceConfig, OnionServiceProtection};
+use crate::tor::{TorSecurityConfig, TorSecurityError, TorSecurityResult};
+use log::info;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+/// An onion service hosted by this process
+#[derive(Debug, Clone)]
+pub struct HostedOnionService {
+    pub address: OnionAddress,
+    pub config: OnionServiceConfig,
+    /// Directory holding the service's key material, i.e. its `HiddenServiceDir`
+    pub key_path: PathBuf,
+    /// Local port Tor forwards the service's connections to
+    pub target_port: u16,
+}
+
+struct ServiceSlot {
+    service: HostedOnionService,
+    protection: OnionServiceProtection,
+}
+
+/// Registry of hosted onion services, each with independent protection
+pub struct MultiOnionManager {
+    tor_config: TorSecurityConfig,
+    services: HashMap<OnionAddress, ServiceSlot>,
+}
+
+impl MultiOnionManager {
+    /// Services added later derive their protection defaults from `tor_config`
+    pub fn new(tor_config: &TorSecurityConfig) -> Self {
+        Self {
+            tor_config: tor_config.clone(),
+            services: HashMap::new(),
+        }
+    }
+
+    /// Start protecting `service`. Fails if its address is already hosted or its
+    /// target port is taken by another service.
+    pub fn add_service(&mut self, service: HostedOnionService) -> TorSecurityResult<()> {
+        if self.services.contains_key(&service.address) {
+            return Err(TorSecurityError::ConfigurationError(format!(
+                "Onion service {} is already hosted",
+                service.address.as_str()
+            )));
+        }
+        if service.target_port == 0 {
+            return Err(TorSecurityError::ConfigurationError(format!(
+                "Onion service {} needs a target port",
+                service.address.as_str()
+            )));
+        }
+        if let Some(other) = self.services.values().find(|slot| slot.service.target_port == service.target_port) {
+            return Err(TorSecurityError::ConfigurationError(format!(
+                "Target port {} is already used by {}",
+                service.target_port,
+                other.service.address.as_str()
+            )));
+        }
+
+        let mut protection = OnionServiceProtection::new(&self.tor_config)?;
+        protection.initialize()?;
+        protection.register_onion_service_with_config(service.address.clone(), service.config.clone())?;
+        info!("Hosting onion service {} on port {}", service.address.as_str(), service.target_port);
+        self.services.insert(service.address.clone(), ServiceSlot { service, protection });
+        Ok(())
+    }
+
+    /// Stop hosting `address`, returning its definition
+    pub fn remove_service(&mut self, address: &OnionAddress) -> Option<HostedOnionService> {
+        let mut slot = self.services.remove(address)?;
+        // Shutdown only clears in-memory tracking
+        let _ = slot.protection.shutdown();
+        Some(slot.service)
+    }
+
+    /// Hosted services, ordered by address
+    pub fn list_services(&self) -> Vec<&HostedOnionService> {
+        let mut services: Vec<_> = self.services.values().map(|slot| &slot.service).collect();
+        services.sort_by(|a, b| a.address.as_str().cmp(b.address.as_str()));
+        services
+    }
+
+    pub fn get_service(&self, address: &OnionAddress) -> Option<&HostedOnionService> {
+        self.services.get(address).map(|slot| &slot.service)
+    }
+
+    fn slot_mut(&mut self, address: &OnionAddress) -> TorSecurityResult<&mut ServiceSlot> {
+        self.services.get_mut(address).ok_or_else(|| {
+            TorSecurityError::InvalidOnionAddress(format!("{} is not hosted here", address.as_str()))
+        })
+    }
+
+    /// Decide whether a connection to `address` is admitted, using that service's limits
+    pub fn should_allow_connection(
+        &mut self,
+        client_ip: IpAddr,
+        address: &OnionAddress,
+        circuit_id: Option<&str>,
+    ) -> TorSecurityResult<bool> {
+        self.slot_mut(address)?
+            .protection
+            .should_allow_connection_on_circuit(client_ip, address, circuit_id)
+    }
+
+    /// Release a connection admitted by `should_allow_connection`
+    pub fn connection_closed(
+        &mut self,
+        client_ip: IpAddr,
+        address: &OnionAddress,
+        circuit_id: Option<&str>,
+    ) -> TorSecurityResult<()> {
+        let protection = &mut self.slot_mut(address)?.protection;
+        match circuit_id {
+            Some(circuit_id) => protection.connection_closed_for_circuit(client_ip, address, circuit_id),
+            None => protection.connection_closed(client_ip, address),
+        }
+        Ok(())
+    }
+
+    /// Connection statistics of one service
+    pub fn service_stats(&self, address: &OnionAddress) -> Option<ConnectionStats> {
+        self.services.get(address).map(|slot| slot.protection.get_connection_stats())
+    }
+
+    /// Drop stale rate limit tracking in every service
+    pub fn cleanup_expired_connections(&mut self) {
+        for slot in self.services.values_mut() {
+            slot.protection.cleanup_expired_connections();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(seed: u8, target_port: u16, max_concurrent_connections: u32) -> HostedOnionService {
+        HostedOnionService {
+            address: OnionAddress::from_public_key(&[seed; 32]),
+            config: OnionServiceConfig { max_concurrent_connections, ..OnionServiceConfig::default() },
+            key_path: PathBuf::from(format!("/var/lib/tor/site{}", seed)),
+            target_port,
+        }
+    }
+
+    #[test]
+    fn test_services_have_independent_limits() {
+        let mut onions = MultiOnionManager::new(&TorSecurityConfig::default());
+        let strict = service(1, 8080, 1);
+        let relaxed = service(2, 8081, 100);
+        onions.add_service(strict.clone()).unwrap();
+        onions.add_service(relaxed.clone()).unwrap();
+        let client: IpAddr = "192.0.2.7".parse().unwrap();
+
+        assert!(onions.should_allow_connection(client, &strict.address, None).unwrap());
+        assert!(!onions.should_allow_connection(client, &strict.address, None).unwrap());
+        assert!(onions.should_allow_connection(client, &relaxed.address, None).unwrap());
+
+        onions.connection_closed(client, &strict.address, None).unwrap();
+        assert!(onions.should_allow_connection(client, &strict.address, None).unwrap());
+        assert_eq!(onions.service_stats(&relaxed.address).unwrap().active_connections, 1);
+    }
+
+    #[test]
+    fn test_add_list_and_remove() {
+        let mut onions = MultiOnionManager::new(&TorSecurityConfig::default());
+        onions.add_service(service(2, 8081, 10)).unwrap();
+        onions.add_service(service(1, 8080, 10)).unwrap();
+
+        assert!(onions.add_service(service(1, 9000, 10)).is_err());
+        assert!(onions.add_service(service(3, 8080, 10)).is_err());
+        assert!(onions.add_service(service(3, 0, 10)).is_err());
+
+        let listed: Vec<_> = onions.list_services().iter().map(|s| s.target_port).collect();
+        assert_eq!(listed.len(), 2);
+        let removed = onions.remove_service(&OnionAddress::from_public_key(&[1; 32])).unwrap();
+        assert_eq!(removed.target_port, 8080);
+        assert_eq!(onions.list_services().len(), 1);
+
+        let gone = OnionAddress::from_public_key(&[1; 32]);
+        assert!(matches!(
+            onions.should_allow_connection("192.0.2.7".parse().unwrap(), &gone, None),
+            Err(TorSecurityError::InvalidOnionAddress(_))
+        ));
+    }
+}
@@ -1,5 +1,159 @@
 //! Steganographic Channels Module
 //! 
 //! Hide data in legitimate-looking traffic
+//!
+//! Payloads are hidden in the least-significant bit of each red, green and blue
+//! sample of a PNG, one bit per sample. A 4-byte big-endian length header comes
+//! first. Alpha is left alone, since changing fully transparent pixels stands out.
+//! The payload is not encrypted; encrypt it first if it must stay secret.
 
-// TODO: Implement steganographic communication functionality
+use image::{ImageFormat, ImageOutputFormat, RgbaImage};
+use std::error::Error;
+use std::fmt;
+use std::io::Cursor;
+
+const LENGTH_HEADER: usize = 4;
+
+/// Errors raised while embedding or extracting
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StegoError {
+    /// The input could not be decoded as a PNG
+    InvalidCarrier(String),
+    PayloadTooLarge { size: usize, capacity: usize },
+    /// The image carries no payload, or the header was damaged
+    NoPayload,
+    EncodingFailed(String),
+}
+
+impl fmt::Display for StegoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StegoError::InvalidCarrier(e) => write!(f, "Carrier is not a readable PNG: {}", e),
+            StegoError::PayloadTooLarge { size, capacity } => {
+                write!(f, "Payload of {} bytes exceeds the carrier's capacity of {} bytes", size, capacity)
+            }
+            StegoError::NoPayload => write!(f, "Image does not carry a payload"),
+            StegoError::EncodingFailed(e) => write!(f, "Failed to encode PNG: {}", e),
+        }
+    }
+}
+
+impl Error for StegoError {}
+
+fn decode(png: &[u8]) -> Result<RgbaImage, StegoError> {
+    image::load_from_memory_with_format(png, ImageFormat::Png)
+        .map(|image| image.to_rgba8())
+        .map_err(|e| StegoError::InvalidCarrier(e.to_string()))
+}
+
+/// Color samples that carry bits, skipping every alpha sample
+fn carrier_samples(image: &mut RgbaImage) -> impl Iterator<Item = &mut u8> {
+    image.pixels_mut().flat_map(|pixel| pixel.0.iter_mut().take(3))
+}
+
+fn payload_capacity(image: &RgbaImage) -> usize {
+    let bits = image.width() as usize * image.height() as usize * 3;
+    (bits / 8).saturating_sub(LENGTH_HEADER)
+}
+
+/// Payload bytes `carrier_png` can hold
+pub fn capacity(carrier_png: &[u8]) -> Result<usize, StegoError> {
+    Ok(payload_capacity(&decode(carrier_png)?))
+}
+
+/// Hide `payload` in `carrier_png`, returning the new PNG
+pub fn embed(carrier_png: &[u8], payload: &[u8]) -> Result<Vec<u8>, StegoError> {
+    let mut image = decode(carrier_png)?;
+    let capacity = payload_capacity(&image);
+    if payload.len() > capacity || payload.len() > u32::MAX as usize {
+        return Err(StegoError::PayloadTooLarge { size: payload.len(), capacity });
+    }
+
+    let header = (payload.len() as u32).to_be_bytes();
+    let bits = header
+        .iter()
+        .chain(payload)
+        .flat_map(|byte| (0..8).rev().map(move |shift| (byte >> shift) & 1));
+    for (sample, bit) in carrier_samples(&mut image).zip(bits) {
+        *sample = (*sample & !1) | bit;
+    }
+
+    let mut output = Cursor::new(Vec::new());
+    image
+        .write_to(&mut output, ImageOutputFormat::Png)
+        .map_err(|e| StegoError::EncodingFailed(e.to_string()))?;
+    Ok(output.into_inner())
+}
+
+/// Recover the payload hidden by `embed`
+pub fn extract(stego_png: &[u8]) -> Result<Vec<u8>, StegoError> {
+    let mut image = decode(stego_png)?;
+    let capacity = payload_capacity(&image);
+    let mut bytes = carrier_samples(&mut image)
+        .map(|sample| *sample & 1)
+        .collect::<Vec<_>>()
+        .chunks_exact(8)
+        .map(|bits| bits.iter().fold(0u8, |byte, bit| (byte << 1) | bit))
+        .collect::<Vec<_>>();
+
+    if bytes.len() < LENGTH_HEADER {
+        return Err(StegoError::NoPayload);
+    }
+    let length = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+    if length > capacity {
+        return Err(StegoError::NoPayload);
+    }
+    bytes.truncate(LENGTH_HEADER + length);
+    bytes.drain(..LENGTH_HEADER);
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn carrier(width: u32, height: u32) -> Vec<u8> {
+        let image = RgbaImage::from_fn(width, height, |x, y| image::Rgba([x as u8, y as u8, (x ^ y) as u8, 255]));
+        let mut png = Cursor::new(Vec::new());
+        image.write_to(&mut png, ImageOutputFormat::Png).unwrap();
+        png.into_inner()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let carrier = carrier(64, 64);
+        assert_eq!(capacity(&carrier).unwrap(), 64 * 64 * 3 / 8 - 4);
+
+        let mut payload = vec![0u8; 1000];
+        rand::thread_rng().fill(payload.as_mut_slice());
+        let stego = embed(&carrier, &payload).unwrap();
+        assert_eq!(extract(&stego).unwrap(), payload);
+
+        // Only the lowest bit of color samples changes
+        let (before, after) = (decode(&carrier).unwrap(), decode(&stego).unwrap());
+        assert!(before.pixels().zip(after.pixels()).all(|(a, b)| {
+            a.0[3] == b.0[3] && a.0.iter().zip(b.0.iter()).all(|(x, y)| x >> 1 == y >> 1)
+        }));
+
+        assert_eq!(extract(&embed(&carrier, b"").unwrap()).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_errors() {
+        let carrier = carrier(8, 8);
+        let capacity = capacity(&carrier).unwrap();
+        assert_eq!(
+            embed(&carrier, &vec![1; capacity + 1]).unwrap_err(),
+            StegoError::PayloadTooLarge { size: capacity + 1, capacity }
+        );
+        assert!(embed(&carrier, &vec![1; capacity]).is_ok());
+
+        assert!(matches!(embed(b"not a png", b"x"), Err(StegoError::InvalidCarrier(_))));
+        // A white image reads as an all-ones length header
+        let blank = RgbaImage::from_pixel(8, 8, image::Rgba([255; 4]));
+        let mut png = Cursor::new(Vec::new());
+        blank.write_to(&mut png, ImageOutputFormat::Png).unwrap();
+        assert_eq!(extract(&png.into_inner()).unwrap_err(), StegoError::NoPayload);
+    }
+}
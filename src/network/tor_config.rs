@@ -1,5 +1,365 @@
 //! Automatic Tor Configuration Module
 //! 
 //! Auto-configure Tor hidden services
+//!
+//! `TorrcConfig` reads the directives RustWall cares about out of an existing
+//! torrc into typed fields. Everything else is kept verbatim, so a config can be
+//! inspected without losing what the parser doesn't model.
 
-// TODO: Implement automatic Tor configuration functionality
+use std::error::Error;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// Errors raised while reading a torrc; `line` is 1-based
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TorConfigError {
+    MissingValue { line: usize, directive: String },
+    InvalidValue { line: usize, directive: String, value: String },
+    /// A `HiddenService*` option appeared before any `HiddenServiceDir`
+    OrphanHiddenServiceOption { line: usize, directive: String },
+}
+
+impl fmt::Display for TorConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TorConfigError::MissingValue { line, directive } => {
+                write!(f, "Line {}: {} needs a value", line, directive)
+            }
+            TorConfigError::InvalidValue { line, directive, value } => {
+                write!(f, "Line {}: invalid {} value '{}'", line, directive, value)
+            }
+            TorConfigError::OrphanHiddenServiceOption { line, directive } => {
+                write!(f, "Line {}: {} must follow a HiddenServiceDir", line, directive)
+            }
+        }
+    }
+}
+
+impl Error for TorConfigError {}
+
+/// Where a port directive listens, or where a hidden service forwards to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortAddress {
+    /// `auto`: Tor picks a free port
+    Auto,
+    /// `0`: the listener is disabled
+    Disabled,
+    Port(u16),
+    Socket(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl PortAddress {
+    fn parse(value: &str) -> Option<Self> {
+        if value.eq_ignore_ascii_case("auto") {
+            return Some(PortAddress::Auto);
+        }
+        if let Some(path) = value.strip_prefix("unix:") {
+            return (!path.is_empty()).then(|| PortAddress::Unix(PathBuf::from(path.trim_matches('"'))));
+        }
+        match value.parse::<u16>() {
+            Ok(0) => Some(PortAddress::Disabled),
+            Ok(port) => Some(PortAddress::Port(port)),
+            Err(_) => value.parse().ok().map(PortAddress::Socket),
+        }
+    }
+}
+
+impl fmt::Display for PortAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortAddress::Auto => write!(f, "auto"),
+            PortAddress::Disabled => write!(f, "0"),
+            PortAddress::Port(port) => write!(f, "{}", port),
+            PortAddress::Socket(addr) => write!(f, "{}", addr),
+            PortAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// A `SocksPort` or `ControlPort` line: the address plus any flags after it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortConfig {
+    pub address: PortAddress,
+    /// Flags such as `IsolateDestAddr`, verbatim
+    pub flags: Vec<String>,
+}
+
+impl fmt::Display for PortConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.address)?;
+        for flag in &self.flags {
+            write!(f, " {}", flag)?;
+        }
+        Ok(())
+    }
+}
+
+/// A `HiddenServicePort VIRTPORT [TARGET]` line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HiddenServicePort {
+    pub virtual_port: u16,
+    /// Where connections are forwarded; Tor uses the virtual port on localhost when absent
+    pub target: Option<PortAddress>,
+}
+
+/// A `HiddenServiceDir` and the options that follow it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HiddenService {
+    pub dir: PathBuf,
+    pub ports: Vec<HiddenServicePort>,
+    /// Other `HiddenService*` lines of this service, such as `HiddenServiceVersion 3`, verbatim
+    pub options: Vec<String>,
+}
+
+/// Structured view of a torrc
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TorrcConfig {
+    pub socks_ports: Vec<PortConfig>,
+    pub control_ports: Vec<PortConfig>,
+    pub hashed_control_passwords: Vec<String>,
+    pub hidden_services: Vec<HiddenService>,
+    /// Comments and directives the parser doesn't model, verbatim and in order
+    pub other_lines: Vec<String>,
+}
+
+/// Renders valid torrc text: preserved lines first, then the port and password
+/// directives, then each hidden service as its `HiddenServiceDir`, its ports in
+/// order and its other options
+impl fmt::Display for TorrcConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.other_lines {
+            writeln!(f, "{}", line)?;
+        }
+        for port in &self.socks_ports {
+            writeln!(f, "SocksPort {}", port)?;
+        }
+        for port in &self.control_ports {
+            writeln!(f, "ControlPort {}", port)?;
+        }
+        for password in &self.hashed_control_passwords {
+            writeln!(f, "HashedControlPassword {}", password)?;
+        }
+        for service in &self.hidden_services {
+            writeln!(f)?;
+            writeln!(f, "HiddenServiceDir {}", service.dir.display())?;
+            for port in &service.ports {
+                match &port.target {
+                    Some(target) => writeln!(f, "HiddenServicePort {} {}", port.virtual_port, target)?,
+                    None => writeln!(f, "HiddenServicePort {}", port.virtual_port)?,
+                }
+            }
+            for option in &service.options {
+                writeln!(f, "{}", option)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Drop a trailing `#` comment; torrc has no quoting for `#` in the options we parse
+fn strip_comment(line: &str) -> &str {
+    line.split('#').next().unwrap_or("").trim()
+}
+
+impl TorrcConfig {
+    /// Parse torrc text. Keywords are matched case-insensitively, as Tor does,
+    /// and lines ending in `\` continue on the next line.
+    pub fn parse(text: &str) -> Result<TorrcConfig, TorConfigError> {
+        let mut config = TorrcConfig::default();
+        let mut pending = String::new();
+        let mut pending_start = 0;
+
+        for (index, raw) in text.lines().enumerate() {
+            if pending.is_empty() {
+                pending_start = index + 1;
+            }
+            if let Some(continued) = raw.strip_suffix('\\') {
+                pending.push_str(continued);
+                continue;
+            }
+            pending.push_str(raw);
+            let line = std::mem::take(&mut pending);
+            config.parse_line(&line, pending_start)?;
+        }
+        if !pending.is_empty() {
+            config.parse_line(&pending, pending_start)?;
+        }
+        Ok(config)
+    }
+
+    fn parse_line(&mut self, line: &str, number: usize) -> Result<(), TorConfigError> {
+        let content = strip_comment(line);
+        if content.is_empty() {
+            if !line.trim().is_empty() {
+                self.other_lines.push(line.trim_end().to_string());
+            }
+            return Ok(());
+        }
+
+        let (keyword, value) = match content.split_once(char::is_whitespace) {
+            Some((keyword, value)) => (keyword, value.trim()),
+            None => (content, ""),
+        };
+        let directive = keyword.to_ascii_lowercase();
+        let missing = || TorConfigError::MissingValue { line: number, directive: keyword.to_string() };
+        let invalid = || TorConfigError::InvalidValue {
+            line: number,
+            directive: keyword.to_string(),
+            value: value.to_string(),
+        };
+
+        match directive.as_str() {
+            "socksport" | "controlport" => {
+                let mut parts = value.split_whitespace();
+                let address = parts.next().ok_or_else(missing)?;
+                let port = PortConfig {
+                    address: PortAddress::parse(address).ok_or_else(invalid)?,
+                    flags: parts.map(str::to_string).collect(),
+                };
+                if directive == "socksport" {
+                    self.socks_ports.push(port);
+                } else {
+                    self.control_ports.push(port);
+                }
+            }
+            "hashedcontrolpassword" => {
+                if value.is_empty() {
+                    return Err(missing());
+                }
+                self.hashed_control_passwords.push(value.to_string());
+            }
+            "hiddenservicedir" => {
+                if value.is_empty() {
+                    return Err(missing());
+                }
+                self.hidden_services.push(HiddenService {
+                    dir: PathBuf::from(value),
+                    ports: Vec::new(),
+                    options: Vec::new(),
+                });
+            }
+            _ if directive.starts_with("hiddenservice") => {
+                // Tor attaches every HiddenService* option to the most recent HiddenServiceDir
+                let service = self.hidden_services.last_mut().ok_or_else(|| {
+                    TorConfigError::OrphanHiddenServiceOption { line: number, directive: keyword.to_string() }
+                })?;
+                if directive == "hiddenserviceport" {
+                    let mut parts = value.split_whitespace();
+                    let virtual_port = parts.next().ok_or_else(missing)?;
+                    let virtual_port = virtual_port.parse::<u16>().ok().filter(|port| *port != 0).ok_or_else(invalid)?;
+                    let target = parts.next().map(|target| PortAddress::parse(target).ok_or_else(invalid)).transpose()?;
+                    if parts.next().is_some() {
+                        return Err(invalid());
+                    }
+                    service.ports.push(HiddenServicePort { virtual_port, target });
+                } else {
+                    service.options.push(content.to_string());
+                }
+            }
+            _ => self.other_lines.push(line.trim_end().to_string()),
+        }
+        Ok(())
+    }
+
+    /// Serialize back into torrc text; same as `to_string()`
+    pub fn render(&self) -> String {
+        self.to_string()
+    }
+
+    /// The hidden service stored in `dir`, if any
+    pub fn hidden_service(&self, dir: impl AsRef<std::path::Path>) -> Option<&HiddenService> {
+        self.hidden_services.iter().find(|service| service.dir == dir.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+# Managed by hand
+SocksPort 9050
+SocksPort 127.0.0.1:9150 IsolateDestAddr
+ControlPort 9051
+HashedControlPassword 16:872860B76453A77D60CA2BB8C1A7042072093276A3D701AD684053EC4C
+Log notice file /var/log/tor/notices.log
+
+HiddenServiceDir /var/lib/tor/web/
+HiddenServiceVersion 3
+HiddenServicePort 80 127.0.0.1:8080
+HiddenServicePort 443 unix:/run/web.sock
+
+HiddenServiceDir /var/lib/tor/ssh/
+HiddenServicePort 22
+ExitPolicy reject *:*
+";
+
+    #[test]
+    fn test_parse_common_directives() {
+        let config = TorrcConfig::parse(SAMPLE).unwrap();
+
+        assert_eq!(config.socks_ports.len(), 2);
+        assert_eq!(config.socks_ports[0].address, PortAddress::Port(9050));
+        assert_eq!(config.socks_ports[1].address, PortAddress::Socket("127.0.0.1:9150".parse().unwrap()));
+        assert_eq!(config.socks_ports[1].flags, ["IsolateDestAddr"]);
+        assert_eq!(config.control_ports[0].address, PortAddress::Port(9051));
+        assert!(config.hashed_control_passwords[0].starts_with("16:"));
+        assert_eq!(
+            config.other_lines,
+            ["# Managed by hand", "Log notice file /var/log/tor/notices.log", "ExitPolicy reject *:*"]
+        );
+    }
+
+    #[test]
+    fn test_hidden_service_stanzas_group_under_their_dir() {
+        let config = TorrcConfig::parse(SAMPLE).unwrap();
+        assert_eq!(config.hidden_services.len(), 2);
+
+        let web = config.hidden_service("/var/lib/tor/web/").unwrap();
+        assert_eq!(web.options, ["HiddenServiceVersion 3"]);
+        assert_eq!(
+            web.ports,
+            [
+                HiddenServicePort { virtual_port: 80, target: Some(PortAddress::Socket("127.0.0.1:8080".parse().unwrap())) },
+                HiddenServicePort { virtual_port: 443, target: Some(PortAddress::Unix("/run/web.sock".into())) },
+            ]
+        );
+
+        let ssh = config.hidden_service("/var/lib/tor/ssh/").unwrap();
+        assert_eq!(ssh.ports, [HiddenServicePort { virtual_port: 22, target: None }]);
+    }
+
+    #[test]
+    fn test_parse_errors_and_continuations() {
+        assert_eq!(
+            TorrcConfig::parse("HiddenServicePort 80").unwrap_err(),
+            TorConfigError::OrphanHiddenServiceOption { line: 1, directive: "HiddenServicePort".to_string() }
+        );
+        assert!(matches!(
+            TorrcConfig::parse("\nSocksPort nine").unwrap_err(),
+            TorConfigError::InvalidValue { line: 2, .. }
+        ));
+        assert!(matches!(TorrcConfig::parse("ControlPort").unwrap_err(), TorConfigError::MissingValue { .. }));
+
+        let config = TorrcConfig::parse("socksport auto \\\n  IsolateSOCKSAuth # continued\n").unwrap();
+        assert_eq!(config.socks_ports[0].address, PortAddress::Auto);
+        assert_eq!(config.socks_ports[0].flags, ["IsolateSOCKSAuth"]);
+    }
+
+    #[test]
+    fn test_render_round_trips() {
+        let config = TorrcConfig::parse(SAMPLE).unwrap();
+        let rendered = config.render();
+        assert_eq!(TorrcConfig::parse(&rendered).unwrap(), config);
+
+        let web = rendered.find("HiddenServiceDir /var/lib/tor/web/").unwrap();
+        let http = rendered.find("HiddenServicePort 80 127.0.0.1:8080").unwrap();
+        let https = rendered.find("HiddenServicePort 443 unix:/run/web.sock").unwrap();
+        let ssh = rendered.find("HiddenServiceDir /var/lib/tor/ssh/").unwrap();
+        assert!(web < http && http < https && https < ssh);
+        assert!(rendered.contains("SocksPort 127.0.0.1:9150 IsolateDestAddr\n"));
+        assert!(rendered.contains("ExitPolicy reject *:*\n"));
+    }
+}
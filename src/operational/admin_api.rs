@@ -0,0 +1,334 @@
+//! Admin API Module
+//!
+//! Bearer-token REST endpoints for managing a running Tor security stack:
+//! blocklist edits, module switches, statistics and the emergency kill switch.
+
+use super::emergency_shutdown::{token_matches, EmergencyShutdown};
+use crate::tor::exit_node_filter::BlocklistSource;
+use crate::tor::{TorModule, TorSecurityError, TorSecurityManager, TorSecurityResult};
+use axum::extract::{Path, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use ipnet::IpNet;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn default_severity() -> u8 {
+    5
+}
+
+/// Body of `POST /admin/blocklist`
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlocklistRequest {
+    /// A single address or a CIDR range
+    pub target: String,
+    pub reason: String,
+    #[serde(default = "default_severity")]
+    pub severity: u8,
+    /// Lift the block after this many seconds; blocks without it are permanent
+    #[serde(default)]
+    pub expires_in_secs: Option<u64>,
+}
+
+/// Body of `POST /admin/emergency`
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmergencyRequest {
+    pub reason: String,
+}
+
+/// Body of `POST /admin/emergency/reset`
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmergencyResetRequest {
+    /// The kill switch's own reset token, on top of the admin bearer token
+    pub reset_token: String,
+}
+
+/// Body of `POST /admin/modules/{name}`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModuleRequest {
+    pub enabled: bool,
+}
+
+#[derive(Clone)]
+struct AdminState {
+    manager: Arc<TorSecurityManager>,
+    shutdown: Arc<EmergencyShutdown>,
+    token_hash: [u8; 32],
+}
+
+/// A failed admin call, answered as `{"error": ...}`
+struct ApiError(StatusCode, String);
+
+impl From<TorSecurityError> for ApiError {
+    fn from(error: TorSecurityError) -> Self {
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+fn parse_target(target: &str) -> Result<IpNet, ApiError> {
+    target
+        .parse::<IpNet>()
+        .or_else(|_| target.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| ApiError(StatusCode::BAD_REQUEST, format!("Invalid address or range: {}", target)))
+}
+
+/// Routes for runtime management, all behind `Authorization: Bearer <token>`
+///
+/// - `POST /admin/blocklist` blocks an address or range, answering 201
+/// - `DELETE /admin/blocklist/{target}` lifts a block, answering 204
+/// - `GET /admin/trusted` lists trusted exit nodes
+/// - `GET /admin/stats` returns statistics from every enabled module
+/// - `POST /admin/emergency` trips the kill switch
+/// - `POST /admin/emergency/reset` clears it, given its reset token, answering 403 otherwise
+/// - `POST /admin/modules/{name}` switches a module on or off
+///
+/// Requests without the token get 401. The token must not be empty.
+pub fn admin_router(
+    manager: Arc<TorSecurityManager>,
+    shutdown: Arc<EmergencyShutdown>,
+    token: &str,
+) -> TorSecurityResult<Router> {
+    if token.is_empty() {
+        return Err(TorSecurityError::ConfigurationError(
+            "Admin API token must not be empty".to_string(),
+        ));
+    }
+
+    let state = AdminState { manager, shutdown, token_hash: Sha256::digest(token.as_bytes()).into() };
+    Ok(Router::new()
+        .route("/admin/blocklist", post(add_block_handler))
+        .route("/admin/blocklist/*target", delete(remove_block_handler))
+        .route("/admin/trusted", get(trusted_handler))
+        .route("/admin/stats", get(stats_handler))
+        .route("/admin/emergency", post(emergency_handler))
+        .route("/admin/emergency/reset", post(emergency_reset_handler))
+        .route("/admin/modules/:name", post(module_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state))
+}
+
+// Reject any request that does not carry the bearer token
+async fn require_token(State(state): State<AdminState>, request: Request, next: Next) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| token_matches(&state.token_hash, token));
+    if !authorized {
+        let mut response = ApiError(StatusCode::UNAUTHORIZED, "Missing or invalid bearer token".to_string()).into_response();
+        response.headers_mut().insert(header::WWW_AUTHENTICATE, header::HeaderValue::from_static("Bearer"));
+        return response;
+    }
+    next.run(request).await
+}
+
+// Route: POST /admin/blocklist - block an address or range
+async fn add_block_handler(
+    State(state): State<AdminState>,
+    Json(body): Json<BlocklistRequest>,
+) -> Result<StatusCode, ApiError> {
+    let target = parse_target(&body.target)?;
+    let mut filter = state.manager.exit_node_filter();
+    let expires_at = body
+        .expires_in_secs
+        .map(|secs| {
+            filter.now().checked_add(Duration::from_secs(secs)).ok_or_else(|| {
+                ApiError(StatusCode::BAD_REQUEST, format!("Block expiry is too far in the future: {}s", secs))
+            })
+        })
+        .transpose()?;
+    filter.add_to_blocklist(target, BlocklistSource::Manual, body.reason, expires_at, body.severity)?;
+    Ok(StatusCode::CREATED)
+}
+
+// Route: DELETE /admin/blocklist/{target} - lift a block
+async fn remove_block_handler(
+    State(state): State<AdminState>,
+    Path(target): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let target = parse_target(&target)?;
+    state.manager.exit_node_filter().remove_from_blocklist(target)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Route: GET /admin/trusted - trusted exit nodes
+async fn trusted_handler(State(state): State<AdminState>) -> Json<Vec<IpAddr>> {
+    Json(state.manager.exit_node_filter().list_trusted())
+}
+
+// Route: GET /admin/stats - statistics from every enabled module
+async fn stats_handler(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.manager.aggregate_stats())
+}
+
+// Route: POST /admin/emergency - trip the kill switch
+async fn emergency_handler(
+    State(state): State<AdminState>,
+    Json(body): Json<EmergencyRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    state.shutdown.trigger(&body.reason)?;
+    Ok(Json(serde_json::json!({ "tripped": true, "reason": state.shutdown.reason() })))
+}
+
+// Route: POST /admin/emergency/reset - clear the kill switch
+async fn emergency_reset_handler(
+    State(state): State<AdminState>,
+    Json(body): Json<EmergencyResetRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    state.shutdown.reset(&body.reset_token).map_err(|e| match e {
+        TorSecurityError::SecurityViolation(message) => ApiError(StatusCode::FORBIDDEN, message),
+        e => e.into(),
+    })?;
+    Ok(Json(serde_json::json!({ "tripped": false })))
+}
+
+// Route: POST /admin/modules/{name} - switch a module on or off
+async fn module_handler(
+    State(state): State<AdminState>,
+    Path(name): Path<String>,
+    Json(body): Json<ModuleRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let module: TorModule = name.parse().map_err(|e: TorSecurityError| ApiError(StatusCode::NOT_FOUND, e.to_string()))?;
+    state.manager.set_module_enabled(module, body.enabled)?;
+    Ok(Json(serde_json::json!({ "module": name, "enabled": body.enabled })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "admin secret";
+
+    fn admin() -> (Arc<TorSecurityManager>, Arc<EmergencyShutdown>, Router) {
+        let manager = Arc::new(TorSecurityManager::new().unwrap());
+        manager.initialize().unwrap();
+        let shutdown = Arc::new(EmergencyShutdown::new(Arc::clone(&manager), "reset token").unwrap());
+        let router = admin_router(Arc::clone(&manager), Arc::clone(&shutdown), TOKEN).unwrap();
+        (manager, shutdown, router)
+    }
+
+    async fn call(router: &Router, method: &str, uri: &str, token: Option<&str>, body: Option<serde_json::Value>) -> Response {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let body = match body {
+            Some(json) => {
+                request = request.header(header::CONTENT_TYPE, "application/json");
+                Body::from(json.to_string())
+            }
+            None => Body::empty(),
+        };
+        router.clone().oneshot(request.body(body).unwrap()).await.unwrap()
+    }
+
+    #[test]
+    fn test_empty_token_rejected() {
+        let (manager, shutdown, _) = admin();
+        assert!(admin_router(manager, shutdown, "").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_requests_need_the_token() {
+        let (_, _, router) = admin();
+
+        let response = call(&router, "GET", "/admin/stats", None, None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+
+        let response = call(&router, "GET", "/admin/stats", Some("guess"), None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = call(&router, "GET", "/admin/stats", Some(TOKEN), None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_blocklist_edits_reach_the_filter() {
+        let (manager, _, router) = admin();
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        assert!(manager.exit_node_filter().should_allow_exit_node(ip).unwrap());
+
+        let body = serde_json::json!({ "target": "203.0.113.0/24", "reason": "abuse reports" });
+        let response = call(&router, "POST", "/admin/blocklist", Some(TOKEN), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert!(!manager.exit_node_filter().should_allow_exit_node(ip).unwrap());
+
+        let response = call(&router, "DELETE", "/admin/blocklist/203.0.113.0/24", Some(TOKEN), None).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(manager.exit_node_filter().should_allow_exit_node(ip).unwrap());
+
+        let body = serde_json::json!({ "target": "not an address", "reason": "typo" });
+        let response = call(&router, "POST", "/admin/blocklist", Some(TOKEN), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body = serde_json::json!({ "target": "203.0.113.7", "reason": "forever", "expires_in_secs": u64::MAX });
+        let response = call(&router, "POST", "/admin/blocklist", Some(TOKEN), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(manager.exit_node_filter().should_allow_exit_node(ip).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_block_expiry_follows_the_filter_clock() {
+        let clock = MockClock::new();
+        let manager = Arc::new(TorSecurityManager::new().unwrap().with_clock(Arc::new(clock.clone())));
+        manager.initialize().unwrap();
+        let shutdown = Arc::new(EmergencyShutdown::new(Arc::clone(&manager), "reset token").unwrap());
+        let router = admin_router(Arc::clone(&manager), shutdown, TOKEN).unwrap();
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+
+        let body = serde_json::json!({ "target": "203.0.113.7", "reason": "cool off", "expires_in_secs": 60 });
+        let response = call(&router, "POST", "/admin/blocklist", Some(TOKEN), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert!(!manager.exit_node_filter().should_allow_exit_node(ip).unwrap());
+
+        clock.advance(Duration::from_secs(61));
+        assert!(manager.exit_node_filter().should_allow_exit_node(ip).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_module_switch_and_emergency() {
+        let (manager, shutdown, router) = admin();
+
+        let body = serde_json::json!({ "enabled": false });
+        let response = call(&router, "POST", "/admin/modules/circuit_analysis", Some(TOKEN), Some(body.clone())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!manager.config().enable_circuit_analysis);
+
+        let response = call(&router, "POST", "/admin/modules/firewall", Some(TOKEN), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let body = serde_json::json!({ "reason": "key compromise" });
+        let response = call(&router, "POST", "/admin/emergency", Some(TOKEN), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(shutdown.is_tripped());
+        assert_eq!(shutdown.reason().as_deref(), Some("key compromise"));
+
+        let body = serde_json::json!({ "reset_token": "guess" });
+        let response = call(&router, "POST", "/admin/emergency/reset", Some(TOKEN), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(shutdown.is_tripped());
+
+        let body = serde_json::json!({ "reset_token": "reset token" });
+        let response = call(&router, "POST", "/admin/emergency/reset", Some(TOKEN), Some(body)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!shutdown.is_tripped());
+        assert!(!manager.lockdown_flag().load(std::sync::atomic::Ordering::SeqCst));
+    }
+}
@@ -1,5 +1,221 @@
 //! Audit Logging Module
 //! 
 //! Comprehensive security audit logging
+//!
+//! Events are appended as JSON lines. Each line carries the hash of the line
+//! before it and its own hash over that link plus the event, so editing or
+//! deleting any entry breaks the chain from that point on.
 
-// TODO: Implement audit logging functionality
+use crate::tor::{TorSecurityError, TorSecurityResult};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// `prev_hash` of the first entry in a log
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// How an audited action turned out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+    Denied,
+}
+
+/// A security-relevant action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub timestamp: DateTime<Utc>,
+    pub actor: String,
+    pub action: String,
+    pub outcome: AuditOutcome,
+}
+
+impl AuditEvent {
+    /// An event stamped with the current time
+    pub fn new(actor: impl Into<String>, action: impl Into<String>, outcome: AuditOutcome) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            actor: actor.into(),
+            action: action.into(),
+            outcome,
+        }
+    }
+}
+
+/// One line of the log file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AuditEntry {
+    #[serde(flatten)]
+    event: AuditEvent,
+    prev_hash: String,
+    hash: String,
+}
+
+fn entry_hash(prev_hash: &str, event: &AuditEvent) -> String {
+    // Serializing strings, a timestamp and a unit variant cannot fail
+    let event_json = serde_json::to_vec(event).unwrap_or_default();
+    let digest = Sha256::new()
+        .chain_update(prev_hash.as_bytes())
+        .chain_update(&event_json)
+        .finalize();
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Append-only, hash-chained audit log backed by a JSON lines file
+pub struct AuditLog {
+    path: PathBuf,
+    last_hash: String,
+    entries: usize,
+}
+
+impl AuditLog {
+    /// Open the log at `path`, continuing the chain of any entries already there
+    pub fn open(path: impl AsRef<Path>) -> TorSecurityResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut last_hash = GENESIS_HASH.to_string();
+        let mut entries = 0;
+
+        if path.exists() {
+            let contents = fs::read_to_string(&path).map_err(|e| {
+                TorSecurityError::ConfigurationError(format!("Failed to read audit log {}: {}", path.display(), e))
+            })?;
+            for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+                let entry: AuditEntry = serde_json::from_str(line).map_err(|e| {
+                    TorSecurityError::SecurityViolation(format!(
+                        "Audit log {} has an unreadable entry {}: {}",
+                        path.display(),
+                        entries,
+                        e
+                    ))
+                })?;
+                last_hash = entry.hash;
+                entries += 1;
+            }
+        }
+
+        Ok(Self { path, last_hash, entries })
+    }
+
+    /// Number of entries in the log
+    pub fn len(&self) -> usize {
+        self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    /// Append `event`, chaining it to the previous entry
+    pub fn append(&mut self, event: AuditEvent) -> TorSecurityResult<()> {
+        let hash = entry_hash(&self.last_hash, &event);
+        let entry = AuditEntry {
+            event,
+            prev_hash: self.last_hash.clone(),
+            hash: hash.clone(),
+        };
+        let mut line = serde_json::to_string(&entry).map_err(|e| {
+            TorSecurityError::ConfigurationError(format!("Failed to serialize audit event: {}", e))
+        })?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| {
+                TorSecurityError::ConfigurationError(format!("Failed to open audit log {}: {}", self.path.display(), e))
+            })?;
+        file.write_all(line.as_bytes())
+            .and_then(|_| file.sync_data())
+            .map_err(|e| {
+                TorSecurityError::ConfigurationError(format!("Failed to write audit log {}: {}", self.path.display(), e))
+            })?;
+
+        self.last_hash = hash;
+        self.entries += 1;
+        Ok(())
+    }
+
+    /// Check every link of the chain, returning the index of the first entry
+    /// that was altered, reordered, unreadable or follows a deleted one.
+    /// A log file that can no longer be read fails at index 0.
+    pub fn verify_chain(&self) -> Result<(), usize> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(_) if self.entries == 0 => return Ok(()),
+            Err(_) => return Err(0),
+        };
+
+        let mut expected_prev = GENESIS_HASH.to_string();
+        let mut count = 0;
+        for (index, line) in contents.lines().filter(|line| !line.trim().is_empty()).enumerate() {
+            let Ok(entry) = serde_json::from_str::<AuditEntry>(line) else { return Err(index) };
+            if entry.prev_hash != expected_prev || entry.hash != entry_hash(&entry.prev_hash, &entry.event) {
+                return Err(index);
+            }
+            expected_prev = entry.hash;
+            count += 1;
+        }
+
+        // Entries cut from the end leave the chain intact but shorter than we wrote
+        if count < self.entries {
+            return Err(count);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_log(path: &Path) -> AuditLog {
+        let mut log = AuditLog::open(path).unwrap();
+        log.append(AuditEvent::new("operator", "login", AuditOutcome::Success)).unwrap();
+        log.append(AuditEvent::new("operator", "block_exit_node 198.51.100.1", AuditOutcome::Success)).unwrap();
+        log.append(AuditEvent::new("anonymous", "reset_emergency_shutdown", AuditOutcome::Denied)).unwrap();
+        log
+    }
+
+    #[test]
+    fn test_chain_verifies_and_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let log = write_log(&path);
+        assert_eq!(log.len(), 3);
+        assert_eq!(log.verify_chain(), Ok(()));
+
+        let mut reopened = AuditLog::open(&path).unwrap();
+        assert_eq!(reopened.len(), 3);
+        reopened.append(AuditEvent::new("operator", "logout", AuditOutcome::Success)).unwrap();
+        assert_eq!(reopened.verify_chain(), Ok(()));
+
+        let first: serde_json::Value = serde_json::from_str(fs::read_to_string(&path).unwrap().lines().next().unwrap()).unwrap();
+        assert_eq!(first["actor"], "operator");
+        assert_eq!(first["outcome"], "success");
+        assert_eq!(first["prev_hash"], GENESIS_HASH);
+    }
+
+    #[test]
+    fn test_tampering_breaks_the_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let log = write_log(&path);
+        let original = fs::read_to_string(&path).unwrap();
+
+        fs::write(&path, original.replace("198.51.100.1", "198.51.100.2")).unwrap();
+        assert_eq!(log.verify_chain(), Err(1));
+
+        let lines: Vec<_> = original.lines().collect();
+        fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+        assert_eq!(log.verify_chain(), Err(1));
+
+        fs::write(&path, format!("{}\n{}\n", lines[0], lines[1])).unwrap();
+        assert_eq!(log.verify_chain(), Err(2));
+    }
+}
@@ -1,5 +1,375 @@
 //! Backup Management Module
 //! 
 //! Secure backup and recovery operations
+//!
+//! A backup is a single file: a short header, then the JSON archive encrypted
+//! with AES-256-GCM under a key derived from the passphrase with Argon2id. The
+//! header (format version, salt and nonce) is authenticated along with the
+//! archive, so any change to the file makes it fail to decrypt.
 
-// TODO: Implement backup management functionality
+#[cfg(feature = "captcha")]
+use crate::captcha::session::{SessionStore, StoredSession};
+use crate::tor::{TorSecurityError, TorSecurityManager, TorSecurityResult, TorStateSnapshot};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+const MAGIC: &[u8; 8] = b"RWBACKUP";
+const FORMAT_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + 1 + SALT_LEN + NONCE_LEN;
+
+/// State owned outside the Tor security manager that belongs in backups,
+/// such as the CAPTCHA server's active sessions
+pub trait BackupSection: Send + Sync {
+    /// Key the section is stored under in the archive
+    fn name(&self) -> &str;
+    fn export(&self) -> TorSecurityResult<serde_json::Value>;
+    /// Check archived data without applying it; called for every section before any restore
+    fn validate(&self, data: &serde_json::Value) -> TorSecurityResult<()>;
+    /// Apply data that passed `validate`
+    fn restore(&self, data: serde_json::Value);
+}
+
+/// Unexpired CAPTCHA sessions, keyed by id. Restoring adds them to the store
+/// alongside any sessions it already holds.
+#[cfg(feature = "captcha")]
+impl BackupSection for SessionStore {
+    fn name(&self) -> &str {
+        "captcha_sessions"
+    }
+
+    fn export(&self) -> TorSecurityResult<serde_json::Value> {
+        let sessions: BTreeMap<_, _> = self
+            .snapshot()
+            .into_iter()
+            .map(|(id, session)| (id, StoredSession::from(&session)))
+            .collect();
+        serde_json::to_value(sessions)
+            .map_err(|e| TorSecurityError::ConfigurationError(format!("Failed to export sessions: {}", e)))
+    }
+
+    fn validate(&self, data: &serde_json::Value) -> TorSecurityResult<()> {
+        serde_json::from_value::<BTreeMap<String, StoredSession>>(data.clone())
+            .map(|_| ())
+            .map_err(|e| TorSecurityError::ConfigurationError(format!("Invalid session backup: {}", e)))
+    }
+
+    fn restore(&self, data: serde_json::Value) {
+        let sessions: BTreeMap<String, StoredSession> = serde_json::from_value(data).unwrap_or_default();
+        for (id, stored) in sessions {
+            if let Err(e) = self.restore_session(id.clone(), stored.into()) {
+                warn!("Failed to restore session {}: {}", id, e);
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct BackupArchive {
+    created_at: DateTime<Utc>,
+    tor: TorStateSnapshot,
+    sections: BTreeMap<String, serde_json::Value>,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> TorSecurityResult<[u8; 32]> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| TorSecurityError::ConfigurationError(format!("Failed to derive backup key: {}", e)))?;
+    Ok(key)
+}
+
+/// Encrypted snapshots of operational state for disaster recovery
+pub struct BackupManager {
+    manager: Arc<TorSecurityManager>,
+    sections: Vec<Box<dyn BackupSection>>,
+}
+
+impl BackupManager {
+    /// Back up the onion registrations, exit node blocklist and reputation held by `manager`
+    pub fn new(manager: Arc<TorSecurityManager>) -> Self {
+        Self { manager, sections: Vec::new() }
+    }
+
+    /// Include another section in backups; a section with the same name is replaced
+    pub fn register_section(&mut self, section: impl BackupSection + 'static) {
+        self.sections.retain(|existing| existing.name() != section.name());
+        self.sections.push(Box::new(section));
+    }
+
+    /// Snapshot all state and write it to `path`, encrypted with `passphrase`
+    pub fn create_backup(&self, path: impl AsRef<Path>, passphrase: &str) -> TorSecurityResult<()> {
+        let path = path.as_ref();
+        if passphrase.is_empty() {
+            return Err(TorSecurityError::ConfigurationError("Backup passphrase must not be empty".to_string()));
+        }
+
+        let mut sections = BTreeMap::new();
+        for section in &self.sections {
+            sections.insert(section.name().to_string(), section.export()?);
+        }
+        let archive = BackupArchive {
+            created_at: Utc::now(),
+            tor: self.manager.export_state(),
+            sections,
+        };
+        let plaintext = serde_json::to_vec(&archive).map_err(|e| {
+            TorSecurityError::ConfigurationError(format!("Failed to serialize backup: {}", e))
+        })?;
+
+        let salt: [u8; SALT_LEN] = rand::random();
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let mut file = Vec::with_capacity(HEADER_LEN + plaintext.len() + 16);
+        file.extend_from_slice(MAGIC);
+        file.push(FORMAT_VERSION);
+        file.extend_from_slice(&salt);
+        file.extend_from_slice(&nonce);
+
+        let key = derive_key(passphrase, &salt)?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: &plaintext, aad: &file })
+            .map_err(|_| TorSecurityError::ConfigurationError("Failed to encrypt backup".to_string()))?;
+        file.extend_from_slice(&ciphertext);
+
+        // Write beside the target and rename, so a crash never leaves a truncated backup
+        let partial = path.with_extension("partial");
+        fs::write(&partial, &file)
+            .and_then(|_| fs::rename(&partial, path))
+            .map_err(|e| {
+                TorSecurityError::ConfigurationError(format!("Failed to write backup {}: {}", path.display(), e))
+            })?;
+
+        info!("Wrote backup to {}", path.display());
+        Ok(())
+    }
+
+    /// Decrypt the backup at `path` and rehydrate the state it holds.
+    ///
+    /// The whole archive is decrypted, parsed and validated before anything is
+    /// applied, so a wrong passphrase or a corrupt file leaves current state untouched.
+    /// Registered sections missing from the archive are left as they are.
+    pub fn restore_backup(&self, path: impl AsRef<Path>, passphrase: &str) -> TorSecurityResult<()> {
+        let path = path.as_ref();
+        let file = fs::read(path).map_err(|e| {
+            TorSecurityError::ConfigurationError(format!("Failed to read backup {}: {}", path.display(), e))
+        })?;
+
+        if file.len() < HEADER_LEN || &file[..MAGIC.len()] != MAGIC {
+            return Err(TorSecurityError::ConfigurationError(format!("{} is not a backup file", path.display())));
+        }
+        if file[MAGIC.len()] != FORMAT_VERSION {
+            return Err(TorSecurityError::ConfigurationError(format!(
+                "Unsupported backup format version {}",
+                file[MAGIC.len()]
+            )));
+        }
+        let (header, ciphertext) = file.split_at(HEADER_LEN);
+        let salt = &header[MAGIC.len() + 1..MAGIC.len() + 1 + SALT_LEN];
+        let nonce = &header[MAGIC.len() + 1 + SALT_LEN..];
+
+        let key = derive_key(passphrase, salt)?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+            .map_err(|_| {
+                TorSecurityError::SecurityViolation(format!(
+                    "Backup {} could not be decrypted: wrong passphrase or corrupted file",
+                    path.display()
+                ))
+            })?;
+
+        let mut archive: BackupArchive = serde_json::from_slice(&plaintext).map_err(|e| {
+            TorSecurityError::ConfigurationError(format!("Malformed backup archive: {}", e))
+        })?;
+        for section in &self.sections {
+            if let Some(data) = archive.sections.get(section.name()) {
+                section.validate(data)?;
+            }
+        }
+
+        self.manager.import_state(archive.tor);
+        for section in &self.sections {
+            if let Some(data) = archive.sections.remove(section.name()) {
+                section.restore(data);
+            }
+        }
+
+        info!("Restored backup taken at {}", archive.created_at);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tor::TorSecurityConfig;
+    use crate::tor::exit_node_filter::{BlocklistSource, ExitNodeFilter};
+    use crate::tor::onion_service::{OnionAddress, OnionServiceProtection};
+    use std::sync::Mutex;
+
+    /// Stand-in for an external session store
+    struct Sessions(Arc<Mutex<Vec<String>>>);
+
+    impl BackupSection for Sessions {
+        fn name(&self) -> &str {
+            "sessions"
+        }
+
+        fn export(&self) -> TorSecurityResult<serde_json::Value> {
+            Ok(serde_json::json!(*self.0.lock().unwrap()))
+        }
+
+        fn validate(&self, data: &serde_json::Value) -> TorSecurityResult<()> {
+            serde_json::from_value::<Vec<String>>(data.clone())
+                .map(|_| ())
+                .map_err(|e| TorSecurityError::ConfigurationError(e.to_string()))
+        }
+
+        fn restore(&self, data: serde_json::Value) {
+            *self.0.lock().unwrap() = serde_json::from_value(data).unwrap_or_default();
+        }
+    }
+
+    fn populated_manager() -> Arc<TorSecurityManager> {
+        let config = TorSecurityConfig::default();
+        let mut filter = ExitNodeFilter::new(&config).unwrap();
+        filter
+            .add_to_blocklist(
+                "198.51.100.0/24".parse::<ipnet::IpNet>().unwrap(),
+                BlocklistSource::Manual,
+                "bad range".to_string(),
+                None,
+                8,
+            )
+            .unwrap();
+        let mut onions = OnionServiceProtection::new(&config).unwrap();
+        onions.register_onion_service(OnionAddress::from_public_key(&[3; 32])).unwrap();
+
+        let manager = TorSecurityManager::new().unwrap();
+        manager.import_state(TorStateSnapshot {
+            onion_service: onions.snapshot(),
+            exit_node_filter: filter.snapshot(),
+        });
+        Arc::new(manager)
+    }
+
+    #[test]
+    fn test_backup_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.backup");
+        let sessions = Arc::new(Mutex::new(vec!["session-a".to_string()]));
+
+        let mut backups = BackupManager::new(populated_manager());
+        backups.register_section(Sessions(Arc::clone(&sessions)));
+        backups.create_backup(&path, "long passphrase").unwrap();
+        assert!(!fs::read(&path).unwrap().windows(9).any(|w| w == b"bad range"));
+
+        let fresh = Arc::new(TorSecurityManager::new().unwrap());
+        let restored_sessions = Arc::new(Mutex::new(Vec::new()));
+        let mut restore = BackupManager::new(Arc::clone(&fresh));
+        restore.register_section(Sessions(Arc::clone(&restored_sessions)));
+        restore.restore_backup(&path, "long passphrase").unwrap();
+
+        let snapshot = serde_json::to_value(fresh.export_state()).unwrap();
+        assert_eq!(snapshot["exit_node_filter"]["blocklist"][0]["reason"], "bad range");
+        assert_eq!(snapshot["onion_service"]["protected_onions"].as_array().unwrap().len(), 1);
+        assert_eq!(*restored_sessions.lock().unwrap(), ["session-a"]);
+    }
+
+    #[cfg(feature = "captcha")]
+    #[test]
+    fn test_session_store_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.backup");
+        let store = SessionStore::new();
+        let kept = store.create_session(4, 20).unwrap();
+        store.validate_and_remove(&kept, 5, 0, None);
+        let answered = store.create_session(7, 45).unwrap();
+        store.validate_and_remove(&answered, 7, 45, None);
+
+        let mut backups = BackupManager::new(populated_manager());
+        backups.register_section(store);
+        backups.create_backup(&path, "long passphrase").unwrap();
+
+        let restored = SessionStore::new();
+        let mut restore = BackupManager::new(Arc::new(TorSecurityManager::new().unwrap()));
+        restore.register_section(restored.clone());
+        restore.restore_backup(&path, "long passphrase").unwrap();
+
+        assert_eq!(restored.session_count(), 1);
+        let session = restored.get_session(&kept).unwrap();
+        assert_eq!((session.correct_hour, session.correct_minute, session.attempts), (4, 20, 1));
+        assert!(!session.is_expired());
+        assert!(restored.get_session(&answered).is_none());
+    }
+
+    #[test]
+    fn test_bad_restores_apply_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.backup");
+        BackupManager::new(populated_manager()).create_backup(&path, "long passphrase").unwrap();
+
+        let fresh = Arc::new(TorSecurityManager::new().unwrap());
+        let restore = BackupManager::new(Arc::clone(&fresh));
+        let empty = serde_json::to_value(fresh.export_state()).unwrap();
+
+        assert!(matches!(
+            restore.restore_backup(&path, "wrong passphrase"),
+            Err(TorSecurityError::SecurityViolation(_))
+        ));
+
+        let mut corrupt = fs::read(&path).unwrap();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0x01;
+        fs::write(&path, &corrupt).unwrap();
+        assert!(restore.restore_backup(&path, "long passphrase").is_err());
+
+        fs::write(&path, b"RWBACKUP").unwrap();
+        assert!(restore.restore_backup(&path, "long passphrase").is_err());
+
+        assert_eq!(serde_json::to_value(fresh.export_state()).unwrap(), empty);
+        assert!(BackupManager::new(fresh).create_backup(&path, "").is_err());
+    }
+
+    #[test]
+    fn test_invalid_section_blocks_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.backup");
+
+        struct Broken;
+        impl BackupSection for Broken {
+            fn name(&self) -> &str {
+                "sessions"
+            }
+            fn export(&self) -> TorSecurityResult<serde_json::Value> {
+                Ok(serde_json::json!({ "not": "a list" }))
+            }
+            fn validate(&self, _data: &serde_json::Value) -> TorSecurityResult<()> {
+                Ok(())
+            }
+            fn restore(&self, _data: serde_json::Value) {}
+        }
+
+        let mut backups = BackupManager::new(populated_manager());
+        backups.register_section(Broken);
+        backups.create_backup(&path, "long passphrase").unwrap();
+
+        let fresh = Arc::new(TorSecurityManager::new().unwrap());
+        let mut restore = BackupManager::new(Arc::clone(&fresh));
+        restore.register_section(Sessions(Arc::new(Mutex::new(Vec::new()))));
+        assert!(restore.restore_backup(&path, "long passphrase").is_err());
+
+        let snapshot = serde_json::to_value(fresh.export_state()).unwrap();
+        assert!(snapshot["exit_node_filter"]["blocklist"].as_array().unwrap().is_empty());
+    }
+}
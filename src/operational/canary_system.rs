@@ -2,4 +2,281 @@
 //! 
 //! Automated warrant canary updates
 
-// TODO: Implement warrant canary system functionality
+use crate::tor::{TorSecurityError, TorSecurityResult};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Contents of a warrant canary
+///
+/// The headlines prove the canary was written no earlier than the news they
+/// describe, so an old canary can't be republished as a fresh one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CanaryDocument {
+    pub statement: String,
+    pub issued_at: DateTime<Utc>,
+    pub headlines: Vec<String>,
+}
+
+/// A canary ready to publish: the document text and a detached signature over it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedCanary {
+    /// JSON-serialized `CanaryDocument`, exactly as signed
+    pub canary: String,
+    /// Base64 Ed25519 signature of `canary`
+    pub signature: String,
+}
+
+impl SignedCanary {
+    /// Parse the signed document; check the signature with `CanarySystem::verify` first
+    pub fn document(&self) -> Option<CanaryDocument> {
+        serde_json::from_str(&self.canary).ok()
+    }
+}
+
+/// Produces warrant canaries signed with the operator's Ed25519 key
+pub struct CanarySystem {
+    signing_key: SigningKey,
+    last_canary: Option<SignedCanary>,
+    state_path: Option<PathBuf>,
+}
+
+impl CanarySystem {
+    /// Create a canary system signing with `signing_key`
+    pub fn new(signing_key: SigningKey) -> Self {
+        Self {
+            signing_key,
+            last_canary: None,
+            state_path: None,
+        }
+    }
+
+    /// Persist published canaries to `path`, picking up the one already
+    /// stored there so staleness survives restarts
+    pub fn with_state_file(mut self, path: impl AsRef<Path>) -> TorSecurityResult<Self> {
+        let path = path.as_ref();
+        if path.exists() {
+            let contents = fs::read_to_string(path).map_err(|e| {
+                TorSecurityError::ConfigurationError(format!("Failed to read canary {}: {}", path.display(), e))
+            })?;
+            let canary = serde_json::from_str(&contents).map_err(|e| {
+                TorSecurityError::ConfigurationError(format!("Failed to parse canary {}: {}", path.display(), e))
+            })?;
+            self.last_canary = Some(canary);
+        }
+        self.state_path = Some(path.to_path_buf());
+        Ok(self)
+    }
+
+    /// Create a canary system with a freshly generated key
+    pub fn generate_key() -> Self {
+        Self::new(SigningKey::from_bytes(&rand::random::<[u8; 32]>()))
+    }
+
+    /// Key consumers use to verify published canaries
+    pub fn public_key(&self) -> VerifyingKey {
+        self.signing_key.verifying_key()
+    }
+
+    /// Write and sign a canary dated now
+    pub fn generate(&self, statement: &str, headlines: &[String]) -> SignedCanary {
+        self.generate_at(statement, headlines, Utc::now())
+    }
+
+    fn generate_at(&self, statement: &str, headlines: &[String], issued_at: DateTime<Utc>) -> SignedCanary {
+        let document = CanaryDocument {
+            statement: statement.to_string(),
+            issued_at,
+            headlines: headlines.to_vec(),
+        };
+        // Serializing strings and a timestamp cannot fail
+        let canary = serde_json::to_string_pretty(&document).unwrap_or_default();
+        let signature = self.signing_key.sign(canary.as_bytes());
+
+        SignedCanary {
+            canary,
+            signature: STANDARD.encode(signature.to_bytes()),
+        }
+    }
+
+    /// Generate a canary, make it the latest published one and save it if a state file is set
+    pub fn publish(&mut self, statement: &str, headlines: &[String]) -> TorSecurityResult<SignedCanary> {
+        let signed = self.generate(statement, headlines);
+        if let Some(path) = &self.state_path {
+            let json = serde_json::to_string(&signed).map_err(|e| {
+                TorSecurityError::ConfigurationError(format!("Failed to serialize canary: {}", e))
+            })?;
+            fs::write(path, json).map_err(|e| {
+                TorSecurityError::ConfigurationError(format!("Failed to write canary {}: {}", path.display(), e))
+            })?;
+        }
+        self.last_canary = Some(signed.clone());
+        Ok(signed)
+    }
+
+    /// The most recently published canary
+    pub fn last_canary(&self) -> Option<&SignedCanary> {
+        self.last_canary.as_ref()
+    }
+
+    /// When the most recently published canary was issued
+    pub fn last_published(&self) -> Option<DateTime<Utc>> {
+        self.last_canary.as_ref()?.document().map(|document| document.issued_at)
+    }
+
+    /// Whether the latest canary is older than `max_age`, or none was ever published.
+    /// Monitoring should alert on this: a canary that stops updating is the signal.
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        self.is_stale_at(max_age, Utc::now())
+    }
+
+    fn is_stale_at(&self, max_age: Duration, now: DateTime<Utc>) -> bool {
+        match self.last_published() {
+            Some(issued_at) => now
+                .signed_duration_since(issued_at)
+                .to_std()
+                .is_ok_and(|age| age > max_age),
+            None => true,
+        }
+    }
+
+    /// Re-publish the canary every `interval` on a background task, with
+    /// headlines from `fetch_headlines` as proof of freshness. A zero interval
+    /// is treated as one millisecond.
+    pub fn spawn_refresh<F>(
+        system: Arc<Mutex<Self>>,
+        interval: Duration,
+        statement: String,
+        fetch_headlines: F,
+    ) -> JoinHandle<()>
+    where
+        F: Fn() -> Vec<String> + Send + 'static,
+    {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval.max(Duration::from_millis(1)));
+            loop {
+                ticker.tick().await;
+                let headlines = fetch_headlines();
+                let Ok(mut system) = system.lock() else {
+                    error!("Canary system lock poisoned, stopping refresh");
+                    return;
+                };
+                if let Err(e) = system.publish(&statement, &headlines) {
+                    warn!("Canary refresh failed: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Check that `canary` was signed by `public_key` and hasn't been altered
+    pub fn verify(canary: &SignedCanary, public_key: &VerifyingKey) -> bool {
+        let Ok(bytes) = STANDARD.decode(&canary.signature) else { return false };
+        let Ok(signature) = Signature::from_slice(&bytes) else { return false };
+        public_key.verify(canary.canary.as_bytes(), &signature).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headlines() -> Vec<String> {
+        vec!["Markets close higher".to_string(), "Storm reaches the coast".to_string()]
+    }
+
+    #[test]
+    fn test_generate_and_verify() {
+        let system = CanarySystem::generate_key();
+        let signed = system.generate("We have not received any secret court orders.", &headlines());
+
+        assert!(CanarySystem::verify(&signed, &system.public_key()));
+        let document = signed.document().unwrap();
+        assert_eq!(document.statement, "We have not received any secret court orders.");
+        assert_eq!(document.headlines, headlines());
+    }
+
+    #[test]
+    fn test_tampering_is_detected() {
+        let system = CanarySystem::generate_key();
+        let signed = system.generate("No warrants served.", &headlines());
+
+        let mut altered = signed.clone();
+        altered.canary = altered.canary.replace("No warrants", "Some warrants");
+        assert!(!CanarySystem::verify(&altered, &system.public_key()));
+
+        let other = CanarySystem::generate_key();
+        assert!(!CanarySystem::verify(&signed, &other.public_key()));
+
+        let mut garbled = signed;
+        garbled.signature = "not a signature".to_string();
+        assert!(!CanarySystem::verify(&garbled, &system.public_key()));
+    }
+
+    #[test]
+    fn test_unrefreshed_canary_goes_stale() {
+        let mut system = CanarySystem::generate_key();
+        let max_age = Duration::from_secs(7 * 24 * 3600);
+        assert!(system.is_stale(max_age));
+
+        let issued_at = Utc::now() - chrono::Duration::days(3);
+        system.last_canary = Some(system.generate_at("No warrants served.", &headlines(), issued_at));
+        assert!(!system.is_stale(max_age));
+        assert!(system.is_stale_at(max_age, issued_at + chrono::Duration::days(8)));
+        assert!(system.is_stale(Duration::from_secs(24 * 3600)));
+    }
+
+    #[test]
+    fn test_published_canary_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("canary.json");
+        let key = SigningKey::from_bytes(&[9; 32]);
+
+        let mut system = CanarySystem::new(key.clone()).with_state_file(&path).unwrap();
+        let published = system.publish("No warrants served.", &headlines()).unwrap();
+
+        let restarted = CanarySystem::new(key).with_state_file(&path).unwrap();
+        assert_eq!(restarted.last_canary(), Some(&published));
+        assert!(!restarted.is_stale(Duration::from_secs(60)));
+        assert!(CanarySystem::verify(restarted.last_canary().unwrap(), &restarted.public_key()));
+
+        fs::write(&path, "garbage").unwrap();
+        assert!(CanarySystem::generate_key().with_state_file(&path).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_task_republishes() {
+        let system = Arc::new(Mutex::new(CanarySystem::generate_key()));
+        let handle = CanarySystem::spawn_refresh(
+            Arc::clone(&system),
+            Duration::from_millis(20),
+            "No warrants served.".to_string(),
+            || vec!["Breaking news".to_string()],
+        );
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        handle.abort();
+
+        let system = system.lock().unwrap();
+        assert!(!system.is_stale(Duration::from_secs(60)));
+        assert_eq!(system.last_canary().unwrap().document().unwrap().headlines, ["Breaking news"]);
+    }
+
+    #[tokio::test]
+    async fn test_zero_refresh_interval_does_not_panic() {
+        let system = Arc::new(Mutex::new(CanarySystem::generate_key()));
+        let handle = CanarySystem::spawn_refresh(Arc::clone(&system), Duration::ZERO, "No warrants served.".to_string(), Vec::new);
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!handle.is_finished());
+        handle.abort();
+        assert!(system.lock().unwrap().last_canary().is_some());
+    }
+}
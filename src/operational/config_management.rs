@@ -1,5 +1,323 @@
 //! Configuration Management Module
 //! 
 //! Secure configuration management and validation
+//!
+//! `ConfigManager` keeps the Tor security manager in step with a TOML file.
+//! Reloads go through `TorSecurityManager::reconfigure`, so a file that fails
+//! to parse or validate is rejected and the running configuration stays put.
+//! Each applied change is reported as a list of `FieldChange`s and the
+//! configuration it replaced is kept for `rollback`.
 
-// TODO: Implement configuration management functionality
+use crate::tor::{TorSecurityConfig, TorSecurityError, TorSecurityManager, TorSecurityResult};
+use log::{info, warn};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::broadcast;
+
+/// Reloads buffered per subscriber before the slowest one starts missing them
+const SUBSCRIBER_CAPACITY: usize = 16;
+const DEFAULT_HISTORY_SIZE: usize = 10;
+
+/// One setting that differs between two configurations
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: serde_json::Value,
+    pub new: serde_json::Value,
+}
+
+struct ConfigState {
+    current: TorSecurityConfig,
+    /// Previously applied configurations, newest first
+    history: VecDeque<TorSecurityConfig>,
+}
+
+/// Loads the Tor security configuration from a file and applies changes to it at runtime
+pub struct ConfigManager {
+    path: PathBuf,
+    manager: Arc<TorSecurityManager>,
+    state: Mutex<ConfigState>,
+    history_size: usize,
+    reloads: broadcast::Sender<TorSecurityConfig>,
+}
+
+/// Keeps a `ConfigManager` watching its file; watching stops when this is dropped
+pub struct ConfigWatch {
+    _watcher: RecommendedWatcher,
+}
+
+impl ConfigManager {
+    /// Load `path` and apply it to `manager`
+    pub fn load(path: impl Into<PathBuf>, manager: Arc<TorSecurityManager>) -> TorSecurityResult<Self> {
+        let path = path.into();
+        let config = read_config(&path)?;
+        config.validate()?;
+        manager.reconfigure(config.clone())?;
+
+        let (reloads, _) = broadcast::channel(SUBSCRIBER_CAPACITY);
+        Ok(Self {
+            path,
+            manager,
+            state: Mutex::new(ConfigState { current: config, history: VecDeque::new() }),
+            history_size: DEFAULT_HISTORY_SIZE,
+            reloads,
+        })
+    }
+
+    /// Keep up to `size` previous configurations for `rollback`
+    pub fn with_history_size(mut self, size: usize) -> Self {
+        self.history_size = size;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn state(&self) -> TorSecurityResult<MutexGuard<'_, ConfigState>> {
+        self.state.lock().map_err(|_| {
+            TorSecurityError::ConfigurationError("Config manager lock poisoned".to_string())
+        })
+    }
+
+    /// The configuration currently applied
+    pub fn current(&self) -> TorSecurityConfig {
+        self.state().map(|state| state.current.clone()).unwrap_or_default()
+    }
+
+    /// Previously applied configurations, newest first
+    pub fn history(&self) -> Vec<TorSecurityConfig> {
+        self.state().map(|state| state.history.iter().cloned().collect()).unwrap_or_default()
+    }
+
+    /// Receive every configuration applied by a successful reload or rollback
+    pub fn subscribe(&self) -> broadcast::Receiver<TorSecurityConfig> {
+        self.reloads.subscribe()
+    }
+
+    /// Settings that differ between `old` and `new`, in field name order
+    pub fn diff(old: &TorSecurityConfig, new: &TorSecurityConfig) -> Vec<FieldChange> {
+        let (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) =
+            (serde_json::to_value(old), serde_json::to_value(new))
+        else {
+            return Vec::new();
+        };
+
+        new.into_iter()
+            .filter_map(|(field, new_value)| {
+                let old_value = old.get(&field).cloned().unwrap_or(serde_json::Value::Null);
+                (old_value != new_value).then_some(FieldChange { field, old: old_value, new: new_value })
+            })
+            .collect()
+    }
+
+    /// Validate `config` and hand it to the manager; `state` is only touched once it applied
+    fn apply(&self, state: &mut ConfigState, config: TorSecurityConfig) -> TorSecurityResult<Vec<FieldChange>> {
+        config.validate()?;
+        let changes = Self::diff(&state.current, &config);
+        if changes.is_empty() {
+            return Ok(changes);
+        }
+
+        self.manager.reconfigure(config.clone())?;
+        state.current = config.clone();
+
+        for change in &changes {
+            info!("Config {}: {} -> {}", change.field, change.old, change.new);
+        }
+        // Nobody listening is fine
+        let _ = self.reloads.send(config);
+        Ok(changes)
+    }
+
+    /// Re-read the file and apply it, returning what changed. On error the
+    /// previous configuration stays active. A file whose contents match the
+    /// running configuration changes nothing and is not announced to subscribers.
+    pub fn reload(&self) -> TorSecurityResult<Vec<FieldChange>> {
+        let config = read_config(&self.path)?;
+        let mut state = self.state()?;
+        let previous = state.current.clone();
+        let changes = self.apply(&mut state, config)?;
+        if !changes.is_empty() {
+            state.history.push_front(previous);
+            state.history.truncate(self.history_size);
+            info!("Reloaded configuration from {}", self.path.display());
+        }
+        Ok(changes)
+    }
+
+    /// Revert to the `n`th previous configuration, `1` being the one before the
+    /// current. It and any newer ones leave the history. The file is not
+    /// rewritten, so its next change is applied as usual.
+    pub fn rollback(&self, n: usize) -> TorSecurityResult<Vec<FieldChange>> {
+        let mut state = self.state()?;
+        if n == 0 || n > state.history.len() {
+            return Err(TorSecurityError::ConfigurationError(format!(
+                "Cannot roll back {} configurations with {} in history",
+                n,
+                state.history.len()
+            )));
+        }
+
+        let target = state.history[n - 1].clone();
+        let changes = self.apply(&mut state, target)?;
+        state.history.drain(..n);
+        info!("Rolled back configuration by {}", n);
+        Ok(changes)
+    }
+
+    /// Reload whenever the file changes. The parent directory is watched, so
+    /// editors that save by replacing the file are picked up too.
+    pub fn watch(self: &Arc<Self>) -> TorSecurityResult<ConfigWatch> {
+        let file_name = self.path.file_name().map(|name| name.to_os_string()).ok_or_else(|| {
+            TorSecurityError::ConfigurationError(format!("{} is not a file", self.path.display()))
+        })?;
+        let directory = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        let config_manager = Arc::clone(self);
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    warn!("Config watch error: {}", e);
+                    return;
+                }
+            };
+            let touches_config = event.paths.iter().any(|path| path.file_name() == Some(file_name.as_os_str()));
+            if touches_config
+                && matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                && let Err(e) = config_manager.reload()
+            {
+                warn!("Rejected configuration reload, keeping previous config: {}", e);
+            }
+        })
+        .map_err(watch_error)?;
+        watcher.watch(&directory, RecursiveMode::NonRecursive).map_err(watch_error)?;
+
+        Ok(ConfigWatch { _watcher: watcher })
+    }
+}
+
+/// Parse the config file, refusing an empty one: it is far more likely to be
+/// caught mid-write than meant to reset everything to the defaults
+fn read_config(path: &Path) -> TorSecurityResult<TorSecurityConfig> {
+    let length = std::fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0);
+    if length == 0 {
+        return Err(TorSecurityError::ConfigurationError(format!(
+            "{} is empty or missing",
+            path.display()
+        )));
+    }
+    TorSecurityConfig::from_toml_path(path)
+}
+
+fn watch_error(e: notify::Error) -> TorSecurityError {
+    TorSecurityError::ConfigurationError(format!("Failed to watch configuration: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::{Duration, Instant};
+
+    fn setup(contents: &str) -> (tempfile::TempDir, Arc<ConfigManager>, Arc<TorSecurityManager>) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tor.toml");
+        fs::write(&path, contents).unwrap();
+        let manager = Arc::new(TorSecurityManager::new().unwrap());
+        let config_manager = Arc::new(ConfigManager::load(path, Arc::clone(&manager)).unwrap());
+        (dir, config_manager, manager)
+    }
+
+    #[test]
+    fn test_invalid_reload_keeps_previous_config() {
+        let (_dir, config_manager, manager) = setup("max_requests_per_window = 300\n");
+        assert_eq!(config_manager.current().max_requests_per_window, 300);
+        let mut reloads = config_manager.subscribe();
+
+        fs::write(config_manager.path(), "rate_limit_window_seconds = 0\n").unwrap();
+        assert!(config_manager.reload().is_err());
+        fs::write(config_manager.path(), "not toml at all [").unwrap();
+        assert!(config_manager.reload().is_err());
+
+        assert_eq!(config_manager.current().max_requests_per_window, 300);
+        assert_eq!(manager.config().max_requests_per_window, 300);
+        assert!(reloads.try_recv().is_err());
+
+        fs::write(config_manager.path(), "enable_circuit_analysis = false\n").unwrap();
+        config_manager.reload().unwrap();
+        assert!(!reloads.try_recv().unwrap().enable_circuit_analysis);
+        assert!(!manager.config().enable_circuit_analysis);
+    }
+
+    #[test]
+    fn test_watch_applies_file_changes() {
+        let (_dir, config_manager, manager) = setup("max_requests_per_window = 300\n");
+        let mut reloads = config_manager.subscribe();
+        let _watch = config_manager.watch().unwrap();
+
+        fs::write(config_manager.path(), "max_requests_per_window = 400\n").unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let reloaded = loop {
+            match reloads.try_recv() {
+                Ok(config) => break config,
+                Err(_) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(20)),
+                Err(e) => panic!("no reload: {}", e),
+            }
+        };
+
+        // The truncation half of the write must not have applied the defaults
+        assert_eq!(reloaded.max_requests_per_window, 400);
+        assert_eq!(manager.config().max_requests_per_window, 400);
+    }
+
+    #[test]
+    fn test_diff_lists_changed_fields() {
+        let old = TorSecurityConfig::default();
+        let new = TorSecurityConfig {
+            enable_ddos_mitigation: false,
+            max_requests_per_window: 500,
+            ..TorSecurityConfig::default()
+        };
+
+        let changes = ConfigManager::diff(&old, &new);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].field, "enable_ddos_mitigation");
+        assert_eq!(changes[0].old, serde_json::json!(true));
+        assert_eq!(changes[1].field, "max_requests_per_window");
+        assert_eq!(changes[1].new, serde_json::json!(500));
+        assert!(ConfigManager::diff(&old, &old).is_empty());
+
+        let json = serde_json::to_value(&changes).unwrap();
+        assert_eq!(json[1]["old"], 100);
+    }
+
+    #[test]
+    fn test_history_and_rollback() {
+        let (_dir, config_manager, manager) = setup("max_requests_per_window = 100\n");
+        let config_manager = Arc::try_unwrap(config_manager).ok().unwrap().with_history_size(2);
+
+        for requests in [200, 300, 400] {
+            fs::write(config_manager.path(), format!("max_requests_per_window = {}\n", requests)).unwrap();
+            let changes = config_manager.reload().unwrap();
+            assert_eq!(changes[0].new, serde_json::json!(requests));
+        }
+        let history: Vec<u32> = config_manager.history().iter().map(|c| c.max_requests_per_window).collect();
+        assert_eq!(history, [300, 200]);
+
+        assert!(config_manager.rollback(0).is_err());
+        assert!(config_manager.rollback(3).is_err());
+        let changes = config_manager.rollback(2).unwrap();
+        assert_eq!((changes[0].old.clone(), changes[0].new.clone()), (serde_json::json!(400), serde_json::json!(200)));
+        assert_eq!(manager.config().max_requests_per_window, 200);
+        assert!(config_manager.history().is_empty());
+        assert!(config_manager.rollback(1).is_err());
+    }
+}
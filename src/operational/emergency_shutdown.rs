@@ -2,4 +2,233 @@
 //! 
 //! Quickly disable services if compromise detected
 
-// TODO: Implement emergency shutdown functionality
+use crate::tor::{TorSecurityError, TorSecurityManager, TorSecurityResult};
+use log::{error, warn};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+/// One-call kill switch for the Tor security stack
+///
+/// Tripping it denies every admission decision straight away, then shuts the
+/// security modules down. It stays tripped until reset with the token it was
+/// created with.
+pub struct EmergencyShutdown {
+    manager: Arc<TorSecurityManager>,
+    tripped: Arc<AtomicBool>,
+    reason: Mutex<Option<String>>,
+    reset_token_hash: [u8; 32],
+}
+
+impl EmergencyShutdown {
+    /// Create a kill switch for `manager` that `reset_token` can clear
+    pub fn new(manager: Arc<TorSecurityManager>, reset_token: &str) -> TorSecurityResult<Self> {
+        if reset_token.is_empty() {
+            return Err(TorSecurityError::ConfigurationError(
+                "Emergency shutdown reset token must not be empty".to_string(),
+            ));
+        }
+
+        let tripped = manager.lockdown_flag();
+        Ok(Self {
+            manager,
+            tripped,
+            reason: Mutex::new(None),
+            reset_token_hash: Sha256::digest(reset_token.as_bytes()).into(),
+        })
+    }
+
+    /// Trip the switch: deny all requests and shut the security modules down
+    pub fn trigger(&self, reason: &str) -> TorSecurityResult<()> {
+        // Set the flag before anything that could fail so admissions stop regardless
+        let already_tripped = self.tripped.swap(true, Ordering::SeqCst);
+        if let Ok(mut current) = self.reason.lock()
+            && current.is_none()
+        {
+            *current = Some(reason.to_string());
+        }
+        if already_tripped {
+            return Ok(());
+        }
+
+        error!("EMERGENCY SHUTDOWN triggered: {}", reason);
+        self.manager.shutdown()
+    }
+
+    /// Whether the switch has been tripped and not yet reset
+    pub fn is_tripped(&self) -> bool {
+        self.tripped.load(Ordering::SeqCst)
+    }
+
+    /// Reason given by the trigger that tripped the switch
+    pub fn reason(&self) -> Option<String> {
+        self.reason.lock().ok().and_then(|reason| reason.clone())
+    }
+
+    /// Clear the tripped state and bring the security modules back up.
+    /// Fails, leaving the switch tripped, unless `auth_token` matches.
+    pub fn reset(&self, auth_token: &str) -> TorSecurityResult<()> {
+        if !token_matches(&self.reset_token_hash, auth_token) {
+            return Err(TorSecurityError::SecurityViolation(
+                "Invalid emergency shutdown reset token".to_string(),
+            ));
+        }
+
+        if !self.is_tripped() {
+            return Ok(());
+        }
+
+        self.manager.initialize()?;
+        if let Ok(mut reason) = self.reason.lock() {
+            *reason = None;
+        }
+        self.tripped.store(false, Ordering::SeqCst);
+        warn!("Emergency shutdown reset");
+        Ok(())
+    }
+}
+
+/// Whether `candidate` hashes to `expected_hash`. Digests are compared so the
+/// check takes the same time wherever the tokens differ.
+pub(crate) fn token_matches(expected_hash: &[u8; 32], candidate: &str) -> bool {
+    let candidate: [u8; 32] = Sha256::digest(candidate.as_bytes()).into();
+    candidate
+        .iter()
+        .zip(expected_hash.iter())
+        .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+        == 0
+}
+
+/// Trips an `EmergencyShutdown` when the operator stops checking in
+///
+/// The operator calls `heartbeat` at least once per `interval`; if a deadline
+/// passes without one, the task started by `spawn` triggers the shutdown and
+/// exits. This protects the service if the operator is compromised or detained.
+pub struct DeadMansSwitch {
+    shutdown: Arc<EmergencyShutdown>,
+    interval: Duration,
+    last_heartbeat: Mutex<Instant>,
+}
+
+impl DeadMansSwitch {
+    /// Create a switch whose first deadline is `interval` from now
+    pub fn new(shutdown: Arc<EmergencyShutdown>, interval: Duration) -> TorSecurityResult<Self> {
+        if interval.is_zero() {
+            return Err(TorSecurityError::ConfigurationError(
+                "Dead man's switch interval must be greater than zero".to_string(),
+            ));
+        }
+        Ok(Self {
+            shutdown,
+            interval,
+            last_heartbeat: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// Check in, pushing the deadline back to a full interval from now
+    pub fn heartbeat(&self) {
+        if let Ok(mut last) = self.last_heartbeat.lock() {
+            *last = Instant::now();
+        }
+    }
+
+    /// Time left before the switch trips without another heartbeat
+    pub fn time_remaining(&self) -> Duration {
+        let last = self.last_heartbeat.lock().map(|last| *last).unwrap_or_else(|e| *e.into_inner());
+        self.interval.saturating_sub(last.elapsed())
+    }
+
+    /// Watch for missed heartbeats on a background task.
+    /// The task finishes once it has triggered the shutdown.
+    pub fn spawn(switch: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let remaining = switch.time_remaining();
+                if remaining.is_zero() {
+                    let reason = format!("no operator heartbeat within {:?}", switch.interval);
+                    if let Err(e) = switch.shutdown.trigger(&reason) {
+                        error!("Dead man's switch failed to shut down cleanly: {}", e);
+                    }
+                    return;
+                }
+                tokio::time::sleep(remaining).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tor::{RequestContext, RequestDecision};
+
+    fn kill_switch() -> (Arc<TorSecurityManager>, EmergencyShutdown) {
+        let manager = Arc::new(TorSecurityManager::new().unwrap());
+        manager.initialize().unwrap();
+        let shutdown = EmergencyShutdown::new(Arc::clone(&manager), "correct horse").unwrap();
+        (manager, shutdown)
+    }
+
+    #[test]
+    fn test_trigger_and_reset() {
+        let (manager, shutdown) = kill_switch();
+        let ctx = RequestContext::default();
+        assert!(!shutdown.is_tripped());
+        assert_eq!(manager.evaluate_request(&ctx).unwrap(), RequestDecision::Allow);
+
+        shutdown.trigger("operator panic button").unwrap();
+        assert!(shutdown.is_tripped());
+        assert_eq!(shutdown.reason().as_deref(), Some("operator panic button"));
+        assert_eq!(manager.evaluate_request(&ctx).unwrap(), RequestDecision::Deny);
+
+        // A second trigger keeps the original reason
+        shutdown.trigger("automated rule").unwrap();
+        assert_eq!(shutdown.reason().as_deref(), Some("operator panic button"));
+
+        shutdown.reset("correct horse").unwrap();
+        assert!(!shutdown.is_tripped());
+        assert!(shutdown.reason().is_none());
+        assert_eq!(manager.evaluate_request(&ctx).unwrap(), RequestDecision::Allow);
+    }
+
+    #[test]
+    fn test_reset_requires_token() {
+        let (manager, shutdown) = kill_switch();
+        shutdown.trigger("compromise suspected").unwrap();
+
+        assert!(matches!(
+            shutdown.reset("wrong token"),
+            Err(TorSecurityError::SecurityViolation(_))
+        ));
+        assert!(shutdown.reset("").is_err());
+        assert!(shutdown.is_tripped());
+        assert_eq!(
+            manager.evaluate_request(&RequestContext::default()).unwrap(),
+            RequestDecision::Deny
+        );
+
+        assert!(EmergencyShutdown::new(manager, "").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dead_mans_switch_trips_without_heartbeat() {
+        let (_manager, shutdown) = kill_switch();
+        let shutdown = Arc::new(shutdown);
+        let switch = Arc::new(DeadMansSwitch::new(Arc::clone(&shutdown), Duration::from_millis(200)).unwrap());
+        let handle = DeadMansSwitch::spawn(Arc::clone(&switch));
+
+        for _ in 0..4 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            switch.heartbeat();
+        }
+        assert!(!shutdown.is_tripped());
+
+        tokio::time::timeout(Duration::from_secs(2), handle).await.unwrap().unwrap();
+        assert!(shutdown.is_tripped());
+        assert!(shutdown.reason().unwrap().contains("heartbeat"));
+
+        assert!(DeadMansSwitch::new(shutdown, Duration::ZERO).is_err());
+    }
+}
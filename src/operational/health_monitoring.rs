@@ -2,4 +2,510 @@
 //! 
 //! System health and security status monitoring
 
-// TODO: Implement health monitoring functionality
+use crate::tor::ddos_mitigation::MitigationState;
+use crate::tor::onion_service::ConnectionStats;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+/// Overall health, ordered from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Healthy,
+    Degraded,
+    Critical,
+}
+
+/// Outcome of a single health check
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CheckResult {
+    pub status: HealthStatus,
+    pub message: String,
+}
+
+impl CheckResult {
+    pub fn healthy(message: impl Into<String>) -> Self {
+        Self { status: HealthStatus::Healthy, message: message.into() }
+    }
+
+    pub fn degraded(message: impl Into<String>) -> Self {
+        Self { status: HealthStatus::Degraded, message: message.into() }
+    }
+
+    pub fn critical(message: impl Into<String>) -> Self {
+        Self { status: HealthStatus::Critical, message: message.into() }
+    }
+}
+
+/// Levels at which the built-in indicators degrade or become critical
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthThresholds {
+    pub degraded_connections: u32,
+    pub critical_connections: u32,
+    pub degraded_sessions: usize,
+    pub critical_sessions: usize,
+    pub degraded_memory_bytes: u64,
+    pub critical_memory_bytes: u64,
+}
+
+impl Default for HealthThresholds {
+    fn default() -> Self {
+        Self {
+            degraded_connections: 800,
+            critical_connections: 1000,
+            degraded_sessions: 8_000,
+            critical_sessions: 10_000,
+            degraded_memory_bytes: 512 * 1024 * 1024,
+            critical_memory_bytes: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+impl HealthThresholds {
+    fn grade<T: PartialOrd>(value: T, degraded: T, critical: T) -> HealthStatus {
+        if value >= critical {
+            HealthStatus::Critical
+        } else if value >= degraded {
+            HealthStatus::Degraded
+        } else {
+            HealthStatus::Healthy
+        }
+    }
+
+    /// Grade the active onion service connections
+    pub fn check_connections(&self, stats: &ConnectionStats) -> CheckResult {
+        CheckResult {
+            status: Self::grade(stats.active_connections, self.degraded_connections, self.critical_connections),
+            message: format!("{} active connections", stats.active_connections),
+        }
+    }
+
+    /// Grade the number of live CAPTCHA sessions
+    pub fn check_sessions(&self, sessions: usize) -> CheckResult {
+        CheckResult {
+            status: Self::grade(sessions, self.degraded_sessions, self.critical_sessions),
+            message: format!("{} live sessions", sessions),
+        }
+    }
+
+    /// Grade resident memory; unknown memory use is reported as healthy
+    pub fn check_memory(&self, resident_bytes: Option<u64>) -> CheckResult {
+        match resident_bytes {
+            Some(bytes) => CheckResult {
+                status: Self::grade(bytes, self.degraded_memory_bytes, self.critical_memory_bytes),
+                message: format!("{} MiB resident", bytes / (1024 * 1024)),
+            },
+            None => CheckResult::healthy("memory use unavailable"),
+        }
+    }
+}
+
+/// Grade the DDoS mitigation state
+pub fn check_mitigation_state(state: MitigationState) -> CheckResult {
+    let message = format!("DDoS mitigation {:?}", state);
+    match state {
+        MitigationState::Normal => CheckResult::healthy(message),
+        MitigationState::EarlyWarning | MitigationState::UnderAttack => CheckResult::degraded(message),
+        MitigationState::Emergency => CheckResult::critical(message),
+    }
+}
+
+/// Resident set size of this process, where the platform exposes it
+pub fn resident_memory_bytes() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let kib = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+/// Health monitor configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthMonitorConfig {
+    pub thresholds: HealthThresholds,
+    /// Samples kept for trend queries
+    pub history_size: usize,
+    #[serde(with = "crate::serde_duration::secs")]
+    pub sample_interval: Duration,
+}
+
+impl Default for HealthMonitorConfig {
+    fn default() -> Self {
+        Self {
+            thresholds: HealthThresholds::default(),
+            history_size: 60,
+            sample_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Result of running every registered check once
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthSample {
+    #[serde(skip)]
+    pub taken_at: Instant,
+    pub status: HealthStatus,
+    pub checks: Vec<(String, CheckResult)>,
+}
+
+type HealthCheck = Box<dyn Fn() -> CheckResult + Send + Sync>;
+
+/// Periodically runs registered health checks and derives an overall status
+///
+/// The overall status is the worst status of any check. Use
+/// `check_*` on `HealthThresholds` to grade the built-in indicators, and
+/// `register_check` for anything else.
+pub struct HealthMonitor {
+    config: HealthMonitorConfig,
+    checks: Vec<(String, HealthCheck)>,
+    history: VecDeque<HealthSample>,
+}
+
+impl HealthMonitor {
+    /// Create a monitor that already checks this process's memory use
+    pub fn new(config: HealthMonitorConfig) -> Self {
+        let mut monitor = Self {
+            config,
+            checks: Vec::new(),
+            history: VecDeque::new(),
+        };
+        let thresholds = monitor.config.thresholds.clone();
+        monitor.register_check("memory", move || thresholds.check_memory(resident_memory_bytes()));
+        monitor
+    }
+
+    pub fn thresholds(&self) -> &HealthThresholds {
+        &self.config.thresholds
+    }
+
+    /// Add a named check run on every sample; a check with the same name is replaced
+    pub fn register_check<F>(&mut self, name: &str, check: F)
+    where
+        F: Fn() -> CheckResult + Send + Sync + 'static,
+    {
+        self.checks.retain(|(existing, _)| existing != name);
+        self.checks.push((name.to_string(), Box::new(check)));
+    }
+
+    /// Check active connections reported by `stats`
+    pub fn register_connection_check<F>(&mut self, stats: F)
+    where
+        F: Fn() -> ConnectionStats + Send + Sync + 'static,
+    {
+        let thresholds = self.config.thresholds.clone();
+        self.register_check("connections", move || thresholds.check_connections(&stats()));
+    }
+
+    /// Check the DDoS mitigation state reported by `state`
+    pub fn register_mitigation_check<F>(&mut self, state: F)
+    where
+        F: Fn() -> MitigationState + Send + Sync + 'static,
+    {
+        self.register_check("ddos_mitigation", move || check_mitigation_state(state()));
+    }
+
+    /// Check the live session count reported by `sessions`
+    pub fn register_session_check<F>(&mut self, sessions: F)
+    where
+        F: Fn() -> usize + Send + Sync + 'static,
+    {
+        let thresholds = self.config.thresholds.clone();
+        self.register_check("sessions", move || thresholds.check_sessions(sessions()));
+    }
+
+    /// Run every check now and record the result
+    pub fn sample(&mut self) -> &HealthSample {
+        let checks: Vec<_> = self.checks.iter().map(|(name, check)| (name.clone(), check())).collect();
+        let status = checks
+            .iter()
+            .map(|(_, result)| result.status)
+            .max()
+            .unwrap_or(HealthStatus::Healthy);
+
+        self.history.push_back(HealthSample { taken_at: Instant::now(), status, checks });
+        while self.history.len() > self.config.history_size.max(1) {
+            self.history.pop_front();
+        }
+        self.history.back().expect("sample was just recorded")
+    }
+
+    /// Status from the latest sample; healthy until the first sample is taken
+    pub fn current_status(&self) -> HealthStatus {
+        self.latest().map_or(HealthStatus::Healthy, |sample| sample.status)
+    }
+
+    /// The latest sample, if any
+    pub fn latest(&self) -> Option<&HealthSample> {
+        self.history.back()
+    }
+
+    /// Recent samples, oldest first
+    pub fn history(&self) -> impl Iterator<Item = &HealthSample> {
+        self.history.iter()
+    }
+
+    /// Worst status seen in samples taken within `window`
+    pub fn worst_status_within(&self, window: Duration) -> HealthStatus {
+        self.history
+            .iter()
+            .filter(|sample| sample.taken_at.elapsed() <= window)
+            .map(|sample| sample.status)
+            .max()
+            .unwrap_or(HealthStatus::Healthy)
+    }
+
+    /// Sample every `sample_interval` on a background task; a zero interval
+    /// samples as often as the runtime allows rather than panicking
+    pub fn spawn_sampling(monitor: Arc<Mutex<Self>>) -> JoinHandle<()> {
+        let interval = monitor
+            .lock()
+            .map(|monitor| monitor.config.sample_interval)
+            .unwrap_or_else(|_| HealthMonitorConfig::default().sample_interval);
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval.max(Duration::from_millis(1)));
+            loop {
+                ticker.tick().await;
+                let Ok(mut monitor) = monitor.lock() else {
+                    error!("Health monitor lock poisoned, stopping sampling");
+                    return;
+                };
+                let status = monitor.sample().status;
+                if status != HealthStatus::Healthy {
+                    warn!("Health status: {:?}", status);
+                }
+            }
+        })
+    }
+}
+
+impl Default for HealthMonitor {
+    fn default() -> Self {
+        Self::new(HealthMonitorConfig::default())
+    }
+}
+
+/// Tracks which subsystems have finished `initialize`
+#[derive(Debug, Default)]
+pub struct Readiness {
+    subsystems: Mutex<BTreeMap<String, bool>>,
+}
+
+impl Readiness {
+    /// Create a tracker waiting on each subsystem in `names`
+    pub fn new<I, S>(names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            subsystems: Mutex::new(names.into_iter().map(|name| (name.into(), false)).collect()),
+        }
+    }
+
+    /// Record that `name` finished initializing
+    pub fn mark_ready(&self, name: &str) {
+        if let Ok(mut subsystems) = self.subsystems.lock() {
+            subsystems.insert(name.to_string(), true);
+        }
+    }
+
+    /// Subsystems still initializing
+    pub fn pending(&self) -> Vec<String> {
+        self.subsystems
+            .lock()
+            .map(|subsystems| {
+                subsystems.iter().filter(|(_, ready)| !**ready).map(|(name, _)| name.clone()).collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.pending().is_empty()
+    }
+}
+
+#[derive(Clone)]
+struct HealthEndpointState {
+    monitor: Arc<Mutex<HealthMonitor>>,
+    readiness: Arc<Readiness>,
+}
+
+/// Routes for load balancers and orchestrators
+///
+/// `GET /health` reports the latest sample taken by `spawn_sampling` and
+/// answers 200 while `Healthy` or `Degraded`, 503 when `Critical`. Probes
+/// don't add to the history, except that one samples if none exists yet.
+/// `GET /ready` answers 200 only once every subsystem in `readiness` is marked
+/// ready. Both return JSON bodies.
+pub fn health_router(monitor: Arc<Mutex<HealthMonitor>>, readiness: Arc<Readiness>) -> Router {
+    Router::new()
+        .route("/health", get(health_handler))
+        .route("/ready", get(ready_handler))
+        .with_state(HealthEndpointState { monitor, readiness })
+}
+
+// Route: GET /health - overall status and each check's result
+async fn health_handler(State(state): State<HealthEndpointState>) -> impl IntoResponse {
+    let Ok(mut monitor) = state.monitor.lock() else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "status": HealthStatus::Critical, "checks": {} })),
+        );
+    };
+
+    if monitor.latest().is_none() {
+        monitor.sample();
+    }
+    let sample = monitor.latest().expect("a sample was just taken");
+    let checks: BTreeMap<_, _> = sample.checks.iter().map(|(name, result)| (name.as_str(), result)).collect();
+    let code = match sample.status {
+        HealthStatus::Critical => StatusCode::SERVICE_UNAVAILABLE,
+        HealthStatus::Healthy | HealthStatus::Degraded => StatusCode::OK,
+    };
+    (code, Json(serde_json::json!({ "status": sample.status, "checks": checks })))
+}
+
+// Route: GET /ready - whether every subsystem finished initializing
+async fn ready_handler(State(state): State<HealthEndpointState>) -> impl IntoResponse {
+    let pending = state.readiness.pending();
+    let code = if pending.is_empty() { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (code, Json(serde_json::json!({ "ready": pending.is_empty(), "pending": pending })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_check_drives_status_to_critical_and_back() {
+        let sessions = Arc::new(AtomicUsize::new(10));
+        let mut monitor = HealthMonitor::new(HealthMonitorConfig {
+            history_size: 3,
+            ..HealthMonitorConfig::default()
+        });
+        let source = Arc::clone(&sessions);
+        monitor.register_session_check(move || source.load(Ordering::SeqCst));
+        monitor.register_check("memory", || CheckResult::healthy("stubbed"));
+
+        assert_eq!(monitor.sample().status, HealthStatus::Healthy);
+
+        sessions.store(9_000, Ordering::SeqCst);
+        assert_eq!(monitor.sample().status, HealthStatus::Degraded);
+
+        sessions.store(20_000, Ordering::SeqCst);
+        monitor.sample();
+        assert_eq!(monitor.current_status(), HealthStatus::Critical);
+
+        sessions.store(5, Ordering::SeqCst);
+        monitor.sample();
+        assert_eq!(monitor.current_status(), HealthStatus::Healthy);
+        assert_eq!(monitor.worst_status_within(Duration::from_secs(60)), HealthStatus::Critical);
+
+        let statuses: Vec<_> = monitor.history().map(|sample| sample.status).collect();
+        assert_eq!(statuses, [HealthStatus::Degraded, HealthStatus::Critical, HealthStatus::Healthy]);
+        assert_eq!(monitor.latest().unwrap().checks.len(), 2);
+    }
+
+    #[test]
+    fn test_builtin_indicators() {
+        let thresholds = HealthThresholds::default();
+        let stats = ConnectionStats { active_connections: 1000, tracked_ips: 1, protected_onions: 1, onions_in_maintenance: Vec::new() };
+        assert_eq!(thresholds.check_connections(&stats).status, HealthStatus::Critical);
+        assert_eq!(check_mitigation_state(MitigationState::UnderAttack).status, HealthStatus::Degraded);
+        assert_eq!(check_mitigation_state(MitigationState::Emergency).status, HealthStatus::Critical);
+        assert_eq!(thresholds.check_memory(None).status, HealthStatus::Healthy);
+        assert_eq!(thresholds.check_memory(Some(2 << 30)).status, HealthStatus::Critical);
+    }
+
+    async fn get_json(router: Router, uri: &str) -> (StatusCode, serde_json::Value) {
+        use tower::ServiceExt;
+
+        let request = axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_health_endpoint_reports_checks() {
+        let sessions = Arc::new(AtomicUsize::new(10));
+        let mut monitor = HealthMonitor::default();
+        let source = Arc::clone(&sessions);
+        monitor.register_session_check(move || source.load(Ordering::SeqCst));
+        monitor.register_check("memory", || CheckResult::healthy("stubbed"));
+        let monitor = Arc::new(Mutex::new(monitor));
+        let router = health_router(Arc::clone(&monitor), Arc::new(Readiness::default()));
+
+        let (status, body) = get_json(router.clone(), "/health").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "healthy");
+        assert_eq!(body["checks"]["sessions"]["message"], "10 live sessions");
+
+        // Probes report the latest sample rather than taking their own
+        sessions.store(9_000, Ordering::SeqCst);
+        let (_, body) = get_json(router.clone(), "/health").await;
+        assert_eq!(body["status"], "healthy");
+        assert_eq!(monitor.lock().unwrap().history().count(), 1);
+
+        monitor.lock().unwrap().sample();
+        let (status, body) = get_json(router.clone(), "/health").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "degraded");
+
+        sessions.store(20_000, Ordering::SeqCst);
+        monitor.lock().unwrap().sample();
+        let (status, body) = get_json(router, "/health").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["checks"]["sessions"]["status"], "critical");
+    }
+
+    #[tokio::test]
+    async fn test_zero_sample_interval_keeps_sampling() {
+        let config = HealthMonitorConfig { sample_interval: Duration::ZERO, ..HealthMonitorConfig::default() };
+        let mut monitor = HealthMonitor::new(config);
+        monitor.register_check("memory", || CheckResult::healthy("stubbed"));
+        let monitor = Arc::new(Mutex::new(monitor));
+
+        let handle = HealthMonitor::spawn_sampling(Arc::clone(&monitor));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!handle.is_finished());
+        assert!(monitor.lock().unwrap().latest().is_some());
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_ready_endpoint_waits_for_subsystems() {
+        let readiness = Arc::new(Readiness::new(["tor_security", "captcha"]));
+        let router = health_router(Arc::new(Mutex::new(HealthMonitor::default())), Arc::clone(&readiness));
+
+        readiness.mark_ready("captcha");
+        let (status, body) = get_json(router.clone(), "/ready").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["pending"], serde_json::json!(["tor_security"]));
+
+        readiness.mark_ready("tor_security");
+        let (status, body) = get_json(router, "/ready").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["ready"], true);
+    }
+}
@@ -1,5 +1,352 @@
 //! Incident Response Module
 //! 
 //! Automated incident detection and response
+//!
+//! An `IncidentResponder` holds playbooks, each an ordered list of actions run
+//! when a matching `SecurityEvent` arrives. Actions are trait objects, so
+//! deployments can add their own next to the built-in ones.
 
-// TODO: Implement incident response functionality
+use crate::operational::audit_logging::{AuditEvent, AuditLog, AuditOutcome};
+use crate::operational::emergency_shutdown::EmergencyShutdown;
+use crate::tor::ddos_mitigation::MitigationState;
+use crate::tor::{TorModule, TorSecurityError, TorSecurityManager, TorSecurityResult};
+use log::{error, info};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+
+/// Something the system should react to
+#[derive(Debug, Clone, PartialEq)]
+pub enum SecurityEvent {
+    /// DDoS mitigation escalated to `Emergency`
+    UnderAttack,
+    /// DDoS mitigation left `Emergency`
+    AttackSubsided,
+    CompromiseSuspected(String),
+    HealthCritical(String),
+}
+
+/// Which kind of event a playbook responds to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SecurityEventKind {
+    UnderAttack,
+    AttackSubsided,
+    CompromiseSuspected,
+    HealthCritical,
+}
+
+impl SecurityEvent {
+    pub fn kind(&self) -> SecurityEventKind {
+        match self {
+            SecurityEvent::UnderAttack => SecurityEventKind::UnderAttack,
+            SecurityEvent::AttackSubsided => SecurityEventKind::AttackSubsided,
+            SecurityEvent::CompromiseSuspected(_) => SecurityEventKind::CompromiseSuspected,
+            SecurityEvent::HealthCritical(_) => SecurityEventKind::HealthCritical,
+        }
+    }
+}
+
+/// A single step of a playbook
+pub trait ResponseAction: Send + Sync {
+    fn name(&self) -> &str;
+    fn execute(&self, event: &SecurityEvent) -> TorSecurityResult<()>;
+}
+
+/// Start a Tor security module, e.g. exit node filtering under attack
+pub struct EnableModule {
+    pub manager: Arc<TorSecurityManager>,
+    pub module: TorModule,
+}
+
+impl ResponseAction for EnableModule {
+    fn name(&self) -> &str {
+        "enable_module"
+    }
+
+    fn execute(&self, _event: &SecurityEvent) -> TorSecurityResult<()> {
+        self.manager.set_module_enabled(self.module, true)
+    }
+}
+
+/// Record the event in the audit log
+pub struct AuditIncident {
+    pub log: Arc<Mutex<AuditLog>>,
+}
+
+impl ResponseAction for AuditIncident {
+    fn name(&self) -> &str {
+        "audit"
+    }
+
+    fn execute(&self, event: &SecurityEvent) -> TorSecurityResult<()> {
+        self.log
+            .lock()
+            .map_err(|_| TorSecurityError::ConfigurationError("Audit log lock poisoned".to_string()))?
+            .append(AuditEvent::new("incident_responder", format!("{:?}", event), AuditOutcome::Success))
+    }
+}
+
+/// Trip the emergency shutdown
+pub struct TriggerShutdown {
+    pub shutdown: Arc<EmergencyShutdown>,
+}
+
+impl ResponseAction for TriggerShutdown {
+    fn name(&self) -> &str {
+        "emergency_shutdown"
+    }
+
+    fn execute(&self, event: &SecurityEvent) -> TorSecurityResult<()> {
+        self.shutdown.trigger(&format!("incident response to {:?}", event))
+    }
+}
+
+/// Wrap a closure as an action, e.g. to tighten limits in a way specific to a deployment
+pub struct CustomAction<F> {
+    name: String,
+    action: F,
+}
+
+impl<F> CustomAction<F>
+where
+    F: Fn(&SecurityEvent) -> TorSecurityResult<()> + Send + Sync,
+{
+    pub fn new(name: impl Into<String>, action: F) -> Self {
+        Self { name: name.into(), action }
+    }
+}
+
+impl<F> ResponseAction for CustomAction<F>
+where
+    F: Fn(&SecurityEvent) -> TorSecurityResult<()> + Send + Sync,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn execute(&self, event: &SecurityEvent) -> TorSecurityResult<()> {
+        (self.action)(event)
+    }
+}
+
+/// Named, ordered list of actions run for one kind of event
+pub struct Playbook {
+    name: String,
+    trigger: SecurityEventKind,
+    actions: Vec<Box<dyn ResponseAction>>,
+}
+
+impl Playbook {
+    pub fn new(name: impl Into<String>, trigger: SecurityEventKind) -> Self {
+        Self { name: name.into(), trigger, actions: Vec::new() }
+    }
+
+    /// Append an action to run after the ones already added
+    pub fn then(mut self, action: impl ResponseAction + 'static) -> Self {
+        self.actions.push(Box::new(action));
+        self
+    }
+}
+
+/// Result of one action run by `IncidentResponder::handle`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionReport {
+    pub playbook: String,
+    pub action: String,
+    pub result: Result<(), String>,
+}
+
+/// Runs the playbooks matching each security event
+#[derive(Default)]
+pub struct IncidentResponder {
+    playbooks: Vec<Playbook>,
+}
+
+impl IncidentResponder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a playbook; a playbook with the same name is replaced
+    pub fn add_playbook(&mut self, playbook: Playbook) {
+        self.playbooks.retain(|existing| existing.name != playbook.name);
+        self.playbooks.push(playbook);
+    }
+
+    /// Run every playbook for the event's kind, in the order they were added.
+    /// A failing action is reported and the rest of the playbook still runs,
+    /// so e.g. a full audit disk can't stop an emergency shutdown.
+    pub fn handle(&self, event: SecurityEvent) -> Vec<ActionReport> {
+        let kind = event.kind();
+        let mut reports = Vec::new();
+
+        for playbook in self.playbooks.iter().filter(|playbook| playbook.trigger == kind) {
+            info!("Running incident playbook {} for {:?}", playbook.name, event);
+            for action in &playbook.actions {
+                let result = action.execute(&event).map_err(|e| e.to_string());
+                if let Err(e) = &result {
+                    error!("Incident action {} failed: {}", action.name(), e);
+                }
+                reports.push(ActionReport {
+                    playbook: playbook.name.clone(),
+                    action: action.name().to_string(),
+                    result,
+                });
+            }
+        }
+        reports
+    }
+
+    /// State-change hook for `TorSecurityManager::on_ddos_state_change` that
+    /// forwards `Emergency` transitions to `events`. Events are queued rather
+    /// than handled inline because the hook runs under the DDoS mitigation lock.
+    pub fn ddos_hook(
+        events: UnboundedSender<SecurityEvent>,
+    ) -> impl Fn(MitigationState, MitigationState) + Send + Sync + 'static {
+        move |old, new| {
+            let event = match (old, new) {
+                (old, MitigationState::Emergency) if old != MitigationState::Emergency => SecurityEvent::UnderAttack,
+                (MitigationState::Emergency, new) if new != MitigationState::Emergency => SecurityEvent::AttackSubsided,
+                _ => return,
+            };
+            // A closed channel just means nobody is responding any more
+            let _ = events.send(event);
+        }
+    }
+
+    /// Handle queued events on a background task until every sender is dropped
+    pub fn spawn(responder: Arc<Self>, mut events: UnboundedReceiver<SecurityEvent>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                let responder = Arc::clone(&responder);
+                // Actions may block on locks or disk, keep them off the async workers
+                if tokio::task::spawn_blocking(move || responder.handle(event)).await.is_err() {
+                    error!("Incident playbook panicked");
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::tor::{RequestContext, TorSecurityConfig};
+    use std::time::Duration;
+
+    struct Recording {
+        step: String,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl ResponseAction for Recording {
+        fn name(&self) -> &str {
+            &self.step
+        }
+
+        fn execute(&self, _event: &SecurityEvent) -> TorSecurityResult<()> {
+            self.log.lock().unwrap().push(self.step.clone());
+            Ok(())
+        }
+    }
+
+    fn recording(log: &Arc<Mutex<Vec<String>>>, step: &str) -> Recording {
+        Recording { step: step.to_string(), log: Arc::clone(log) }
+    }
+
+    #[test]
+    fn test_attack_runs_playbook_in_order() {
+        let ran = Arc::new(Mutex::new(Vec::new()));
+        let mut responder = IncidentResponder::new();
+        responder.add_playbook(
+            Playbook::new("ddos", SecurityEventKind::UnderAttack)
+                .then(recording(&ran, "tighten_limits"))
+                .then(CustomAction::new("page_operator", |_event: &SecurityEvent| {
+                    Err(TorSecurityError::NetworkError("pager unreachable".to_string()))
+                }))
+                .then(recording(&ran, "enable_exit_filtering")),
+        );
+        responder.add_playbook(Playbook::new("compromise", SecurityEventKind::CompromiseSuspected).then(recording(&ran, "shutdown")));
+
+        let reports = responder.handle(SecurityEvent::UnderAttack);
+        assert_eq!(*ran.lock().unwrap(), ["tighten_limits", "enable_exit_filtering"]);
+        let actions: Vec<_> = reports.iter().map(|report| report.action.as_str()).collect();
+        assert_eq!(actions, ["tighten_limits", "page_operator", "enable_exit_filtering"]);
+        assert!(reports[1].result.is_err());
+
+        assert!(responder.handle(SecurityEvent::HealthCritical("disk".to_string())).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_ddos_emergency_dispatches_under_attack() {
+        let manager = Arc::new(TorSecurityManager::with_config(crate::tor::TorSecurityConfig {
+            enable_exit_node_filtering: false,
+            ..crate::tor::TorSecurityConfig::default()
+        }).unwrap());
+        let mut responder = IncidentResponder::new();
+        responder.add_playbook(Playbook::new("ddos", SecurityEventKind::UnderAttack).then(EnableModule {
+            manager: Arc::clone(&manager),
+            module: TorModule::ExitNodeFiltering,
+        }));
+
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let hook = IncidentResponder::ddos_hook(sender);
+        hook(MitigationState::Normal, MitigationState::EarlyWarning);
+        hook(MitigationState::UnderAttack, MitigationState::Emergency);
+        drop(hook);
+
+        IncidentResponder::spawn(Arc::new(responder), receiver).await.unwrap();
+        assert!(manager.is_module_enabled(TorModule::ExitNodeFiltering));
+    }
+
+    #[tokio::test]
+    async fn test_request_flood_opens_incident() {
+        let clock = MockClock::new();
+        let manager = Arc::new(
+            TorSecurityManager::with_config(TorSecurityConfig {
+                enable_exit_node_filtering: false,
+                ..TorSecurityConfig::default()
+            })
+            .unwrap()
+            .with_clock(Arc::new(clock.clone())),
+        );
+        let opened = Arc::new(Mutex::new(Vec::new()));
+        let mut responder = IncidentResponder::new();
+        responder.add_playbook(
+            Playbook::new("ddos", SecurityEventKind::UnderAttack)
+                .then(recording(&opened, "open_incident"))
+                .then(EnableModule { manager: Arc::clone(&manager), module: TorModule::ExitNodeFiltering }),
+        );
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        manager.on_ddos_state_change(IncidentResponder::ddos_hook(sender));
+        let task = IncidentResponder::spawn(Arc::new(responder), receiver);
+
+        // Five times the request limit within a second, just before the periodic analysis is due
+        let ctx = RequestContext {
+            source_ip: Some("192.0.2.1".parse().unwrap()),
+            onion_address: None,
+            circuit_id: None,
+            exit_node: None,
+            request_size: 512,
+        };
+        clock.advance(Duration::from_millis(9_500));
+        for _ in 0..500 {
+            manager.evaluate_request(&ctx).unwrap();
+        }
+        assert!(opened.lock().unwrap().is_empty());
+        clock.advance(Duration::from_millis(600));
+        manager.evaluate_request(&ctx).unwrap();
+        assert_eq!(manager.ddos_mitigation().get_mitigation_stats().current_state, MitigationState::Emergency);
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !manager.is_module_enabled(TorModule::ExitNodeFiltering) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(*opened.lock().unwrap(), ["open_incident"]);
+        task.abort();
+    }
+}
@@ -10,5 +10,4 @@ pub mod incident_response;
 pub mod backup_management;
 pub mod audit_logging;
 pub mod config_management;
-
-// TODO: Implement operational functionality
+pub mod admin_api;
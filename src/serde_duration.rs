@@ -0,0 +1,73 @@
+//! Serde adapters for `Duration` configuration fields
+//!
+//! Configuration files express durations as plain numbers rather than serde's
+//! default `{ secs, nanos }` pair. Use `#[serde(with = "crate::serde_duration::secs")]`
+//! for timeouts and windows, and `millis` for delays measured in milliseconds.
+
+/// A `Duration` as seconds. Whole seconds serialize as integers; fractional
+/// values are accepted and written as floats.
+pub mod secs {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        if duration.subsec_nanos() == 0 {
+            serializer.serialize_u64(duration.as_secs())
+        } else {
+            serializer.serialize_f64(duration.as_secs_f64())
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let secs = f64::deserialize(deserializer)?;
+        Duration::try_from_secs_f64(secs)
+            .map_err(|_| D::Error::custom(format!("{} is not a valid number of seconds", secs)))
+    }
+}
+
+/// A `Duration` as whole milliseconds
+pub mod millis {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use std::time::Duration;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Timings {
+        #[serde(with = "super::secs")]
+        timeout: Duration,
+        #[serde(with = "super::millis")]
+        delay: Duration,
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let timings = Timings { timeout: Duration::from_secs(30), delay: Duration::from_millis(250) };
+        let json = serde_json::to_string(&timings).unwrap();
+        assert_eq!(json, r#"{"timeout":30,"delay":250}"#);
+        assert_eq!(serde_json::from_str::<Timings>(&json).unwrap(), timings);
+
+        let fractional: Timings = serde_json::from_str(r#"{"timeout":1.5,"delay":0}"#).unwrap();
+        assert_eq!(fractional.timeout, Duration::from_millis(1500));
+        assert_eq!(serde_json::to_string(&fractional).unwrap(), r#"{"timeout":1.5,"delay":0}"#);
+    }
+
+    #[test]
+    fn test_negative_seconds_are_rejected() {
+        assert!(serde_json::from_str::<Timings>(r#"{"timeout":-1,"delay":0}"#).is_err());
+        assert!(serde_json::from_str::<Timings>(r#"{"timeout":1,"delay":-1}"#).is_err());
+    }
+}
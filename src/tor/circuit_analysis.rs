@@ -3,13 +3,24 @@
 //! Monitor and analyze Tor circuit patterns for anomalies and security threats.
 //! Detects suspicious circuit behavior, timing attacks, and circuit correlation attempts.
 
+use crate::clock::{self, Clock};
 use crate::tor::{TorSecurityConfig, TorSecurityResult};
+use log::info;
+use serde::{Deserialize, Serialize, Serializer};
 use std::collections::{HashMap, VecDeque};
 use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
 use std::time::{Duration, Instant};
 
+/// Render a `Duration` as whole milliseconds
+fn serialize_ms<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
 /// Circuit state tracking
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum CircuitState {
     Building,
     Built,
@@ -20,7 +31,8 @@ pub enum CircuitState {
 }
 
 /// Circuit anomaly types
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum CircuitAnomaly {
     RapidRebuild,
     UnusualTiming,
@@ -30,8 +42,17 @@ pub enum CircuitAnomaly {
     AbnormalTraffic,
 }
 
-/// Circuit path information
+/// Anomaly pushed to the sink set with `set_anomaly_sink` as soon as it is found
 #[derive(Debug, Clone)]
+pub struct AnomalyEvent {
+    pub circuit_id: String,
+    pub anomaly: CircuitAnomaly,
+    /// The circuit's anomaly score after this analysis
+    pub score: f64,
+}
+
+/// Circuit path information
+#[derive(Debug, Clone, Serialize)]
 pub struct CircuitPath {
     pub guard_node: Option<String>,
     pub middle_node: Option<String>,
@@ -40,13 +61,16 @@ pub struct CircuitPath {
 }
 
 /// Circuit metrics for analysis
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CircuitMetrics {
+    #[serde(serialize_with = "serialize_ms")]
     pub build_time: Duration,
+    #[serde(serialize_with = "serialize_ms")]
     pub lifetime: Duration,
     pub bytes_sent: u64,
     pub bytes_received: u64,
     pub request_count: u32,
+    #[serde(serialize_with = "serialize_ms")]
     pub average_response_time: Duration,
 }
 
@@ -64,16 +88,83 @@ pub struct CircuitInfo {
     pub detected_anomalies: Vec<CircuitAnomaly>,
 }
 
+/// A circuit as exported, with its timestamps as milliseconds before `now`
+#[derive(Serialize)]
+struct CircuitSnapshot<'a> {
+    circuit_id: &'a str,
+    state: &'a CircuitState,
+    created_at: u64,
+    last_activity: u64,
+    source_ip: Option<IpAddr>,
+    path: &'a CircuitPath,
+    metrics: &'a CircuitMetrics,
+    anomaly_score: f64,
+    detected_anomalies: &'a [CircuitAnomaly],
+}
+
+impl<'a> CircuitSnapshot<'a> {
+    fn new(circuit: &'a CircuitInfo, now: Instant) -> Self {
+        let age_ms = |at: Instant| now.saturating_duration_since(at).as_millis() as u64;
+        Self {
+            circuit_id: &circuit.circuit_id,
+            state: &circuit.state,
+            created_at: age_ms(circuit.created_at),
+            last_activity: age_ms(circuit.last_activity),
+            source_ip: circuit.source_ip,
+            path: &circuit.path,
+            metrics: &circuit.metrics,
+            anomaly_score: circuit.anomaly_score,
+            detected_anomalies: &circuit.detected_anomalies,
+        }
+    }
+}
+
+/// Contribution of each anomaly to a circuit's anomaly score
+///
+/// Contributions of the anomalies found on a circuit are added up and the
+/// total is capped at 1.0, so weights can be raised freely.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnomalyWeights {
+    pub rapid_rebuild: f64,
+    pub unusual_timing: f64,
+    pub suspicious_path: f64,
+    pub correlation_attempt: f64,
+    /// Also applies to abnormal traffic volume
+    pub excessive_connections: f64,
+}
+
+impl Default for AnomalyWeights {
+    fn default() -> Self {
+        Self {
+            rapid_rebuild: 0.3,
+            unusual_timing: 0.2,
+            suspicious_path: 0.25,
+            correlation_attempt: 0.4,
+            excessive_connections: 0.3,
+        }
+    }
+}
+
 /// Circuit analysis configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CircuitAnalysisConfig {
+    #[serde(with = "crate::serde_duration::secs")]
     pub max_circuit_lifetime: Duration,
+    #[serde(with = "crate::serde_duration::secs")]
     pub max_build_time: Duration,
     pub anomaly_threshold: f64,
+    #[serde(with = "crate::serde_duration::secs")]
     pub correlation_window: Duration,
     pub max_circuits_per_source: u32,
     pub enable_path_analysis: bool,
     pub enable_timing_analysis: bool,
+    pub weights: AnomalyWeights,
+    /// Requests on one circuit above which it is flagged for excessive connections
+    pub max_requests_per_circuit: u32,
+    /// Bytes sent or received on one circuit above which it is flagged for abnormal traffic
+    pub max_bytes_per_circuit: u64,
 }
 
 impl Default for CircuitAnalysisConfig {
@@ -86,6 +177,9 @@ impl Default for CircuitAnalysisConfig {
             max_circuits_per_source: 10,
             enable_path_analysis: true,
             enable_timing_analysis: true,
+            weights: AnomalyWeights::default(),
+            max_requests_per_circuit: 1000,
+            max_bytes_per_circuit: 100_000_000,
         }
     }
 }
@@ -98,6 +192,9 @@ pub struct CircuitAnalysis {
     timing_patterns: HashMap<IpAddr, Vec<Instant>>,
     path_patterns: HashMap<String, u32>,
     last_analysis: Instant,
+    anomaly_sink: Option<Sender<AnomalyEvent>>,
+    expired_circuits: Vec<String>,
+    clock: Arc<dyn Clock>,
 }
 
 impl CircuitAnalysis {
@@ -111,6 +208,8 @@ impl CircuitAnalysis {
             max_circuits_per_source: tor_config.max_connections_per_circuit,
             enable_path_analysis: true,
             enable_timing_analysis: true,
+            weights: AnomalyWeights::default(),
+            ..CircuitAnalysisConfig::default()
         };
 
         Ok(Self {
@@ -120,17 +219,34 @@ impl CircuitAnalysis {
             timing_patterns: HashMap::new(),
             path_patterns: HashMap::new(),
             last_analysis: Instant::now(),
+            anomaly_sink: None,
+            expired_circuits: Vec::new(),
+            clock: clock::system(),
         })
     }
 
+    /// Read the time from `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.last_analysis = clock.now();
+        self.clock = clock;
+        self
+    }
+
+    /// Re-derive the limits taken from the shared configuration, keeping tracked circuits
+    pub fn apply_tor_config(&mut self, tor_config: &TorSecurityConfig) {
+        self.config.correlation_window = Duration::from_secs(tor_config.rate_limit_window_seconds);
+        self.config.max_circuits_per_source = tor_config.max_connections_per_circuit;
+    }
+
     /// Initialize the circuit analysis system
     pub fn initialize(&mut self) -> TorSecurityResult<()> {
         self.circuits.clear();
         self.circuit_history.clear();
         self.timing_patterns.clear();
         self.path_patterns.clear();
-        self.last_analysis = Instant::now();
-        println!("Circuit Analysis initialized");
+        self.expired_circuits.clear();
+        self.last_analysis = self.clock.now();
+        info!("Circuit Analysis initialized");
         Ok(())
     }
 
@@ -140,10 +256,17 @@ impl CircuitAnalysis {
         self.circuit_history.clear();
         self.timing_patterns.clear();
         self.path_patterns.clear();
-        println!("Circuit Analysis shutdown");
+        self.expired_circuits.clear();
+        info!("Circuit Analysis shutdown");
         Ok(())
     }
 
+    /// Send every anomaly found by `analyze_circuits` to `sink` for live alerting.
+    /// The sink is dropped once its receiver goes away.
+    pub fn set_anomaly_sink(&mut self, sink: Sender<AnomalyEvent>) {
+        self.anomaly_sink = Some(sink);
+    }
+
     /// Register a new circuit for monitoring
     pub fn register_circuit(
         &mut self,
@@ -151,7 +274,7 @@ impl CircuitAnalysis {
         source_ip: Option<IpAddr>,
         path: CircuitPath,
     ) -> TorSecurityResult<()> {
-        let now = Instant::now();
+        let now = self.clock.now();
 
         let circuit_info = CircuitInfo {
             circuit_id: circuit_id.clone(),
@@ -174,7 +297,7 @@ impl CircuitAnalysis {
 
         // Track timing patterns
         if let Some(ip) = source_ip {
-            self.timing_patterns.entry(ip).or_insert_with(Vec::new).push(now);
+            self.timing_patterns.entry(ip).or_default().push(now);
         }
 
         // Track path patterns
@@ -191,8 +314,8 @@ impl CircuitAnalysis {
         circuit_id: &str,
         new_state: CircuitState,
     ) -> TorSecurityResult<()> {
+        let now = self.clock.now();
         if let Some(circuit) = self.circuits.get_mut(circuit_id) {
-            let now = Instant::now();
 
             // Calculate build time when circuit is built
             if new_state == CircuitState::Built && circuit.state == CircuitState::Building {
@@ -222,7 +345,7 @@ impl CircuitAnalysis {
         response_time: Duration,
     ) -> TorSecurityResult<()> {
         if let Some(circuit) = self.circuits.get_mut(circuit_id) {
-            circuit.last_activity = Instant::now();
+            circuit.last_activity = self.clock.now();
             circuit.metrics.bytes_sent += bytes_sent;
             circuit.metrics.bytes_received += bytes_received;
             circuit.metrics.request_count += 1;
@@ -236,7 +359,7 @@ impl CircuitAnalysis {
 
     /// Analyze circuits for anomalies
     pub fn analyze_circuits(&mut self) -> TorSecurityResult<Vec<CircuitAnomaly>> {
-        let now = Instant::now();
+        let now = self.clock.now();
         let mut detected_anomalies = Vec::new();
 
         // Skip analysis if too recent
@@ -246,6 +369,7 @@ impl CircuitAnalysis {
 
         // Collect circuit data to avoid borrowing issues
         let mut circuit_anomalies: Vec<(String, Vec<CircuitAnomaly>, f64)> = Vec::new();
+        let weights = &self.config.weights;
 
         for (circuit_id, circuit) in &self.circuits {
             let mut anomalies = Vec::new();
@@ -254,72 +378,138 @@ impl CircuitAnalysis {
             // Check for rapid rebuild patterns
             if let Some(anomaly) = self.check_rapid_rebuild(circuit) {
                 anomalies.push(anomaly);
-                anomaly_score += 0.3;
+                anomaly_score += weights.rapid_rebuild;
             }
 
             // Check for unusual timing
-            if self.config.enable_timing_analysis {
-                if let Some(anomaly) = self.check_unusual_timing(circuit) {
-                    anomalies.push(anomaly);
-                    anomaly_score += 0.2;
-                }
+            if self.config.enable_timing_analysis
+                && let Some(anomaly) = self.check_unusual_timing(circuit)
+            {
+                anomalies.push(anomaly);
+                anomaly_score += weights.unusual_timing;
             }
 
             // Check for suspicious paths
-            if self.config.enable_path_analysis {
-                if let Some(anomaly) = self.check_suspicious_path(circuit) {
-                    anomalies.push(anomaly);
-                    anomaly_score += 0.25;
-                }
+            if self.config.enable_path_analysis
+                && let Some(anomaly) = self.check_suspicious_path(circuit)
+            {
+                anomalies.push(anomaly);
+                anomaly_score += weights.suspicious_path;
             }
 
-            // Check for correlation attempts
-            if let Some(anomaly) = self.check_correlation_attempt(circuit) {
+            // Check for correlation attempts, concurrent or spread over time
+            if let Some(anomaly) = self
+                .check_correlation_attempt(circuit)
+                .or_else(|| self.check_temporal_correlation(circuit))
+            {
                 anomalies.push(anomaly);
-                anomaly_score += 0.4;
+                anomaly_score += weights.correlation_attempt;
             }
 
             // Check for excessive connections
             if let Some(anomaly) = self.check_excessive_connections(circuit) {
                 anomalies.push(anomaly);
-                anomaly_score += 0.3;
+                anomaly_score += weights.excessive_connections;
             }
 
-            circuit_anomalies.push((circuit_id.clone(), anomalies, anomaly_score));
+            circuit_anomalies.push((circuit_id.clone(), anomalies, anomaly_score.clamp(0.0, 1.0)));
         }
 
         // Now update the circuits with the detected anomalies
         for (circuit_id, anomalies, score) in circuit_anomalies {
             if let Some(circuit) = self.circuits.get_mut(&circuit_id) {
+                if let Some(sink) = &self.anomaly_sink {
+                    let delivered = anomalies.iter().all(|anomaly| {
+                        sink.send(AnomalyEvent {
+                            circuit_id: circuit_id.clone(),
+                            anomaly: anomaly.clone(),
+                            score,
+                        })
+                        .is_ok()
+                    });
+                    if !delivered {
+                        self.anomaly_sink = None;
+                    }
+                }
+
                 circuit.detected_anomalies = anomalies.clone();
                 circuit.anomaly_score = score;
                 detected_anomalies.extend(anomalies);
             }
         }
 
+        // Flag circuits that outlived max_circuit_lifetime so the caller can tear them down
+        self.expired_circuits.clear();
+        for circuit in self.circuits.values_mut() {
+            if now.duration_since(circuit.created_at) > self.config.max_circuit_lifetime {
+                circuit.state = CircuitState::Closing;
+                self.expired_circuits.push(circuit.circuit_id.clone());
+            }
+        }
+        self.expired_circuits.sort();
+
         self.cleanup_old_data(now);
         self.last_analysis = now;
         Ok(detected_anomalies)
     }
 
+    /// Analyze a single circuit on demand, e.g. right after `record_activity`,
+    /// without waiting for the next full sweep. Stored scores are left unchanged.
+    pub fn analyze_circuit(&self, circuit_id: &str) -> Vec<CircuitAnomaly> {
+        let mut anomalies = Vec::new();
+        anomalies.extend(self.check_rapid_rebuild_by_id(circuit_id));
+        if self.config.enable_timing_analysis {
+            anomalies.extend(self.check_unusual_timing_by_id(circuit_id));
+        }
+        if self.config.enable_path_analysis {
+            anomalies.extend(self.check_suspicious_path_by_id(circuit_id));
+        }
+        anomalies.extend(self.check_correlation_attempt_by_id(circuit_id).or_else(|| {
+            self.circuits
+                .get(circuit_id)
+                .and_then(|circuit| self.check_temporal_correlation(circuit))
+        }));
+        anomalies.extend(self.check_excessive_connections_by_id(circuit_id));
+        anomalies
+    }
+
+    /// Whether `circuit_id` is an open circuit being tracked
+    pub fn is_tracking(&self, circuit_id: &str) -> bool {
+        self.circuits.contains_key(circuit_id)
+    }
+
+    /// Whether the latest sweep scored `circuit_id` above the anomaly threshold.
+    /// Unknown circuits are not considered suspicious.
+    pub fn is_circuit_suspicious(&self, circuit_id: &str) -> bool {
+        self.circuits
+            .get(circuit_id)
+            .is_some_and(|circuit| circuit.anomaly_score > self.config.anomaly_threshold)
+    }
+
+    /// Circuits found by the latest `analyze_circuits` to have outlived
+    /// `max_circuit_lifetime`. They are moved to `Closing`; close them and report
+    /// `Closed` through `update_circuit_state`.
+    pub fn expired_circuits(&self) -> &[String] {
+        &self.expired_circuits
+    }
+
     /// Check for rapid circuit rebuild patterns
     fn check_rapid_rebuild(&self, circuit: &CircuitInfo) -> Option<CircuitAnomaly> {
-        if let Some(ip) = circuit.source_ip {
-            if let Some(timings) = self.timing_patterns.get(&ip) {
-                let recent_builds = timings.iter()
-                    .filter(|&&t| circuit.created_at.duration_since(t) < Duration::from_secs(60))
-                    .count();
+        if let Some(ip) = circuit.source_ip
+            && let Some(timings) = self.timing_patterns.get(&ip)
+        {
+            let recent_builds = timings.iter()
+                .filter(|&&t| circuit.created_at.duration_since(t) < Duration::from_secs(60))
+                .count();
 
-                if recent_builds > 5 {
-                    return Some(CircuitAnomaly::RapidRebuild);
-                }
+            if recent_builds > 5 {
+                return Some(CircuitAnomaly::RapidRebuild);
             }
         }
         None
     }
 
     /// Check for rapid circuit rebuild patterns by circuit ID
-    #[allow(dead_code)]
     fn check_rapid_rebuild_by_id(&self, circuit_id: &str) -> Option<CircuitAnomaly> {
         if let Some(circuit) = self.circuits.get(circuit_id) {
             self.check_rapid_rebuild(circuit)
@@ -342,7 +532,6 @@ impl CircuitAnalysis {
     }
 
     /// Check for unusual timing patterns by circuit ID
-    #[allow(dead_code)]
     fn check_unusual_timing_by_id(&self, circuit_id: &str) -> Option<CircuitAnomaly> {
         if let Some(circuit) = self.circuits.get(circuit_id) {
             self.check_unusual_timing(circuit)
@@ -359,17 +548,16 @@ impl CircuitAnalysis {
 
         // Check for repeated path patterns
         let path_key = format!("{:?}", circuit.path);
-        if let Some(&count) = self.path_patterns.get(&path_key) {
-            if count > 10 {
-                return Some(CircuitAnomaly::SuspiciousPath);
-            }
+        if let Some(&count) = self.path_patterns.get(&path_key)
+            && count > 10
+        {
+            return Some(CircuitAnomaly::SuspiciousPath);
         }
 
         None
     }
 
     /// Check for suspicious circuit paths by circuit ID
-    #[allow(dead_code)]
     fn check_suspicious_path_by_id(&self, circuit_id: &str) -> Option<CircuitAnomaly> {
         if let Some(circuit) = self.circuits.get(circuit_id) {
             self.check_suspicious_path(circuit)
@@ -392,8 +580,22 @@ impl CircuitAnalysis {
         None
     }
 
+    /// Check for an IP churning through circuits one after another, staying under
+    /// the concurrency cap but opening too many within `correlation_window`
+    fn check_temporal_correlation(&self, circuit: &CircuitInfo) -> Option<CircuitAnomaly> {
+        let ip = circuit.source_ip?;
+        let now = self.clock.now();
+        let recent_closed = self.circuit_history.iter()
+            .filter(|c| c.source_ip == Some(ip) && now.saturating_duration_since(c.created_at) < self.config.correlation_window)
+            .count();
+
+        if recent_closed > self.config.max_circuits_per_source as usize {
+            return Some(CircuitAnomaly::CorrelationAttempt);
+        }
+        None
+    }
+
     /// Check for correlation attempts by circuit ID
-    #[allow(dead_code)]
     fn check_correlation_attempt_by_id(&self, circuit_id: &str) -> Option<CircuitAnomaly> {
         if let Some(circuit) = self.circuits.get(circuit_id) {
             self.check_correlation_attempt(circuit)
@@ -404,11 +606,12 @@ impl CircuitAnalysis {
 
     /// Check for excessive connections
     fn check_excessive_connections(&self, circuit: &CircuitInfo) -> Option<CircuitAnomaly> {
-        if circuit.metrics.request_count > 1000 {
+        if circuit.metrics.request_count > self.config.max_requests_per_circuit {
             return Some(CircuitAnomaly::ExcessiveConnections);
         }
 
-        if circuit.metrics.bytes_sent > 100_000_000 || circuit.metrics.bytes_received > 100_000_000 {
+        let max_bytes = self.config.max_bytes_per_circuit;
+        if circuit.metrics.bytes_sent > max_bytes || circuit.metrics.bytes_received > max_bytes {
             return Some(CircuitAnomaly::AbnormalTraffic);
         }
 
@@ -416,7 +619,6 @@ impl CircuitAnalysis {
     }
 
     /// Check for excessive connections by circuit ID
-    #[allow(dead_code)]
     fn check_excessive_connections_by_id(&self, circuit_id: &str) -> Option<CircuitAnomaly> {
         if let Some(circuit) = self.circuits.get(circuit_id) {
             self.check_excessive_connections(circuit)
@@ -448,6 +650,22 @@ impl CircuitAnalysis {
         });
     }
 
+    /// JSON snapshot of every active circuit and the current statistics, for
+    /// incident reports. Durations are in milliseconds, and `created_at` and
+    /// `last_activity` are milliseconds before the snapshot was taken.
+    pub fn export_snapshot(&self) -> String {
+        let now = self.clock.now();
+        let mut circuits: Vec<CircuitSnapshot<'_>> =
+            self.circuits.values().map(|circuit| CircuitSnapshot::new(circuit, now)).collect();
+        circuits.sort_by(|a, b| a.circuit_id.cmp(b.circuit_id));
+
+        let snapshot = serde_json::json!({
+            "circuits": circuits,
+            "stats": self.get_analysis_stats(),
+        });
+        snapshot.to_string()
+    }
+
     /// Get circuit analysis statistics
     pub fn get_analysis_stats(&self) -> CircuitAnalysisStats {
         let total_anomalies = self.circuits.values()
@@ -469,7 +687,7 @@ impl CircuitAnalysis {
 }
 
 /// Circuit analysis statistics
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CircuitAnalysisStats {
     pub active_circuits: usize,
     pub historical_circuits: usize,
@@ -481,6 +699,7 @@ pub struct CircuitAnalysisStats {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::MockClock;
 
     #[test]
     fn test_circuit_analysis_creation() {
@@ -509,4 +728,240 @@ mod tests {
         );
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_build_time_and_lifetime_follow_the_clock() {
+        let clock = MockClock::new();
+        let mut analysis = CircuitAnalysis::new(&TorSecurityConfig::default())
+            .unwrap()
+            .with_clock(Arc::new(clock.clone()));
+        let path = CircuitPath { guard_node: None, middle_node: None, exit_node: None, path_length: 3 };
+        analysis.register_circuit("slow".to_string(), None, path).unwrap();
+
+        clock.advance(Duration::from_secs(45));
+        analysis.update_circuit_state("slow", CircuitState::Built).unwrap();
+        assert_eq!(analysis.circuits["slow"].metrics.build_time, Duration::from_secs(45));
+
+        clock.advance(Duration::from_secs(600));
+        analysis.update_circuit_state("slow", CircuitState::Closed).unwrap();
+        assert_eq!(analysis.circuit_history.back().unwrap().metrics.lifetime, Duration::from_secs(645));
+    }
+
+    #[test]
+    fn test_analyze_single_circuit() {
+        let config = TorSecurityConfig::default();
+        let mut analysis = CircuitAnalysis::new(&config).unwrap();
+        analysis.initialize().unwrap();
+
+        let short_path = CircuitPath {
+            guard_node: Some("guard1".to_string()),
+            middle_node: None,
+            exit_node: Some("exit1".to_string()),
+            path_length: 2,
+        };
+        analysis.register_circuit("short".to_string(), None, short_path).unwrap();
+        analysis.record_activity("short", 200_000_000, 0, Duration::from_millis(50)).unwrap();
+
+        let anomalies = analysis.analyze_circuit("short");
+        assert_eq!(anomalies.len(), 2);
+        assert!(matches!(anomalies[0], CircuitAnomaly::SuspiciousPath));
+        assert!(matches!(anomalies[1], CircuitAnomaly::AbnormalTraffic));
+        assert!(analysis.analyze_circuit("unknown").is_empty());
+    }
+
+    #[test]
+    fn test_traffic_thresholds_come_from_config() {
+        let mut analysis = CircuitAnalysis::new(&TorSecurityConfig::default()).unwrap();
+        let path = CircuitPath {
+            guard_node: Some("guard1".to_string()),
+            middle_node: Some("middle1".to_string()),
+            exit_node: Some("exit1".to_string()),
+            path_length: 3,
+        };
+        analysis.register_circuit("modest".to_string(), None, path).unwrap();
+        analysis.record_activity("modest", 0, 5_000_000, Duration::from_millis(50)).unwrap();
+        assert!(analysis.analyze_circuit("modest").is_empty());
+
+        analysis.config.max_bytes_per_circuit = 1_000_000;
+        let anomalies = analysis.analyze_circuit("modest");
+        assert_eq!(anomalies.len(), 1);
+        assert!(matches!(anomalies[0], CircuitAnomaly::AbnormalTraffic));
+
+        analysis.config.max_requests_per_circuit = 0;
+        assert!(matches!(analysis.analyze_circuit("modest")[..], [CircuitAnomaly::ExcessiveConnections]));
+    }
+
+    #[test]
+    fn test_weights_change_high_risk_circuits() {
+        let path = |length| CircuitPath {
+            guard_node: Some("guard1".to_string()),
+            middle_node: None,
+            exit_node: Some("exit1".to_string()),
+            path_length: length,
+        };
+        let run = |weights: AnomalyWeights| {
+            let clock = MockClock::new();
+            let mut analysis = CircuitAnalysis::new(&TorSecurityConfig::default())
+                .unwrap()
+                .with_clock(Arc::new(clock.clone()));
+            analysis.config.weights = weights;
+            analysis.register_circuit("short_path".to_string(), None, path(2)).unwrap();
+            analysis.register_circuit("bulk".to_string(), None, path(3)).unwrap();
+            analysis.record_activity("bulk", 200_000_000, 0, Duration::from_millis(50)).unwrap();
+
+            clock.advance(Duration::from_secs(11));
+            analysis.analyze_circuits().unwrap();
+            let mut high_risk: Vec<_> = analysis.circuits.values()
+                .filter(|c| c.anomaly_score > analysis.config.anomaly_threshold)
+                .map(|c| c.circuit_id.clone())
+                .collect();
+            high_risk.sort();
+            (high_risk, analysis.circuits["short_path"].anomaly_score)
+        };
+
+        assert_eq!(run(AnomalyWeights::default()), (Vec::<String>::new(), 0.25));
+
+        let (high_risk, score) = run(AnomalyWeights { suspicious_path: 3.0, ..AnomalyWeights::default() });
+        assert_eq!(high_risk, vec!["short_path".to_string()]);
+        assert_eq!(score, 1.0);
+
+        let (high_risk, _) = run(AnomalyWeights { excessive_connections: 0.9, ..AnomalyWeights::default() });
+        assert_eq!(high_risk, vec!["bulk".to_string()]);
+    }
+
+    #[test]
+    fn test_anomalies_are_pushed_to_sink() {
+        let clock = MockClock::new();
+        let mut analysis = CircuitAnalysis::new(&TorSecurityConfig::default())
+            .unwrap()
+            .with_clock(Arc::new(clock.clone()));
+        let (tx, rx) = std::sync::mpsc::channel();
+        analysis.set_anomaly_sink(tx);
+
+        let path = CircuitPath {
+            guard_node: Some("guard1".to_string()),
+            middle_node: None,
+            exit_node: Some("exit1".to_string()),
+            path_length: 2,
+        };
+        analysis.register_circuit("short_path".to_string(), None, path).unwrap();
+        clock.advance(Duration::from_secs(11));
+        analysis.analyze_circuits().unwrap();
+
+        let event = rx.try_recv().unwrap();
+        assert_eq!(event.circuit_id, "short_path");
+        assert!(matches!(event.anomaly, CircuitAnomaly::SuspiciousPath));
+        assert_eq!(event.score, 0.25);
+        assert!(rx.try_recv().is_err());
+
+        // A dropped receiver detaches the sink instead of failing analysis
+        drop(rx);
+        clock.advance(Duration::from_secs(11));
+        analysis.analyze_circuits().unwrap();
+        assert!(analysis.anomaly_sink.is_none());
+    }
+
+    #[test]
+    fn test_export_snapshot() {
+        let clock = MockClock::new();
+        let mut analysis = CircuitAnalysis::new(&TorSecurityConfig::default())
+            .unwrap()
+            .with_clock(Arc::new(clock.clone()));
+        let path = CircuitPath {
+            guard_node: Some("guard1".to_string()),
+            middle_node: Some("middle1".to_string()),
+            exit_node: Some("exit1".to_string()),
+            path_length: 3,
+        };
+        analysis.register_circuit("b".to_string(), None, path.clone()).unwrap();
+        analysis.register_circuit("a".to_string(), Some("127.0.0.1".parse().unwrap()), path).unwrap();
+        clock.advance(Duration::from_secs(2));
+        analysis.record_activity("a", 10, 20, Duration::from_millis(1500)).unwrap();
+        clock.advance(Duration::from_millis(500));
+
+        let snapshot: serde_json::Value = serde_json::from_str(&analysis.export_snapshot()).unwrap();
+        let circuit = &snapshot["circuits"][0];
+        assert_eq!(circuit["circuit_id"], "a");
+        assert_eq!(circuit["state"], "building");
+        assert_eq!(circuit["source_ip"], "127.0.0.1");
+        assert_eq!(circuit["path"]["exit_node"], "exit1");
+        assert_eq!(circuit["metrics"]["average_response_time"], 1500);
+        assert_eq!(circuit["created_at"], 2500);
+        assert_eq!(circuit["last_activity"], 500);
+        assert_eq!(snapshot["circuits"][1]["circuit_id"], "b");
+        assert_eq!(snapshot["stats"]["active_circuits"], 2);
+    }
+
+    #[test]
+    fn test_serial_circuits_trigger_correlation() {
+        let clock = MockClock::new();
+        let mut analysis = CircuitAnalysis::new(&TorSecurityConfig::default())
+            .unwrap()
+            .with_clock(Arc::new(clock.clone()));
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let path = CircuitPath {
+            guard_node: Some("guard1".to_string()),
+            middle_node: Some("middle1".to_string()),
+            exit_node: Some("exit1".to_string()),
+            path_length: 3,
+        };
+
+        // Never more than one circuit open at a time
+        for i in 0..=analysis.config.max_circuits_per_source {
+            let circuit_id = format!("serial_{}", i);
+            analysis.register_circuit(circuit_id.clone(), Some(ip), path.clone()).unwrap();
+            analysis.update_circuit_state(&circuit_id, CircuitState::Closed).unwrap();
+        }
+        analysis.register_circuit("current".to_string(), Some(ip), path.clone()).unwrap();
+        analysis.register_circuit("other".to_string(), Some("192.0.2.2".parse().unwrap()), path).unwrap();
+
+        assert!(analysis.analyze_circuit("current").iter().any(|a| matches!(a, CircuitAnomaly::CorrelationAttempt)));
+        assert!(!analysis.analyze_circuit("other").iter().any(|a| matches!(a, CircuitAnomaly::CorrelationAttempt)));
+
+        clock.advance(Duration::from_secs(11));
+        analysis.analyze_circuits().unwrap();
+        assert!(analysis.circuits["current"].detected_anomalies.iter().any(|a| matches!(a, CircuitAnomaly::CorrelationAttempt)));
+    }
+
+    #[test]
+    fn test_expired_circuits_are_flagged() {
+        let clock = MockClock::new();
+        let mut analysis = CircuitAnalysis::new(&TorSecurityConfig::default())
+            .unwrap()
+            .with_clock(Arc::new(clock.clone()));
+        let path = CircuitPath {
+            guard_node: Some("guard1".to_string()),
+            middle_node: Some("middle1".to_string()),
+            exit_node: Some("exit1".to_string()),
+            path_length: 3,
+        };
+        analysis.register_circuit("old".to_string(), None, path.clone()).unwrap();
+        clock.advance(Duration::from_secs(7200));
+        // Still in use, so idle cleanup leaves it for the lifetime check
+        analysis.record_activity("old", 10, 10, Duration::from_millis(50)).unwrap();
+        analysis.register_circuit("fresh".to_string(), None, path).unwrap();
+        analysis.analyze_circuits().unwrap();
+
+        assert_eq!(analysis.expired_circuits(), ["old".to_string()]);
+        assert_eq!(analysis.circuits["old"].state, CircuitState::Closing);
+        assert_eq!(analysis.circuits["fresh"].state, CircuitState::Building);
+    }
+
+    #[test]
+    fn test_suspicious_circuit_lookup() {
+        let mut analysis = CircuitAnalysis::new(&TorSecurityConfig::default()).unwrap();
+        let path = CircuitPath {
+            guard_node: Some("guard1".to_string()),
+            middle_node: Some("middle1".to_string()),
+            exit_node: Some("exit1".to_string()),
+            path_length: 3,
+        };
+        analysis.register_circuit("calm".to_string(), None, path.clone()).unwrap();
+        analysis.register_circuit("noisy".to_string(), None, path).unwrap();
+        analysis.circuits.get_mut("noisy").unwrap().anomaly_score = 0.9;
+
+        assert!(analysis.is_circuit_suspicious("noisy"));
+        assert!(!analysis.is_circuit_suspicious("calm"));
+        assert!(!analysis.is_circuit_suspicious("unknown"));
+    }
 }
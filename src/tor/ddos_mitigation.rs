@@ -3,13 +3,19 @@
 //! Specialized protection against Tor-based DDoS attacks targeting hidden services.
 //! Implements adaptive rate limiting, traffic pattern analysis, and circuit-based filtering.
 
-use crate::tor::{TorSecurityConfig, TorSecurityResult};
-use std::collections::{HashMap, VecDeque};
+use crate::clock::{self, Clock};
+use crate::tor::{TorSecurityConfig, TorSecurityError, TorSecurityResult};
+use log::info;
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::net::IpAddr;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 /// DDoS attack pattern detection
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum AttackPattern {
     HighFrequency,
     LowAndSlow,
@@ -31,14 +37,44 @@ struct TrafficSample {
 }
 
 /// DDoS mitigation configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct DDoSConfig {
     pub max_requests_per_second: u32,
+    #[serde(with = "crate::serde_duration::secs")]
     pub circuit_timeout: Duration,
     pub max_circuits_per_ip: u32,
+    /// Payload bytes per second a single circuit may send before it looks suspicious
+    pub max_bytes_per_second: u64,
+    #[serde(with = "crate::serde_duration::secs")]
     pub analysis_window: Duration,
     pub mitigation_threshold: f64,
     pub enable_adaptive_limits: bool,
+    /// Traffic load, as a multiple of `max_requests_per_second`, entering `EarlyWarning`
+    pub early_warning_load: f64,
+    /// Traffic load entering `UnderAttack`
+    pub under_attack_load: f64,
+    /// Traffic load entering `Emergency`
+    pub emergency_load: f64,
+    /// Factor applied to the adaptive limit under heavy load
+    pub tighten_factor: f64,
+    /// Factor applied to the adaptive limit when traffic is light
+    pub relax_factor: f64,
+    /// Traffic load above which the adaptive limit is tightened
+    pub tighten_above_load: f64,
+    /// Traffic load below which the adaptive limit is relaxed
+    pub relax_below_load: f64,
+    /// Lowest the adaptive limit is tightened to, as a multiple of `max_requests_per_second`
+    pub adaptive_limit_floor: f64,
+    /// Highest the adaptive limit is relaxed to, as a multiple of `max_requests_per_second`
+    pub adaptive_limit_ceiling: f64,
+    /// Cap on the penalty for repeat offenders, as a divisor of the adaptive limit
+    pub max_penalty_multiplier: u32,
+    /// Quiet period after which a repeat offender's penalty halves
+    #[serde(with = "crate::serde_duration::secs")]
+    pub penalty_decay: Duration,
+    /// Sources that are always allowed, whatever the state or their rate
+    pub allowlist: Vec<IpNet>,
 }
 
 impl Default for DDoSConfig {
@@ -47,13 +83,77 @@ impl Default for DDoSConfig {
             max_requests_per_second: 100,
             circuit_timeout: Duration::from_secs(300),
             max_circuits_per_ip: 5,
+            max_bytes_per_second: 1024 * 1024,
             analysis_window: Duration::from_secs(60),
             mitigation_threshold: 0.8,
             enable_adaptive_limits: true,
+            early_warning_load: 1.0,
+            under_attack_load: 1.5,
+            emergency_load: 2.0,
+            tighten_factor: 0.8,
+            relax_factor: 1.1,
+            tighten_above_load: 1.2,
+            relax_below_load: 0.5,
+            adaptive_limit_floor: 0.25,
+            adaptive_limit_ceiling: 2.0,
+            max_penalty_multiplier: 16,
+            penalty_decay: Duration::from_secs(300),
+            allowlist: Vec::new(),
         }
     }
 }
 
+impl DDoSConfig {
+    /// Check that the load thresholds increase and the adaptation factors point the right way
+    fn validate(&self) -> TorSecurityResult<()> {
+        if !(0.0 < self.early_warning_load
+            && self.early_warning_load < self.under_attack_load
+            && self.under_attack_load < self.emergency_load)
+        {
+            return Err(TorSecurityError::ConfigurationError(format!(
+                "DDoS load thresholds must be positive and increasing, got {} / {} / {}",
+                self.early_warning_load, self.under_attack_load, self.emergency_load
+            )));
+        }
+        if !(self.tighten_factor > 0.0 && self.tighten_factor < 1.0) {
+            return Err(TorSecurityError::ConfigurationError(format!(
+                "DDoS tighten factor must be between 0 and 1, got {}",
+                self.tighten_factor
+            )));
+        }
+        if self.relax_factor.is_nan() || self.relax_factor < 1.0 {
+            return Err(TorSecurityError::ConfigurationError(format!(
+                "DDoS relax factor must be at least 1, got {}",
+                self.relax_factor
+            )));
+        }
+        if !(0.0 <= self.relax_below_load && self.relax_below_load < self.tighten_above_load) {
+            return Err(TorSecurityError::ConfigurationError(format!(
+                "DDoS adaptation loads must satisfy 0 <= relax below < tighten above, got {} / {}",
+                self.relax_below_load, self.tighten_above_load
+            )));
+        }
+        // The adaptive limit starts at `max_requests_per_second`, a multiple of 1
+        if !(0.0 < self.adaptive_limit_floor && self.adaptive_limit_floor <= 1.0 && self.adaptive_limit_ceiling >= 1.0) {
+            return Err(TorSecurityError::ConfigurationError(format!(
+                "DDoS adaptive limit bounds must satisfy 0 < floor <= 1 <= ceiling, got {} / {}",
+                self.adaptive_limit_floor, self.adaptive_limit_ceiling
+            )));
+        }
+        Ok(())
+    }
+
+    /// Lowest adaptive limit, in requests per second and never below one
+    fn adaptive_limit_floor(&self) -> u32 {
+        ((self.max_requests_per_second as f64 * self.adaptive_limit_floor) as u32).max(1)
+    }
+
+    /// Highest adaptive limit, in requests per second
+    fn adaptive_limit_ceiling(&self) -> u32 {
+        (self.max_requests_per_second as f64 * self.adaptive_limit_ceiling) as u32
+    }
+}
+
 /// Circuit information tracking
 #[derive(Debug, Clone)]
 struct CircuitInfo {
@@ -62,12 +162,76 @@ struct CircuitInfo {
     source_ip: Option<IpAddr>,
     created_at: Instant,
     request_count: u32,
+    bytes_total: u64,
     last_activity: Instant,
     suspicious_score: f64,
 }
 
-/// DDoS mitigation state
+/// Per-IP request counter over a rolling second
+///
+/// Keeps the counts for the current and previous one-second buckets and
+/// weights the previous one by how much of it still overlaps the last second,
+/// so bursts straddling a bucket boundary are still counted together while
+/// memory stays constant per IP.
 #[derive(Debug, Clone)]
+struct SlidingWindow {
+    bucket_start: Instant,
+    current: u32,
+    previous: u32,
+}
+
+impl SlidingWindow {
+    const BUCKET: Duration = Duration::from_secs(1);
+
+    fn new(now: Instant) -> Self {
+        Self {
+            bucket_start: now,
+            current: 0,
+            previous: 0,
+        }
+    }
+
+    /// Move the buckets forward so `now` falls in the current one
+    fn advance(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.bucket_start);
+        if elapsed >= Self::BUCKET * 2 {
+            self.previous = 0;
+            self.current = 0;
+            self.bucket_start = now;
+        } else if elapsed >= Self::BUCKET {
+            self.previous = self.current;
+            self.current = 0;
+            self.bucket_start += Self::BUCKET;
+        }
+    }
+
+    fn record(&mut self, now: Instant) {
+        self.advance(now);
+        self.current += 1;
+    }
+
+    /// Estimated requests over the second ending at `now`
+    fn rate(&self, now: Instant) -> f64 {
+        let mut window = self.clone();
+        window.advance(now);
+        let into_bucket = now.saturating_duration_since(window.bucket_start).as_secs_f64();
+        let overlap = (1.0 - into_bucket / Self::BUCKET.as_secs_f64()).max(0.0);
+        window.previous as f64 * overlap + window.current as f64
+    }
+}
+
+/// Escalating penalty for an IP that keeps exceeding its limit
+#[derive(Debug, Clone)]
+struct Penalty {
+    /// The adaptive limit is divided by this, and each offence blocks the IP for this many seconds
+    multiplier: u32,
+    blocked_until: Instant,
+    last_offense: Instant,
+}
+
+/// DDoS mitigation state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum MitigationState {
     Normal,
     EarlyWarning,
@@ -75,15 +239,39 @@ pub enum MitigationState {
     Emergency,
 }
 
+/// Outcome of checking a request against the mitigation state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestDecision {
+    Allow,
+    Deny,
+    /// Serve a challenge such as the clock CAPTCHA, then call
+    /// `mark_challenge_passed` for the circuit once it is solved
+    Challenge,
+}
+
+/// Callback invoked with the old and new state on every mitigation state change
+pub type StateChangeCallback = Box<dyn Fn(MitigationState, MitigationState) + Send + Sync>;
+
 /// Main DDoS mitigation system
 pub struct DDoSMitigation {
     config: DDoSConfig,
     traffic_samples: VecDeque<TrafficSample>,
     circuit_tracker: HashMap<String, CircuitInfo>,
-    ip_request_counts: HashMap<IpAddr, (u32, Instant)>,
+    ip_request_counts: HashMap<IpAddr, SlidingWindow>,
+    penalties: HashMap<IpAddr, Penalty>,
     current_state: MitigationState,
     adaptive_limit: u32,
+    /// Circuits allowed per IP, tightened while circuit flooding is detected
+    circuit_limit: u32,
+    /// Window traffic is analysed over, lengthened while low-and-slow traffic is detected
+    analysis_window: Duration,
+    last_attack_pattern: AttackPattern,
     last_analysis: Instant,
+    allowlisted_requests: u64,
+    /// Circuits that solved a challenge while under attack
+    verified_circuits: HashSet<String>,
+    state_change_callback: Option<StateChangeCallback>,
+    clock: Arc<dyn Clock>,
 }
 
 impl DDoSMitigation {
@@ -94,29 +282,64 @@ impl DDoSMitigation {
             circuit_timeout: Duration::from_secs(300),
             max_circuits_per_ip: tor_config.max_connections_per_circuit,
             analysis_window: Duration::from_secs(tor_config.rate_limit_window_seconds),
-            mitigation_threshold: 0.8,
-            enable_adaptive_limits: true,
+            ..DDoSConfig::default()
         };
+        Self::with_config(config)
+    }
+
+    /// Create a DDoS mitigation instance with custom configuration
+    pub fn with_config(config: DDoSConfig) -> TorSecurityResult<Self> {
+        config.validate()?;
 
         Ok(Self {
             adaptive_limit: config.max_requests_per_second,
+            circuit_limit: config.max_circuits_per_ip,
+            analysis_window: config.analysis_window,
+            last_attack_pattern: AttackPattern::Unknown,
             config,
             traffic_samples: VecDeque::new(),
             circuit_tracker: HashMap::new(),
             ip_request_counts: HashMap::new(),
+            penalties: HashMap::new(),
             current_state: MitigationState::Normal,
             last_analysis: Instant::now(),
+            allowlisted_requests: 0,
+            verified_circuits: HashSet::new(),
+            state_change_callback: None,
+            clock: clock::system(),
         })
     }
 
+    /// Read the time from `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.last_analysis = clock.now();
+        self.clock = clock;
+        self
+    }
+
+    /// Re-derive the limits taken from the shared configuration, keeping traffic history and penalties
+    pub fn apply_tor_config(&mut self, tor_config: &TorSecurityConfig) {
+        self.config.max_requests_per_second = tor_config.max_requests_per_window / tor_config.rate_limit_window_seconds as u32;
+        self.config.max_circuits_per_ip = tor_config.max_connections_per_circuit;
+        self.config.analysis_window = Duration::from_secs(tor_config.rate_limit_window_seconds);
+        self.adaptive_limit = self.config.max_requests_per_second;
+        self.circuit_limit = self.config.max_circuits_per_ip;
+        self.analysis_window = self.config.analysis_window;
+    }
+
     /// Initialize the DDoS mitigation system
     pub fn initialize(&mut self) -> TorSecurityResult<()> {
         self.traffic_samples.clear();
         self.circuit_tracker.clear();
         self.ip_request_counts.clear();
+        self.penalties.clear();
+        self.verified_circuits.clear();
         self.current_state = MitigationState::Normal;
-        self.last_analysis = Instant::now();
-        println!("DDoS Mitigation initialized");
+        self.circuit_limit = self.config.max_circuits_per_ip;
+        self.analysis_window = self.config.analysis_window;
+        self.last_attack_pattern = AttackPattern::Unknown;
+        self.last_analysis = self.clock.now();
+        info!("DDoS Mitigation initialized");
         Ok(())
     }
 
@@ -125,10 +348,42 @@ impl DDoSMitigation {
         self.traffic_samples.clear();
         self.circuit_tracker.clear();
         self.ip_request_counts.clear();
-        println!("DDoS Mitigation shutdown");
+        self.penalties.clear();
+        self.verified_circuits.clear();
+        info!("DDoS Mitigation shutdown");
         Ok(())
     }
 
+    /// Register a callback run with `(old, new)` whenever the mitigation state
+    /// changes, e.g. to page on-call or reroute upstream. Replaces any earlier one.
+    pub fn on_state_change<F>(&mut self, callback: F)
+    where
+        F: Fn(MitigationState, MitigationState) + Send + Sync + 'static,
+    {
+        self.state_change_callback = Some(Box::new(callback));
+    }
+
+    /// Always allow `range`, which may be a single address or a CIDR range
+    pub fn add_allowlisted(&mut self, range: impl Into<IpNet>) {
+        let range = range.into().trunc();
+        if !self.config.allowlist.contains(&range) {
+            self.config.allowlist.push(range);
+        }
+    }
+
+    /// Remove an allowlisted address or range, returning whether it was present
+    pub fn remove_allowlisted(&mut self, range: impl Into<IpNet>) -> bool {
+        let range = range.into().trunc();
+        let before = self.config.allowlist.len();
+        self.config.allowlist.retain(|entry| entry.trunc() != range);
+        self.config.allowlist.len() != before
+    }
+
+    /// Check whether `ip` falls inside an allowlisted range
+    pub fn is_allowlisted(&self, ip: IpAddr) -> bool {
+        self.config.allowlist.iter().any(|range| range.contains(&ip))
+    }
+
     /// Record a new request for analysis
     pub fn record_request(
         &mut self,
@@ -136,7 +391,7 @@ impl DDoSMitigation {
         request_size: u64,
         circuit_id: Option<String>,
     ) -> TorSecurityResult<()> {
-        let now = Instant::now();
+        let now = self.clock.now();
 
         // Add traffic sample
         let sample = TrafficSample {
@@ -149,7 +404,7 @@ impl DDoSMitigation {
 
         // Update circuit tracking
         if let Some(cid) = circuit_id {
-            self.update_circuit_tracking(cid, source_ip, now)?;
+            self.update_circuit_tracking(cid, source_ip, request_size, now)?;
         }
 
         // Update IP request counts
@@ -166,57 +421,120 @@ impl DDoSMitigation {
         Ok(())
     }
 
-    /// Check if a request should be allowed
+    /// Check if a request should be allowed, treating a challenge as a refusal
     pub fn should_allow_request(
         &mut self,
         source_ip: Option<IpAddr>,
         circuit_id: Option<String>,
     ) -> TorSecurityResult<bool> {
-        let now = Instant::now();
+        Ok(self.evaluate_request(source_ip, circuit_id)? == RequestDecision::Allow)
+    }
+
+    /// Decide whether to allow, deny or challenge a request
+    ///
+    /// Sources over the rate or circuit limits are denied, as is everything in
+    /// `Emergency`. While `UnderAttack`, requests are challenged instead of
+    /// dropped unless their circuit already passed a challenge or looks calm.
+    ///
+    /// Each time an IP goes over its limit its penalty doubles, up to
+    /// `max_penalty_multiplier`: it is refused for that many seconds, and then
+    /// held to the adaptive limit divided by the penalty. The penalty halves
+    /// again for every `penalty_decay` the IP stays within its limit.
+    pub fn evaluate_request(
+        &mut self,
+        source_ip: Option<IpAddr>,
+        circuit_id: Option<String>,
+    ) -> TorSecurityResult<RequestDecision> {
+        let now = self.clock.now();
+        self.evaluate_request_at(source_ip, circuit_id, now)
+    }
 
-        // Check IP rate limiting
+    fn evaluate_request_at(
+        &mut self,
+        source_ip: Option<IpAddr>,
+        circuit_id: Option<String>,
+        now: Instant,
+    ) -> TorSecurityResult<RequestDecision> {
+        // Allowlisted sources bypass every check; they are still recorded for analysis
+        if let Some(ip) = source_ip
+            && self.is_allowlisted(ip)
+        {
+            self.allowlisted_requests += 1;
+            return Ok(RequestDecision::Allow);
+        }
+
+        // Check IP rate limiting, escalating the penalty of repeat offenders
         if let Some(ip) = source_ip {
-            if let Some((count, window_start)) = self.ip_request_counts.get(&ip) {
-                if now.duration_since(*window_start) < Duration::from_secs(1) {
-                    if *count >= self.adaptive_limit {
-                        return Ok(false);
-                    }
-                }
+            let multiplier = self.penalty_multiplier(ip, now);
+            if self.penalties.get(&ip).is_some_and(|penalty| now < penalty.blocked_until) {
+                return Ok(RequestDecision::Deny);
+            }
+            if self.ip_over_limit(ip, now, multiplier) {
+                self.escalate_penalty(ip, now);
+                return Ok(RequestDecision::Deny);
             }
         }
 
         // Check circuit limits
-        if let Some(ref cid) = circuit_id {
-            if let Some(circuit) = self.circuit_tracker.get(cid) {
-                if circuit.suspicious_score > self.config.mitigation_threshold {
-                    return Ok(false);
-                }
+        if let Some(ref cid) = circuit_id
+            && let Some(circuit) = self.circuit_tracker.get(cid)
+        {
+            if circuit.suspicious_score > self.config.mitigation_threshold {
+                return Ok(RequestDecision::Deny);
+            }
 
-                if source_ip.is_some() {
-                    let circuits_for_ip = self.circuit_tracker.values()
-                        .filter(|c| c.source_ip == source_ip)
-                        .count();
+            if source_ip.is_some() {
+                let circuits_for_ip = self.circuit_tracker.values()
+                    .filter(|c| c.source_ip == source_ip)
+                    .count();
 
-                    if circuits_for_ip >= self.config.max_circuits_per_ip as usize {
-                        return Ok(false);
-                    }
+                if circuits_for_ip >= self.circuit_limit as usize {
+                    return Ok(RequestDecision::Deny);
                 }
             }
         }
 
         // Check global state
         match self.current_state {
-            MitigationState::Emergency => Ok(false),
-            MitigationState::UnderAttack => Ok(source_ip.is_some() && circuit_id.is_some()),
-            _ => Ok(true),
+            MitigationState::Emergency => Ok(RequestDecision::Deny),
+            MitigationState::UnderAttack => {
+                let Some(cid) = circuit_id else {
+                    return Ok(RequestDecision::Challenge);
+                };
+                if self.verified_circuits.contains(&cid) {
+                    return Ok(RequestDecision::Allow);
+                }
+                let calm = source_ip.is_some()
+                    && self.circuit_tracker.get(&cid).is_some_and(|circuit| {
+                        circuit.suspicious_score <= self.config.mitigation_threshold / 2.0
+                    });
+                Ok(if calm { RequestDecision::Allow } else { RequestDecision::Challenge })
+            }
+            _ => Ok(RequestDecision::Allow),
         }
     }
 
+    /// Total payload bytes seen on a tracked circuit
+    pub fn circuit_bytes(&self, circuit_id: &str) -> Option<u64> {
+        self.circuit_tracker.get(circuit_id).map(|circuit| circuit.bytes_total)
+    }
+
+    /// Admit a circuit that solved the challenge served for it
+    pub fn mark_challenge_passed(&mut self, circuit_id: impl Into<String>) {
+        self.verified_circuits.insert(circuit_id.into());
+    }
+
+    /// Whether `mark_challenge_passed` admitted the circuit
+    pub fn has_passed_challenge(&self, circuit_id: &str) -> bool {
+        self.verified_circuits.contains(circuit_id)
+    }
+
     /// Update circuit tracking information
     fn update_circuit_tracking(
         &mut self,
         circuit_id: String,
         source_ip: Option<IpAddr>,
+        request_size: u64,
         now: Instant,
     ) -> TorSecurityResult<()> {
         let circuit = self.circuit_tracker.entry(circuit_id.clone()).or_insert(CircuitInfo {
@@ -224,45 +542,88 @@ impl DDoSMitigation {
             source_ip,
             created_at: now,
             request_count: 0,
+            bytes_total: 0,
             last_activity: now,
             suspicious_score: 0.0,
         });
 
         circuit.request_count += 1;
+        circuit.bytes_total = circuit.bytes_total.saturating_add(request_size);
         circuit.last_activity = now;
 
-        // Calculate suspicious score based on request frequency
+        // Calculate suspicious score based on request frequency and payload volume,
+        // whichever is further over its limit
         let duration = now.duration_since(circuit.created_at).as_secs_f64();
+        let mut score: f64 = 0.0;
         if duration > 0.0 {
             let request_rate = circuit.request_count as f64 / duration;
-            circuit.suspicious_score = (request_rate / self.config.max_requests_per_second as f64).min(1.0);
+            score = request_rate / self.config.max_requests_per_second as f64;
         }
+        // Measure over at least a second so one large payload is scored straight away
+        let byte_rate = circuit.bytes_total as f64 / duration.max(1.0);
+        score = score.max(byte_rate / self.config.max_bytes_per_second.max(1) as f64);
+        circuit.suspicious_score = score.min(1.0);
 
         Ok(())
     }
 
     /// Update IP tracking information
     fn update_ip_tracking(&mut self, ip: IpAddr, now: Instant) {
-        let (count, window_start) = self.ip_request_counts.entry(ip).or_insert((0, now));
+        self.ip_request_counts
+            .entry(ip)
+            .or_insert_with(|| SlidingWindow::new(now))
+            .record(now);
+    }
 
-        if now.duration_since(*window_start) >= Duration::from_secs(1) {
-            *count = 1;
-            *window_start = now;
-        } else {
-            *count += 1;
+    /// Check whether `ip` has used up the adaptive limit, divided by its penalty, over the last second
+    fn ip_over_limit(&self, ip: IpAddr, now: Instant, multiplier: u32) -> bool {
+        let limit = (self.adaptive_limit / multiplier.max(1)).max(1);
+        self.ip_request_counts
+            .get(&ip)
+            .is_some_and(|window| window.rate(now) >= limit as f64)
+    }
+
+    /// Current penalty for `ip` after decaying it for quiet periods, 1 when unpenalised
+    fn penalty_multiplier(&mut self, ip: IpAddr, now: Instant) -> u32 {
+        let decay = self.config.penalty_decay.max(Duration::from_secs(1));
+        let Some(penalty) = self.penalties.get_mut(&ip) else { return 1 };
+
+        let quiet_periods = (now.saturating_duration_since(penalty.last_offense).as_secs_f64()
+            / decay.as_secs_f64()) as u32;
+        if quiet_periods > 0 {
+            penalty.multiplier = penalty.multiplier.checked_shr(quiet_periods).unwrap_or(0);
+            penalty.last_offense += decay * quiet_periods;
         }
+        if penalty.multiplier <= 1 {
+            self.penalties.remove(&ip);
+            return 1;
+        }
+        penalty.multiplier
+    }
+
+    /// Double the penalty for `ip` after it went over its limit
+    fn escalate_penalty(&mut self, ip: IpAddr, now: Instant) {
+        let max_multiplier = self.config.max_penalty_multiplier.max(1);
+        let penalty = self.penalties.entry(ip).or_insert(Penalty {
+            multiplier: 1,
+            blocked_until: now,
+            last_offense: now,
+        });
+        penalty.multiplier = penalty.multiplier.saturating_mul(2).min(max_multiplier);
+        penalty.blocked_until = now + Duration::from_secs(penalty.multiplier as u64);
+        penalty.last_offense = now;
     }
 
     /// Analyze traffic patterns and update mitigation state
     fn analyze_traffic(&mut self) -> TorSecurityResult<()> {
-        let now = Instant::now();
+        let now = self.clock.now();
 
         // Clean up old data
         self.cleanup_old_data(now);
 
         // Analyze traffic patterns
-        let attack_pattern = self.detect_attack_pattern();
-        let traffic_load = self.calculate_traffic_load();
+        let attack_pattern = self.detect_attack_pattern(now);
+        let traffic_load = self.calculate_traffic_load(now);
 
         // Update mitigation state
         self.update_mitigation_state(traffic_load, attack_pattern);
@@ -276,16 +637,16 @@ impl DDoSMitigation {
     }
 
     /// Detect attack patterns in traffic
-    fn detect_attack_pattern(&self) -> AttackPattern {
+    fn detect_attack_pattern(&self, now: Instant) -> AttackPattern {
         let recent_samples: Vec<_> = self.traffic_samples.iter()
-            .filter(|s| s.timestamp.elapsed() < self.config.analysis_window)
+            .filter(|s| now.saturating_duration_since(s.timestamp) < self.analysis_window)
             .collect();
 
         if recent_samples.is_empty() {
             return AttackPattern::Unknown;
         }
 
-        let request_rate = recent_samples.len() as f64 / self.config.analysis_window.as_secs_f64();
+        let request_rate = recent_samples.len() as f64 / self.analysis_window.as_secs_f64();
 
         if request_rate > self.config.max_requests_per_second as f64 * 2.0 {
             AttackPattern::HighFrequency
@@ -299,45 +660,77 @@ impl DDoSMitigation {
     }
 
     /// Calculate current traffic load
-    fn calculate_traffic_load(&self) -> f64 {
+    fn calculate_traffic_load(&self, now: Instant) -> f64 {
         let recent_count = self.traffic_samples.iter()
-            .filter(|s| s.timestamp.elapsed() < Duration::from_secs(1))
+            .filter(|s| now.saturating_duration_since(s.timestamp) < Duration::from_secs(1))
             .count();
 
         recent_count as f64 / self.config.max_requests_per_second as f64
     }
 
     /// Update mitigation state based on analysis
-    fn update_mitigation_state(&mut self, traffic_load: f64, _attack_pattern: AttackPattern) {
-        self.current_state = if traffic_load > 2.0 {
+    ///
+    /// The detected pattern shapes the response: circuit flooding halves the
+    /// circuits allowed per IP, high frequency traffic halves the adaptive rate
+    /// limit, and low-and-slow traffic stretches the analysis window so slow
+    /// sources accumulate enough samples to stand out. Limits return to their
+    /// configured values once the pattern is gone.
+    fn update_mitigation_state(&mut self, traffic_load: f64, attack_pattern: AttackPattern) {
+        let previous_state = self.current_state;
+        self.current_state = if traffic_load > self.config.emergency_load {
             MitigationState::Emergency
-        } else if traffic_load > 1.5 {
+        } else if traffic_load > self.config.under_attack_load {
             MitigationState::UnderAttack
-        } else if traffic_load > 1.0 {
+        } else if traffic_load > self.config.early_warning_load {
             MitigationState::EarlyWarning
         } else {
             MitigationState::Normal
         };
+        if self.current_state != previous_state
+            && let Some(callback) = &self.state_change_callback
+        {
+            callback(previous_state, self.current_state);
+        }
+
+        self.circuit_limit = self.config.max_circuits_per_ip;
+        self.analysis_window = self.config.analysis_window;
+        // With adaptation on, `adapt_rate_limits` relaxes the limit back gradually
+        if !self.config.enable_adaptive_limits {
+            self.adaptive_limit = self.config.max_requests_per_second;
+        }
+        match attack_pattern {
+            AttackPattern::CircuitFlooding => {
+                self.circuit_limit = (self.config.max_circuits_per_ip / 2).max(1);
+            }
+            AttackPattern::HighFrequency => {
+                self.adaptive_limit = (self.adaptive_limit / 2).max(self.config.adaptive_limit_floor());
+            }
+            AttackPattern::LowAndSlow => {
+                self.analysis_window = self.config.analysis_window * 4;
+            }
+            AttackPattern::RendezvousOverload | AttackPattern::Unknown => {}
+        }
+        self.last_attack_pattern = attack_pattern;
     }
 
     /// Adapt rate limits based on current conditions
     fn adapt_rate_limits(&mut self, traffic_load: f64) {
-        if traffic_load > 1.2 {
-            self.adaptive_limit = (self.adaptive_limit as f64 * 0.8) as u32;
-        } else if traffic_load < 0.5 {
-            self.adaptive_limit = (self.adaptive_limit as f64 * 1.1) as u32;
+        if traffic_load > self.config.tighten_above_load {
+            self.adaptive_limit = (self.adaptive_limit as f64 * self.config.tighten_factor) as u32;
+        } else if traffic_load < self.config.relax_below_load {
+            self.adaptive_limit = (self.adaptive_limit as f64 * self.config.relax_factor) as u32;
         }
 
         self.adaptive_limit = self.adaptive_limit
-            .max(self.config.max_requests_per_second / 4)
-            .min(self.config.max_requests_per_second * 2);
+            .max(self.config.adaptive_limit_floor())
+            .min(self.config.adaptive_limit_ceiling());
     }
 
     /// Clean up old tracking data
     fn cleanup_old_data(&mut self, now: Instant) {
         // Remove old traffic samples
         while let Some(sample) = self.traffic_samples.front() {
-            if now.duration_since(sample.timestamp) > self.config.analysis_window * 2 {
+            if now.duration_since(sample.timestamp) > self.analysis_window * 2 {
                 self.traffic_samples.pop_front();
             } else {
                 break;
@@ -349,37 +742,72 @@ impl DDoSMitigation {
             now.duration_since(circuit.last_activity) < self.config.circuit_timeout
         });
 
+        // Forget verifications for circuits that are gone
+        let circuit_tracker = &self.circuit_tracker;
+        self.verified_circuits.retain(|cid| circuit_tracker.contains_key(cid));
+
+        // Drop penalties that have fully decayed
+        let decay = self.config.penalty_decay;
+        self.penalties.retain(|_, penalty| {
+            now.duration_since(penalty.last_offense) < decay * penalty.multiplier
+        });
+
         // Remove old IP tracking data
-        self.ip_request_counts.retain(|_, (_, window_start)| {
-            now.duration_since(*window_start) < Duration::from_secs(60)
+        self.ip_request_counts.retain(|_, window| {
+            now.duration_since(window.bucket_start) < Duration::from_secs(60)
         });
     }
 
+    /// Current mitigation statistics as JSON, for dashboards
+    pub fn stats_json(&self) -> String {
+        // Serializing plain numbers and unit variants cannot fail
+        serde_json::to_string(&self.get_mitigation_stats()).unwrap_or_default()
+    }
+
     /// Get current mitigation statistics
     pub fn get_mitigation_stats(&self) -> MitigationStats {
         MitigationStats {
-            current_state: self.current_state.clone(),
+            current_state: self.current_state,
             active_circuits: self.circuit_tracker.len(),
             tracked_ips: self.ip_request_counts.len(),
+            penalized_ips: self.penalties.len(),
             recent_samples: self.traffic_samples.len(),
             adaptive_limit: self.adaptive_limit,
+            circuit_limit: self.circuit_limit,
+            analysis_window: self.analysis_window,
+            last_attack_pattern: self.last_attack_pattern.clone(),
+            allowlisted_requests: self.allowlisted_requests,
         }
     }
 }
 
+fn serialize_secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
 /// DDoS mitigation statistics
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct MitigationStats {
     pub current_state: MitigationState,
     pub active_circuits: usize,
     pub tracked_ips: usize,
+    /// IPs currently serving a repeat-offender penalty
+    pub penalized_ips: usize,
     pub recent_samples: usize,
     pub adaptive_limit: u32,
+    pub circuit_limit: u32,
+    #[serde(rename = "analysis_window_secs", serialize_with = "serialize_secs")]
+    pub analysis_window: Duration,
+    /// Pattern found by the latest analysis, explaining the current limits
+    pub last_attack_pattern: AttackPattern,
+    /// Requests let through by the allowlist
+    pub allowlisted_requests: u64,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::MockClock;
 
     #[test]
     fn test_ddos_mitigation_creation() {
@@ -388,6 +816,22 @@ mod tests {
         assert!(mitigation.is_ok());
     }
 
+    #[test]
+    fn test_config_from_partial_json() {
+        let config: DDoSConfig = serde_json::from_str(
+            r#"{"circuit_timeout": 120, "penalty_decay": 0.5, "allowlist": ["10.0.0.0/8"]}"#,
+        )
+        .unwrap();
+        assert_eq!(config.circuit_timeout, Duration::from_secs(120));
+        assert_eq!(config.penalty_decay, Duration::from_millis(500));
+        assert_eq!(config.allowlist, vec!["10.0.0.0/8".parse::<IpNet>().unwrap()]);
+        assert_eq!(config.analysis_window, DDoSConfig::default().analysis_window);
+
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["circuit_timeout"], 120);
+        assert_eq!(json["analysis_window"], 60);
+    }
+
     #[test]
     fn test_request_recording() {
         let config = TorSecurityConfig::default();
@@ -401,4 +845,368 @@ mod tests {
         );
         assert!(result.is_ok());
     }
+
+    /// Mitigation allowing 10 requests per second over a 10 second window
+    fn test_mitigation() -> DDoSMitigation {
+        let mut mitigation = DDoSMitigation::with_config(DDoSConfig {
+            max_requests_per_second: 10,
+            analysis_window: Duration::from_secs(10),
+            ..DDoSConfig::default()
+        }).unwrap();
+        mitigation.initialize().unwrap();
+        mitigation
+    }
+
+    /// Queue `count` samples spaced `spacing` apart, ending now
+    fn push_samples(mitigation: &mut DDoSMitigation, count: u32, spacing: Duration) {
+        let now = Instant::now();
+        for i in (0..count).rev() {
+            mitigation.traffic_samples.push_back(TrafficSample {
+                timestamp: now - spacing * i,
+                source_ip: None,
+                request_size: 512,
+                circuit_id: None,
+            });
+        }
+    }
+
+    #[test]
+    fn test_high_frequency_drops_adaptive_limit() {
+        let mut mitigation = test_mitigation();
+        let ip = "192.0.2.1".parse().unwrap();
+        for _ in 0..250 {
+            mitigation.record_request(Some(ip), 512, None).unwrap();
+        }
+        mitigation.analyze_traffic().unwrap();
+
+        let stats = mitigation.get_mitigation_stats();
+        assert_eq!(stats.last_attack_pattern, AttackPattern::HighFrequency);
+        assert!(matches!(stats.current_state, MitigationState::Emergency));
+        // Halved by the pattern, then cut again for the load
+        assert_eq!(stats.adaptive_limit, 4);
+        assert_eq!(stats.circuit_limit, 5);
+    }
+
+    #[test]
+    fn test_high_frequency_limit_recovers_without_adaptation() {
+        let mut mitigation = DDoSMitigation::with_config(DDoSConfig {
+            max_requests_per_second: 10,
+            enable_adaptive_limits: false,
+            ..DDoSConfig::default()
+        }).unwrap();
+        for _ in 0..3 {
+            mitigation.update_mitigation_state(2.5, AttackPattern::HighFrequency);
+        }
+        // Halved once for the pattern, not once per analysis
+        assert_eq!(mitigation.get_mitigation_stats().adaptive_limit, 5);
+
+        mitigation.update_mitigation_state(0.1, AttackPattern::Unknown);
+        assert_eq!(mitigation.get_mitigation_stats().adaptive_limit, 10);
+    }
+
+    #[test]
+    fn test_sustained_load_converges_to_configured_floor() {
+        let mut mitigation = DDoSMitigation::with_config(DDoSConfig {
+            max_requests_per_second: 100,
+            adaptive_limit_floor: 0.1,
+            // Keep every sample so the load never drops between analyses
+            analysis_window: Duration::from_secs(60),
+            ..DDoSConfig::default()
+        }).unwrap();
+        mitigation.initialize().unwrap();
+        push_samples(&mut mitigation, 150, Duration::ZERO);
+
+        for _ in 0..20 {
+            mitigation.analyze_traffic().unwrap();
+        }
+        // 10 rather than the old hardcoded quarter, 25
+        assert_eq!(mitigation.get_mitigation_stats().adaptive_limit, 10);
+
+        // Quiet traffic relaxes up to the configured ceiling
+        let mut mitigation = DDoSMitigation::with_config(DDoSConfig {
+            max_requests_per_second: 100,
+            adaptive_limit_ceiling: 1.5,
+            ..DDoSConfig::default()
+        }).unwrap();
+        for _ in 0..20 {
+            mitigation.analyze_traffic().unwrap();
+        }
+        assert_eq!(mitigation.get_mitigation_stats().adaptive_limit, 150);
+    }
+
+    #[test]
+    fn test_adaptive_limit_bounds_validation() {
+        for (floor, ceiling) in [(0.0, 2.0), (1.5, 2.0), (0.25, 0.5), (f64::NAN, 2.0)] {
+            assert!(DDoSMitigation::with_config(DDoSConfig {
+                adaptive_limit_floor: floor,
+                adaptive_limit_ceiling: ceiling,
+                ..DDoSConfig::default()
+            }).is_err());
+        }
+        assert!(DDoSMitigation::with_config(DDoSConfig {
+            adaptive_limit_floor: 1.0,
+            adaptive_limit_ceiling: 1.0,
+            ..DDoSConfig::default()
+        }).is_ok());
+    }
+
+    #[test]
+    fn test_circuit_flooding_tightens_circuits_per_ip() {
+        let mut mitigation = test_mitigation();
+        let ip = "192.0.2.1".parse().unwrap();
+        for i in 0..60 {
+            mitigation.record_request(Some(ip), 512, Some(format!("circuit_{}", i))).unwrap();
+        }
+        mitigation.analyze_traffic().unwrap();
+
+        let stats = mitigation.get_mitigation_stats();
+        assert_eq!(stats.last_attack_pattern, AttackPattern::CircuitFlooding);
+        assert_eq!(stats.circuit_limit, 2);
+        assert_eq!(stats.analysis_window, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_low_and_slow_lengthens_analysis_window() {
+        let mut mitigation = test_mitigation();
+        // 100 samples over 9.9 seconds: above half the limit on average
+        push_samples(&mut mitigation, 100, Duration::from_millis(100));
+        mitigation.analyze_traffic().unwrap();
+
+        let stats = mitigation.get_mitigation_stats();
+        assert_eq!(stats.last_attack_pattern, AttackPattern::LowAndSlow);
+        assert!(matches!(stats.current_state, MitigationState::Normal | MitigationState::EarlyWarning));
+        assert_eq!(stats.analysis_window, Duration::from_secs(40));
+        assert_eq!(stats.adaptive_limit, 10);
+
+        // Once traffic stops the window returns to its configured length
+        mitigation.traffic_samples.clear();
+        mitigation.analyze_traffic().unwrap();
+        let stats = mitigation.get_mitigation_stats();
+        assert_eq!(stats.last_attack_pattern, AttackPattern::Unknown);
+        assert_eq!(stats.analysis_window, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_state_change_callback() {
+        use std::sync::{Arc, Mutex};
+
+        let mut mitigation = test_mitigation();
+        let transitions = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&transitions);
+        mitigation.on_state_change(move |old, new| recorded.lock().unwrap().push((old, new)));
+
+        // Ramp up to 1.2x the limit, then 3x, hold, then go quiet
+        push_samples(&mut mitigation, 12, Duration::from_millis(50));
+        mitigation.analyze_traffic().unwrap();
+        push_samples(&mut mitigation, 18, Duration::from_millis(10));
+        mitigation.analyze_traffic().unwrap();
+        mitigation.analyze_traffic().unwrap();
+        mitigation.traffic_samples.clear();
+        mitigation.analyze_traffic().unwrap();
+
+        assert_eq!(*transitions.lock().unwrap(), vec![
+            (MitigationState::Normal, MitigationState::EarlyWarning),
+            (MitigationState::EarlyWarning, MitigationState::Emergency),
+            (MitigationState::Emergency, MitigationState::Normal),
+        ]);
+    }
+
+    #[test]
+    fn test_allowlist_bypasses_mitigation() {
+        let mut mitigation = test_mitigation();
+        let monitor: IpAddr = "192.0.2.10".parse().unwrap();
+        let health_check: IpAddr = "198.51.100.7".parse().unwrap();
+        let other: IpAddr = "203.0.113.1".parse().unwrap();
+        mitigation.add_allowlisted(monitor);
+        mitigation.add_allowlisted("198.51.100.0/24".parse::<IpNet>().unwrap());
+
+        // Flood from the monitor itself and push the system into Emergency
+        for _ in 0..250 {
+            mitigation.record_request(Some(monitor), 512, None).unwrap();
+        }
+        mitigation.analyze_traffic().unwrap();
+        assert_eq!(mitigation.get_mitigation_stats().current_state, MitigationState::Emergency);
+
+        assert!(mitigation.should_allow_request(Some(monitor), None).unwrap());
+        assert!(mitigation.should_allow_request(Some(health_check), None).unwrap());
+        assert!(!mitigation.should_allow_request(Some(other), None).unwrap());
+
+        let stats = mitigation.get_mitigation_stats();
+        assert_eq!(stats.allowlisted_requests, 2);
+        assert_eq!(stats.recent_samples, 250);
+
+        assert!(mitigation.remove_allowlisted("198.51.100.1/24".parse::<IpNet>().unwrap()));
+        assert!(!mitigation.remove_allowlisted(other));
+        assert!(!mitigation.should_allow_request(Some(health_check), None).unwrap());
+    }
+
+    #[test]
+    fn test_under_attack_challenges_instead_of_dropping() {
+        let mut mitigation = test_mitigation();
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        mitigation.record_request(Some(ip), 512, Some("calm".to_string())).unwrap();
+        mitigation.record_request(Some(ip), 512, Some("busy".to_string())).unwrap();
+        mitigation.circuit_tracker.get_mut("busy").unwrap().suspicious_score = 0.6;
+        mitigation.current_state = MitigationState::UnderAttack;
+
+        assert_eq!(mitigation.evaluate_request(Some(ip), Some("calm".to_string())).unwrap(), RequestDecision::Allow);
+        assert_eq!(mitigation.evaluate_request(Some(ip), Some("busy".to_string())).unwrap(), RequestDecision::Challenge);
+        assert_eq!(mitigation.evaluate_request(Some(ip), None).unwrap(), RequestDecision::Challenge);
+        assert!(!mitigation.should_allow_request(Some(ip), Some("busy".to_string())).unwrap());
+
+        // Solving the challenge admits the circuit
+        mitigation.mark_challenge_passed("busy");
+        assert_eq!(mitigation.evaluate_request(Some(ip), Some("busy".to_string())).unwrap(), RequestDecision::Allow);
+
+        mitigation.current_state = MitigationState::Emergency;
+        assert_eq!(mitigation.evaluate_request(Some(ip), Some("busy".to_string())).unwrap(), RequestDecision::Deny);
+    }
+
+    #[test]
+    fn test_burst_straddling_bucket_boundary_is_caught() {
+        let mut mitigation = test_mitigation();
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let start = Instant::now();
+        mitigation.update_ip_tracking(ip, start);
+
+        // 8 requests just before the boundary and 8 just after: 16 in 200ms.
+        // A fixed one-second bucket would only ever see 9 and 8 of them.
+        for _ in 0..8 {
+            mitigation.update_ip_tracking(ip, start + Duration::from_millis(900));
+        }
+        assert!(!mitigation.ip_over_limit(ip, start + Duration::from_millis(900), 1));
+        for _ in 0..8 {
+            mitigation.update_ip_tracking(ip, start + Duration::from_millis(1100));
+        }
+        assert!(mitigation.ip_over_limit(ip, start + Duration::from_millis(1100), 1));
+
+        // The burst ages out of the rolling second
+        assert!(!mitigation.ip_over_limit(ip, start + Duration::from_millis(2500), 1));
+        assert_eq!(mitigation.ip_request_counts.len(), 1);
+    }
+
+    #[test]
+    fn test_offender_recovers_as_clock_advances() {
+        let clock = MockClock::new();
+        let mut mitigation = test_mitigation().with_clock(Arc::new(clock.clone()));
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+
+        for _ in 0..10 {
+            mitigation.record_request(Some(ip), 512, None).unwrap();
+        }
+        assert_eq!(mitigation.evaluate_request(Some(ip), None).unwrap(), RequestDecision::Deny);
+
+        // Still inside the two second block
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(mitigation.evaluate_request(Some(ip), None).unwrap(), RequestDecision::Deny);
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(mitigation.evaluate_request(Some(ip), None).unwrap(), RequestDecision::Allow);
+    }
+
+    #[test]
+    fn test_threshold_validation() {
+        assert!(DDoSMitigation::with_config(DDoSConfig::default()).is_ok());
+        assert!(DDoSMitigation::with_config(DDoSConfig {
+            under_attack_load: 2.5,
+            ..DDoSConfig::default()
+        }).is_err());
+        assert!(DDoSMitigation::with_config(DDoSConfig {
+            early_warning_load: 0.0,
+            ..DDoSConfig::default()
+        }).is_err());
+        assert!(DDoSMitigation::with_config(DDoSConfig {
+            tighten_factor: 1.2,
+            ..DDoSConfig::default()
+        }).is_err());
+        assert!(DDoSMitigation::with_config(DDoSConfig {
+            relax_factor: f64::NAN,
+            ..DDoSConfig::default()
+        }).is_err());
+        assert!(DDoSMitigation::with_config(DDoSConfig {
+            relax_below_load: 1.5,
+            ..DDoSConfig::default()
+        }).is_err());
+
+        // Custom thresholds move the state boundaries
+        let mut mitigation = DDoSMitigation::with_config(DDoSConfig {
+            early_warning_load: 0.1,
+            under_attack_load: 0.2,
+            emergency_load: 5.0,
+            ..DDoSConfig::default()
+        }).unwrap();
+        mitigation.update_mitigation_state(0.5, AttackPattern::Unknown);
+        assert_eq!(mitigation.get_mitigation_stats().current_state, MitigationState::UnderAttack);
+    }
+
+    #[test]
+    fn test_large_payloads_raise_suspicion() {
+        let mut mitigation = test_mitigation();
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+
+        // A single request, far below the request rate limit, but 4 MiB in size
+        mitigation.record_request(Some(ip), 4 * 1024 * 1024, Some("bulk".to_string())).unwrap();
+        mitigation.record_request(Some(ip), 512, Some("small".to_string())).unwrap();
+
+        assert_eq!(mitigation.circuit_bytes("bulk"), Some(4 * 1024 * 1024));
+        assert_eq!(mitigation.circuit_bytes("small"), Some(512));
+        assert_eq!(mitigation.circuit_bytes("unknown"), None);
+
+        assert!(!mitigation.should_allow_request(Some(ip), Some("bulk".to_string())).unwrap());
+        assert!(mitigation.should_allow_request(Some(ip), Some("small".to_string())).unwrap());
+    }
+
+    #[test]
+    fn test_stats_json() {
+        let mut mitigation = test_mitigation();
+        mitigation.update_mitigation_state(1.7, AttackPattern::HighFrequency);
+
+        let stats: serde_json::Value = serde_json::from_str(&mitigation.stats_json()).unwrap();
+        assert_eq!(stats["current_state"], "under_attack");
+        assert_eq!(stats["last_attack_pattern"], "high_frequency");
+        assert_eq!(stats["adaptive_limit"], 5);
+        assert_eq!(stats["analysis_window_secs"], 10.0);
+    }
+
+    #[test]
+    fn test_repeat_offenders_back_off_exponentially() {
+        let mut mitigation = DDoSMitigation::with_config(DDoSConfig {
+            max_requests_per_second: 10,
+            max_penalty_multiplier: 4,
+            penalty_decay: Duration::from_secs(60),
+            ..DDoSConfig::default()
+        }).unwrap();
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let burst = |mitigation: &mut DDoSMitigation, count: u32, now: Instant| {
+            for _ in 0..count {
+                mitigation.update_ip_tracking(ip, now);
+            }
+        };
+
+        // First offence: blocked for 2 seconds, then held to 10 / 2
+        burst(&mut mitigation, 10, at(0));
+        assert_eq!(mitigation.evaluate_request_at(Some(ip), None, at(0)).unwrap(), RequestDecision::Deny);
+        assert_eq!(mitigation.evaluate_request_at(Some(ip), None, at(1)).unwrap(), RequestDecision::Deny);
+        burst(&mut mitigation, 4, at(3));
+        assert_eq!(mitigation.evaluate_request_at(Some(ip), None, at(3)).unwrap(), RequestDecision::Allow);
+
+        // Second offence at the reduced limit
+        burst(&mut mitigation, 1, at(3));
+        assert_eq!(mitigation.evaluate_request_at(Some(ip), None, at(3)).unwrap(), RequestDecision::Deny);
+        assert_eq!(mitigation.penalties[&ip].multiplier, 4);
+        assert_eq!(mitigation.evaluate_request_at(Some(ip), None, at(6)).unwrap(), RequestDecision::Deny);
+
+        // The multiplier is capped
+        burst(&mut mitigation, 3, at(8));
+        assert_eq!(mitigation.evaluate_request_at(Some(ip), None, at(8)).unwrap(), RequestDecision::Deny);
+        assert_eq!(mitigation.penalties[&ip].multiplier, 4);
+        assert_eq!(mitigation.get_mitigation_stats().penalized_ips, 1);
+
+        // Two quiet decay periods restore the full limit
+        burst(&mut mitigation, 9, at(128));
+        assert_eq!(mitigation.evaluate_request_at(Some(ip), None, at(128)).unwrap(), RequestDecision::Allow);
+        assert!(mitigation.penalties.is_empty());
+    }
 }
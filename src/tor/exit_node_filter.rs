@@ -3,10 +3,57 @@
 //! Block known malicious Tor exit nodes and maintain dynamic blocklists.
 //! Provides protection against compromised or malicious exit nodes.
 
-use crate::tor::{TorSecurityConfig, TorSecurityResult};
+use crate::clock::{self, Clock};
+use crate::tor::{TorSecurityConfig, TorSecurityError, TorSecurityResult};
+use log::{error, info, warn};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::net::IpAddr;
-use std::time::{Duration, Instant};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::task::JoinHandle;
+
+/// Severity given to imported blocklist entries that don't specify one
+const DEFAULT_IMPORT_SEVERITY: u8 = 5;
+
+/// Parse one `ip[,reason[,severity]]` blocklist line; the address may be a CIDR range
+fn parse_blocklist_line(line: &str) -> Option<(IpNet, String, u8)> {
+    let mut fields = line.splitn(3, ',').map(str::trim);
+    let target = fields.next()?;
+    let target = target
+        .parse::<IpNet>()
+        .or_else(|_| target.parse::<IpAddr>().map(IpNet::from))
+        .ok()?;
+    let reason = match fields.next() {
+        Some(reason) if !reason.is_empty() => reason.to_string(),
+        _ => "Imported from blocklist".to_string(),
+    };
+    let severity = match fields.next() {
+        Some(severity) => severity.parse().ok()?,
+        None => DEFAULT_IMPORT_SEVERITY,
+    };
+    Some((target, reason, severity))
+}
+
+/// Parse a newline-delimited or CSV blocklist, skipping `#` comments and
+/// blank lines. Malformed lines are reported and skipped.
+fn parse_blocklist(text: &str, origin: &str) -> Vec<(IpNet, String, u8)> {
+    let mut entries = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_blocklist_line(line) {
+            Some(entry) => entries.push(entry),
+            None => warn!("Skipping malformed blocklist line {} in {}: {}", number + 1, origin, line),
+        }
+    }
+    entries
+}
 
 /// Exit node reputation score
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
@@ -56,8 +103,89 @@ pub struct ExitNodeInfo {
     pub block_reason: Option<String>,
 }
 
+/// Exit node as written by `save_reputation`; times are unix seconds
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedExitNode {
+    ip_address: IpAddr,
+    nickname: Option<String>,
+    fingerprint: Option<String>,
+    country_code: Option<String>,
+    reputation: f64,
+    first_seen: u64,
+    last_seen: u64,
+    connection_count: u32,
+    malicious_activity_count: u32,
+    is_blocked: bool,
+    block_reason: Option<String>,
+}
+
+/// Blocklist entry as stored in an `ExitNodeFilterSnapshot`; times are unix seconds
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedBlocklistEntry {
+    target: IpNet,
+    source: BlocklistSource,
+    reason: String,
+    added_at: u64,
+    expires_at: Option<u64>,
+    severity: u8,
+}
+
+/// Everything an `ExitNodeFilter` has learned, for backups: known nodes and
+/// their reputation, the blocklist, trusted nodes and blocked fingerprints
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExitNodeFilterSnapshot {
+    nodes: Vec<SavedExitNode>,
+    blocklist: Vec<SavedBlocklistEntry>,
+    trusted_nodes: Vec<IpAddr>,
+    blocked_fingerprints: HashMap<String, String>,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// `now` is the current reading of the clock `at` was taken from
+fn instant_to_unix(at: Instant, now: Instant) -> u64 {
+    unix_now().saturating_sub(now.saturating_duration_since(at).as_secs())
+}
+
+/// Times in the future clamp to now
+fn unix_to_instant(timestamp: u64, now: Instant) -> Instant {
+    now.checked_sub(Duration::from_secs(unix_now().saturating_sub(timestamp)))
+        .unwrap_or(now)
+}
+
+/// Canonical form of a relay fingerprint: no `$` prefix or spaces, upper case
+fn normalize_fingerprint(fingerprint: &str) -> String {
+    fingerprint
+        .trim()
+        .trim_start_matches('$')
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase()
+}
+
+impl ExitNodeInfo {
+    fn new(ip_address: IpAddr, seen_at: Instant) -> Self {
+        Self {
+            ip_address,
+            nickname: None,
+            fingerprint: None,
+            country_code: None,
+            reputation: ReputationScore::default(),
+            last_seen: seen_at,
+            first_seen: seen_at,
+            connection_count: 0,
+            malicious_activity_count: 0,
+            is_blocked: false,
+            block_reason: None,
+        }
+    }
+}
+
 /// Blocklist source types
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum BlocklistSource {
     Manual,
     ThreatIntelligence,
@@ -69,7 +197,10 @@ pub enum BlocklistSource {
 /// Blocklist entry
 #[derive(Debug, Clone)]
 pub struct BlocklistEntry {
+    /// The blocked address, or the network address of a blocked range
     pub ip_address: IpAddr,
+    /// Set when the entry blocks a whole range rather than one address
+    pub range: Option<IpNet>,
     pub source: BlocklistSource,
     pub reason: String,
     pub added_at: Instant,
@@ -77,11 +208,29 @@ pub struct BlocklistEntry {
     pub severity: u8, // 1-10 scale
 }
 
+impl BlocklistEntry {
+    /// The address or range this entry blocks
+    pub fn target(&self) -> IpNet {
+        self.range.unwrap_or_else(|| IpNet::from(self.ip_address))
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|expires_at| now > expires_at)
+    }
+}
+
+/// True when `target` covers a single address
+fn is_single_address(target: &IpNet) -> bool {
+    target.prefix_len() == target.max_prefix_len()
+}
+
 /// Exit node filter configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ExitNodeFilterConfig {
     pub enable_reputation_filtering: bool,
     pub minimum_reputation_score: f64,
+    #[serde(with = "crate::serde_duration::secs")]
     pub blocklist_update_interval: Duration,
     pub reputation_decay_rate: f64,
     pub max_connections_per_node: u32,
@@ -112,9 +261,16 @@ pub struct ExitNodeFilter {
     config: ExitNodeFilterConfig,
     exit_nodes: HashMap<IpAddr, ExitNodeInfo>,
     blocklist: HashMap<IpAddr, BlocklistEntry>,
+    range_blocklist: HashMap<IpNet, BlocklistEntry>,
+    /// Blocked relay fingerprints and the reason for each
+    blocked_fingerprints: HashMap<String, String>,
     trusted_nodes: HashSet<IpAddr>,
     last_update: Instant,
+    last_blocklist_refresh: Option<Instant>,
     connection_stats: HashMap<IpAddr, (u32, Instant)>,
+    #[cfg(feature = "geoip")]
+    geoip: Option<maxminddb::Reader<Vec<u8>>>,
+    clock: Arc<dyn Clock>,
 }
 
 impl ExitNodeFilter {
@@ -136,24 +292,99 @@ impl ExitNodeFilter {
             config,
             exit_nodes: HashMap::new(),
             blocklist: HashMap::new(),
+            range_blocklist: HashMap::new(),
+            blocked_fingerprints: HashMap::new(),
             trusted_nodes: HashSet::new(),
             last_update: Instant::now(),
+            last_blocklist_refresh: None,
             connection_stats: HashMap::new(),
+            #[cfg(feature = "geoip")]
+            geoip: None,
+            clock: clock::system(),
         })
     }
 
+    /// Read the time from `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.set_clock(clock);
+        self
+    }
+
+    /// `with_clock` for a filter that is already shared, e.g. with its maintenance task
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.last_update = clock.now();
+        self.clock = clock;
+    }
+
+    /// The current time on the filter's clock, which block expiries are measured against
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// Re-derive the limits taken from the shared configuration, keeping reputation and blocklists
+    pub fn apply_tor_config(&mut self, tor_config: &TorSecurityConfig) {
+        self.config.max_connections_per_node = tor_config.max_connections_per_circuit;
+    }
+
+    /// Resolve exit node countries from a MaxMind GeoLite2 Country or City
+    /// database. If it can't be loaded, country filtering lets every node through.
+    #[cfg(feature = "geoip")]
+    pub fn with_geoip(mut self, db_path: impl AsRef<Path>) -> Self {
+        let db_path = db_path.as_ref();
+        match maxminddb::Reader::open_readfile(db_path) {
+            Ok(reader) => {
+                info!("Loaded GeoIP database {}", db_path.display());
+                self.geoip = Some(reader);
+            }
+            Err(e) => warn!("GeoIP database {} unavailable, country filtering disabled: {}", db_path.display(), e),
+        }
+        self
+    }
+
+    /// Look up the ISO country code of an address in the GeoIP database
+    #[cfg(feature = "geoip")]
+    fn lookup_country(&self, ip_address: IpAddr) -> Option<String> {
+        let reader = self.geoip.as_ref()?;
+        match reader
+            .lookup(ip_address)
+            .and_then(|result| result.decode::<maxminddb::geoip2::Country>())
+        {
+            Ok(record) => record?.country.iso_code.map(str::to_string),
+            Err(e) => {
+                log::debug!("GeoIP lookup failed for {}: {}", ip_address, e);
+                None
+            }
+        }
+    }
+
+    #[cfg(not(feature = "geoip"))]
+    fn lookup_country(&self, _ip_address: IpAddr) -> Option<String> {
+        None
+    }
+
+    /// Country of an exit node, from what is known about it or a fresh lookup
+    fn country_of(&self, ip_address: IpAddr) -> Option<String> {
+        self.exit_nodes
+            .get(&ip_address)
+            .and_then(|node_info| node_info.country_code.clone())
+            .or_else(|| self.lookup_country(ip_address))
+    }
+
     /// Initialize the exit node filter system
     pub fn initialize(&mut self) -> TorSecurityResult<()> {
         self.exit_nodes.clear();
         self.blocklist.clear();
+        self.range_blocklist.clear();
+        self.blocked_fingerprints.clear();
         self.trusted_nodes.clear();
         self.connection_stats.clear();
-        self.last_update = Instant::now();
+        self.last_update = self.clock.now();
+        self.last_blocklist_refresh = None;
         
         // Load default trusted nodes (could be from a config file)
         self.load_default_trusted_nodes()?;
         
-        println!("Exit Node Filter initialized");
+        info!("Exit Node Filter initialized");
         Ok(())
     }
 
@@ -161,18 +392,20 @@ impl ExitNodeFilter {
     pub fn shutdown(&mut self) -> TorSecurityResult<()> {
         self.exit_nodes.clear();
         self.blocklist.clear();
+        self.range_blocklist.clear();
+        self.blocked_fingerprints.clear();
         self.trusted_nodes.clear();
         self.connection_stats.clear();
-        println!("Exit Node Filter shutdown");
+        info!("Exit Node Filter shutdown");
         Ok(())
     }
 
     /// Check if an exit node should be allowed
     pub fn should_allow_exit_node(&mut self, ip_address: IpAddr) -> TorSecurityResult<bool> {
-        let now = Instant::now();
+        let now = self.clock.now();
 
-        // Check if explicitly blocked
-        if self.is_blocked(ip_address) {
+        // Check if explicitly blocked, by address or by relay fingerprint
+        if self.is_blocked(ip_address) || self.is_fingerprint_blocked(ip_address) {
             return Ok(false);
         }
 
@@ -187,23 +420,19 @@ impl ExitNodeFilter {
         }
 
         // Check reputation if enabled
-        if self.config.enable_reputation_filtering {
-            if let Some(node_info) = self.exit_nodes.get(&ip_address) {
-                if node_info.reputation.value() < self.config.minimum_reputation_score {
-                    return Ok(false);
-                }
-            }
+        if self.config.enable_reputation_filtering
+            && let Some(node_info) = self.exit_nodes.get(&ip_address)
+            && node_info.reputation.value() < self.config.minimum_reputation_score
+        {
+            return Ok(false);
         }
 
-        // Check country filtering if enabled
-        if self.config.enable_country_filtering {
-            if let Some(node_info) = self.exit_nodes.get(&ip_address) {
-                if let Some(ref country) = node_info.country_code {
-                    if self.config.blocked_countries.contains(country) {
-                        return Ok(false);
-                    }
-                }
-            }
+        // Check country filtering if enabled; unknown countries are allowed
+        if self.config.enable_country_filtering
+            && let Some(country) = self.country_of(ip_address)
+            && self.config.blocked_countries.contains(&country)
+        {
+            return Ok(false);
         }
 
         // Update connection tracking
@@ -214,23 +443,21 @@ impl ExitNodeFilter {
 
     /// Record a connection to an exit node
     pub fn record_connection(&mut self, ip_address: IpAddr, timestamp: Instant) {
+        let country_code = match self.exit_nodes.get(&ip_address) {
+            Some(node_info) if node_info.country_code.is_some() => None,
+            _ => self.lookup_country(ip_address),
+        };
+
         // Update exit node info
-        let node_info = self.exit_nodes.entry(ip_address).or_insert_with(|| ExitNodeInfo {
-            ip_address,
-            nickname: None,
-            fingerprint: None,
-            country_code: None,
-            reputation: ReputationScore::default(),
-            last_seen: timestamp,
-            first_seen: timestamp,
-            connection_count: 0,
-            malicious_activity_count: 0,
-            is_blocked: false,
-            block_reason: None,
-        });
+        let node_info = self.exit_nodes
+            .entry(ip_address)
+            .or_insert_with(|| ExitNodeInfo::new(ip_address, timestamp));
 
         node_info.connection_count += 1;
         node_info.last_seen = timestamp;
+        if country_code.is_some() {
+            node_info.country_code = country_code;
+        }
 
         // Update connection stats for rate limiting
         let (count, window_start) = self.connection_stats.entry(ip_address).or_insert((0, timestamp));
@@ -276,46 +503,152 @@ impl ExitNodeFilter {
         Ok(())
     }
 
-    /// Add an IP address to the blocklist
+    /// Add an IP address, or a CIDR range given as an `IpNet`, to the blocklist
     pub fn add_to_blocklist(
         &mut self,
-        ip_address: IpAddr,
+        target: impl Into<IpNet>,
         source: BlocklistSource,
         reason: String,
         expires_at: Option<Instant>,
         severity: u8,
     ) -> TorSecurityResult<()> {
+        let target = target.into().trunc();
+        let single = is_single_address(&target);
         let entry = BlocklistEntry {
-            ip_address,
+            ip_address: target.network(),
+            range: if single { None } else { Some(target) },
             source,
             reason: reason.clone(),
-            added_at: Instant::now(),
+            added_at: self.clock.now(),
             expires_at,
             severity: severity.clamp(1, 10),
         };
 
-        self.blocklist.insert(ip_address, entry);
+        if single {
+            self.blocklist.insert(target.addr(), entry);
+        } else {
+            self.range_blocklist.insert(target, entry);
+        }
 
-        // Mark the node as blocked if it exists
-        if let Some(node_info) = self.exit_nodes.get_mut(&ip_address) {
-            node_info.is_blocked = true;
-            node_info.block_reason = Some(reason.clone());
+        // Mark the nodes as blocked if they exist
+        for node_info in self.exit_nodes.values_mut() {
+            if target.contains(&node_info.ip_address) {
+                node_info.is_blocked = true;
+                node_info.block_reason = Some(reason.clone());
+            }
         }
 
-        println!("Added {} to blocklist: {}", ip_address, reason);
+        info!("Added {} to blocklist: {}", target, reason);
         Ok(())
     }
 
-    /// Remove an IP address from the blocklist
-    pub fn remove_from_blocklist(&mut self, ip_address: IpAddr) -> TorSecurityResult<()> {
-        self.blocklist.remove(&ip_address);
+    /// Import a blocklist file of `ip,reason,severity` lines, where reason and
+    /// severity are optional. Returns the number of entries imported.
+    pub fn load_blocklist_from_file(&mut self, path: impl AsRef<Path>) -> TorSecurityResult<usize> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| {
+            TorSecurityError::ConfigurationError(format!("Failed to read blocklist {}: {}", path.display(), e))
+        })?;
 
-        if let Some(node_info) = self.exit_nodes.get_mut(&ip_address) {
-            node_info.is_blocked = false;
-            node_info.block_reason = None;
+        let entries = parse_blocklist(&text, &path.display().to_string());
+        let imported = entries.len();
+        for (ip_address, reason, severity) in entries {
+            self.add_to_blocklist(ip_address, BlocklistSource::Manual, reason, None, severity)?;
+        }
+
+        info!("Imported {} blocklist entries from {}", imported, path.display());
+        Ok(imported)
+    }
+
+    /// Download a threat intelligence blocklist from `url`, in the same format as
+    /// `load_blocklist_from_file`, and make it the current feed.
+    ///
+    /// Returns `Ok(None)` without fetching when the last successful refresh was
+    /// less than `blocklist_update_interval` ago, so a background task can call
+    /// this on a short timer. On network errors the previous list is kept. The
+    /// lock is released while the download is in flight.
+    #[cfg(feature = "network")]
+    pub async fn refresh_blocklist_from_url(filter: &Mutex<Self>, url: &str) -> TorSecurityResult<Option<usize>> {
+        {
+            let filter = filter.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+            if let Some(last_refresh) = filter.last_blocklist_refresh
+                && filter.clock.now().saturating_duration_since(last_refresh) < filter.config.blocklist_update_interval
+            {
+                return Ok(None);
+            }
         }
 
-        println!("Removed {} from blocklist", ip_address);
+        let network_error =
+            |e: reqwest::Error| TorSecurityError::NetworkError(format!("Failed to fetch blocklist {}: {}", url, e));
+        let text = reqwest::get(url)
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(network_error)?
+            .text()
+            .await
+            .map_err(network_error)?;
+
+        let mut filter = filter.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        let imported = filter.apply_threat_feed(&text, url)?;
+        filter.last_blocklist_refresh = Some(filter.clock.now());
+        info!("Refreshed {} threat intelligence blocklist entries from {}", imported, url);
+        Ok(Some(imported))
+    }
+
+    /// Replace the threat intelligence entries with those in `text`. Entries
+    /// from other sources are kept, and win over the feed for the same address.
+    #[cfg_attr(not(feature = "network"), allow(dead_code))]
+    fn apply_threat_feed(&mut self, text: &str, origin: &str) -> TorSecurityResult<usize> {
+        let entries = parse_blocklist(text, origin);
+
+        let stale: Vec<IpNet> = self.blocklist.values()
+            .chain(self.range_blocklist.values())
+            .filter(|entry| entry.source == BlocklistSource::ThreatIntelligence)
+            .map(BlocklistEntry::target)
+            .collect();
+        for target in stale {
+            self.remove_from_blocklist(target)?;
+        }
+
+        let mut imported = 0;
+        for (target, reason, severity) in entries {
+            let target = target.trunc();
+            let listed = if is_single_address(&target) {
+                self.blocklist.contains_key(&target.addr())
+            } else {
+                self.range_blocklist.contains_key(&target)
+            };
+            if listed {
+                continue;
+            }
+            self.add_to_blocklist(target, BlocklistSource::ThreatIntelligence, reason, None, severity)?;
+            imported += 1;
+        }
+        Ok(imported)
+    }
+
+    /// Remove an IP address or CIDR range from the blocklist. Addresses inside a
+    /// removed range stay blocked if another entry still covers them.
+    pub fn remove_from_blocklist(&mut self, target: impl Into<IpNet>) -> TorSecurityResult<()> {
+        let target = target.into().trunc();
+        if is_single_address(&target) {
+            self.blocklist.remove(&target.addr());
+        } else {
+            self.range_blocklist.remove(&target);
+        }
+
+        let unblocked: Vec<IpAddr> = self.exit_nodes.keys()
+            .copied()
+            .filter(|ip| target.contains(ip) && !self.is_blocked(*ip))
+            .collect();
+        for ip_address in unblocked {
+            if let Some(node_info) = self.exit_nodes.get_mut(&ip_address) {
+                node_info.is_blocked = false;
+                node_info.block_reason = None;
+            }
+        }
+
+        info!("Removed {} from blocklist", target);
         Ok(())
     }
 
@@ -331,32 +664,135 @@ impl ExitNodeFilter {
             node_info.reputation = ReputationScore::new(1.0);
         }
 
-        println!("Added {} to trusted nodes", ip_address);
+        info!("Added {} to trusted nodes", ip_address);
         Ok(())
     }
 
-    /// Check if an IP address is blocked
-    fn is_blocked(&self, ip_address: IpAddr) -> bool {
-        if let Some(entry) = self.blocklist.get(&ip_address) {
-            // Check if entry has expired
-            if let Some(expires_at) = entry.expires_at {
-                if Instant::now() > expires_at {
-                    return false; // Entry expired
+    /// The unexpired entry blocking an IP address, checking exact entries before ranges
+    fn active_block(&self, ip_address: IpAddr) -> Option<&BlocklistEntry> {
+        let now = self.clock.now();
+        self.blocklist
+            .get(&ip_address)
+            .filter(|entry| !entry.is_expired(now))
+            .or_else(|| {
+                self.range_blocklist
+                    .values()
+                    .find(|entry| !entry.is_expired(now) && entry.target().contains(&ip_address))
+            })
+    }
+
+    /// Block a relay by fingerprint, whatever address it uses
+    pub fn block_fingerprint(&mut self, fingerprint: &str, reason: String) -> TorSecurityResult<()> {
+        let fingerprint = normalize_fingerprint(fingerprint);
+        for node_info in self.exit_nodes.values_mut() {
+            if node_info.fingerprint.as_deref() == Some(fingerprint.as_str()) {
+                node_info.is_blocked = true;
+                node_info.block_reason = Some(reason.clone());
+            }
+        }
+
+        info!("Blocked relay fingerprint {}: {}", fingerprint, reason);
+        self.blocked_fingerprints.insert(fingerprint, reason);
+        Ok(())
+    }
+
+    /// Unblock a relay fingerprint
+    pub fn unblock_fingerprint(&mut self, fingerprint: &str) -> TorSecurityResult<()> {
+        let fingerprint = normalize_fingerprint(fingerprint);
+        self.blocked_fingerprints.remove(&fingerprint);
+
+        let unblocked: Vec<IpAddr> = self.exit_nodes.values()
+            .filter(|node| node.fingerprint.as_deref() == Some(fingerprint.as_str()))
+            .map(|node| node.ip_address)
+            .filter(|ip| !self.is_blocked(*ip))
+            .collect();
+        for ip_address in unblocked {
+            if let Some(node_info) = self.exit_nodes.get_mut(&ip_address) {
+                node_info.is_blocked = false;
+                node_info.block_reason = None;
+            }
+        }
+
+        info!("Unblocked relay fingerprint {}", fingerprint);
+        Ok(())
+    }
+
+    /// Record that the relay with `fingerprint` is at `ip_address`, e.g. from
+    /// consensus data. A relay has one address, so any older association of the
+    /// fingerprint with another address is dropped.
+    pub fn set_node_fingerprint(&mut self, ip_address: IpAddr, fingerprint: &str) {
+        let fingerprint = normalize_fingerprint(fingerprint);
+        let moved_from: Vec<IpAddr> = self.exit_nodes.values()
+            .filter(|node| node.ip_address != ip_address && node.fingerprint.as_deref() == Some(fingerprint.as_str()))
+            .map(|node| node.ip_address)
+            .collect();
+        for old_ip in moved_from {
+            let still_blocked = self.is_blocked(old_ip);
+            if let Some(node_info) = self.exit_nodes.get_mut(&old_ip) {
+                node_info.fingerprint = None;
+                if !still_blocked {
+                    node_info.is_blocked = false;
+                    node_info.block_reason = None;
                 }
             }
-            return true;
         }
-        false
+
+        let reason = self.blocked_fingerprints.get(&fingerprint).cloned();
+        let now = self.clock.now();
+        let node_info = self.exit_nodes
+            .entry(ip_address)
+            .or_insert_with(|| ExitNodeInfo::new(ip_address, now));
+        node_info.fingerprint = Some(fingerprint);
+        if let Some(reason) = reason {
+            node_info.is_blocked = true;
+            node_info.block_reason = Some(reason);
+        }
+    }
+
+    /// Check if the relay known to be at an IP address has a blocked fingerprint
+    fn is_fingerprint_blocked(&self, ip_address: IpAddr) -> bool {
+        self.exit_nodes
+            .get(&ip_address)
+            .and_then(|node_info| node_info.fingerprint.as_ref())
+            .is_some_and(|fingerprint| self.blocked_fingerprints.contains_key(fingerprint))
+    }
+
+    /// Unexpired blocklist entries, single addresses and ranges alike, ordered by target
+    pub fn list_blocked(&self) -> Vec<BlocklistEntry> {
+        let now = self.clock.now();
+        let mut entries: Vec<BlocklistEntry> = self.blocklist.values()
+            .chain(self.range_blocklist.values())
+            .filter(|entry| !entry.is_expired(now))
+            .cloned()
+            .collect();
+        entries.sort_by_key(BlocklistEntry::target);
+        entries
+    }
+
+    /// Explicitly trusted exit nodes, in address order
+    pub fn list_trusted(&self) -> Vec<IpAddr> {
+        let mut trusted: Vec<IpAddr> = self.trusted_nodes.iter().copied().collect();
+        trusted.sort();
+        trusted
+    }
+
+    /// Why an IP address is blocked, if it currently is
+    pub fn block_reason(&self, ip_address: IpAddr) -> Option<String> {
+        self.active_block(ip_address).map(|entry| entry.reason.clone())
+    }
+
+    /// Check if an IP address is blocked, directly or by a range
+    fn is_blocked(&self, ip_address: IpAddr) -> bool {
+        self.active_block(ip_address).is_some()
     }
 
     /// Check connection limits for an exit node
     fn check_connection_limits(&self, ip_address: IpAddr, now: Instant) -> TorSecurityResult<bool> {
-        if let Some((count, window_start)) = self.connection_stats.get(&ip_address) {
-            if now.duration_since(*window_start) < Duration::from_secs(60) {
-                if *count >= self.config.max_connections_per_node {
-                    return Ok(false);
-                }
-            }
+        if let Some((count, window_start)) = self.connection_stats.get(&ip_address)
+            && now.duration_since(*window_start) < Duration::from_secs(60)
+            && *count >= self.config.max_connections_per_node
+        {
+            return Ok(false);
         }
         Ok(true)
     }
@@ -382,7 +818,7 @@ impl ExitNodeFilter {
 
     /// Update reputation scores (decay over time)
     pub fn update_reputation_scores(&mut self) -> TorSecurityResult<()> {
-        let now = Instant::now();
+        let now = self.clock.now();
         let decay_amount = self.config.reputation_decay_rate;
 
         for node_info in self.exit_nodes.values_mut() {
@@ -401,16 +837,11 @@ impl ExitNodeFilter {
 
     /// Clean up expired blocklist entries and old data
     pub fn cleanup_expired_data(&mut self) {
-        let now = Instant::now();
+        let now = self.clock.now();
 
         // Remove expired blocklist entries
-        self.blocklist.retain(|_, entry| {
-            if let Some(expires_at) = entry.expires_at {
-                now <= expires_at
-            } else {
-                true // No expiration
-            }
-        });
+        self.blocklist.retain(|_, entry| !entry.is_expired(now));
+        self.range_blocklist.retain(|_, entry| !entry.is_expired(now));
 
         // Remove old connection stats
         self.connection_stats.retain(|_, (_, window_start)| {
@@ -423,9 +854,172 @@ impl ExitNodeFilter {
         });
     }
 
+    /// Write what is known about each exit node to `path` as JSON, so reputation
+    /// survives a restart
+    pub fn save_reputation(&self, path: impl AsRef<Path>) -> TorSecurityResult<()> {
+        let path = path.as_ref();
+        let saved = self.saved_nodes();
+
+        let json = serde_json::to_string(&saved).map_err(|e| {
+            TorSecurityError::ConfigurationError(format!("Failed to serialize exit node reputation: {}", e))
+        })?;
+        fs::write(path, json).map_err(|e| {
+            TorSecurityError::ConfigurationError(format!(
+                "Failed to write exit node reputation to {}: {}",
+                path.display(),
+                e
+            ))
+        })
+    }
+
+    /// Restore exit nodes written by `save_reputation`, replacing what is known
+    /// about the same addresses. Returns the number of nodes restored.
+    pub fn load_reputation(&mut self, path: impl AsRef<Path>) -> TorSecurityResult<usize> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path).map_err(|e| {
+            TorSecurityError::ConfigurationError(format!(
+                "Failed to read exit node reputation from {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        let saved: Vec<SavedExitNode> = serde_json::from_str(&json).map_err(|e| {
+            TorSecurityError::ConfigurationError(format!("Malformed exit node reputation: {}", e))
+        })?;
+
+        let restored = saved.len();
+        self.restore_nodes(saved);
+
+        info!("Restored reputation for {} exit nodes", restored);
+        Ok(restored)
+    }
+
+    fn saved_nodes(&self) -> Vec<SavedExitNode> {
+        let now = self.clock.now();
+        self.exit_nodes.values()
+            .map(|node| SavedExitNode {
+                ip_address: node.ip_address,
+                nickname: node.nickname.clone(),
+                fingerprint: node.fingerprint.clone(),
+                country_code: node.country_code.clone(),
+                reputation: node.reputation.value(),
+                first_seen: instant_to_unix(node.first_seen, now),
+                last_seen: instant_to_unix(node.last_seen, now),
+                connection_count: node.connection_count,
+                malicious_activity_count: node.malicious_activity_count,
+                is_blocked: node.is_blocked,
+                block_reason: node.block_reason.clone(),
+            })
+            .collect()
+    }
+
+    fn restore_nodes(&mut self, saved: Vec<SavedExitNode>) {
+        let now = self.clock.now();
+        for node in saved {
+            self.exit_nodes.insert(node.ip_address, ExitNodeInfo {
+                ip_address: node.ip_address,
+                nickname: node.nickname,
+                fingerprint: node.fingerprint,
+                country_code: node.country_code,
+                // Goes through new() so out-of-range values are clamped
+                reputation: ReputationScore::new(node.reputation),
+                last_seen: unix_to_instant(node.last_seen, now),
+                first_seen: unix_to_instant(node.first_seen, now),
+                connection_count: node.connection_count,
+                malicious_activity_count: node.malicious_activity_count,
+                is_blocked: node.is_blocked,
+                block_reason: node.block_reason,
+            });
+        }
+    }
+
+    /// Capture the filter's learned state for a backup
+    pub fn snapshot(&self) -> ExitNodeFilterSnapshot {
+        let now = self.clock.now();
+        let blocklist = self.blocklist.values()
+            .chain(self.range_blocklist.values())
+            .filter(|entry| !entry.is_expired(now))
+            .map(|entry| SavedBlocklistEntry {
+                target: entry.target(),
+                source: entry.source.clone(),
+                reason: entry.reason.clone(),
+                added_at: instant_to_unix(entry.added_at, now),
+                // Expiry is in the future, so count forward from now
+                expires_at: entry.expires_at.map(|at| unix_now() + at.saturating_duration_since(now).as_secs()),
+                severity: entry.severity,
+            })
+            .collect();
+
+        ExitNodeFilterSnapshot {
+            nodes: self.saved_nodes(),
+            blocklist,
+            trusted_nodes: self.trusted_nodes.iter().copied().collect(),
+            blocked_fingerprints: self.blocked_fingerprints.clone(),
+        }
+    }
+
+    /// Replace the blocklist, trusted nodes and blocked fingerprints with those in
+    /// `snapshot`, and restore the exit nodes it knows about
+    pub fn restore_snapshot(&mut self, snapshot: ExitNodeFilterSnapshot) {
+        let now = self.clock.now();
+        self.blocklist.clear();
+        self.range_blocklist.clear();
+        for saved in snapshot.blocklist {
+            let target = saved.target.trunc();
+            let single = is_single_address(&target);
+            let entry = BlocklistEntry {
+                ip_address: target.network(),
+                range: if single { None } else { Some(target) },
+                source: saved.source,
+                reason: saved.reason,
+                added_at: unix_to_instant(saved.added_at, now),
+                expires_at: saved.expires_at.map(|at| now + Duration::from_secs(at.saturating_sub(unix_now()))),
+                severity: saved.severity,
+            };
+            if single {
+                self.blocklist.insert(target.network(), entry);
+            } else {
+                self.range_blocklist.insert(target, entry);
+            }
+        }
+
+        self.trusted_nodes = snapshot.trusted_nodes.into_iter().collect();
+        self.blocked_fingerprints = snapshot.blocked_fingerprints;
+        self.restore_nodes(snapshot.nodes);
+    }
+
+    /// Run `cleanup_expired_data` and `update_reputation_scores` every `interval`,
+    /// or every `blocklist_update_interval` when `None`, on the tokio runtime.
+    /// Abort the returned handle on shutdown.
+    pub fn spawn_maintenance(filter: Arc<Mutex<Self>>, interval: Option<Duration>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let period = match interval {
+                Some(interval) => interval,
+                None => match filter.lock() {
+                    Ok(filter) => filter.config.blocklist_update_interval,
+                    Err(_) => return,
+                },
+            };
+            let mut ticker = tokio::time::interval(period.max(Duration::from_millis(1)));
+            ticker.tick().await;
+
+            loop {
+                ticker.tick().await;
+                let Ok(mut filter) = filter.lock() else {
+                    error!("Exit node filter lock poisoned, stopping maintenance");
+                    return;
+                };
+                filter.cleanup_expired_data();
+                if let Err(e) = filter.update_reputation_scores() {
+                    warn!("Failed to update exit node reputation: {}", e);
+                }
+            }
+        })
+    }
+
     /// Get exit node filter statistics
     pub fn get_filter_stats(&self) -> ExitNodeFilterStats {
-        let blocked_count = self.blocklist.len();
+        let blocked_count = self.blocklist.len() + self.range_blocklist.len();
         let trusted_count = self.trusted_nodes.len();
         let total_nodes = self.exit_nodes.len();
         let suspicious_nodes = self.exit_nodes.values()
@@ -443,7 +1037,7 @@ impl ExitNodeFilter {
 }
 
 /// Exit node filter statistics
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ExitNodeFilterStats {
     pub total_nodes: usize,
     pub blocked_count: usize,
@@ -455,6 +1049,7 @@ pub struct ExitNodeFilterStats {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::MockClock;
 
     #[test]
     fn test_exit_node_filter_creation() {
@@ -497,4 +1092,263 @@ mod tests {
         // Should be allowed now
         assert!(filter.should_allow_exit_node(test_ip).unwrap());
     }
+
+    #[test]
+    fn test_load_blocklist_from_file() {
+        use std::io::Write;
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "# exported exit list").unwrap();
+        writeln!(file, "192.0.2.1,Scanning,7").unwrap();
+        writeln!(file, "192.0.2.2").unwrap();
+        writeln!(file).unwrap();
+        writeln!(file, "not-an-ip,Bad,3").unwrap();
+        writeln!(file, "192.0.2.3,Bad severity,high").unwrap();
+        writeln!(file, " 2001:db8::1 , SSL stripping, 9 ").unwrap();
+
+        let mut filter = ExitNodeFilter::new(&TorSecurityConfig::default()).unwrap();
+        filter.initialize().unwrap();
+        assert_eq!(filter.load_blocklist_from_file(file.path()).unwrap(), 3);
+
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        assert_eq!(filter.blocklist[&ip].reason, "Scanning");
+        assert_eq!(filter.blocklist[&ip].severity, 7);
+        let ip: IpAddr = "192.0.2.2".parse().unwrap();
+        assert_eq!(filter.blocklist[&ip].severity, DEFAULT_IMPORT_SEVERITY);
+        assert!(!filter.should_allow_exit_node("2001:db8::1".parse().unwrap()).unwrap());
+        assert!(filter.should_allow_exit_node("192.0.2.3".parse().unwrap()).unwrap());
+
+        assert!(filter.load_blocklist_from_file("/nonexistent/blocklist.csv").is_err());
+    }
+
+    #[test]
+    fn test_threat_feed_keeps_manual_entries() {
+        let mut filter = ExitNodeFilter::new(&TorSecurityConfig::default()).unwrap();
+        filter.initialize().unwrap();
+        let manual: IpAddr = "192.0.2.1".parse().unwrap();
+        let dropped: IpAddr = "192.0.2.2".parse().unwrap();
+        let listed: IpAddr = "192.0.2.3".parse().unwrap();
+        filter.add_to_blocklist(manual, BlocklistSource::Manual, "Operator".to_string(), None, 5).unwrap();
+
+        assert_eq!(filter.apply_threat_feed("192.0.2.1,Feed,9\n192.0.2.2,Feed,6\n", "feed").unwrap(), 1);
+        assert_eq!(filter.blocklist[&manual].source, BlocklistSource::Manual);
+        assert_eq!(filter.blocklist[&dropped].source, BlocklistSource::ThreatIntelligence);
+
+        // The next feed replaces the previous one but never the manual entry
+        assert_eq!(filter.apply_threat_feed("192.0.2.3,Feed,6\n", "feed").unwrap(), 1);
+        assert!(filter.is_blocked(manual));
+        assert!(!filter.is_blocked(dropped));
+        assert!(filter.is_blocked(listed));
+    }
+
+    #[cfg(feature = "network")]
+    #[tokio::test]
+    async fn test_refresh_failure_keeps_previous_list() {
+        let filter = Mutex::new(ExitNodeFilter::new(&TorSecurityConfig::default()).unwrap());
+        filter.lock().unwrap().initialize().unwrap();
+        filter.lock().unwrap().apply_threat_feed("192.0.2.3,Feed,6\n", "feed").unwrap();
+
+        let result = ExitNodeFilter::refresh_blocklist_from_url(&filter, "http://127.0.0.1:9/blocklist.csv").await;
+        assert!(matches!(result, Err(TorSecurityError::NetworkError(_))));
+        assert!(filter.lock().unwrap().is_blocked("192.0.2.3".parse().unwrap()));
+
+        // A recent successful refresh defers the next one
+        filter.lock().unwrap().last_blocklist_refresh = Some(Instant::now());
+        let deferred = ExitNodeFilter::refresh_blocklist_from_url(&filter, "http://127.0.0.1:9/blocklist.csv").await;
+        assert_eq!(deferred.unwrap(), None);
+    }
+
+    #[test]
+    fn test_cidr_blocklist() {
+        let mut filter = ExitNodeFilter::new(&TorSecurityConfig::default()).unwrap();
+        filter.initialize().unwrap();
+        let range: IpNet = "198.51.100.0/24".parse().unwrap();
+        filter.add_to_blocklist(range, BlocklistSource::ThreatIntelligence, "Bad /24".to_string(), None, 7).unwrap();
+        filter.add_to_blocklist(
+            "2001:db8:bad::/48".parse::<IpNet>().unwrap(),
+            BlocklistSource::Manual,
+            "Bad /48".to_string(),
+            None,
+            7,
+        ).unwrap();
+
+        assert!(!filter.should_allow_exit_node("198.51.100.42".parse().unwrap()).unwrap());
+        assert!(filter.should_allow_exit_node("198.51.101.42".parse().unwrap()).unwrap());
+        assert!(!filter.should_allow_exit_node("2001:db8:bad:1::1".parse().unwrap()).unwrap());
+        assert!(filter.should_allow_exit_node("2001:db8:beef::1".parse().unwrap()).unwrap());
+        assert_eq!(filter.get_filter_stats().blocked_count, 2);
+
+        // Known nodes are marked and unmarked with the range
+        let inside: IpAddr = "198.51.100.7".parse().unwrap();
+        filter.remove_from_blocklist(range).unwrap();
+        filter.record_connection(inside, Instant::now());
+        filter.add_to_blocklist(range, BlocklistSource::Manual, "Bad /24".to_string(), None, 7).unwrap();
+        assert!(filter.exit_nodes[&inside].is_blocked);
+        filter.remove_from_blocklist(range).unwrap();
+        assert!(!filter.exit_nodes[&inside].is_blocked);
+        assert!(filter.should_allow_exit_node(inside).unwrap());
+
+        // Ranges can be imported from blocklist files too
+        assert_eq!(filter.apply_threat_feed("203.0.113.0/28,Feed,6\n", "feed").unwrap(), 1);
+        assert!(filter.is_blocked("203.0.113.9".parse().unwrap()));
+        assert!(!filter.is_blocked("203.0.113.16".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_country_filtering() {
+        let mut filter = ExitNodeFilter::new(&TorSecurityConfig::default()).unwrap();
+        filter.config.enable_country_filtering = true;
+        filter.config.blocked_countries.insert("XX".to_string());
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+
+        // Without a known country the node is allowed
+        assert!(filter.should_allow_exit_node(ip).unwrap());
+
+        filter.exit_nodes.get_mut(&ip).unwrap().country_code = Some("XX".to_string());
+        assert!(!filter.should_allow_exit_node(ip).unwrap());
+    }
+
+    #[cfg(feature = "geoip")]
+    #[test]
+    fn test_missing_geoip_database_allows_traffic() {
+        let mut filter = ExitNodeFilter::new(&TorSecurityConfig::default())
+            .unwrap()
+            .with_geoip("/nonexistent/GeoLite2-Country.mmdb");
+        filter.config.enable_country_filtering = true;
+        filter.config.blocked_countries.insert("XX".to_string());
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+
+        assert!(filter.geoip.is_none());
+        assert!(filter.should_allow_exit_node(ip).unwrap());
+        assert_eq!(filter.exit_nodes[&ip].country_code, None);
+    }
+
+    #[test]
+    fn test_reputation_round_trip() {
+        let mut filter = ExitNodeFilter::new(&TorSecurityConfig::default()).unwrap();
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        filter.record_connection(ip, Instant::now() - Duration::from_secs(120));
+        filter.record_connection(ip, Instant::now());
+        filter.report_malicious_activity(ip, "Test".to_string()).unwrap();
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        filter.save_reputation(file.path()).unwrap();
+
+        let mut restarted = ExitNodeFilter::new(&TorSecurityConfig::default()).unwrap();
+        assert_eq!(restarted.load_reputation(file.path()).unwrap(), 1);
+        let node = &restarted.exit_nodes[&ip];
+        assert_eq!(node.connection_count, 2);
+        assert_eq!(node.malicious_activity_count, 1);
+        assert!((node.reputation.value() - 0.4).abs() < 1e-9);
+        assert!((119..=121).contains(&node.first_seen.elapsed().as_secs()));
+
+        // Scores outside 0..=1 are clamped on load
+        std::fs::write(file.path(), format!(
+            r#"[{{"ip_address":"{}","nickname":null,"fingerprint":null,"country_code":null,"reputation":7.5,
+                "first_seen":0,"last_seen":0,"connection_count":0,"malicious_activity_count":0,
+                "is_blocked":false,"block_reason":null}}]"#,
+            ip
+        )).unwrap();
+        restarted.load_reputation(file.path()).unwrap();
+        assert_eq!(restarted.exit_nodes[&ip].reputation.value(), 1.0);
+    }
+
+    #[test]
+    fn test_temporary_block_expires_as_clock_advances() {
+        let clock = MockClock::new();
+        let mut filter = ExitNodeFilter::new(&TorSecurityConfig::default())
+            .unwrap()
+            .with_clock(Arc::new(clock.clone()));
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let expires_at = clock.now() + Duration::from_secs(600);
+        filter.add_to_blocklist(ip, BlocklistSource::BehaviorAnalysis, "Probe".to_string(), Some(expires_at), 5).unwrap();
+
+        clock.advance(Duration::from_secs(599));
+        assert!(!filter.should_allow_exit_node(ip).unwrap());
+
+        clock.advance(Duration::from_secs(2));
+        assert!(filter.should_allow_exit_node(ip).unwrap());
+        filter.cleanup_expired_data();
+        assert!(filter.list_blocked().is_empty());
+    }
+
+    #[test]
+    fn test_list_blocked_and_trusted() {
+        let mut filter = ExitNodeFilter::new(&TorSecurityConfig::default()).unwrap();
+        let blocked: IpAddr = "192.0.2.1".parse().unwrap();
+        let expired: IpAddr = "192.0.2.2".parse().unwrap();
+        let in_range: IpAddr = "198.51.100.9".parse().unwrap();
+        filter.add_to_blocklist(blocked, BlocklistSource::Manual, "Manual".to_string(), None, 5).unwrap();
+        filter.add_to_blocklist(
+            expired,
+            BlocklistSource::Manual,
+            "Expired".to_string(),
+            Some(Instant::now() - Duration::from_secs(1)),
+            5,
+        ).unwrap();
+        filter.add_to_blocklist(
+            "198.51.100.0/24".parse::<IpNet>().unwrap(),
+            BlocklistSource::ThreatIntelligence,
+            "Feed".to_string(),
+            None,
+            5,
+        ).unwrap();
+        filter.add_trusted_node("203.0.113.2".parse().unwrap()).unwrap();
+        filter.add_trusted_node("203.0.113.1".parse().unwrap()).unwrap();
+
+        let listed: Vec<IpNet> = filter.list_blocked().iter().map(BlocklistEntry::target).collect();
+        assert_eq!(listed, vec![IpNet::from(blocked), "198.51.100.0/24".parse().unwrap()]);
+        assert_eq!(filter.list_trusted(), vec![
+            "203.0.113.1".parse::<IpAddr>().unwrap(),
+            "203.0.113.2".parse::<IpAddr>().unwrap(),
+        ]);
+
+        assert_eq!(filter.block_reason(blocked).as_deref(), Some("Manual"));
+        assert_eq!(filter.block_reason(in_range).as_deref(), Some("Feed"));
+        assert_eq!(filter.block_reason(expired), None);
+    }
+
+    #[test]
+    fn test_fingerprint_block_follows_relay() {
+        let mut filter = ExitNodeFilter::new(&TorSecurityConfig::default()).unwrap();
+        let old_ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let new_ip: IpAddr = "198.51.100.1".parse().unwrap();
+        let fingerprint = "$9695DFC35FFEB861329B9F1AB04C46397020CE31";
+
+        filter.set_node_fingerprint(old_ip, fingerprint);
+        filter.block_fingerprint(fingerprint, "Malicious relay".to_string()).unwrap();
+        assert!(!filter.should_allow_exit_node(old_ip).unwrap());
+        assert!(filter.should_allow_exit_node(new_ip).unwrap());
+
+        // The relay moves to a new address under the same fingerprint
+        filter.set_node_fingerprint(new_ip, "9695 dfc3 5ffe b861 329b 9f1a b04c 4639 7020 ce31");
+        assert!(!filter.should_allow_exit_node(new_ip).unwrap());
+        assert_eq!(filter.exit_nodes[&new_ip].block_reason.as_deref(), Some("Malicious relay"));
+        assert!(filter.should_allow_exit_node(old_ip).unwrap());
+        assert!(!filter.exit_nodes[&old_ip].is_blocked);
+
+        filter.unblock_fingerprint(fingerprint).unwrap();
+        assert!(filter.should_allow_exit_node(new_ip).unwrap());
+        assert!(!filter.exit_nodes[&new_ip].is_blocked);
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_task_expires_entries() {
+        let filter = Arc::new(Mutex::new(ExitNodeFilter::new(&TorSecurityConfig::default()).unwrap()));
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        filter.lock().unwrap().add_to_blocklist(
+            ip,
+            BlocklistSource::Manual,
+            "Temporary".to_string(),
+            Some(Instant::now() + Duration::from_millis(20)),
+            5,
+        ).unwrap();
+
+        let handle = ExitNodeFilter::spawn_maintenance(Arc::clone(&filter), Some(Duration::from_millis(10)));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(filter.lock().unwrap().blocklist.is_empty());
+
+        handle.abort();
+        assert!(handle.await.unwrap_err().is_cancelled());
+    }
 }
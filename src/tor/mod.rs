@@ -2,6 +2,10 @@
 //! 
 //! This module provides specialized security features for hosting sites on the Tor network.
 //! It includes protection mechanisms specifically designed for .onion services and hidden services.
+//!
+//! Diagnostics go through the `log` facade: lifecycle and blocklist changes at
+//! `info`, degraded operation at `warn`, per-request lookups at `debug`. Install a
+//! logger such as `env_logger` to see them.
 
 pub mod onion_service;
 pub mod ddos_mitigation;
@@ -9,8 +13,18 @@ pub mod circuit_analysis;
 pub mod exit_node_filter;
 pub mod rendezvous_security;
 
+use crate::clock::Clock;
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+pub use ddos_mitigation::RequestDecision;
 
 /// Common error types for Tor security features
 #[derive(Debug)]
@@ -40,7 +54,10 @@ impl Error for TorSecurityError {}
 pub type TorSecurityResult<T> = Result<T, TorSecurityError>;
 
 /// Configuration for Tor security features
-#[derive(Debug, Clone)]
+///
+/// Deserializing fills any missing field from `Default`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct TorSecurityConfig {
     pub enable_onion_protection: bool,
     pub enable_ddos_mitigation: bool,
@@ -67,14 +84,189 @@ impl Default for TorSecurityConfig {
     }
 }
 
+impl TorSecurityConfig {
+    /// Start building a configuration from the defaults
+    pub fn builder() -> TorSecurityConfigBuilder {
+        TorSecurityConfigBuilder::default()
+    }
+
+    /// Reject out-of-range values, such as a zero-length rate limit window
+    pub fn validate(&self) -> TorSecurityResult<()> {
+        if self.rate_limit_window_seconds == 0 {
+            return Err(TorSecurityError::ConfigurationError(
+                "Rate limit window must be at least one second".to_string(),
+            ));
+        }
+        if self.max_connections_per_circuit == 0 {
+            return Err(TorSecurityError::ConfigurationError(
+                "Max connections per circuit must be greater than zero".to_string(),
+            ));
+        }
+        // The DDoS module derives its per-second limit from these two, and would refuse everything at zero
+        if (self.max_requests_per_window as u64) < self.rate_limit_window_seconds {
+            return Err(TorSecurityError::ConfigurationError(format!(
+                "Max requests per window ({}) must allow at least one request per second of the {}s window",
+                self.max_requests_per_window, self.rate_limit_window_seconds
+            )));
+        }
+        Ok(())
+    }
+
+    /// Load and validate a configuration from a TOML file
+    #[cfg(feature = "operational")]
+    pub fn from_toml_path(path: impl AsRef<std::path::Path>) -> TorSecurityResult<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            TorSecurityError::ConfigurationError(format!("Failed to read {}: {}", path.display(), e))
+        })?;
+        let config: Self = toml::from_str(&contents).map_err(|e| {
+            TorSecurityError::ConfigurationError(format!("Failed to parse {}: {}", path.display(), e))
+        })?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Chainable builder for `TorSecurityConfig` that validates the result
+#[derive(Debug, Clone, Default)]
+pub struct TorSecurityConfigBuilder {
+    config: TorSecurityConfig,
+}
+
+impl TorSecurityConfigBuilder {
+    pub fn enable_onion_protection(mut self, enabled: bool) -> Self {
+        self.config.enable_onion_protection = enabled;
+        self
+    }
+
+    pub fn enable_ddos_mitigation(mut self, enabled: bool) -> Self {
+        self.config.enable_ddos_mitigation = enabled;
+        self
+    }
+
+    pub fn enable_circuit_analysis(mut self, enabled: bool) -> Self {
+        self.config.enable_circuit_analysis = enabled;
+        self
+    }
+
+    pub fn enable_exit_node_filtering(mut self, enabled: bool) -> Self {
+        self.config.enable_exit_node_filtering = enabled;
+        self
+    }
+
+    pub fn enable_rendezvous_security(mut self, enabled: bool) -> Self {
+        self.config.enable_rendezvous_security = enabled;
+        self
+    }
+
+    pub fn max_connections_per_circuit(mut self, max: u32) -> Self {
+        self.config.max_connections_per_circuit = max;
+        self
+    }
+
+    pub fn rate_limit_window_seconds(mut self, seconds: u64) -> Self {
+        self.config.rate_limit_window_seconds = seconds;
+        self
+    }
+
+    pub fn max_requests_per_window(mut self, max: u32) -> Self {
+        self.config.max_requests_per_window = max;
+        self
+    }
+
+    /// Check the configuration and return it
+    pub fn build(self) -> TorSecurityResult<TorSecurityConfig> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// Everything known about an incoming request, as far as the security checks care
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    pub source_ip: Option<IpAddr>,
+    pub onion_address: Option<onion_service::OnionAddress>,
+    pub circuit_id: Option<String>,
+    pub exit_node: Option<IpAddr>,
+    pub request_size: u64,
+}
+
+/// Long-lived state of the Tor security modules, for backups
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TorStateSnapshot {
+    pub onion_service: onion_service::OnionServiceSnapshot,
+    pub exit_node_filter: exit_node_filter::ExitNodeFilterSnapshot,
+}
+
+/// Tor security modules that can be switched on and off at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TorModule {
+    OnionProtection,
+    DDoSMitigation,
+    CircuitAnalysis,
+    ExitNodeFiltering,
+    RendezvousSecurity,
+}
+
+impl FromStr for TorModule {
+    type Err = TorSecurityError;
+
+    /// Parse the snake_case name of a module, e.g. `exit_node_filtering`
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "onion_protection" => Ok(TorModule::OnionProtection),
+            "ddos_mitigation" => Ok(TorModule::DDoSMitigation),
+            "circuit_analysis" => Ok(TorModule::CircuitAnalysis),
+            "exit_node_filtering" => Ok(TorModule::ExitNodeFiltering),
+            "rendezvous_security" => Ok(TorModule::RendezvousSecurity),
+            other => Err(TorSecurityError::ConfigurationError(format!("Unknown Tor module: {}", other))),
+        }
+    }
+}
+
+/// Statistics from every enabled Tor security module; disabled modules are left out
+#[derive(Debug, Clone, Serialize)]
+pub struct TorSecurityStats {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub onion_service: Option<onion_service::ConnectionStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ddos_mitigation: Option<ddos_mitigation::MitigationStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub circuit_analysis: Option<circuit_analysis::CircuitAnalysisStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_node_filter: Option<exit_node_filter::ExitNodeFilterStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rendezvous_security: Option<rendezvous_security::RendezvousStats>,
+}
+
 /// Main Tor security manager
+///
+/// Every method takes `&self`, so the manager is shared as an
+/// `Arc<TorSecurityManager>` without an outer lock. Each subsystem sits behind
+/// its own mutex and `evaluate_request` holds one at a time, so an admission
+/// check in DDoS mitigation never waits on, say, a blocklist import in exit
+/// node filtering. The configuration is behind a read-write lock: requests take
+/// a copy of the enable flags up front, while `set_module_enabled` and
+/// `reconfigure` hold the write lock for the whole change so concurrent toggles
+/// can't interleave. Locks are always taken configuration first, then at most
+/// one subsystem. A subsystem whose lock was poisoned by a panic keeps serving
+/// with whatever state it had.
 pub struct TorSecurityManager {
-    config: TorSecurityConfig,
-    onion_service: onion_service::OnionServiceProtection,
-    ddos_mitigation: ddos_mitigation::DDoSMitigation,
-    circuit_analysis: circuit_analysis::CircuitAnalysis,
-    exit_node_filter: exit_node_filter::ExitNodeFilter,
-    rendezvous_security: rendezvous_security::RendezvousPointSecurity,
+    config: RwLock<TorSecurityConfig>,
+    onion_service: Mutex<onion_service::OnionServiceProtection>,
+    ddos_mitigation: Mutex<ddos_mitigation::DDoSMitigation>,
+    circuit_analysis: Mutex<circuit_analysis::CircuitAnalysis>,
+    exit_node_filter: Arc<Mutex<exit_node_filter::ExitNodeFilter>>,
+    rendezvous_security: Mutex<rendezvous_security::RendezvousPointSecurity>,
+    lockdown: Arc<AtomicBool>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn map_locked<T>(mutex: Mutex<T>, f: impl FnOnce(T) -> T) -> Mutex<T> {
+    Mutex::new(f(mutex.into_inner().unwrap_or_else(PoisonError::into_inner)))
 }
 
 impl TorSecurityManager {
@@ -83,51 +275,626 @@ impl TorSecurityManager {
         Self::with_config(TorSecurityConfig::default())
     }
 
-    /// Create a new Tor security manager with custom configuration
+    /// Create a new Tor security manager with custom configuration, which
+    /// must pass `TorSecurityConfig::validate`
     pub fn with_config(config: TorSecurityConfig) -> TorSecurityResult<Self> {
+        config.validate()?;
         Ok(Self {
-            onion_service: onion_service::OnionServiceProtection::new(&config)?,
-            ddos_mitigation: ddos_mitigation::DDoSMitigation::new(&config)?,
-            circuit_analysis: circuit_analysis::CircuitAnalysis::new(&config)?,
-            exit_node_filter: exit_node_filter::ExitNodeFilter::new(&config)?,
-            rendezvous_security: rendezvous_security::RendezvousPointSecurity::new(&config)?,
-            config,
+            onion_service: Mutex::new(onion_service::OnionServiceProtection::new(&config)?),
+            ddos_mitigation: Mutex::new(ddos_mitigation::DDoSMitigation::new(&config)?),
+            circuit_analysis: Mutex::new(circuit_analysis::CircuitAnalysis::new(&config)?),
+            exit_node_filter: Arc::new(Mutex::new(exit_node_filter::ExitNodeFilter::new(&config)?)),
+            rendezvous_security: Mutex::new(rendezvous_security::RendezvousPointSecurity::new(&config)?),
+            config: RwLock::new(config),
+            lockdown: Arc::new(AtomicBool::new(false)),
         })
     }
 
+    /// Have every subsystem read the time from `clock` instead of the system clock
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        // The exit node filter may already be shared with its maintenance task
+        lock(&self.exit_node_filter).set_clock(Arc::clone(&clock));
+        Self {
+            onion_service: map_locked(self.onion_service, |s| s.with_clock(Arc::clone(&clock))),
+            ddos_mitigation: map_locked(self.ddos_mitigation, |s| s.with_clock(Arc::clone(&clock))),
+            circuit_analysis: map_locked(self.circuit_analysis, |s| s.with_clock(Arc::clone(&clock))),
+            rendezvous_security: map_locked(self.rendezvous_security, |s| s.with_clock(clock)),
+            ..self
+        }
+    }
+
     /// Initialize all security features
-    pub fn initialize(&mut self) -> TorSecurityResult<()> {
-        if self.config.enable_onion_protection {
-            self.onion_service.initialize()?;
+    pub fn initialize(&self) -> TorSecurityResult<()> {
+        let config = self.config();
+        if config.enable_onion_protection {
+            lock(&self.onion_service).initialize()?;
+        }
+        if config.enable_ddos_mitigation {
+            lock(&self.ddos_mitigation).initialize()?;
+        }
+        if config.enable_circuit_analysis {
+            lock(&self.circuit_analysis).initialize()?;
         }
-        if self.config.enable_ddos_mitigation {
-            self.ddos_mitigation.initialize()?;
+        if config.enable_exit_node_filtering {
+            lock(&self.exit_node_filter).initialize()?;
         }
-        if self.config.enable_circuit_analysis {
-            self.circuit_analysis.initialize()?;
+        if config.enable_rendezvous_security {
+            lock(&self.rendezvous_security).initialize()?;
+        }
+        Ok(())
+    }
+
+    /// Run the enabled checks against a request, stopping at the first one that refuses it
+    ///
+    /// Checks run from cheapest to most stateful: exit node filtering, circuit
+    /// analysis, DDoS mitigation, then onion service connection limits. The onion
+    /// check comes last because an allowed connection takes a slot that the caller
+    /// releases with `request_finished`. A DDoS `Challenge` is returned as is.
+    /// Checks whose context field is missing are skipped.
+    pub fn evaluate_request(&self, ctx: &RequestContext) -> TorSecurityResult<RequestDecision> {
+        if self.lockdown.load(Ordering::SeqCst) {
+            return Ok(RequestDecision::Deny);
+        }
+        let config = self.config();
+
+        if config.enable_exit_node_filtering
+            && let Some(exit_node) = ctx.exit_node
+            && !lock(&self.exit_node_filter).should_allow_exit_node(exit_node)?
+        {
+            return Ok(RequestDecision::Deny);
+        }
+
+        if config.enable_circuit_analysis
+            && let Some(circuit_id) = &ctx.circuit_id
+            && lock(&self.circuit_analysis).is_circuit_suspicious(circuit_id)
+        {
+            return Ok(RequestDecision::Deny);
         }
-        if self.config.enable_exit_node_filtering {
-            self.exit_node_filter.initialize()?;
+
+        if config.enable_ddos_mitigation {
+            let mut ddos_mitigation = lock(&self.ddos_mitigation);
+            // Decide before recording so the request doesn't count against its own limit
+            let decision = ddos_mitigation.evaluate_request(ctx.source_ip, ctx.circuit_id.clone())?;
+            ddos_mitigation.record_request(ctx.source_ip, ctx.request_size, ctx.circuit_id.clone())?;
+            if decision != RequestDecision::Allow {
+                return Ok(decision);
+            }
+        }
+
+        if config.enable_onion_protection
+            && let (Some(source_ip), Some(onion_address)) = (ctx.source_ip, &ctx.onion_address)
+            && !lock(&self.onion_service).should_allow_connection_on_circuit(
+                source_ip,
+                onion_address,
+                ctx.circuit_id.as_deref(),
+            )?
+        {
+            return Ok(RequestDecision::Deny);
+        }
+
+        Ok(RequestDecision::Allow)
+    }
+
+    /// Release the onion service connection slot taken by an allowed request
+    pub fn request_finished(&self, ctx: &RequestContext) {
+        if !self.config().enable_onion_protection {
+            return;
+        }
+        if let (Some(source_ip), Some(onion_address)) = (ctx.source_ip, &ctx.onion_address) {
+            let mut onion_service = lock(&self.onion_service);
+            match &ctx.circuit_id {
+                Some(circuit_id) => onion_service.connection_closed_for_circuit(source_ip, onion_address, circuit_id),
+                None => onion_service.connection_closed(source_ip, onion_address),
+            }
+        }
+    }
+
+    /// Process a rendezvous handshake attempt, waiting out the timing delay with
+    /// the rendezvous lock released so other callers aren't held up and the
+    /// future can be spawned. Attempts are always admitted while rendezvous
+    /// security is disabled.
+    pub async fn process_rendezvous_handshake(
+        &self,
+        rendezvous_node: String,
+        client_circuit: Option<String>,
+        service_circuit: Option<String>,
+        success: bool,
+        failure_reason: Option<String>,
+        response_time: Duration,
+    ) -> TorSecurityResult<bool> {
+        if !self.config().enable_rendezvous_security {
+            return Ok(true);
+        }
+        let admitted = lock(&self.rendezvous_security).admit_handshake_attempt(
+            rendezvous_node,
+            client_circuit,
+            service_circuit,
+            success,
+            failure_reason,
+            response_time,
+        )?;
+        let Some(delay) = admitted else { return Ok(false) };
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        Ok(true)
+    }
+
+    /// Admit `circuit_id` past DDoS challenges once it solved the one it was served
+    pub fn mark_challenge_passed(&self, circuit_id: impl Into<String>) {
+        lock(&self.ddos_mitigation).mark_challenge_passed(circuit_id);
+    }
+
+    /// Call `callback` with the old and new state whenever DDoS mitigation changes state.
+    /// It runs while the DDoS mitigation lock is held, so hand work off rather than
+    /// call back into the manager.
+    pub fn on_ddos_state_change<F>(&self, callback: F)
+    where
+        F: Fn(ddos_mitigation::MitigationState, ddos_mitigation::MitigationState) + Send + Sync + 'static,
+    {
+        lock(&self.ddos_mitigation).on_state_change(callback);
+    }
+
+    /// Capture the state worth keeping across a disaster: registered onion
+    /// services, the exit node blocklist and reputation data
+    pub fn export_state(&self) -> TorStateSnapshot {
+        TorStateSnapshot {
+            onion_service: lock(&self.onion_service).snapshot(),
+            exit_node_filter: lock(&self.exit_node_filter).snapshot(),
+        }
+    }
+
+    /// Replace the state captured by `export_state`
+    pub fn import_state(&self, snapshot: TorStateSnapshot) {
+        lock(&self.onion_service).restore_snapshot(snapshot.onion_service);
+        lock(&self.exit_node_filter).restore_snapshot(snapshot.exit_node_filter);
+    }
+
+    /// Shared flag that, while set, makes `evaluate_request` deny everything.
+    /// Held by kill switches that must work without touching the manager's locks.
+    pub fn lockdown_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.lockdown)
+    }
+
+    /// Whether `module` is currently enabled
+    pub fn is_module_enabled(&self, module: TorModule) -> bool {
+        *module_flag(&mut self.config(), module)
+    }
+
+    /// Start or stop a module at runtime, e.g. to tighten filtering mid-attack.
+    /// Starting a module initializes it from scratch; a module already in the
+    /// requested state is left untouched.
+    pub fn set_module_enabled(&self, module: TorModule, enabled: bool) -> TorSecurityResult<()> {
+        let mut config = self.config.write().unwrap_or_else(PoisonError::into_inner);
+        self.switch_module(&mut config, module, enabled)
+    }
+
+    fn switch_module(&self, config: &mut TorSecurityConfig, module: TorModule, enabled: bool) -> TorSecurityResult<()> {
+        let flag = module_flag(config, module);
+        if *flag == enabled {
+            return Ok(());
         }
-        if self.config.enable_rendezvous_security {
-            self.rendezvous_security.initialize()?;
+
+        match (module, enabled) {
+            (TorModule::OnionProtection, true) => lock(&self.onion_service).initialize()?,
+            (TorModule::OnionProtection, false) => lock(&self.onion_service).shutdown()?,
+            (TorModule::DDoSMitigation, true) => lock(&self.ddos_mitigation).initialize()?,
+            (TorModule::DDoSMitigation, false) => lock(&self.ddos_mitigation).shutdown()?,
+            (TorModule::CircuitAnalysis, true) => lock(&self.circuit_analysis).initialize()?,
+            (TorModule::CircuitAnalysis, false) => lock(&self.circuit_analysis).shutdown()?,
+            (TorModule::ExitNodeFiltering, true) => lock(&self.exit_node_filter).initialize()?,
+            (TorModule::ExitNodeFiltering, false) => lock(&self.exit_node_filter).shutdown()?,
+            (TorModule::RendezvousSecurity, true) => lock(&self.rendezvous_security).initialize()?,
+            (TorModule::RendezvousSecurity, false) => lock(&self.rendezvous_security).shutdown()?,
         }
+
+        *flag = enabled;
         Ok(())
     }
 
+    /// Switch to a new configuration at runtime. Limits are re-derived in every
+    /// module without dropping its state, then modules are started or stopped
+    /// to match the enable flags. An invalid configuration changes nothing.
+    pub fn reconfigure(&self, config: TorSecurityConfig) -> TorSecurityResult<()> {
+        config.validate()?;
+        let mut current = self.config.write().unwrap_or_else(PoisonError::into_inner);
+
+        lock(&self.onion_service).apply_tor_config(&config);
+        lock(&self.ddos_mitigation).apply_tor_config(&config);
+        lock(&self.circuit_analysis).apply_tor_config(&config);
+        lock(&self.exit_node_filter).apply_tor_config(&config);
+        lock(&self.rendezvous_security).apply_tor_config(&config);
+        current.max_connections_per_circuit = config.max_connections_per_circuit;
+        current.rate_limit_window_seconds = config.rate_limit_window_seconds;
+        current.max_requests_per_window = config.max_requests_per_window;
+
+        self.switch_module(&mut current, TorModule::OnionProtection, config.enable_onion_protection)?;
+        self.switch_module(&mut current, TorModule::DDoSMitigation, config.enable_ddos_mitigation)?;
+        self.switch_module(&mut current, TorModule::CircuitAnalysis, config.enable_circuit_analysis)?;
+        self.switch_module(&mut current, TorModule::ExitNodeFiltering, config.enable_exit_node_filtering)?;
+        self.switch_module(&mut current, TorModule::RendezvousSecurity, config.enable_rendezvous_security)?;
+        Ok(())
+    }
+
+    /// A copy of the configuration currently in effect
+    pub fn config(&self) -> TorSecurityConfig {
+        self.config.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Direct access to onion service protection. Admission checks that need it
+    /// wait while the guard is held, so drop it promptly.
+    pub fn onion_service(&self) -> MutexGuard<'_, onion_service::OnionServiceProtection> {
+        lock(&self.onion_service)
+    }
+
+    /// Direct access to DDoS mitigation; see `onion_service` about holding the guard
+    pub fn ddos_mitigation(&self) -> MutexGuard<'_, ddos_mitigation::DDoSMitigation> {
+        lock(&self.ddos_mitigation)
+    }
+
+    /// Direct access to circuit analysis; see `onion_service` about holding the guard
+    pub fn circuit_analysis(&self) -> MutexGuard<'_, circuit_analysis::CircuitAnalysis> {
+        lock(&self.circuit_analysis)
+    }
+
+    /// Direct access to exit node filtering; see `onion_service` about holding the guard
+    pub fn exit_node_filter(&self) -> MutexGuard<'_, exit_node_filter::ExitNodeFilter> {
+        lock(&self.exit_node_filter)
+    }
+
+    /// Expire blocklist entries and refresh exit node reputation in the background;
+    /// see `ExitNodeFilter::spawn_maintenance`. Abort the handle on shutdown.
+    pub fn spawn_exit_maintenance(&self, interval: Option<Duration>) -> JoinHandle<()> {
+        exit_node_filter::ExitNodeFilter::spawn_maintenance(Arc::clone(&self.exit_node_filter), interval)
+    }
+
+    /// Refresh the exit node threat feed from `url` without holding the filter
+    /// lock during the download; see `ExitNodeFilter::refresh_blocklist_from_url`
+    #[cfg(feature = "network")]
+    pub async fn refresh_exit_blocklist(&self, url: &str) -> TorSecurityResult<Option<usize>> {
+        exit_node_filter::ExitNodeFilter::refresh_blocklist_from_url(&self.exit_node_filter, url).await
+    }
+
+    /// Direct access to rendezvous point security; see `onion_service` about holding
+    /// the guard, and don't hold it across `process_handshake_attempt`'s delay
+    pub fn rendezvous_security(&self) -> MutexGuard<'_, rendezvous_security::RendezvousPointSecurity> {
+        lock(&self.rendezvous_security)
+    }
+
+    /// Gather statistics from every enabled module
+    pub fn aggregate_stats(&self) -> TorSecurityStats {
+        let config = self.config();
+        TorSecurityStats {
+            onion_service: config.enable_onion_protection
+                .then(|| lock(&self.onion_service).get_connection_stats()),
+            ddos_mitigation: config.enable_ddos_mitigation
+                .then(|| lock(&self.ddos_mitigation).get_mitigation_stats()),
+            circuit_analysis: config.enable_circuit_analysis
+                .then(|| lock(&self.circuit_analysis).get_analysis_stats()),
+            exit_node_filter: config.enable_exit_node_filtering
+                .then(|| lock(&self.exit_node_filter).get_filter_stats()),
+            rendezvous_security: config.enable_rendezvous_security
+                .then(|| lock(&self.rendezvous_security).get_rendezvous_stats()),
+        }
+    }
+
     /// Shutdown all security features
-    pub fn shutdown(&mut self) -> TorSecurityResult<()> {
-        self.onion_service.shutdown()?;
-        self.ddos_mitigation.shutdown()?;
-        self.circuit_analysis.shutdown()?;
-        self.exit_node_filter.shutdown()?;
-        self.rendezvous_security.shutdown()?;
+    pub fn shutdown(&self) -> TorSecurityResult<()> {
+        lock(&self.onion_service).shutdown()?;
+        lock(&self.ddos_mitigation).shutdown()?;
+        lock(&self.circuit_analysis).shutdown()?;
+        lock(&self.exit_node_filter).shutdown()?;
+        lock(&self.rendezvous_security).shutdown()?;
         Ok(())
     }
 }
 
+fn module_flag(config: &mut TorSecurityConfig, module: TorModule) -> &mut bool {
+    match module {
+        TorModule::OnionProtection => &mut config.enable_onion_protection,
+        TorModule::DDoSMitigation => &mut config.enable_ddos_mitigation,
+        TorModule::CircuitAnalysis => &mut config.enable_circuit_analysis,
+        TorModule::ExitNodeFiltering => &mut config.enable_exit_node_filtering,
+        TorModule::RendezvousSecurity => &mut config.enable_rendezvous_security,
+    }
+}
+
 impl Default for TorSecurityManager {
     fn default() -> Self {
         Self::new().expect("Failed to create default TorSecurityManager")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::tor::exit_node_filter::BlocklistSource;
+
+    fn context(exit_node: &str) -> RequestContext {
+        RequestContext {
+            circuit_id: Some("circuit_1".to_string()),
+            exit_node: Some(exit_node.parse().unwrap()),
+            request_size: 512,
+            ..RequestContext::default()
+        }
+    }
+
+    #[test]
+    fn test_evaluate_request_runs_enabled_checks() {
+        let manager = TorSecurityManager::new().unwrap();
+        manager.initialize().unwrap();
+        lock(&manager.exit_node_filter).add_to_blocklist(
+            "198.51.100.1".parse::<IpAddr>().unwrap(),
+            BlocklistSource::Manual,
+            "test".to_string(),
+            None,
+            5,
+        ).unwrap();
+
+        assert_eq!(manager.evaluate_request(&context("198.51.100.2")).unwrap(), RequestDecision::Allow);
+        assert_eq!(manager.evaluate_request(&context("198.51.100.1")).unwrap(), RequestDecision::Deny);
+
+        let config = TorSecurityConfig {
+            enable_exit_node_filtering: false,
+            ..TorSecurityConfig::default()
+        };
+        let manager = TorSecurityManager::with_config(config).unwrap();
+        lock(&manager.exit_node_filter).add_to_blocklist(
+            "198.51.100.1".parse::<IpAddr>().unwrap(),
+            BlocklistSource::Manual,
+            "test".to_string(),
+            None,
+            5,
+        ).unwrap();
+        assert_eq!(manager.evaluate_request(&context("198.51.100.1")).unwrap(), RequestDecision::Allow);
+    }
+
+    #[test]
+    fn test_concurrent_evaluate_request() {
+        let config = TorSecurityConfig {
+            max_connections_per_circuit: 1000,
+            max_requests_per_window: 60_000,
+            ..TorSecurityConfig::default()
+        };
+        let manager = Arc::new(TorSecurityManager::with_config(config).unwrap());
+        manager.initialize().unwrap();
+        lock(&manager.exit_node_filter).add_to_blocklist(
+            "198.51.100.1".parse::<IpAddr>().unwrap(),
+            BlocklistSource::Manual,
+            "test".to_string(),
+            None,
+            5,
+        ).unwrap();
+
+        let workers: Vec<_> = (1..=8)
+            .map(|worker| {
+                let manager = Arc::clone(&manager);
+                std::thread::spawn(move || {
+                    (0..25)
+                        .map(|request| RequestContext {
+                            circuit_id: Some(format!("circuit_{}_{}", worker, request)),
+                            ..context(&format!("198.51.100.{}", worker))
+                        })
+                        .filter(|ctx| manager.evaluate_request(ctx).unwrap() == RequestDecision::Allow)
+                        .count()
+                })
+            })
+            .collect();
+        // Unrelated operations proceed while the workers run
+        for _ in 0..10 {
+            manager.set_module_enabled(TorModule::RendezvousSecurity, false).unwrap();
+            manager.set_module_enabled(TorModule::RendezvousSecurity, true).unwrap();
+            manager.aggregate_stats();
+        }
+
+        let allowed: Vec<usize> = workers.into_iter().map(|worker| worker.join().unwrap()).collect();
+        assert_eq!(allowed, [0, 25, 25, 25, 25, 25, 25, 25]);
+        assert_eq!(manager.aggregate_stats().ddos_mitigation.unwrap().active_circuits, 7 * 25);
+    }
+
+    #[cfg(feature = "operational")]
+    #[test]
+    fn test_config_from_partial_toml() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tor.toml");
+        std::fs::write(&path, "enable_circuit_analysis = false\nmax_requests_per_window = 250\n").unwrap();
+
+        let config = TorSecurityConfig::from_toml_path(&path).unwrap();
+        let defaults = TorSecurityConfig::default();
+        assert!(!config.enable_circuit_analysis);
+        assert_eq!(config.max_requests_per_window, 250);
+        assert!(config.enable_onion_protection);
+        assert_eq!(config.rate_limit_window_seconds, defaults.rate_limit_window_seconds);
+        assert_eq!(config.max_connections_per_circuit, defaults.max_connections_per_circuit);
+
+        std::fs::write(&path, "max_requests_per_window = \"lots\"\n").unwrap();
+        assert!(matches!(
+            TorSecurityConfig::from_toml_path(&path),
+            Err(TorSecurityError::ConfigurationError(_))
+        ));
+        assert!(TorSecurityConfig::from_toml_path(dir.path().join("missing.toml")).is_err());
+
+        std::fs::write(&path, "rate_limit_window_seconds = 0\n").unwrap();
+        assert!(matches!(
+            TorSecurityConfig::from_toml_path(&path),
+            Err(TorSecurityError::ConfigurationError(_))
+        ));
+        let zero_window = TorSecurityConfig { rate_limit_window_seconds: 0, ..TorSecurityConfig::default() };
+        assert!(matches!(
+            TorSecurityManager::with_config(zero_window),
+            Err(TorSecurityError::ConfigurationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_config_builder() {
+        let config = TorSecurityConfig::builder()
+            .enable_circuit_analysis(false)
+            .rate_limit_window_seconds(30)
+            .max_requests_per_window(300)
+            .build()
+            .unwrap();
+        assert!(!config.enable_circuit_analysis);
+        assert!(config.enable_onion_protection);
+        assert_eq!(config.rate_limit_window_seconds, 30);
+        assert_eq!(config.max_requests_per_window, 300);
+        assert_eq!(config.max_connections_per_circuit, 10);
+
+        assert!(TorSecurityConfig::builder().build().is_ok());
+        assert!(TorSecurityConfig::builder().rate_limit_window_seconds(0).build().is_err());
+        assert!(TorSecurityConfig::builder().max_connections_per_circuit(0).build().is_err());
+        assert!(matches!(
+            TorSecurityConfig::builder().max_requests_per_window(30).build(),
+            Err(TorSecurityError::ConfigurationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_toggle_module_at_runtime() {
+        let config = TorSecurityConfig {
+            enable_exit_node_filtering: false,
+            ..TorSecurityConfig::default()
+        };
+        let manager = TorSecurityManager::with_config(config).unwrap();
+        manager.initialize().unwrap();
+        assert_eq!(manager.evaluate_request(&context("198.51.100.1")).unwrap(), RequestDecision::Allow);
+
+        manager.set_module_enabled(TorModule::ExitNodeFiltering, true).unwrap();
+        assert!(manager.is_module_enabled(TorModule::ExitNodeFiltering));
+        lock(&manager.exit_node_filter).add_to_blocklist(
+            "198.51.100.1".parse::<IpAddr>().unwrap(),
+            BlocklistSource::Manual,
+            "test".to_string(),
+            None,
+            5,
+        ).unwrap();
+        assert_eq!(manager.evaluate_request(&context("198.51.100.1")).unwrap(), RequestDecision::Deny);
+
+        // Enabling an enabled module keeps its state
+        manager.set_module_enabled(TorModule::ExitNodeFiltering, true).unwrap();
+        assert_eq!(manager.evaluate_request(&context("198.51.100.1")).unwrap(), RequestDecision::Deny);
+
+        manager.set_module_enabled(TorModule::ExitNodeFiltering, false).unwrap();
+        assert!(!manager.config().enable_exit_node_filtering);
+        assert!(manager.aggregate_stats().exit_node_filter.is_none());
+        assert_eq!(manager.evaluate_request(&context("198.51.100.1")).unwrap(), RequestDecision::Allow);
+    }
+
+    #[test]
+    fn test_aggregate_stats_skips_disabled_modules() {
+        let config = TorSecurityConfig {
+            enable_circuit_analysis: false,
+            ..TorSecurityConfig::default()
+        };
+        let manager = TorSecurityManager::with_config(config).unwrap();
+        manager.evaluate_request(&context("198.51.100.2")).unwrap();
+
+        let stats = manager.aggregate_stats();
+        assert!(stats.circuit_analysis.is_none());
+        assert_eq!(stats.exit_node_filter.as_ref().unwrap().total_nodes, 1);
+        assert_eq!(stats.ddos_mitigation.as_ref().unwrap().active_circuits, 1);
+
+        let json = serde_json::to_value(&stats).unwrap();
+        assert!(json.get("circuit_analysis").is_none());
+        assert!(json["onion_service"].is_object());
+        assert!(json["rendezvous_security"].is_object());
+    }
+
+    #[test]
+    fn test_evaluate_request_enforces_onion_limits() {
+        let config = TorSecurityConfig {
+            enable_ddos_mitigation: false,
+            rate_limit_window_seconds: 2,
+            max_requests_per_window: 2,
+            ..TorSecurityConfig::default()
+        };
+        let manager = TorSecurityManager::with_config(config).unwrap();
+        let onion = onion_service::OnionAddress::from_public_key(&[7; 32]);
+        let ctx = RequestContext {
+            source_ip: Some("192.0.2.10".parse().unwrap()),
+            onion_address: Some(onion),
+            ..RequestContext::default()
+        };
+
+        assert_eq!(manager.evaluate_request(&ctx).unwrap(), RequestDecision::Allow);
+        assert_eq!(manager.evaluate_request(&ctx).unwrap(), RequestDecision::Allow);
+        assert_eq!(manager.evaluate_request(&ctx).unwrap(), RequestDecision::Deny);
+
+        manager.request_finished(&ctx);
+        assert_eq!(lock(&manager.onion_service).get_connection_stats().active_connections, 1);
+    }
+
+    #[tokio::test]
+    async fn test_rendezvous_delay_runs_without_the_lock() {
+        let manager = Arc::new(TorSecurityManager::new().unwrap());
+        manager.initialize().unwrap();
+
+        // Spawning requires the handshake future to be `Send`
+        let handshake = tokio::spawn({
+            let manager = Arc::clone(&manager);
+            async move {
+                manager
+                    .process_rendezvous_handshake("node".to_string(), None, None, true, None, Duration::from_millis(20))
+                    .await
+            }
+        });
+
+        // The default delay is at least 100ms; meanwhile the lock is free and the attempt recorded
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(manager.rendezvous_security().get_security_stats().total_handshakes, 1);
+        assert!(!handshake.is_finished());
+        assert!(handshake.await.unwrap().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_exit_maintenance_expires_manager_blocklist() {
+        let manager = TorSecurityManager::new().unwrap();
+        manager.initialize().unwrap();
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let expires = manager.exit_node_filter().now() + Duration::from_millis(20);
+        manager.exit_node_filter()
+            .add_to_blocklist(ip, BlocklistSource::Manual, "Temporary".to_string(), Some(expires), 5)
+            .unwrap();
+
+        let handle = manager.spawn_exit_maintenance(Some(Duration::from_millis(10)));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(manager.exit_node_filter().get_filter_stats().blocked_count, 0);
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_clock_can_be_set_after_maintenance_starts() {
+        let manager = TorSecurityManager::new().unwrap();
+        let handle = manager.spawn_exit_maintenance(None);
+
+        let clock = MockClock::new();
+        let manager = manager.with_clock(Arc::new(clock.clone()));
+        clock.advance(Duration::from_secs(3600));
+        assert_eq!(manager.exit_node_filter().now(), clock.now());
+        handle.abort();
+    }
+
+    #[test]
+    fn test_reconfigure_keeps_state() {
+        let manager = TorSecurityManager::new().unwrap();
+        manager.initialize().unwrap();
+        let onion = onion_service::OnionAddress::from_public_key(&[7; 32]);
+        lock(&manager.onion_service).register_onion_service(onion).unwrap();
+
+        let config = TorSecurityConfig {
+            enable_circuit_analysis: false,
+            max_connections_per_circuit: 4,
+            ..TorSecurityConfig::default()
+        };
+        manager.reconfigure(config.clone()).unwrap();
+        assert_eq!(manager.config(), config);
+        assert!(!manager.is_module_enabled(TorModule::CircuitAnalysis));
+        let state = serde_json::to_value(manager.export_state()).unwrap();
+        assert_eq!(state["onion_service"]["protected_onions"].as_array().unwrap().len(), 1);
+
+        let invalid = TorSecurityConfig { rate_limit_window_seconds: 0, ..TorSecurityConfig::default() };
+        assert!(manager.reconfigure(invalid).is_err());
+        assert_eq!(manager.config(), config);
+    }
+}
@@ -3,30 +3,67 @@
 //! Provides automatic rate limiting and connection management specifically for .onion domains.
 //! This module implements specialized protection mechanisms for Tor hidden services.
 
+use crate::clock::{self, Clock};
 use crate::tor::{TorSecurityConfig, TorSecurityError, TorSecurityResult};
-use std::collections::HashMap;
-use std::net::IpAddr;
-use std::time::{Duration, Instant};
-
-/// Represents an onion address
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+use log::info;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Represents an onion address; deserializing one validates it like `new`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub struct OnionAddress(String);
 
 impl OnionAddress {
-    /// Create a new onion address
+    /// Create a new onion address, accepting only well-formed v3 addresses.
+    /// Subdomains (`www.<id>.onion`) are allowed; the address is stored lowercase.
     pub fn new(address: String) -> TorSecurityResult<Self> {
-        if !Self::is_valid_onion_address(&address) {
-            return Err(TorSecurityError::InvalidOnionAddress(
-                format!("Invalid onion address format: {}", address)
-            ));
-        }
+        let address = address.to_ascii_lowercase();
+        Self::validate_v3(&address).map_err(|reason| {
+            TorSecurityError::InvalidOnionAddress(format!("{}: {}", address, reason))
+        })?;
         Ok(OnionAddress(address))
     }
 
-    /// Validate onion address format
-    fn is_valid_onion_address(address: &str) -> bool {
-        // Basic validation for .onion addresses
-        address.ends_with(".onion") && address.len() >= 22
+    /// Build the v3 address for an ed25519 public key
+    pub fn from_public_key(pubkey: &[u8; 32]) -> Self {
+        let mut raw = Vec::with_capacity(V3_DECODED_LEN);
+        raw.extend_from_slice(pubkey);
+        raw.extend_from_slice(&v3_checksum(pubkey));
+        raw.push(V3_VERSION);
+        OnionAddress(format!("{}.onion", base32_encode(&raw)))
+    }
+
+    /// Check a v3 address: 56 base32 characters decoding to
+    /// `pubkey (32) || checksum (2) || version (1)`, where the checksum is the
+    /// first two bytes of `SHA3-256(".onion checksum" || pubkey || version)`
+    fn validate_v3(address: &str) -> Result<(), &'static str> {
+        let host = address.strip_suffix(".onion").ok_or("missing .onion suffix")?;
+        let label = host.rsplit('.').next().unwrap_or(host);
+        if label.len() != V3_LABEL_LEN {
+            return Err("v3 addresses are 56 characters");
+        }
+
+        let raw = base32_decode(label).ok_or("not valid base32")?;
+        if raw.len() != V3_DECODED_LEN {
+            return Err("wrong decoded length");
+        }
+
+        let (pubkey, rest) = raw.split_at(32);
+        if rest[2] != V3_VERSION {
+            return Err("unsupported version");
+        }
+        let pubkey: &[u8; 32] = pubkey.try_into().map_err(|_| "wrong key length")?;
+        if rest[..2] != v3_checksum(pubkey) {
+            return Err("checksum mismatch");
+        }
+        Ok(())
     }
 
     /// Get the raw address string
@@ -35,6 +72,101 @@ impl OnionAddress {
     }
 }
 
+impl TryFrom<String> for OnionAddress {
+    type Error = TorSecurityError;
+
+    fn try_from(address: String) -> TorSecurityResult<Self> {
+        Self::new(address)
+    }
+}
+
+impl From<OnionAddress> for String {
+    fn from(address: OnionAddress) -> Self {
+        address.0
+    }
+}
+
+const V3_LABEL_LEN: usize = 56;
+const V3_DECODED_LEN: usize = 35;
+const V3_VERSION: u8 = 3;
+const BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+fn v3_checksum(pubkey: &[u8; 32]) -> [u8; 2] {
+    let digest = Sha3_256::new()
+        .chain_update(b".onion checksum")
+        .chain_update(pubkey)
+        .chain_update([V3_VERSION])
+        .finalize();
+    [digest[0], digest[1]]
+}
+
+/// RFC 4648 base32 without padding, lowercase
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+fn base32_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 5 / 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let value = BASE32_ALPHABET.iter().position(|&a| a == c)? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// Key for per-client tracking, scoped to the onion service being accessed so
+/// each service's limits apply independently
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ConnectionKey {
+    onion: OnionAddress,
+    client: IpAddr,
+}
+
+/// Mask an address down to the configured prefix so neighbouring addresses
+/// share one rate limit bucket
+fn subnet_of(ip: IpAddr, config: &OnionServiceConfig) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => {
+            let bits = config.subnet_ipv4_bits.min(32) as u32;
+            let mask = u32::MAX.checked_shl(32 - bits).unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask))
+        }
+        IpAddr::V6(v6) => {
+            let bits = config.subnet_ipv6_bits.min(128) as u32;
+            let mask = u128::MAX.checked_shl(128 - bits).unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask))
+        }
+    }
+}
+
+/// Key for concurrent connections on one circuit
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CircuitKey {
+    connection: ConnectionKey,
+    circuit_id: String,
+}
+
 /// Connection information for rate limiting
 #[derive(Debug, Clone)]
 struct ConnectionInfo {
@@ -43,13 +175,61 @@ struct ConnectionInfo {
     first_connection: Instant,
 }
 
+/// Tracker entry as written by `save_state`; times are unix milliseconds
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedConnection {
+    onion: String,
+    client: IpAddr,
+    count: u32,
+    first_connection_ms: u64,
+    last_connection_ms: u64,
+}
+
+/// Maps `Instant`s to and from wall-clock time through a single reference point
+struct ClockAnchor {
+    instant: Instant,
+    unix_ms: u64,
+}
+
+impl ClockAnchor {
+    /// Pair `instant`, the current monotonic time, with the current wall-clock time
+    fn new(instant: Instant) -> Self {
+        Self {
+            instant,
+            unix_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+        }
+    }
+
+    fn to_unix_ms(&self, at: Instant) -> u64 {
+        let age = self.instant.saturating_duration_since(at).as_millis() as u64;
+        self.unix_ms.saturating_sub(age)
+    }
+
+    /// Times in the future, e.g. after the wall clock stepped back, clamp to now
+    fn to_instant(&self, unix_ms: u64) -> Instant {
+        let age = Duration::from_millis(self.unix_ms.saturating_sub(unix_ms));
+        self.instant.checked_sub(age).unwrap_or(self.instant)
+    }
+}
+
 /// Onion service protection configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct OnionServiceConfig {
     pub max_connections_per_ip: u32,
+    #[serde(with = "crate::serde_duration::secs")]
     pub connection_window: Duration,
     pub max_concurrent_connections: u32,
     pub enable_circuit_isolation: bool,
+    /// Concurrent connections allowed on one circuit when circuit isolation is enabled
+    pub max_connections_per_circuit: u32,
+    /// IPv4 prefix length clients are grouped by for rate limiting (32 = exact address)
+    pub subnet_ipv4_bits: u8,
+    /// IPv6 prefix length clients are grouped by for rate limiting (128 = exact address)
+    pub subnet_ipv6_bits: u8,
 }
 
 impl Default for OnionServiceConfig {
@@ -59,16 +239,32 @@ impl Default for OnionServiceConfig {
             connection_window: Duration::from_secs(60),
             max_concurrent_connections: 1000,
             enable_circuit_isolation: true,
+            max_connections_per_circuit: 10,
+            subnet_ipv4_bits: 32,
+            subnet_ipv6_bits: 128,
         }
     }
 }
 
+/// Registered onion services and their limits, for backups
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OnionServiceSnapshot {
+    protected_onions: Vec<(OnionAddress, OnionServiceConfig)>,
+}
+
 /// Main onion service protection system
 pub struct OnionServiceProtection {
     config: OnionServiceConfig,
-    connection_tracker: HashMap<IpAddr, ConnectionInfo>,
+    connection_tracker: HashMap<ConnectionKey, ConnectionInfo>,
+    circuit_connections: HashMap<CircuitKey, u32>,
     protected_onions: HashMap<OnionAddress, OnionServiceConfig>,
+    /// Onions refusing new connections while existing ones drain
+    maintenance: HashSet<OnionAddress>,
+    /// Open connections per onion, checked against its `max_concurrent_connections`
+    onion_connections: HashMap<OnionAddress, u32>,
+    /// Open connections across every onion
     active_connections: u32,
+    clock: Arc<dyn Clock>,
 }
 
 impl OnionServiceProtection {
@@ -79,30 +275,56 @@ impl OnionServiceProtection {
             connection_window: Duration::from_secs(tor_config.rate_limit_window_seconds),
             max_concurrent_connections: tor_config.max_requests_per_window,
             enable_circuit_isolation: true,
+            max_connections_per_circuit: tor_config.max_connections_per_circuit,
+            ..OnionServiceConfig::default()
         };
 
         Ok(Self {
             config,
             connection_tracker: HashMap::new(),
+            circuit_connections: HashMap::new(),
             protected_onions: HashMap::new(),
+            maintenance: HashSet::new(),
+            onion_connections: HashMap::new(),
             active_connections: 0,
+            clock: clock::system(),
         })
     }
 
+    /// Read the time from `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Re-derive the default limits from the shared configuration. Services
+    /// registered with their own configuration keep it.
+    pub fn apply_tor_config(&mut self, tor_config: &TorSecurityConfig) {
+        self.config.max_connections_per_ip = tor_config.max_connections_per_circuit;
+        self.config.connection_window = Duration::from_secs(tor_config.rate_limit_window_seconds);
+        self.config.max_concurrent_connections = tor_config.max_requests_per_window;
+        self.config.max_connections_per_circuit = tor_config.max_connections_per_circuit;
+    }
+
     /// Initialize the protection system
     pub fn initialize(&mut self) -> TorSecurityResult<()> {
         self.connection_tracker.clear();
+        self.circuit_connections.clear();
+        self.onion_connections.clear();
         self.active_connections = 0;
-        println!("Onion Service Protection initialized");
+        info!("Onion Service Protection initialized");
         Ok(())
     }
 
     /// Shutdown the protection system
     pub fn shutdown(&mut self) -> TorSecurityResult<()> {
         self.connection_tracker.clear();
+        self.circuit_connections.clear();
         self.protected_onions.clear();
+        self.maintenance.clear();
+        self.onion_connections.clear();
         self.active_connections = 0;
-        println!("Onion Service Protection shutdown");
+        info!("Onion Service Protection shutdown");
         Ok(())
     }
 
@@ -118,28 +340,104 @@ impl OnionServiceProtection {
         config: OnionServiceConfig,
     ) -> TorSecurityResult<()> {
         self.protected_onions.insert(address.clone(), config);
-        println!("Registered onion service: {}", address.as_str());
+        info!("Registered onion service: {}", address.as_str());
         Ok(())
     }
 
+    /// Put `address` into or out of maintenance. While in maintenance the onion
+    /// refuses new connections; connections already open keep running and are
+    /// closed as usual.
+    pub fn set_maintenance(&mut self, address: &OnionAddress, enabled: bool) {
+        if enabled {
+            if self.maintenance.insert(address.clone()) {
+                info!("Onion service {} entered maintenance", address.as_str());
+            }
+        } else if self.maintenance.remove(address) {
+            info!("Onion service {} left maintenance", address.as_str());
+        }
+    }
+
+    /// Whether `address` is refusing new connections for maintenance
+    pub fn in_maintenance(&self, address: &OnionAddress) -> bool {
+        self.maintenance.contains(address)
+    }
+
+    /// Capture the registered onion services for a backup
+    pub fn snapshot(&self) -> OnionServiceSnapshot {
+        OnionServiceSnapshot {
+            protected_onions: self.protected_onions.iter()
+                .map(|(address, config)| (address.clone(), config.clone()))
+                .collect(),
+        }
+    }
+
+    /// Replace the registered onion services with those in `snapshot`
+    pub fn restore_snapshot(&mut self, snapshot: OnionServiceSnapshot) {
+        self.protected_onions = snapshot.protected_onions.into_iter().collect();
+    }
+
     /// Check if a connection should be allowed
     pub fn should_allow_connection(
         &mut self,
         client_ip: IpAddr,
         onion_address: &OnionAddress,
     ) -> TorSecurityResult<bool> {
-        // Check if onion service is registered
-        let service_config = self.protected_onions.get(onion_address)
-            .unwrap_or(&self.config);
+        self.should_allow_connection_on_circuit(client_ip, onion_address, None)
+    }
 
-        // Check global connection limit
-        if self.active_connections >= service_config.max_concurrent_connections {
+    /// Check if a connection arriving on `circuit_id` should be allowed.
+    ///
+    /// The per-IP rate limit always counts every connection from the client,
+    /// whichever circuit it uses, so opening more circuits buys no extra
+    /// connections. With circuit isolation enabled and a circuit given, each
+    /// circuit additionally gets its own quota of `max_connections_per_circuit`
+    /// concurrent connections, so one busy circuit can't starve the client's
+    /// others. Close such connections with `connection_closed_for_circuit`.
+    pub fn should_allow_connection_on_circuit(
+        &mut self,
+        client_ip: IpAddr,
+        onion_address: &OnionAddress,
+        circuit_id: Option<&str>,
+    ) -> TorSecurityResult<bool> {
+        // Refuse before touching any counters so draining connections are unaffected
+        if self.maintenance.contains(onion_address) {
+            return Ok(false);
+        }
+
+        // Use the onion's own limits when registered; the config is small, so clone
+        // it rather than hold a borrow of `protected_onions` across the update
+        let service_config = self.config_for(onion_address);
+
+        // Check the onion's own concurrent connection limit
+        let open = self.onion_connections.get(onion_address).copied().unwrap_or(0);
+        if open >= service_config.max_concurrent_connections {
             return Ok(false);
         }
 
         // Check per-IP rate limiting
-        let now = Instant::now();
-        let connection_info = self.connection_tracker.entry(client_ip).or_insert(ConnectionInfo {
+        let now = self.clock.now();
+        let key = ConnectionKey {
+            onion: onion_address.clone(),
+            client: subnet_of(client_ip, &service_config),
+        };
+
+        // Check the circuit's concurrent quota before spending a rate limit slot
+        let circuit_key = match circuit_id {
+            Some(circuit_id) if service_config.enable_circuit_isolation => {
+                let circuit_key = CircuitKey {
+                    connection: key.clone(),
+                    circuit_id: circuit_id.to_string(),
+                };
+                let open = self.circuit_connections.get(&circuit_key).copied().unwrap_or(0);
+                if open >= service_config.max_connections_per_circuit {
+                    return Ok(false);
+                }
+                Some(circuit_key)
+            }
+            _ => None,
+        };
+
+        let connection_info = self.connection_tracker.entry(key).or_insert(ConnectionInfo {
             count: 0,
             last_connection: now,
             first_connection: now,
@@ -159,53 +457,194 @@ impl OnionServiceProtection {
         // Allow connection and update counters
         connection_info.count += 1;
         connection_info.last_connection = now;
+        *self.onion_connections.entry(onion_address.clone()).or_insert(0) += 1;
         self.active_connections += 1;
+        if let Some(circuit_key) = circuit_key {
+            *self.circuit_connections.entry(circuit_key).or_insert(0) += 1;
+        }
 
         Ok(true)
     }
 
-    /// Record connection closure
-    pub fn connection_closed(&mut self, _client_ip: IpAddr) {
-        if self.active_connections > 0 {
-            self.active_connections -= 1;
+    /// Limits that apply to an onion service, falling back to the defaults
+    fn config_for(&self, onion_address: &OnionAddress) -> OnionServiceConfig {
+        self.protected_onions
+            .get(onion_address)
+            .unwrap_or(&self.config)
+            .clone()
+    }
+
+    /// Record connection closure, freeing a slot of `onion_address`'s concurrent limit
+    pub fn connection_closed(&mut self, _client_ip: IpAddr, onion_address: &OnionAddress) {
+        if let Some(open) = self.onion_connections.get_mut(onion_address) {
+            *open = open.saturating_sub(1);
+            if *open == 0 {
+                self.onion_connections.remove(onion_address);
+            }
+            self.active_connections = self.active_connections.saturating_sub(1);
         }
     }
 
+    /// Record closure of a connection admitted on `circuit_id`, releasing its
+    /// slot in the circuit's quota
+    pub fn connection_closed_for_circuit(
+        &mut self,
+        client_ip: IpAddr,
+        onion_address: &OnionAddress,
+        circuit_id: &str,
+    ) {
+        let service_config = self.config_for(onion_address);
+        let circuit_key = CircuitKey {
+            connection: ConnectionKey {
+                onion: onion_address.clone(),
+                client: subnet_of(client_ip, &service_config),
+            },
+            circuit_id: circuit_id.to_string(),
+        };
+
+        if let Some(open) = self.circuit_connections.get_mut(&circuit_key) {
+            *open = open.saturating_sub(1);
+            if *open == 0 {
+                self.circuit_connections.remove(&circuit_key);
+            }
+        }
+        self.connection_closed(client_ip, onion_address);
+    }
+
     /// Clean up expired connection tracking data
     pub fn cleanup_expired_connections(&mut self) {
-        let now = Instant::now();
-        self.connection_tracker.retain(|_, info| {
-            now.duration_since(info.last_connection) < self.config.connection_window * 2
+        let now = self.clock.now();
+        let protected_onions = &self.protected_onions;
+        let default_window = self.config.connection_window;
+        self.connection_tracker.retain(|key, info| {
+            let window = protected_onions
+                .get(&key.onion)
+                .map_or(default_window, |config| config.connection_window);
+            now.duration_since(info.last_connection) < window * 2
         });
     }
 
+    /// Write the rate limit tracker to `path` as JSON so a restarted process
+    /// can pick up where this one left off. Open connections are not saved.
+    pub fn save_state(&self, path: impl AsRef<Path>) -> TorSecurityResult<()> {
+        let anchor = ClockAnchor::new(self.clock.now());
+        let saved: Vec<SavedConnection> = self
+            .connection_tracker
+            .iter()
+            .map(|(key, info)| SavedConnection {
+                onion: key.onion.as_str().to_string(),
+                client: key.client,
+                count: info.count,
+                first_connection_ms: anchor.to_unix_ms(info.first_connection),
+                last_connection_ms: anchor.to_unix_ms(info.last_connection),
+            })
+            .collect();
+
+        let json = serde_json::to_string(&saved).map_err(|e| {
+            TorSecurityError::ConfigurationError(format!("Failed to serialize onion state: {}", e))
+        })?;
+        fs::write(path.as_ref(), json).map_err(|e| {
+            TorSecurityError::ConfigurationError(format!(
+                "Failed to write onion state to {}: {}",
+                path.as_ref().display(),
+                e
+            ))
+        })
+    }
+
+    /// Restore a tracker written by `save_state`, merging it into the current
+    /// one. Entries whose window has already expired are dropped. Returns the
+    /// number of entries restored.
+    pub fn load_state(&mut self, path: impl AsRef<Path>) -> TorSecurityResult<usize> {
+        let json = fs::read_to_string(path.as_ref()).map_err(|e| {
+            TorSecurityError::ConfigurationError(format!(
+                "Failed to read onion state from {}: {}",
+                path.as_ref().display(),
+                e
+            ))
+        })?;
+        let saved: Vec<SavedConnection> = serde_json::from_str(&json).map_err(|e| {
+            TorSecurityError::ConfigurationError(format!("Malformed onion state: {}", e))
+        })?;
+
+        let anchor = ClockAnchor::new(self.clock.now());
+        let mut restored = 0;
+        for entry in saved {
+            let Ok(onion) = OnionAddress::new(entry.onion) else { continue };
+            let first_connection = anchor.to_instant(entry.first_connection_ms);
+            let window = self.config_for(&onion).connection_window;
+            if anchor.instant.duration_since(first_connection) > window {
+                continue;
+            }
+
+            self.connection_tracker.insert(
+                ConnectionKey { onion, client: entry.client },
+                ConnectionInfo {
+                    count: entry.count,
+                    first_connection,
+                    last_connection: anchor.to_instant(entry.last_connection_ms),
+                },
+            );
+            restored += 1;
+        }
+
+        info!("Restored {} onion connection tracking entries", restored);
+        Ok(restored)
+    }
+
     /// Get current connection statistics
     pub fn get_connection_stats(&self) -> ConnectionStats {
+        let mut onions_in_maintenance: Vec<OnionAddress> = self.maintenance.iter().cloned().collect();
+        onions_in_maintenance.sort_by(|a, b| a.as_str().cmp(b.as_str()));
         ConnectionStats {
             active_connections: self.active_connections,
             tracked_ips: self.connection_tracker.len(),
             protected_onions: self.protected_onions.len(),
+            onions_in_maintenance,
         }
     }
 }
 
 /// Connection statistics
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ConnectionStats {
     pub active_connections: u32,
     pub tracked_ips: usize,
     pub protected_onions: usize,
+    /// Onions currently refusing new connections, sorted by address
+    pub onions_in_maintenance: Vec<OnionAddress>,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// A valid v3 address derived from a repeated-byte key
+    fn test_onion(seed: u8) -> OnionAddress {
+        OnionAddress::from_public_key(&[seed; 32])
+    }
+
     #[test]
     fn test_onion_address_validation() {
-        assert!(OnionAddress::new("facebookcorewwwi.onion".to_string()).is_ok());
+        let valid = "aeaqcaibaeaqcaibaeaqcaibaeaqcaibaeaqcaibaeaqcaibaea37ead.onion";
+        assert!(OnionAddress::new(valid.to_string()).is_ok());
+        assert!(OnionAddress::new(valid.to_uppercase()).is_ok());
+        assert!(OnionAddress::new(format!("www.{}", valid)).is_ok());
+        assert_eq!(test_onion(1).as_str(), valid);
+
+        // v2 addresses, wrong TLDs and short labels are rejected
+        assert!(OnionAddress::new("facebookcorewwwi.onion".to_string()).is_err());
         assert!(OnionAddress::new("invalid.com".to_string()).is_err());
         assert!(OnionAddress::new("short.onion".to_string()).is_err());
+
+        // Flipping a character breaks the checksum
+        let tampered = valid.replacen('a', "b", 1);
+        assert!(matches!(
+            OnionAddress::new(tampered),
+            Err(TorSecurityError::InvalidOnionAddress(_))
+        ));
+        // Non-base32 characters
+        assert!(OnionAddress::new(valid.replacen('a', "1", 1)).is_err());
     }
 
     #[test]
@@ -214,7 +653,7 @@ mod tests {
         let mut protection = OnionServiceProtection::new(&config).unwrap();
         protection.initialize().unwrap();
 
-        let onion = OnionAddress::new("test1234567890123456.onion".to_string()).unwrap();
+        let onion = test_onion(1);
         protection.register_onion_service(onion.clone()).unwrap();
 
         let client_ip = "127.0.0.1".parse().unwrap();
@@ -222,4 +661,197 @@ mod tests {
         // First connection should be allowed
         assert!(protection.should_allow_connection(client_ip, &onion).unwrap());
     }
+
+    #[test]
+    fn test_per_onion_limits_are_independent() {
+        let config = TorSecurityConfig::default();
+        let mut protection = OnionServiceProtection::new(&config).unwrap();
+        protection.initialize().unwrap();
+
+        let strict = test_onion(2);
+        let relaxed = test_onion(3);
+        protection.register_onion_service_with_config(strict.clone(), OnionServiceConfig {
+            max_connections_per_ip: 1,
+            ..OnionServiceConfig::default()
+        }).unwrap();
+        protection.register_onion_service_with_config(relaxed.clone(), OnionServiceConfig {
+            max_connections_per_ip: 3,
+            ..OnionServiceConfig::default()
+        }).unwrap();
+
+        let client_ip = "127.0.0.1".parse().unwrap();
+
+        assert!(protection.should_allow_connection(client_ip, &strict).unwrap());
+        assert!(!protection.should_allow_connection(client_ip, &strict).unwrap());
+
+        for _ in 0..3 {
+            assert!(protection.should_allow_connection(client_ip, &relaxed).unwrap());
+        }
+        assert!(!protection.should_allow_connection(client_ip, &relaxed).unwrap());
+    }
+
+    #[test]
+    fn test_concurrent_limits_are_per_onion() {
+        let mut protection = OnionServiceProtection::new(&TorSecurityConfig::default()).unwrap();
+        protection.initialize().unwrap();
+
+        let busy = test_onion(8);
+        let quiet = test_onion(9);
+        protection.register_onion_service_with_config(busy.clone(), OnionServiceConfig {
+            max_concurrent_connections: 2,
+            ..OnionServiceConfig::default()
+        }).unwrap();
+        protection.register_onion_service_with_config(quiet.clone(), OnionServiceConfig {
+            max_concurrent_connections: 3,
+            ..OnionServiceConfig::default()
+        }).unwrap();
+        let client = |n: u8| IpAddr::V4(Ipv4Addr::new(10, 0, 0, n));
+
+        // Filling the busy onion leaves the quiet one its full quota
+        assert!(protection.should_allow_connection(client(1), &busy).unwrap());
+        assert!(protection.should_allow_connection(client(2), &busy).unwrap());
+        assert!(!protection.should_allow_connection(client(3), &busy).unwrap());
+        for n in 4..7 {
+            assert!(protection.should_allow_connection(client(n), &quiet).unwrap());
+        }
+        assert!(!protection.should_allow_connection(client(7), &quiet).unwrap());
+        assert_eq!(protection.get_connection_stats().active_connections, 5);
+
+        // Closing frees a slot on the onion it belonged to only
+        protection.connection_closed(client(4), &quiet);
+        assert!(!protection.should_allow_connection(client(3), &busy).unwrap());
+        protection.connection_closed(client(1), &busy);
+        assert!(protection.should_allow_connection(client(3), &busy).unwrap());
+        assert_eq!(protection.get_connection_stats().active_connections, 4);
+
+        // Closing on an onion with nothing open changes nothing
+        protection.connection_closed(client(1), &test_onion(10));
+        assert_eq!(protection.get_connection_stats().active_connections, 4);
+    }
+
+    #[test]
+    fn test_subnet_aggregation() {
+        let config = TorSecurityConfig::default();
+        let mut protection = OnionServiceProtection::new(&config).unwrap();
+        protection.initialize().unwrap();
+
+        let onion = test_onion(4);
+        protection.register_onion_service_with_config(onion.clone(), OnionServiceConfig {
+            max_connections_per_ip: 1,
+            subnet_ipv4_bits: 24,
+            subnet_ipv6_bits: 64,
+            ..OnionServiceConfig::default()
+        }).unwrap();
+
+        assert!(protection.should_allow_connection("10.0.0.5".parse().unwrap(), &onion).unwrap());
+        assert!(!protection.should_allow_connection("10.0.0.6".parse().unwrap(), &onion).unwrap());
+        assert!(protection.should_allow_connection("10.0.1.6".parse().unwrap(), &onion).unwrap());
+
+        assert!(protection.should_allow_connection("2001:db8::1".parse().unwrap(), &onion).unwrap());
+        assert!(!protection.should_allow_connection("2001:db8::ffff".parse().unwrap(), &onion).unwrap());
+
+        let exact = OnionServiceConfig::default();
+        let ip: IpAddr = "10.0.0.5".parse().unwrap();
+        assert_eq!(subnet_of(ip, &exact), ip);
+    }
+
+    #[test]
+    fn test_circuit_isolation() {
+        let config = TorSecurityConfig::default();
+        let mut protection = OnionServiceProtection::new(&config).unwrap();
+        protection.initialize().unwrap();
+
+        let onion = test_onion(5);
+        protection.register_onion_service_with_config(onion.clone(), OnionServiceConfig {
+            max_connections_per_ip: 3,
+            max_connections_per_circuit: 1,
+            ..OnionServiceConfig::default()
+        }).unwrap();
+        let client_ip = "127.0.0.1".parse().unwrap();
+
+        // Each circuit has its own concurrent quota
+        assert!(protection.should_allow_connection_on_circuit(client_ip, &onion, Some("a")).unwrap());
+        assert!(!protection.should_allow_connection_on_circuit(client_ip, &onion, Some("a")).unwrap());
+        assert!(protection.should_allow_connection_on_circuit(client_ip, &onion, Some("b")).unwrap());
+
+        // Closing frees the circuit's slot
+        protection.connection_closed_for_circuit(client_ip, &onion, "a");
+        assert!(protection.should_allow_connection_on_circuit(client_ip, &onion, Some("a")).unwrap());
+
+        // A fresh circuit doesn't get around the per-IP limit
+        assert!(!protection.should_allow_connection_on_circuit(client_ip, &onion, Some("c")).unwrap());
+        assert_eq!(protection.get_connection_stats().active_connections, 2);
+    }
+
+    #[test]
+    fn test_maintenance_refuses_only_new_connections() {
+        let config = TorSecurityConfig::default();
+        let mut protection = OnionServiceProtection::new(&config).unwrap();
+        protection.initialize().unwrap();
+
+        let draining = test_onion(6);
+        let other = test_onion(7);
+        protection.register_onion_service(draining.clone()).unwrap();
+        protection.register_onion_service(other.clone()).unwrap();
+        let client_ip = "127.0.0.1".parse().unwrap();
+
+        assert!(protection.should_allow_connection(client_ip, &draining).unwrap());
+        protection.set_maintenance(&draining, true);
+        assert!(protection.in_maintenance(&draining));
+        assert_eq!(protection.get_connection_stats().onions_in_maintenance, vec![draining.clone()]);
+
+        // New connections to the draining onion are refused, the other onion still admits
+        assert!(!protection.should_allow_connection(client_ip, &draining).unwrap());
+        assert!(protection.should_allow_connection(client_ip, &other).unwrap());
+        assert_eq!(protection.get_connection_stats().active_connections, 2);
+
+        // The connection opened before maintenance still closes normally
+        protection.connection_closed(client_ip, &draining);
+        assert_eq!(protection.get_connection_stats().active_connections, 1);
+
+        protection.set_maintenance(&draining, false);
+        assert!(protection.should_allow_connection(client_ip, &draining).unwrap());
+        assert!(protection.get_connection_stats().onions_in_maintenance.is_empty());
+    }
+
+    #[test]
+    fn test_state_round_trip_drops_expired() {
+        let config = TorSecurityConfig::default();
+        let mut protection = OnionServiceProtection::new(&config).unwrap();
+        protection.initialize().unwrap();
+
+        let onion = test_onion(6);
+        protection.register_onion_service_with_config(onion.clone(), OnionServiceConfig {
+            max_connections_per_ip: 2,
+            ..OnionServiceConfig::default()
+        }).unwrap();
+        let client_ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let stale_ip: IpAddr = "127.0.0.2".parse().unwrap();
+        assert!(protection.should_allow_connection(client_ip, &onion).unwrap());
+        assert!(protection.should_allow_connection(client_ip, &onion).unwrap());
+        assert!(protection.should_allow_connection(stale_ip, &onion).unwrap());
+
+        // Age the second client's window past expiry
+        let stale_key = ConnectionKey { onion: onion.clone(), client: stale_ip };
+        let long_ago = Instant::now() - Duration::from_secs(3600);
+        let stale = protection.connection_tracker.get_mut(&stale_key).unwrap();
+        stale.first_connection = long_ago;
+        stale.last_connection = long_ago;
+
+        let path = std::env::temp_dir().join(format!("rustwall-onion-state-{}.json", std::process::id()));
+        protection.save_state(&path).unwrap();
+
+        let mut restarted = OnionServiceProtection::new(&config).unwrap();
+        restarted.register_onion_service_with_config(onion.clone(), OnionServiceConfig {
+            max_connections_per_ip: 2,
+            ..OnionServiceConfig::default()
+        }).unwrap();
+        assert_eq!(restarted.load_state(&path).unwrap(), 1);
+        fs::remove_file(&path).unwrap();
+
+        // The restored client is still at its limit
+        assert!(!restarted.should_allow_connection(client_ip, &onion).unwrap());
+        assert!(restarted.should_allow_connection(stale_ip, &onion).unwrap());
+        assert!(restarted.load_state("/nonexistent/rustwall-state.json").is_err());
+    }
 }
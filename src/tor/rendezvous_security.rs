@@ -3,11 +3,21 @@
 //! Enhanced protection for Tor handshake processes and rendezvous point security.
 //! Monitors and protects against attacks on the hidden service rendezvous protocol.
 
-use crate::tor::{TorSecurityConfig, TorSecurityResult};
-use std::collections::{HashMap, VecDeque};
+use crate::clock::{self, Clock};
+use crate::tor::{TorSecurityConfig, TorSecurityError, TorSecurityResult};
+use log::info;
+use rand::Rng;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::net::IpAddr;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+/// Render a `Duration` as whole milliseconds
+fn serialize_ms<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
 /// Rendezvous point information
 #[derive(Debug, Clone)]
 pub struct RendezvousPoint {
@@ -34,7 +44,8 @@ pub struct HandshakeAttempt {
 }
 
 /// Rendezvous security threats
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RendezvousThreat {
     HandshakeFlooding,
     RendezvousCorruption,
@@ -44,43 +55,162 @@ pub enum RendezvousThreat {
     ServiceDiscovery,
 }
 
+/// Shape of the random delay added to handshakes, always kept within
+/// `min_handshake_delay..=max_handshake_delay`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DelayDistribution {
+    /// Every delay in the bounds is equally likely
+    #[default]
+    Uniform,
+    /// The minimum plus an exponential tail with the given mean, cut off at the
+    /// maximum; most handshakes are quick and a few are slow, like real latency
+    Exponential { mean_ms: u64 },
+    /// A bell curve around `mean_ms`, resampled when it falls outside the bounds
+    Normal { mean_ms: u64, std_dev_ms: u64 },
+}
+
+/// Draws before a normal delay outside the bounds is clamped instead
+const MAX_NORMAL_DRAWS: usize = 64;
+
+impl DelayDistribution {
+    pub fn sample(&self, min: Duration, max: Duration, rng: &mut impl Rng) -> Duration {
+        match *self {
+            DelayDistribution::Uniform => rng.gen_range(min..=max),
+            DelayDistribution::Exponential { mean_ms } => {
+                // Inverse CDF of the exponential truncated to the width of the bounds
+                let mean = mean_ms as f64 / 1000.0;
+                let width = (max - min).as_secs_f64();
+                let uniform: f64 = rng.r#gen();
+                let excess = -mean * (1.0 - uniform * (1.0 - (-width / mean).exp())).ln();
+                min + Duration::from_secs_f64(excess.clamp(0.0, width))
+            }
+            DelayDistribution::Normal { mean_ms, std_dev_ms } => {
+                let (mean, std_dev) = (mean_ms as f64 / 1000.0, std_dev_ms as f64 / 1000.0);
+                let (low, high) = (min.as_secs_f64(), max.as_secs_f64());
+                let mut delay = mean;
+                for _ in 0..MAX_NORMAL_DRAWS {
+                    // Box-Muller; 1 - U lies in (0, 1], keeping ln finite
+                    let radius = (-2.0 * (1.0 - rng.r#gen::<f64>()).ln()).sqrt();
+                    let angle = std::f64::consts::TAU * rng.r#gen::<f64>();
+                    delay = mean + std_dev * radius * angle.cos();
+                    if (low..=high).contains(&delay) {
+                        break;
+                    }
+                }
+                Duration::from_secs_f64(delay.clamp(low, high))
+            }
+        }
+    }
+
+    fn validate(&self, min: Duration, max: Duration) -> TorSecurityResult<()> {
+        match *self {
+            DelayDistribution::Exponential { mean_ms: 0 } => Err(TorSecurityError::ConfigurationError(
+                "Rendezvous exponential delay mean must be greater than zero".to_string(),
+            )),
+            DelayDistribution::Normal { std_dev_ms: 0, .. } => Err(TorSecurityError::ConfigurationError(
+                "Rendezvous normal delay standard deviation must be greater than zero".to_string(),
+            )),
+            DelayDistribution::Normal { mean_ms, .. } if !(min..=max).contains(&Duration::from_millis(mean_ms)) => {
+                Err(TorSecurityError::ConfigurationError(format!(
+                    "Rendezvous normal delay mean {}ms lies outside the delay bounds",
+                    mean_ms
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
 /// Rendezvous security configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RendezvousSecurityConfig {
     pub max_handshakes_per_minute: u32,
+    /// Cap on handshakes per minute across every rendezvous node combined
+    pub max_global_handshakes_per_minute: u32,
     pub max_failed_handshakes: u32,
+    #[serde(with = "crate::serde_duration::secs")]
     pub handshake_timeout: Duration,
+    #[serde(with = "crate::serde_duration::secs")]
     pub rendezvous_lifetime: Duration,
     pub enable_timing_protection: bool,
     pub enable_traffic_padding: bool,
+    /// Padded handshake payloads are rounded up to a multiple of this many bytes
+    pub padding_bucket_size: usize,
+    #[serde(with = "crate::serde_duration::millis")]
     pub min_handshake_delay: Duration,
+    #[serde(with = "crate::serde_duration::millis")]
     pub max_handshake_delay: Duration,
+    /// How delays are spread between the two bounds
+    pub delay_distribution: DelayDistribution,
     pub suspicious_failure_rate: f64,
+    /// A client/service circuit pair seen on more rendezvous nodes than this is treated as linking
+    pub max_nodes_per_circuit_pair: usize,
+    /// Window over which handshakes are examined for service discovery probing
+    #[serde(with = "crate::serde_duration::secs")]
+    pub probe_window: Duration,
+    /// Distinct client circuits within `probe_window` before probing is considered
+    pub min_probing_clients: usize,
+    /// Success rate at or below which many distinct clients look like probing
+    pub max_probe_success_rate: f64,
 }
 
 impl Default for RendezvousSecurityConfig {
     fn default() -> Self {
         Self {
             max_handshakes_per_minute: 60,
+            max_global_handshakes_per_minute: 600,
             max_failed_handshakes: 10,
             handshake_timeout: Duration::from_secs(30),
             rendezvous_lifetime: Duration::from_secs(600), // 10 minutes
             enable_timing_protection: true,
             enable_traffic_padding: true,
+            padding_bucket_size: 512,
             min_handshake_delay: Duration::from_millis(100),
             max_handshake_delay: Duration::from_millis(500),
+            delay_distribution: DelayDistribution::Uniform,
             suspicious_failure_rate: 0.5,
+            max_nodes_per_circuit_pair: 1,
+            probe_window: Duration::from_secs(60),
+            min_probing_clients: 20,
+            max_probe_success_rate: 0.05,
+        }
+    }
+}
+
+impl RendezvousSecurityConfig {
+    fn validate(&self) -> TorSecurityResult<()> {
+        if self.min_handshake_delay > self.max_handshake_delay {
+            return Err(TorSecurityError::ConfigurationError(format!(
+                "Rendezvous handshake delay bounds are inverted: min {:?} exceeds max {:?}",
+                self.min_handshake_delay, self.max_handshake_delay
+            )));
+        }
+        if self.padding_bucket_size == 0 {
+            return Err(TorSecurityError::ConfigurationError(
+                "Rendezvous padding bucket size must be greater than zero".to_string(),
+            ));
         }
+        self.delay_distribution.validate(self.min_handshake_delay, self.max_handshake_delay)
     }
 }
 
+/// Bytes used by the big-endian length prefix on framed handshake payloads
+const PADDING_LENGTH_PREFIX: usize = 4;
+
 /// Security metrics for monitoring
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SecurityMetrics {
     pub total_handshakes: u32,
     pub successful_handshakes: u32,
     pub failed_handshakes: u32,
+    /// Handshakes rejected by the global limit
+    pub global_rate_limited: u32,
+    /// Handshakes rejected by a rendezvous node's own limit
+    pub node_rate_limited: u32,
     pub detected_threats: Vec<RendezvousThreat>,
+    #[serde(rename = "average_handshake_time_ms", serialize_with = "serialize_ms")]
     pub average_handshake_time: Duration,
     pub suspicious_rendezvous_points: u32,
 }
@@ -94,6 +224,8 @@ pub struct RendezvousPointSecurity {
     threat_patterns: HashMap<RendezvousThreat, u32>,
     last_analysis: Instant,
     security_metrics: SecurityMetrics,
+    last_timing_delay: Option<Duration>,
+    clock: Arc<dyn Clock>,
 }
 
 impl RendezvousPointSecurity {
@@ -101,15 +233,19 @@ impl RendezvousPointSecurity {
     pub fn new(tor_config: &TorSecurityConfig) -> TorSecurityResult<Self> {
         let config = RendezvousSecurityConfig {
             max_handshakes_per_minute: tor_config.max_requests_per_window,
-            max_failed_handshakes: 10,
-            handshake_timeout: Duration::from_secs(30),
-            rendezvous_lifetime: Duration::from_secs(600),
-            enable_timing_protection: true,
-            enable_traffic_padding: true,
-            min_handshake_delay: Duration::from_millis(100),
-            max_handshake_delay: Duration::from_millis(500),
-            suspicious_failure_rate: 0.5,
+            ..RendezvousSecurityConfig::default()
         };
+        Self::with_config(config)
+    }
+
+    /// Re-derive the limits taken from the shared configuration, keeping handshake history
+    pub fn apply_tor_config(&mut self, tor_config: &TorSecurityConfig) {
+        self.config.max_handshakes_per_minute = tor_config.max_requests_per_window;
+    }
+
+    /// Create a rendezvous point security instance with custom configuration
+    pub fn with_config(config: RendezvousSecurityConfig) -> TorSecurityResult<Self> {
+        config.validate()?;
 
         Ok(Self {
             config,
@@ -122,29 +258,43 @@ impl RendezvousPointSecurity {
                 total_handshakes: 0,
                 successful_handshakes: 0,
                 failed_handshakes: 0,
+                global_rate_limited: 0,
+                node_rate_limited: 0,
                 detected_threats: Vec::new(),
                 average_handshake_time: Duration::default(),
                 suspicious_rendezvous_points: 0,
             },
+            last_timing_delay: None,
+            clock: clock::system(),
         })
     }
 
+    /// Read the time from `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.last_analysis = clock.now();
+        self.clock = clock;
+        self
+    }
+
     /// Initialize the rendezvous point security system
     pub fn initialize(&mut self) -> TorSecurityResult<()> {
         self.rendezvous_points.clear();
         self.handshake_history.clear();
         self.timing_samples.clear();
         self.threat_patterns.clear();
-        self.last_analysis = Instant::now();
+        self.last_analysis = self.clock.now();
+        self.last_timing_delay = None;
         self.security_metrics = SecurityMetrics {
             total_handshakes: 0,
             successful_handshakes: 0,
             failed_handshakes: 0,
+            global_rate_limited: 0,
+            node_rate_limited: 0,
             detected_threats: Vec::new(),
             average_handshake_time: Duration::default(),
             suspicious_rendezvous_points: 0,
         };
-        println!("Rendezvous Point Security initialized");
+        info!("Rendezvous Point Security initialized");
         Ok(())
     }
 
@@ -154,7 +304,7 @@ impl RendezvousPointSecurity {
         self.handshake_history.clear();
         self.timing_samples.clear();
         self.threat_patterns.clear();
-        println!("Rendezvous Point Security shutdown");
+        info!("Rendezvous Point Security shutdown");
         Ok(())
     }
 
@@ -164,7 +314,7 @@ impl RendezvousPointSecurity {
         node_id: String,
         ip_address: Option<IpAddr>,
     ) -> TorSecurityResult<()> {
-        let now = Instant::now();
+        let now = self.clock.now();
         
         let rendezvous_point = RendezvousPoint {
             node_id: node_id.clone(),
@@ -178,12 +328,16 @@ impl RendezvousPointSecurity {
         };
 
         self.rendezvous_points.insert(node_id.clone(), rendezvous_point);
-        println!("Registered rendezvous point: {}", node_id);
+        info!("Registered rendezvous point: {}", node_id);
         Ok(())
     }
 
-    /// Process a handshake attempt
-    pub fn process_handshake_attempt(
+    /// Admit and record a handshake attempt without waiting out the timing
+    /// delay. Returns `None` when rate limited, otherwise how long to wait
+    /// before answering, zero with timing protection off. Callers sharing this
+    /// behind a lock release it before waiting, as
+    /// `TorSecurityManager::process_rendezvous_handshake` does.
+    pub fn admit_handshake_attempt(
         &mut self,
         rendezvous_node: String,
         client_circuit: Option<String>,
@@ -191,35 +345,86 @@ impl RendezvousPointSecurity {
         success: bool,
         failure_reason: Option<String>,
         response_time: Duration,
-    ) -> TorSecurityResult<bool> {
-        let now = Instant::now();
+    ) -> TorSecurityResult<Option<Duration>> {
+        let now = self.clock.now();
 
         // Check rate limiting
-        if !self.check_handshake_rate_limit(&rendezvous_node, now)? {
-            return Ok(false);
+        if !self.admit_handshake(&rendezvous_node, now)? {
+            return Ok(None);
         }
 
-        // Apply timing protection if enabled
-        if self.config.enable_timing_protection {
-            self.apply_timing_protection(response_time)?;
+        self.record_handshake(HandshakeAttempt {
+            timestamp: now,
+            rendezvous_node,
+            client_circuit,
+            service_circuit,
+            success,
+            failure_reason,
+            response_time,
+        })?;
+        Ok(Some(self.apply_timing_protection().unwrap_or_default()))
+    }
+
+    /// Process a handshake attempt, delaying asynchronously when timing protection is enabled.
+    /// This borrows `self` for the whole delay; through `TorSecurityManager` use
+    /// `process_rendezvous_handshake`, which doesn't hold the lock while waiting.
+    pub async fn process_handshake_attempt(
+        &mut self,
+        rendezvous_node: String,
+        client_circuit: Option<String>,
+        service_circuit: Option<String>,
+        success: bool,
+        failure_reason: Option<String>,
+        response_time: Duration,
+    ) -> TorSecurityResult<bool> {
+        let admitted = self.admit_handshake_attempt(
+            rendezvous_node,
+            client_circuit,
+            service_circuit,
+            success,
+            failure_reason,
+            response_time,
+        )?;
+        let Some(delay) = admitted else { return Ok(false) };
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
         }
+        Ok(true)
+    }
 
-        // Record the handshake attempt
-        let attempt = HandshakeAttempt {
-            timestamp: now,
-            rendezvous_node: rendezvous_node.clone(),
+    /// Process a handshake attempt from a non-async caller, blocking the thread for the timing delay
+    #[cfg(feature = "blocking-timing")]
+    pub fn process_handshake_attempt_blocking(
+        &mut self,
+        rendezvous_node: String,
+        client_circuit: Option<String>,
+        service_circuit: Option<String>,
+        success: bool,
+        failure_reason: Option<String>,
+        response_time: Duration,
+    ) -> TorSecurityResult<bool> {
+        let admitted = self.admit_handshake_attempt(
+            rendezvous_node,
             client_circuit,
             service_circuit,
             success,
-            failure_reason: failure_reason.clone(),
+            failure_reason,
             response_time,
-        };
+        )?;
+        let Some(delay) = admitted else { return Ok(false) };
+        std::thread::sleep(delay);
+        Ok(true)
+    }
 
-        self.handshake_history.push_back(attempt);
-        self.timing_samples.push_back(response_time);
+    /// Record an admitted handshake attempt and update statistics
+    fn record_handshake(&mut self, attempt: HandshakeAttempt) -> TorSecurityResult<()> {
+        let now = attempt.timestamp;
+        let success = attempt.success;
+
+        self.timing_samples.push_back(attempt.response_time);
 
         // Update rendezvous point statistics
-        if let Some(rp) = self.rendezvous_points.get_mut(&rendezvous_node) {
+        if let Some(rp) = self.rendezvous_points.get_mut(&attempt.rendezvous_node) {
             rp.handshake_count += 1;
             rp.last_activity = now;
             
@@ -234,6 +439,8 @@ impl RendezvousPointSecurity {
             }
         }
 
+        self.handshake_history.push_back(attempt);
+
         // Update security metrics
         self.security_metrics.total_handshakes += 1;
         if success {
@@ -248,9 +455,31 @@ impl RendezvousPointSecurity {
             self.last_analysis = now;
         }
 
+        Ok(())
+    }
+
+    /// Apply the global and then the per-node handshake limits, counting which one rejected
+    fn admit_handshake(&mut self, rendezvous_node: &str, now: Instant) -> TorSecurityResult<bool> {
+        if !self.check_global_handshake_rate_limit(now)? {
+            self.security_metrics.global_rate_limited += 1;
+            return Ok(false);
+        }
+        if !self.check_handshake_rate_limit(rendezvous_node, now)? {
+            self.security_metrics.node_rate_limited += 1;
+            return Ok(false);
+        }
         Ok(true)
     }
 
+    /// Check handshake rate limiting across all rendezvous nodes
+    fn check_global_handshake_rate_limit(&self, now: Instant) -> TorSecurityResult<bool> {
+        let recent_handshakes = self.handshake_history.iter()
+            .filter(|attempt| now.duration_since(attempt.timestamp) < Duration::from_secs(60))
+            .count();
+
+        Ok(recent_handshakes < self.config.max_global_handshakes_per_minute as usize)
+    }
+
     /// Check handshake rate limiting
     fn check_handshake_rate_limit(
         &self,
@@ -267,20 +496,21 @@ impl RendezvousPointSecurity {
         Ok(recent_handshakes < self.config.max_handshakes_per_minute as usize)
     }
 
-    /// Apply timing protection to prevent timing attacks
-    fn apply_timing_protection(&self, _response_time: Duration) -> TorSecurityResult<()> {
-        // In a real implementation, this would add random delays
-        // and normalize response times to prevent timing attacks
-        
-        // Simulate processing time (in real implementation, this would be actual delay)
-        std::thread::sleep(Duration::from_millis(10));
-        
-        Ok(())
+    /// Pick the delay to apply before answering a handshake, if timing protection is enabled
+    fn apply_timing_protection(&mut self) -> Option<Duration> {
+        if !self.config.enable_timing_protection {
+            return None;
+        }
+
+        // A fresh random delay per handshake hides the real processing time
+        let delay = self.generate_timing_delay();
+        self.last_timing_delay = Some(delay);
+        Some(delay)
     }
 
     /// Analyze threats and update security metrics
     fn analyze_threats(&mut self) -> TorSecurityResult<()> {
-        let now = Instant::now();
+        let now = self.clock.now();
         let mut detected_threats = Vec::new();
 
         // Analyze handshake flooding
@@ -294,11 +524,11 @@ impl RendezvousPointSecurity {
         }
 
         // Analyze timing patterns
-        if self.timing_samples.len() > 10 {
-            if let Some(timing_threat) = self.analyze_timing_patterns() {
-                detected_threats.push(timing_threat.clone());
-                *self.threat_patterns.entry(timing_threat).or_insert(0) += 1;
-            }
+        if self.timing_samples.len() > 10
+            && let Some(timing_threat) = self.analyze_timing_patterns()
+        {
+            detected_threats.push(timing_threat.clone());
+            *self.threat_patterns.entry(timing_threat).or_insert(0) += 1;
         }
 
         // Analyze rendezvous corruption
@@ -314,6 +544,18 @@ impl RendezvousPointSecurity {
             *self.threat_patterns.entry(RendezvousThreat::RendezvousCorruption).or_insert(0) += 1;
         }
 
+        // Analyze circuit linking
+        if self.detect_circuit_linking() {
+            detected_threats.push(RendezvousThreat::CircuitLinking);
+            *self.threat_patterns.entry(RendezvousThreat::CircuitLinking).or_insert(0) += 1;
+        }
+
+        // Analyze service discovery probing
+        if self.detect_service_discovery(now) {
+            detected_threats.push(RendezvousThreat::ServiceDiscovery);
+            *self.threat_patterns.entry(RendezvousThreat::ServiceDiscovery).or_insert(0) += 1;
+        }
+
         // Update security metrics
         self.security_metrics.detected_threats = detected_threats;
         self.security_metrics.suspicious_rendezvous_points = self.rendezvous_points.values()
@@ -332,6 +574,40 @@ impl RendezvousPointSecurity {
         Ok(())
     }
 
+    /// Check whether a client/service circuit pair recurs across rendezvous nodes
+    fn detect_circuit_linking(&self) -> bool {
+        let mut nodes_per_pair: HashMap<(&str, &str), HashSet<&str>> = HashMap::new();
+
+        for attempt in &self.handshake_history {
+            if let (Some(client), Some(service)) = (&attempt.client_circuit, &attempt.service_circuit) {
+                nodes_per_pair
+                    .entry((client.as_str(), service.as_str()))
+                    .or_default()
+                    .insert(attempt.rendezvous_node.as_str());
+            }
+        }
+
+        nodes_per_pair.values().any(|nodes| nodes.len() > self.config.max_nodes_per_circuit_pair)
+    }
+
+    /// Check for many distinct clients probing the service with almost no successful handshakes
+    fn detect_service_discovery(&self, now: Instant) -> bool {
+        let recent: Vec<_> = self.handshake_history.iter()
+            .filter(|attempt| now.duration_since(attempt.timestamp) < self.config.probe_window)
+            .collect();
+
+        let distinct_clients = recent.iter()
+            .filter_map(|attempt| attempt.client_circuit.as_deref())
+            .collect::<HashSet<_>>()
+            .len();
+        if distinct_clients < self.config.min_probing_clients {
+            return false;
+        }
+
+        let successes = recent.iter().filter(|attempt| attempt.success).count();
+        successes as f64 / recent.len() as f64 <= self.config.max_probe_success_rate
+    }
+
     /// Analyze timing patterns for potential attacks
     fn analyze_timing_patterns(&self) -> Option<RendezvousThreat> {
         // Simple timing analysis - in practice this would be more sophisticated
@@ -368,21 +644,47 @@ impl RendezvousPointSecurity {
         None
     }
 
-    /// Generate random delay for timing protection
-    pub fn generate_timing_delay(&self) -> Duration {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
+    /// Frame handshake data with a length prefix, padding it with random bytes up to the
+    /// next bucket boundary when traffic padding is enabled
+    pub fn pad_handshake_payload(&self, data: &[u8]) -> Vec<u8> {
+        use rand::RngCore;
 
-        // Simple pseudo-random delay generation
-        let mut hasher = DefaultHasher::new();
-        Instant::now().elapsed().hash(&mut hasher);
-        let hash = hasher.finish();
-        
-        let delay_range = self.config.max_handshake_delay.as_millis() 
-            - self.config.min_handshake_delay.as_millis();
-        let random_delay = (hash % delay_range as u64) as u64;
-        
-        self.config.min_handshake_delay + Duration::from_millis(random_delay)
+        let framed_len = PADDING_LENGTH_PREFIX + data.len();
+        let target_len = if self.config.enable_traffic_padding {
+            framed_len.div_ceil(self.config.padding_bucket_size) * self.config.padding_bucket_size
+        } else {
+            framed_len
+        };
+
+        let mut padded = Vec::with_capacity(target_len);
+        padded.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        padded.extend_from_slice(data);
+        padded.resize(target_len, 0);
+        rand::thread_rng().fill_bytes(&mut padded[framed_len..]);
+        padded
+    }
+
+    /// Recover the original handshake data from a payload framed by `pad_handshake_payload`
+    pub fn strip_padding(&self, padded: &[u8]) -> TorSecurityResult<Vec<u8>> {
+        let (prefix, rest) = padded.split_first_chunk::<PADDING_LENGTH_PREFIX>().ok_or_else(|| {
+            TorSecurityError::SecurityViolation("Padded handshake payload is missing its length prefix".to_string())
+        })?;
+
+        let len = u32::from_be_bytes(*prefix) as usize;
+        rest.get(..len).map(<[u8]>::to_vec).ok_or_else(|| {
+            TorSecurityError::SecurityViolation(format!(
+                "Padded handshake payload declares {} bytes but carries only {}",
+                len,
+                rest.len()
+            ))
+        })
+    }
+
+    /// Generate random delay for timing protection, shaped by `delay_distribution`
+    pub fn generate_timing_delay(&self) -> Duration {
+        let min = self.config.min_handshake_delay;
+        let max = self.config.max_handshake_delay;
+        self.config.delay_distribution.sample(min, max, &mut rand::thread_rng())
     }
 
     /// Clean up old tracking data
@@ -421,12 +723,21 @@ impl RendezvousPointSecurity {
         &self.security_metrics
     }
 
+    /// Export security metrics and rendezvous statistics as JSON for monitoring
+    pub fn metrics_json(&self) -> String {
+        let metrics = serde_json::json!({
+            "security": self.get_security_stats(),
+            "rendezvous": self.get_rendezvous_stats(),
+        });
+        metrics.to_string()
+    }
+
     /// Get rendezvous point statistics
     pub fn get_rendezvous_stats(&self) -> RendezvousStats {
         RendezvousStats {
             total_rendezvous_points: self.rendezvous_points.len(),
             active_rendezvous_points: self.rendezvous_points.values()
-                .filter(|rp| rp.last_activity.elapsed() < Duration::from_secs(300))
+                .filter(|rp| self.clock.now().saturating_duration_since(rp.last_activity) < Duration::from_secs(300))
                 .count(),
             suspicious_rendezvous_points: self.rendezvous_points.values()
                 .filter(|rp| rp.is_suspicious)
@@ -439,7 +750,7 @@ impl RendezvousPointSecurity {
 }
 
 /// Rendezvous statistics
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct RendezvousStats {
     pub total_rendezvous_points: usize,
     pub active_rendezvous_points: usize,
@@ -452,6 +763,7 @@ pub struct RendezvousStats {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::MockClock;
 
     #[test]
     fn test_rendezvous_security_creation() {
@@ -473,8 +785,8 @@ mod tests {
         assert!(result.is_ok());
     }
 
-    #[test]
-    fn test_handshake_processing() {
+    #[tokio::test]
+    async fn test_handshake_processing() {
         let config = TorSecurityConfig::default();
         let mut security = RendezvousPointSecurity::new(&config).unwrap();
         security.initialize().unwrap();
@@ -491,11 +803,251 @@ mod tests {
             true,
             None,
             Duration::from_millis(200),
-        );
+        ).await;
         assert!(result.is_ok());
         assert!(result.unwrap());
     }
 
+    #[test]
+    fn test_with_config() {
+        let config = RendezvousSecurityConfig {
+            suspicious_failure_rate: 0.25,
+            handshake_timeout: Duration::from_secs(10),
+            min_handshake_delay: Duration::from_millis(50),
+            max_handshake_delay: Duration::from_millis(50),
+            ..RendezvousSecurityConfig::default()
+        };
+        let security = RendezvousPointSecurity::with_config(config).unwrap();
+        assert_eq!(security.config.suspicious_failure_rate, 0.25);
+        assert_eq!(security.config.handshake_timeout, Duration::from_secs(10));
+        assert_eq!(security.generate_timing_delay(), Duration::from_millis(50));
+
+        let inverted = RendezvousSecurityConfig {
+            min_handshake_delay: Duration::from_millis(500),
+            max_handshake_delay: Duration::from_millis(100),
+            ..RendezvousSecurityConfig::default()
+        };
+        assert!(matches!(
+            RendezvousPointSecurity::with_config(inverted),
+            Err(TorSecurityError::ConfigurationError(_))
+        ));
+
+        let tor_config = TorSecurityConfig::default();
+        let derived = RendezvousPointSecurity::new(&tor_config).unwrap();
+        assert_eq!(derived.config.max_handshakes_per_minute, tor_config.max_requests_per_window);
+    }
+
+    #[tokio::test]
+    async fn test_applied_delay_within_bounds() {
+        let mut security = RendezvousPointSecurity::with_config(RendezvousSecurityConfig {
+            min_handshake_delay: Duration::from_millis(20),
+            max_handshake_delay: Duration::from_millis(40),
+            ..RendezvousSecurityConfig::default()
+        }).unwrap();
+
+        for _ in 0..3 {
+            let started = Instant::now();
+            let admitted = security.process_handshake_attempt(
+                "test_node".to_string(),
+                None,
+                None,
+                true,
+                None,
+                Duration::from_millis(200),
+            ).await.unwrap();
+            assert!(admitted);
+
+            let delay = security.last_timing_delay.unwrap();
+            assert!(delay >= security.config.min_handshake_delay);
+            assert!(delay <= security.config.max_handshake_delay);
+            assert!(started.elapsed() >= delay);
+        }
+
+        security.config.enable_timing_protection = false;
+        assert!(security.apply_timing_protection().is_none());
+    }
+
+    #[cfg(feature = "blocking-timing")]
+    #[test]
+    fn test_blocking_handshake_processing() {
+        let mut security = RendezvousPointSecurity::with_config(RendezvousSecurityConfig {
+            min_handshake_delay: Duration::from_millis(5),
+            max_handshake_delay: Duration::from_millis(10),
+            ..RendezvousSecurityConfig::default()
+        }).unwrap();
+
+        let started = Instant::now();
+        let admitted = security.process_handshake_attempt_blocking(
+            "test_node".to_string(),
+            None,
+            None,
+            false,
+            Some("timeout".to_string()),
+            Duration::from_millis(200),
+        ).unwrap();
+        assert!(admitted);
+        assert!(started.elapsed() >= security.config.min_handshake_delay);
+        assert_eq!(security.get_security_stats().failed_handshakes, 1);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_limit_resets_after_a_minute() {
+        let clock = MockClock::new();
+        let mut security = RendezvousPointSecurity::with_config(RendezvousSecurityConfig {
+            max_handshakes_per_minute: 2,
+            enable_timing_protection: false,
+            ..RendezvousSecurityConfig::default()
+        })
+        .unwrap()
+        .with_clock(Arc::new(clock.clone()));
+
+        let handshake = async |security: &mut RendezvousPointSecurity| {
+            security
+                .process_handshake_attempt("node".to_string(), None, None, true, None, Duration::from_millis(200))
+                .await
+                .unwrap()
+        };
+        assert!(handshake(&mut security).await);
+        assert!(handshake(&mut security).await);
+        assert!(!handshake(&mut security).await);
+
+        clock.advance(Duration::from_secs(59));
+        assert!(!handshake(&mut security).await);
+        clock.advance(Duration::from_secs(1));
+        assert!(handshake(&mut security).await);
+        assert_eq!(security.get_security_stats().node_rate_limited, 2);
+    }
+
+    fn attempt(node: &str, client: &str, service: &str, success: bool) -> HandshakeAttempt {
+        HandshakeAttempt {
+            timestamp: Instant::now(),
+            rendezvous_node: node.to_string(),
+            client_circuit: Some(client.to_string()),
+            service_circuit: Some(service.to_string()),
+            success,
+            failure_reason: None,
+            response_time: Duration::from_millis(200),
+        }
+    }
+
+    #[test]
+    fn test_circuit_linking_detection() {
+        let config = TorSecurityConfig::default();
+        let mut security = RendezvousPointSecurity::new(&config).unwrap();
+
+        security.handshake_history.push_back(attempt("node_a", "client_1", "service_1", true));
+        security.handshake_history.push_back(attempt("node_a", "client_1", "service_1", true));
+        security.handshake_history.push_back(attempt("node_b", "client_2", "service_1", true));
+        security.analyze_threats().unwrap();
+        assert!(!security.get_security_stats().detected_threats.contains(&RendezvousThreat::CircuitLinking));
+
+        security.handshake_history.push_back(attempt("node_c", "client_1", "service_1", true));
+        security.analyze_threats().unwrap();
+        assert!(security.get_security_stats().detected_threats.contains(&RendezvousThreat::CircuitLinking));
+        assert_eq!(security.threat_patterns[&RendezvousThreat::CircuitLinking], 1);
+    }
+
+    #[test]
+    fn test_service_discovery_detection() {
+        let config = TorSecurityConfig::default();
+        let mut security = RendezvousPointSecurity::new(&config).unwrap();
+
+        for i in 0..19 {
+            security.handshake_history.push_back(attempt("node_a", &format!("client_{}", i), "service_1", false));
+        }
+        security.analyze_threats().unwrap();
+        assert!(!security.get_security_stats().detected_threats.contains(&RendezvousThreat::ServiceDiscovery));
+
+        security.handshake_history.push_back(attempt("node_a", "client_19", "service_1", false));
+        security.analyze_threats().unwrap();
+        assert!(security.get_security_stats().detected_threats.contains(&RendezvousThreat::ServiceDiscovery));
+
+        // Plenty of successful handshakes means real clients, not probing
+        for i in 0..10 {
+            security.handshake_history.push_back(attempt("node_a", &format!("client_{}", i), "service_1", true));
+        }
+        security.analyze_threats().unwrap();
+        assert!(!security.get_security_stats().detected_threats.contains(&RendezvousThreat::ServiceDiscovery));
+    }
+
+    #[test]
+    fn test_handshake_padding() {
+        let config = TorSecurityConfig::default();
+        let mut security = RendezvousPointSecurity::new(&config).unwrap();
+
+        for len in [0, 10, 508, 509, 1500] {
+            let data = vec![0xab; len];
+            let padded = security.pad_handshake_payload(&data);
+            assert_eq!(padded.len() % 512, 0);
+            assert!(padded.len() >= len + PADDING_LENGTH_PREFIX);
+            assert_eq!(security.strip_padding(&padded).unwrap(), data);
+        }
+        assert_eq!(security.pad_handshake_payload(&[1; 10]).len(), 512);
+        assert_eq!(security.pad_handshake_payload(&[1; 509]).len(), 1024);
+
+        assert!(security.strip_padding(&[0, 0]).is_err());
+        assert!(security.strip_padding(&[0, 0, 0, 9, 1, 2]).is_err());
+
+        security.config.enable_traffic_padding = false;
+        let framed = security.pad_handshake_payload(b"hello");
+        assert_eq!(framed.len(), 5 + PADDING_LENGTH_PREFIX);
+        assert_eq!(security.strip_padding(&framed).unwrap(), b"hello");
+
+        let zero_bucket = RendezvousSecurityConfig {
+            padding_bucket_size: 0,
+            ..RendezvousSecurityConfig::default()
+        };
+        assert!(RendezvousPointSecurity::with_config(zero_bucket).is_err());
+    }
+
+    #[test]
+    fn test_metrics_json() {
+        let config = TorSecurityConfig::default();
+        let mut security = RendezvousPointSecurity::new(&config).unwrap();
+        security.handshake_history.push_back(attempt("node_a", "client_1", "service_1", true));
+        security.handshake_history.push_back(attempt("node_b", "client_1", "service_1", true));
+        security.timing_samples.push_back(Duration::from_millis(250));
+        security.analyze_threats().unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&security.metrics_json()).unwrap();
+        assert_eq!(json["security"]["detected_threats"], serde_json::json!(["circuit_linking"]));
+        assert_eq!(json["security"]["average_handshake_time_ms"], 250);
+        assert_eq!(json["rendezvous"]["threat_detections"], 1);
+    }
+
+    async fn handshake(security: &mut RendezvousPointSecurity, node: &str) -> bool {
+        security.process_handshake_attempt(
+            node.to_string(),
+            None,
+            None,
+            true,
+            None,
+            Duration::from_millis(200),
+        ).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_global_and_node_rate_limits() {
+        let mut security = RendezvousPointSecurity::with_config(RendezvousSecurityConfig {
+            max_handshakes_per_minute: 2,
+            max_global_handshakes_per_minute: 3,
+            enable_timing_protection: false,
+            ..RendezvousSecurityConfig::default()
+        }).unwrap();
+
+        assert!(handshake(&mut security, "node_a").await);
+        assert!(handshake(&mut security, "node_a").await);
+        assert!(!handshake(&mut security, "node_a").await);
+        assert_eq!(security.get_security_stats().node_rate_limited, 1);
+
+        // Each node stays under its own limit, but together they hit the global cap
+        assert!(handshake(&mut security, "node_b").await);
+        assert!(!handshake(&mut security, "node_c").await);
+        assert_eq!(security.get_security_stats().global_rate_limited, 1);
+        assert_eq!(security.get_security_stats().node_rate_limited, 1);
+        assert_eq!(security.get_security_stats().total_handshakes, 3);
+    }
+
     #[test]
     fn test_timing_delay_generation() {
         let config = TorSecurityConfig::default();
@@ -505,4 +1057,61 @@ mod tests {
         assert!(delay >= security.config.min_handshake_delay);
         assert!(delay <= security.config.max_handshake_delay);
     }
+
+    #[test]
+    fn test_delay_distributions_have_their_shape() {
+        let (min, max) = (Duration::from_millis(100), Duration::from_millis(500));
+        let mut rng = rand::thread_rng();
+        let samples = |distribution: DelayDistribution, rng: &mut rand::rngs::ThreadRng| -> Vec<f64> {
+            (0..5000).map(|_| {
+                let delay = distribution.sample(min, max, rng);
+                assert!((min..=max).contains(&delay), "{:?} out of bounds", delay);
+                delay.as_secs_f64() * 1000.0
+            }).collect()
+        };
+        let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+        let share = |values: &[f64], range: std::ops::Range<f64>| {
+            values.iter().filter(|value| range.contains(value)).count() as f64 / values.len() as f64
+        };
+
+        // Uniform: centred, each quarter of the range equally full
+        let uniform = samples(DelayDistribution::Uniform, &mut rng);
+        assert!((290.0..310.0).contains(&mean(&uniform)), "uniform mean {}", mean(&uniform));
+        assert!((0.22..0.28).contains(&share(&uniform, 100.0..200.0)));
+
+        // Exponential: 50ms mean above the minimum, 1 - 1/e within one mean
+        let exponential = samples(DelayDistribution::Exponential { mean_ms: 50 }, &mut rng);
+        assert!((145.0..155.0).contains(&mean(&exponential)), "exponential mean {}", mean(&exponential));
+        assert!((0.60..0.66).contains(&share(&exponential, 100.0..150.0)));
+
+        // Normal: 68% within one standard deviation, thin tails
+        let normal = samples(DelayDistribution::Normal { mean_ms: 300, std_dev_ms: 50 }, &mut rng);
+        assert!((295.0..305.0).contains(&mean(&normal)), "normal mean {}", mean(&normal));
+        assert!((0.65..0.72).contains(&share(&normal, 250.0..350.0)));
+        assert!(share(&normal, 100.0..150.0) < 0.01);
+
+        // A wide bell curve is truncated, not clamped onto the bounds
+        let wide = samples(DelayDistribution::Normal { mean_ms: 300, std_dev_ms: 1000 }, &mut rng);
+        assert!(wide.iter().filter(|value| **value == 100.0 || **value == 500.0).count() < 5);
+    }
+
+    #[test]
+    fn test_delay_distribution_validation() {
+        let config = |delay_distribution| RendezvousSecurityConfig { delay_distribution, ..RendezvousSecurityConfig::default() };
+        assert!(RendezvousPointSecurity::with_config(config(DelayDistribution::Exponential { mean_ms: 80 })).is_ok());
+        for invalid in [
+            DelayDistribution::Exponential { mean_ms: 0 },
+            DelayDistribution::Normal { mean_ms: 300, std_dev_ms: 0 },
+            DelayDistribution::Normal { mean_ms: 900, std_dev_ms: 50 },
+        ] {
+            assert!(matches!(
+                RendezvousPointSecurity::with_config(config(invalid)),
+                Err(TorSecurityError::ConfigurationError(_))
+            ));
+        }
+
+        let parsed: RendezvousSecurityConfig =
+            serde_json::from_str(r#"{"delay_distribution": {"normal": {"mean_ms": 250, "std_dev_ms": 40}}}"#).unwrap();
+        assert_eq!(parsed.delay_distribution, DelayDistribution::Normal { mean_ms: 250, std_dev_ms: 40 });
+    }
 }
@@ -0,0 +1,182 @@
+//! End-to-end Tor traffic through `TorSecurityManager`
+//!
+//! Drives a scripted workload through `evaluate_request` on a mock clock and
+//! checks how the modules compose: legitimate clients keep getting through, a
+//! burst of bots pushes DDoS mitigation into `UnderAttack` and back out, and an
+//! exit node reported for abuse ends up on the blocklist.
+
+use rustwall::clock::MockClock;
+use rustwall::tor::circuit_analysis::{CircuitPath, CircuitState};
+use rustwall::tor::ddos_mitigation::MitigationState;
+use rustwall::tor::exit_node_filter::BlocklistSource;
+use rustwall::tor::onion_service::OnionAddress;
+use rustwall::tor::{RequestContext, RequestDecision};
+use rustwall::{TorSecurityConfig, TorSecurityManager};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const GOOD_EXIT: IpAddr = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1));
+const BAD_EXIT: IpAddr = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 66));
+
+type Transitions = Arc<Mutex<Vec<(MitigationState, MitigationState)>>>;
+
+struct Harness {
+    manager: TorSecurityManager,
+    clock: MockClock,
+    onion: OnionAddress,
+    transitions: Transitions,
+}
+
+impl Harness {
+    /// 10 requests per second, so 16 to 20 requests within a second is `UnderAttack`
+    fn new() -> Self {
+        let config = TorSecurityConfig::builder()
+            .rate_limit_window_seconds(10)
+            .max_requests_per_window(100)
+            .max_connections_per_circuit(50)
+            .build()
+            .unwrap();
+        let clock = MockClock::new();
+        let manager = TorSecurityManager::with_config(config).unwrap().with_clock(Arc::new(clock.clone()));
+        manager.initialize().unwrap();
+
+        let onion = OnionAddress::from_public_key(&[7; 32]);
+        manager.onion_service().register_onion_service(onion.clone()).unwrap();
+
+        let transitions = Transitions::default();
+        let sink = Arc::clone(&transitions);
+        manager.on_ddos_state_change(move |from, to| sink.lock().unwrap().push((from, to)));
+
+        Self { manager, clock, onion, transitions }
+    }
+
+    /// Build a three hop circuit for `client` and have circuit analysis watch it
+    fn open_circuit(&self, circuit_id: &str, client: IpAddr) {
+        let path = CircuitPath {
+            guard_node: Some("guard".to_string()),
+            middle_node: Some("middle".to_string()),
+            exit_node: Some("exit".to_string()),
+            path_length: 3,
+        };
+        let mut analysis = self.manager.circuit_analysis();
+        analysis.register_circuit(circuit_id.to_string(), Some(client), path).unwrap();
+        analysis.update_circuit_state(circuit_id, CircuitState::Built).unwrap();
+    }
+
+    /// Send one request and close its connection straight away when allowed
+    fn send(&self, client: IpAddr, circuit_id: Option<&str>, exit_node: Option<IpAddr>) -> RequestDecision {
+        let ctx = RequestContext {
+            source_ip: Some(client),
+            onion_address: Some(self.onion.clone()),
+            circuit_id: circuit_id.map(str::to_string),
+            exit_node,
+            request_size: 512,
+        };
+        let decision = self.manager.evaluate_request(&ctx).unwrap();
+        if decision == RequestDecision::Allow {
+            self.manager.request_finished(&ctx);
+        }
+        decision
+    }
+
+    fn state(&self) -> MitigationState {
+        self.manager.ddos_mitigation().get_mitigation_stats().current_state
+    }
+
+    fn transitions(&self) -> Vec<(MitigationState, MitigationState)> {
+        self.transitions.lock().unwrap().clone()
+    }
+}
+
+fn client(n: u8) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::new(10, 0, 0, n))
+}
+
+fn bot(n: u8) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::new(203, 0, 113, n))
+}
+
+#[test]
+fn test_ddos_burst_escalates_and_recovers() {
+    let harness = Harness::new();
+    let (alice, bob) = (client(1), client(2));
+    harness.open_circuit("alice", alice);
+    harness.open_circuit("bob", bob);
+
+    // Steady legitimate traffic
+    for _ in 0..3 {
+        assert_eq!(harness.send(alice, Some("alice"), Some(GOOD_EXIT)), RequestDecision::Allow);
+        assert_eq!(harness.send(bob, Some("bob"), Some(GOOD_EXIT)), RequestDecision::Allow);
+        harness.clock.advance(Duration::from_secs(1));
+    }
+    harness.clock.advance(Duration::from_millis(6_500));
+    assert_eq!(harness.state(), MitigationState::Normal);
+
+    // 18 bots on fresh circuits inside one second. Each is still admitted: the
+    // state only moves when traffic is next analysed.
+    for n in 0..18 {
+        let circuit = format!("bot-{}", n);
+        assert_eq!(harness.send(bot(n), Some(&circuit), None), RequestDecision::Allow);
+    }
+    assert!(harness.transitions().is_empty());
+
+    // The next request triggers analysis over 19 requests in the last second
+    harness.clock.advance(Duration::from_millis(700));
+    assert_eq!(harness.send(alice, Some("alice"), Some(GOOD_EXIT)), RequestDecision::Allow);
+    assert_eq!(harness.state(), MitigationState::UnderAttack);
+    assert_eq!(harness.transitions(), [(MitigationState::Normal, MitigationState::UnderAttack)]);
+
+    // Under attack, calm circuits pass, unknown circuits are challenged until solved
+    assert_eq!(harness.send(bob, Some("bob"), Some(GOOD_EXIT)), RequestDecision::Allow);
+    assert_eq!(harness.send(bot(100), Some("bot-new"), None), RequestDecision::Challenge);
+    assert_eq!(harness.send(bot(101), None, None), RequestDecision::Challenge);
+    harness.manager.mark_challenge_passed("bot-new");
+    assert_eq!(harness.send(bot(100), Some("bot-new"), None), RequestDecision::Allow);
+
+    // Once the burst is over the next analysis stands mitigation down
+    harness.clock.advance(Duration::from_secs(11));
+    assert_eq!(harness.send(alice, Some("alice"), Some(GOOD_EXIT)), RequestDecision::Allow);
+    assert_eq!(harness.state(), MitigationState::Normal);
+    assert_eq!(
+        harness.transitions(),
+        [
+            (MitigationState::Normal, MitigationState::UnderAttack),
+            (MitigationState::UnderAttack, MitigationState::Normal),
+        ]
+    );
+    assert_eq!(harness.send(bot(101), None, None), RequestDecision::Allow);
+}
+
+#[test]
+fn test_reported_exit_node_is_auto_blocked() {
+    let harness = Harness::new();
+    let (alice, carol) = (client(1), client(3));
+    harness.open_circuit("alice", alice);
+    harness.open_circuit("carol", carol);
+
+    assert_eq!(harness.send(alice, Some("alice"), Some(GOOD_EXIT)), RequestDecision::Allow);
+    assert_eq!(harness.send(carol, Some("carol"), Some(BAD_EXIT)), RequestDecision::Allow);
+
+    // Abuse reports against the exit carol's traffic used, up to the auto-block threshold
+    for report in 1..=10 {
+        harness.clock.advance(Duration::from_secs(1));
+        harness
+            .manager
+            .exit_node_filter()
+            .report_malicious_activity(BAD_EXIT, format!("injected content #{}", report))
+            .unwrap();
+    }
+
+    let blocked = harness.manager.exit_node_filter().list_blocked();
+    assert_eq!(blocked.len(), 1);
+    assert_eq!(blocked[0].ip_address, BAD_EXIT);
+    assert_eq!(blocked[0].source, BlocklistSource::BehaviorAnalysis);
+
+    // Requests through the blocked exit are refused before any other check runs
+    assert_eq!(harness.send(carol, Some("carol"), Some(BAD_EXIT)), RequestDecision::Deny);
+    assert_eq!(harness.send(alice, Some("alice"), Some(BAD_EXIT)), RequestDecision::Deny);
+    assert_eq!(harness.send(alice, Some("alice"), Some(GOOD_EXIT)), RequestDecision::Allow);
+    assert_eq!(harness.send(carol, Some("carol"), Some(GOOD_EXIT)), RequestDecision::Allow);
+    assert_eq!(harness.state(), MitigationState::Normal);
+}